@@ -1,57 +1,629 @@
-use anyhow::Result;
-use arboard::Clipboard;
-use std::time::Duration;
+use anyhow::{bail, Result};
+use arboard::{Clipboard, ImageData};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use synapse_protocol::{ImageEncoding, Message};
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, warn};
 
+pub use synapse_protocol::image::{decode_png, encode_png};
+
+/// 剪贴板图片默认像素上限（8K 分辨率）
+pub const DEFAULT_MAX_IMAGE_PIXELS: u64 = 7680 * 4320;
+
+/// 校验 RGBA 图片的尺寸与数据长度一致，且像素数不超过 `max_pixels`
+pub fn validate_image(width: u32, height: u32, data: &[u8], max_pixels: u64) -> Result<()> {
+    let pixels = width as u64 * height as u64;
+    if pixels > max_pixels {
+        bail!("clipboard image too large: {}x{} (max {} pixels)", width, height, max_pixels);
+    }
+    let expected = pixels * 4;
+    if data.len() as u64 != expected {
+        bail!(
+            "clipboard image size mismatch: {}x{} expects {} bytes, got {}",
+            width, height, expected, data.len()
+        );
+    }
+    Ok(())
+}
+
 /// 剪贴板变更事件
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ClipboardContent {
     Text(String),
     Image { width: usize, height: usize, data: Vec<u8> },
+    /// 剪贴板被清空
+    Cleared,
+}
+
+impl ClipboardContent {
+    /// 内容占用的字节数（文本长度或图片像素数据长度）
+    pub fn size(&self) -> usize {
+        match self {
+            ClipboardContent::Text(text) => text.len(),
+            ClipboardContent::Image { data, .. } => data.len(),
+            ClipboardContent::Cleared => 0,
+        }
+    }
+
+    /// 转换为剪贴板同步协议消息，图片压缩为 PNG（编码失败时退回原始 RGBA）
+    ///
+    /// `transfer_id` 置 0，由实际发送时分配
+    pub fn into_message(self) -> Message {
+        match self {
+            ClipboardContent::Text(text) => Message::ClipboardText { text, transfer_id: 0 },
+            ClipboardContent::Cleared => Message::ClipboardClear { transfer_id: 0 },
+            ClipboardContent::Image { width, height, data } => {
+                let (width, height) = (width as u32, height as u32);
+                let (encoding, data) = match encode_png(width, height, &data) {
+                    Ok(png) => {
+                        debug!(raw = data.len(), png = png.len(), "clipboard image encoded");
+                        (ImageEncoding::Png, png)
+                    }
+                    Err(e) => {
+                        warn!("PNG encoding failed, sending raw image: {}", e);
+                        (ImageEncoding::Raw, data)
+                    }
+                };
+                Message::ClipboardImage { width, height, encoding, data, transfer_id: 0 }
+            }
+        }
+    }
+}
+
+/// 轮询间隔较长时，等待期间检查取消的间隔，使停止监控不必等满一个轮询周期
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 剪贴板历史默认保留的条数
+pub const DEFAULT_HISTORY_ITEMS: usize = 20;
+
+/// 剪贴板历史默认占用的字节上限，低于单帧上限，整份历史通常能在一帧内发出
+pub const DEFAULT_HISTORY_BYTES: usize = 8 * 1024 * 1024;
+
+/// 最近的剪贴板内容，按条数与总字节数双重限制，超出时淘汰最旧的条目
+#[derive(Debug, Clone)]
+pub struct ClipboardHistory {
+    items: VecDeque<ClipboardContent>,
+    max_items: usize,
+    max_bytes: usize,
+    bytes: usize,
+}
+
+impl ClipboardHistory {
+    pub fn new(max_items: usize, max_bytes: usize) -> Self {
+        Self { items: VecDeque::new(), max_items, max_bytes, bytes: 0 }
+    }
+
+    /// 记录一条新内容；清空事件、与最新条目相同的内容以及单条即超出字节上限的内容不记录
+    pub fn push(&mut self, content: ClipboardContent) {
+        if content == ClipboardContent::Cleared
+            || self.items.front() == Some(&content)
+            || content.size() > self.max_bytes
+            || self.max_items == 0
+        {
+            return;
+        }
+        self.bytes += content.size();
+        self.items.push_front(content);
+        while self.items.len() > self.max_items || self.bytes > self.max_bytes {
+            match self.items.pop_back() {
+                Some(evicted) => self.bytes -= evicted.size(),
+                None => break,
+            }
+        }
+    }
+
+    /// 第 `index` 条内容，0 为最新
+    pub fn get(&self, index: usize) -> Option<&ClipboardContent> {
+        self.items.get(index)
+    }
+
+    /// 从新到旧遍历
+    pub fn iter(&self) -> impl Iterator<Item = &ClipboardContent> {
+        self.items.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 当前占用的字节数
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// 转换为 [`Message::ClipboardHistory`] 的条目，按从新到旧排列
+    pub fn to_messages(&self) -> Vec<Message> {
+        self.items.iter().cloned().map(ClipboardContent::into_message).collect()
+    }
+}
+
+impl Default for ClipboardHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_ITEMS, DEFAULT_HISTORY_BYTES)
+    }
+}
+
+/// 按内容类型与大小决定哪些剪贴板内容参与同步
+#[derive(Debug, Clone)]
+pub struct ClipboardPolicy {
+    /// 同步文本
+    pub text: bool,
+    /// 同步图片
+    pub images: bool,
+    /// 文本最大字节数，超出时不同步；`None` 表示不限制
+    pub max_text_len: Option<usize>,
+}
+
+impl Default for ClipboardPolicy {
+    fn default() -> Self {
+        Self { text: true, images: true, max_text_len: None }
+    }
+}
+
+impl ClipboardPolicy {
+    /// 该内容是否允许同步
+    pub fn allows(&self, content: &ClipboardContent) -> bool {
+        match content {
+            ClipboardContent::Text(text) => self.allows_text(text),
+            ClipboardContent::Image { .. } => self.images,
+            ClipboardContent::Cleared => self.text || self.images,
+        }
+    }
+
+    /// 收到的剪贴板消息是否允许写入本机剪贴板；非剪贴板消息不受限制
+    pub fn allows_message(&self, msg: &Message) -> bool {
+        match msg {
+            Message::ClipboardText { text, .. } => self.allows_text(text),
+            Message::ClipboardImage { .. } => self.images,
+            Message::ClipboardClear { .. } => self.text || self.images,
+            _ => true,
+        }
+    }
+
+    fn allows_text(&self, text: &str) -> bool {
+        self.text && self.max_text_len.is_none_or(|max| text.len() <= max)
+    }
+}
+
+/// 自适应轮询间隔：检测到变更后的一段时间内快速轮询（连续复制时同步更及时），空闲后逐渐放慢
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptivePoll {
+    /// 活跃期内的轮询间隔
+    pub min: Duration,
+    /// 空闲时的轮询间隔
+    pub max: Duration,
+    /// 变更后保持快速轮询的时长，之后在同样长的时间内线性放慢到 `max`
+    pub active_window: Duration,
+}
+
+impl AdaptivePoll {
+    /// 距上次变更 `since_change` 时的轮询间隔；`None` 表示尚未检测到变更
+    pub fn interval(&self, since_change: Option<Duration>) -> Duration {
+        let elapsed = match since_change {
+            Some(elapsed) => elapsed,
+            None => return self.max,
+        };
+        if elapsed < self.active_window {
+            return self.min;
+        }
+        let decay = (elapsed - self.active_window).as_secs_f64()
+            / self.active_window.as_secs_f64().max(f64::EPSILON);
+        if decay >= 1.0 || self.max <= self.min {
+            return self.max;
+        }
+        self.min + (self.max - self.min).mul_f64(decay)
+    }
 }
 
 /// 剪贴板监控器，通过轮询检测变更
 pub struct ClipboardWatcher {
-    poll_interval: Duration,
+    poll: AdaptivePoll,
+    detect_clears: bool,
+    history: Option<Arc<Mutex<ClipboardHistory>>>,
 }
 
 impl ClipboardWatcher {
+    /// 以固定间隔轮询
     pub fn new(poll_interval: Duration) -> Self {
-        Self { poll_interval }
+        Self {
+            poll: AdaptivePoll {
+                min: poll_interval,
+                max: poll_interval,
+                active_window: Duration::ZERO,
+            },
+            detect_clears: false,
+            history: None,
+        }
+    }
+
+    /// 检测到变更后的 `active_window` 内以 `min` 间隔快速轮询，空闲后放慢到构造时的间隔
+    pub fn with_adaptive_polling(mut self, min: Duration, active_window: Duration) -> Self {
+        self.poll.min = min.min(self.poll.max);
+        self.poll.active_window = active_window;
+        self
+    }
+
+    /// 剪贴板从有文本变为空时发出 [`ClipboardContent::Cleared`]（如安全工具清除复制的密码后）
+    ///
+    /// 启动时剪贴板为空不会触发
+    pub fn with_clear_detection(mut self, enabled: bool) -> Self {
+        self.detect_clears = enabled;
+        self
+    }
+
+    /// 将检测到的变更同时记入 `history`，供查询剪贴板历史
+    pub fn with_history(mut self, history: Arc<Mutex<ClipboardHistory>>) -> Self {
+        self.history = Some(history);
+        self
     }
 
     /// 启动剪贴板监控，变更时发送到 channel
-    pub async fn watch(&self, tx: mpsc::UnboundedSender<ClipboardContent>) -> Result<()> {
-        let interval = self.poll_interval;
+    ///
+    /// `cancel` 被取消或接收端关闭后轮询线程退出，重新启动服务时不会残留旧的轮询线程
+    pub async fn watch(
+        &self,
+        tx: mpsc::UnboundedSender<ClipboardContent>,
+        cancel: CancellationToken,
+    ) -> Result<()> {
+        let poll = self.poll;
+        let detect_clears = self.detect_clears;
+        let history = self.history.clone();
 
         tokio::task::spawn_blocking(move || {
             let mut clipboard = Clipboard::new().expect("failed to access clipboard");
-            let mut last_text = String::new();
-
-            loop {
-                match clipboard.get_text() {
-                    Ok(text) if text != last_text && !text.is_empty() => {
-                        debug!(len = text.len(), "clipboard text changed");
-                        last_text = text.clone();
-                        let _ = tx.send(ClipboardContent::Text(text));
-                    }
-                    Err(e) => {
-                        warn!("clipboard read error: {}", e);
-                    }
-                    _ => {}
-                }
-                std::thread::sleep(interval);
-            }
+            poll_clipboard(&mut clipboard, poll, detect_clears, history.as_deref(), &tx, &cancel);
+            debug!("clipboard watcher stopped");
         });
 
         Ok(())
     }
 
+    /// 一次性读取当前剪贴板内容（优先文本，其次图片）
+    ///
+    /// 剪贴板为空或无法访问时返回 `Ok(None)`
+    pub fn get_current() -> Result<Option<ClipboardContent>> {
+        let mut clipboard = match Clipboard::new() {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("clipboard unavailable: {}", e);
+                return Ok(None);
+            }
+        };
+
+        let text = clipboard.get_text();
+        current_content(text, || clipboard.get_image())
+    }
+
+    /// 清空剪贴板
+    pub fn clear() -> Result<()> {
+        let mut clipboard = Clipboard::new()?;
+        clipboard.clear()?;
+        Ok(())
+    }
+
     /// 设置剪贴板文本
     pub fn set_text(text: &str) -> Result<()> {
         let mut clipboard = Clipboard::new()?;
         clipboard.set_text(text)?;
         Ok(())
     }
+
+    /// 设置剪贴板图片，按 `encoding` 解码为 RGBA，写入前校验尺寸与数据长度
+    pub fn set_image(
+        width: u32,
+        height: u32,
+        encoding: ImageEncoding,
+        data: &[u8],
+        max_pixels: u64,
+    ) -> Result<()> {
+        let decoded;
+        let data = match encoding {
+            ImageEncoding::Raw => data,
+            ImageEncoding::Png => {
+                let (png_w, png_h, pixels) = decode_png(data, max_pixels)?;
+                if (png_w, png_h) != (width, height) {
+                    bail!(
+                        "clipboard image size mismatch: header {}x{}, PNG {}x{}",
+                        width, height, png_w, png_h
+                    );
+                }
+                decoded = pixels;
+                &decoded
+            }
+        };
+        validate_image(width, height, data, max_pixels)?;
+        let mut clipboard = Clipboard::new()?;
+        clipboard.set_image(ImageData {
+            width: width as usize,
+            height: height as usize,
+            bytes: Cow::Borrowed(data),
+        })?;
+        Ok(())
+    }
+
+    /// 把收到的剪贴板消息写入本机剪贴板，图片像素数上限为 [`DEFAULT_MAX_IMAGE_PIXELS`]；
+    /// 非剪贴板消息不做任何事
+    pub fn write_message(msg: &Message) -> Result<()> {
+        match msg {
+            Message::ClipboardText { text, .. } => Self::set_text(text),
+            Message::ClipboardClear { .. } => Self::clear(),
+            Message::ClipboardImage { width, height, encoding, data, .. } => {
+                Self::set_image(*width, *height, *encoding, data, DEFAULT_MAX_IMAGE_PIXELS)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 轮询读取的剪贴板，测试中可替换为脚本化的内容
+trait ClipboardSource {
+    fn text(&mut self) -> std::result::Result<String, arboard::Error>;
+    /// 剪贴板中是否有图片
+    fn has_image(&mut self) -> bool;
+}
+
+impl ClipboardSource for Clipboard {
+    fn text(&mut self) -> std::result::Result<String, arboard::Error> {
+        self.get_text()
+    }
+
+    fn has_image(&mut self) -> bool {
+        !matches!(self.get_image(), Err(arboard::Error::ContentNotAvailable))
+    }
+}
+
+/// 轮询 `source` 直到 `cancel` 被触发或接收端关闭，文本变更与清空发送到 `tx`
+fn poll_clipboard(
+    source: &mut impl ClipboardSource,
+    poll: AdaptivePoll,
+    detect_clears: bool,
+    history: Option<&Mutex<ClipboardHistory>>,
+    tx: &mpsc::UnboundedSender<ClipboardContent>,
+    cancel: &CancellationToken,
+) {
+    let mut last_text = String::new();
+    let mut last_change: Option<Instant> = None;
+
+    while !cancel.is_cancelled() && !tx.is_closed() {
+        let text = match source.text() {
+            Ok(text) => Some(text),
+            Err(arboard::Error::ContentNotAvailable) => Some(String::new()),
+            Err(e) => {
+                warn!("clipboard read error: {}", e);
+                None
+            }
+        };
+        match text {
+            Some(text) if text.is_empty() && !last_text.is_empty() => {
+                // 文本消失且没有换成图片，视为被清空
+                let cleared = !source.has_image();
+                if detect_clears && cleared {
+                    debug!("clipboard cleared");
+                    let _ = tx.send(ClipboardContent::Cleared);
+                }
+                last_text.clear();
+                last_change = Some(Instant::now());
+            }
+            Some(text) if text != last_text && !text.is_empty() => {
+                debug!(len = text.len(), "clipboard text changed");
+                last_text = text.clone();
+                last_change = Some(Instant::now());
+                if let Some(history) = history {
+                    if let Ok(mut history) = history.lock() {
+                        history.push(ClipboardContent::Text(text.clone()));
+                    }
+                }
+                let _ = tx.send(ClipboardContent::Text(text));
+            }
+            _ => {}
+        }
+        let deadline = Instant::now() + poll.interval(last_change.map(|t| t.elapsed()));
+        while !cancel.is_cancelled() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            std::thread::sleep(remaining.min(CANCEL_CHECK_INTERVAL));
+        }
+    }
+}
+
+/// 由一次文本读取结果得出当前内容；没有文本时才调用 `image` 读取图片
+fn current_content(
+    text: std::result::Result<String, arboard::Error>,
+    image: impl FnOnce() -> std::result::Result<arboard::ImageData<'static>, arboard::Error>,
+) -> Result<Option<ClipboardContent>> {
+    match text {
+        Ok(text) if !text.is_empty() => return Ok(Some(ClipboardContent::Text(text))),
+        Ok(_) | Err(arboard::Error::ContentNotAvailable) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    match image() {
+        Ok(image) => Ok(Some(ClipboardContent::Image {
+            width: image.width,
+            height: image.height,
+            data: image.bytes.into_owned(),
+        })),
+        Err(arboard::Error::ContentNotAvailable) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_image_dimensions() {
+        assert!(validate_image(2, 2, &[0; 16], DEFAULT_MAX_IMAGE_PIXELS).is_ok());
+
+        let err = validate_image(2, 2, &[0; 15], DEFAULT_MAX_IMAGE_PIXELS).unwrap_err();
+        assert!(err.to_string().contains("mismatch"), "{err}");
+
+        // 超大尺寸在按尺寸分配前就被拒绝，乘法不会溢出
+        let err = validate_image(u32::MAX, u32::MAX, &[0; 4], DEFAULT_MAX_IMAGE_PIXELS).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{err}");
+
+        assert!(validate_image(3, 3, &[0; 36], 8).is_err());
+        assert!(validate_image(2, 4, &[0; 32], 8).is_ok());
+    }
+
+    /// 依次返回预设文本的剪贴板，读完后保持最后一条
+    struct Scripted(VecDeque<&'static str>);
+
+    impl ClipboardSource for Scripted {
+        fn text(&mut self) -> std::result::Result<String, arboard::Error> {
+            let text = if self.0.len() > 1 { self.0.pop_front() } else { self.0.front().copied() };
+            Ok(text.unwrap_or_default().to_string())
+        }
+
+        fn has_image(&mut self) -> bool {
+            false
+        }
+    }
+
+    /// 在线程中运行轮询循环
+    fn spawn_poll(
+        script: Vec<&'static str>,
+        interval: Duration,
+        tx: mpsc::UnboundedSender<ClipboardContent>,
+        cancel: CancellationToken,
+    ) -> std::thread::JoinHandle<()> {
+        let poll = AdaptivePoll { min: interval, max: interval, active_window: Duration::ZERO };
+        std::thread::spawn(move || {
+            let mut source = Scripted(VecDeque::from(script));
+            poll_clipboard(&mut source, poll, true, None, &tx, &cancel);
+        })
+    }
+
+    #[test]
+    fn poll_loop_reports_changes_and_clears() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cancel = CancellationToken::new();
+        let script = vec!["copied", "copied", "", "again"];
+        let worker = spawn_poll(script, Duration::from_millis(1), tx, cancel.clone());
+        let expected = [
+            ClipboardContent::Text("copied".into()),
+            ClipboardContent::Cleared,
+            ClipboardContent::Text("again".into()),
+        ];
+        for content in expected {
+            assert_eq!(rx.blocking_recv(), Some(content));
+        }
+        cancel.cancel();
+        worker.join().unwrap();
+        // 发送端随循环结束被释放，没有多余的事件
+        assert_eq!(rx.blocking_recv(), None);
+    }
+
+    #[test]
+    fn cancel_stops_poll_loop_promptly() {
+        // 轮询间隔远长于测试时限，只有等待期间检查取消才能及时退出
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cancel = CancellationToken::new();
+        let worker = spawn_poll(vec!["copied"], Duration::from_secs(60), tx, cancel.clone());
+        assert_eq!(rx.blocking_recv(), Some(ClipboardContent::Text("copied".into())));
+
+        let started = Instant::now();
+        cancel.cancel();
+        worker.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1), "took {:?}", started.elapsed());
+    }
+
+    #[test]
+    fn adaptive_poll_ramps_up_and_decays() {
+        let ms = Duration::from_millis;
+        let poll = AdaptivePoll { min: ms(50), max: ms(500), active_window: ms(2000) };
+        // 未检测到变更时保持慢速
+        assert_eq!(poll.interval(None), ms(500));
+        // 变更后的活跃期内快速轮询
+        assert_eq!(poll.interval(Some(ms(0))), ms(50));
+        assert_eq!(poll.interval(Some(ms(1999))), ms(50));
+        // 之后在同样长的时间内线性放慢
+        assert_eq!(poll.interval(Some(ms(3000))), ms(275));
+        assert_eq!(poll.interval(Some(ms(4000))), ms(500));
+        assert_eq!(poll.interval(Some(ms(60_000))), ms(500));
+
+        // 固定间隔的监控器不受变更影响
+        let fixed = ClipboardWatcher::new(ms(500)).poll;
+        assert_eq!(fixed.interval(Some(ms(0))), ms(500));
+        let adaptive = ClipboardWatcher::new(ms(500)).with_adaptive_polling(ms(50), ms(2000)).poll;
+        assert_eq!(adaptive, poll);
+    }
+
+    #[test]
+    fn policy_limits_text_and_images() {
+        let policy = ClipboardPolicy { images: false, max_text_len: Some(3), ..ClipboardPolicy::default() };
+        assert!(policy.allows(&ClipboardContent::Text("abc".into())));
+        assert!(!policy.allows(&ClipboardContent::Text("abcd".into())));
+        assert!(!policy.allows(&ClipboardContent::Image { width: 1, height: 1, data: vec![0; 4] }));
+        assert!(policy.allows(&ClipboardContent::Cleared));
+
+        let text = |text: &str| Message::ClipboardText { text: text.into(), transfer_id: 1 };
+        assert!(policy.allows_message(&text("abc")));
+        assert!(!policy.allows_message(&text("abcd")));
+        assert!(!policy.allows_message(&Message::ClipboardImage {
+            width: 1,
+            height: 1,
+            encoding: ImageEncoding::Raw,
+            data: vec![0; 4],
+            transfer_id: 2,
+        }));
+        assert!(policy.allows_message(&Message::Ping(1)));
+    }
+
+    #[test]
+    fn history_evicts_oldest_over_limits() {
+        let text = |s: &str| ClipboardContent::Text(s.into());
+        let mut history = ClipboardHistory::new(3, 10);
+        for s in ["aaaa", "bbbb", "cc"] {
+            history.push(text(s));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.bytes(), 10);
+
+        // 超出字节上限，淘汰最旧的一条
+        history.push(text("d"));
+        assert_eq!(history.iter().cloned().collect::<Vec<_>>(), [text("d"), text("cc"), text("bbbb")]);
+        assert_eq!(history.bytes(), 7);
+
+        // 超出条数上限
+        history.push(text("e"));
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(2), Some(&text("cc")));
+
+        // 单条即超出上限、与最新相同或清空事件都不记录
+        history.push(text("0123456789a"));
+        history.push(text("e"));
+        history.push(ClipboardContent::Cleared);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(0), Some(&text("e")));
+    }
+
+    #[test]
+    fn current_prefers_text_and_treats_empty_as_none() {
+        let no_image = || Err(arboard::Error::ContentNotAvailable);
+        let text = current_content(Ok("hello".into()), || panic!("image read despite text"));
+        assert_eq!(text.unwrap(), Some(ClipboardContent::Text("hello".into())));
+
+        // 空文本与没有文本都视为无文本，再看图片
+        assert_eq!(current_content(Ok(String::new()), no_image).unwrap(), None);
+        assert_eq!(current_content(Err(arboard::Error::ContentNotAvailable), no_image).unwrap(), None);
+        let image = current_content(Err(arboard::Error::ContentNotAvailable), || {
+            Ok(arboard::ImageData { width: 1, height: 1, bytes: vec![1, 2, 3, 4].into() })
+        });
+        assert_eq!(image.unwrap(), Some(ClipboardContent::Image { width: 1, height: 1, data: vec![1, 2, 3, 4] }));
+
+        // 其他读取错误向上报告
+        assert!(current_content(Err(arboard::Error::ClipboardOccupied), no_image).is_err());
+    }
 }
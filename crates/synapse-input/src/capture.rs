@@ -1,8 +1,13 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+
 use anyhow::Result;
 use synapse_protocol::input::{ButtonAction, KeyAction, KeyCode, MouseButton};
+use synapse_protocol::screen::{ScreenId, ScreenInfo, ScreenRect};
 use synapse_protocol::Message;
 use tokio::sync::mpsc;
-use tracing::info;
+use tracing::{info, warn};
 
 /// 获取主屏幕分辨率
 pub fn get_screen_size() -> (u32, u32) {
@@ -10,29 +15,234 @@ pub fn get_screen_size() -> (u32, u32) {
     (w as u32, h as u32)
 }
 
+/// 获取本机屏幕列表（含虚拟桌面中的原点、系统报告的名称与主屏标记）
+///
+/// 无法枚举显示器时退回 rdev 查询的主屏尺寸，主屏在各平台上均位于虚拟桌面原点
+pub fn get_screens() -> Vec<ScreenInfo> {
+    match display_info::DisplayInfo::all() {
+        Ok(displays) if !displays.is_empty() => {
+            let mut screens: Vec<ScreenInfo> = displays
+                .iter()
+                .enumerate()
+                .map(|(index, display)| ScreenInfo {
+                    id: ScreenId(index as u32),
+                    name: screen_name(index, &display.name),
+                    rect: ScreenRect {
+                        x: display.x,
+                        y: display.y,
+                        width: display.width,
+                        height: display.height,
+                    },
+                    is_primary: display.is_primary,
+                })
+                .collect();
+            // 部分平台不报告主屏，取位于原点的屏幕
+            if !screens.iter().any(|s| s.is_primary) {
+                let primary = screens
+                    .iter()
+                    .position(|s| s.rect.x == 0 && s.rect.y == 0)
+                    .unwrap_or(0);
+                screens[primary].is_primary = true;
+            }
+            screens
+        }
+        result => {
+            if let Err(e) = result {
+                warn!("failed to enumerate displays, assuming a single screen: {e}");
+            }
+            let (width, height) = get_screen_size();
+            vec![ScreenInfo {
+                id: ScreenId(0),
+                name: screen_name(0, ""),
+                rect: ScreenRect { x: 0, y: 0, width, height },
+                is_primary: true,
+            }]
+        }
+    }
+}
+
+/// 系统报告的显示器名称；为空时按序号生成稳定的名称（"Display-1" 起）
+pub fn screen_name(index: usize, reported: &str) -> String {
+    let reported = reported.trim();
+    if reported.is_empty() {
+        format!("Display-{}", index + 1)
+    } else {
+        reported.to_string()
+    }
+}
+
+/// 主屏幕在虚拟桌面中的矩形区域
+pub fn primary_screen_rect() -> ScreenRect {
+    let screens = get_screens();
+    screens
+        .iter()
+        .find(|s| s.is_primary)
+        .or(screens.first())
+        .map(|s| s.rect)
+        .unwrap_or_else(|| {
+            let (width, height) = get_screen_size();
+            ScreenRect { x: 0, y: 0, width, height }
+        })
+}
+
+// ── 监听线程 ──
+//
+// rdev::listen 会一直阻塞且无法中止，因此整个进程只启动一个监听线程，
+// 线程随进程存活；各 InputCapturer 通过设置/清空转发目标来启停捕获，
+// 重复 start 不会产生第二个监听线程
+
+/// 当前的事件转发目标：(捕获器 ID, channel)
+static FORWARD: Mutex<Option<(u64, mpsc::UnboundedSender<rdev::Event>)>> = Mutex::new(None);
+static LISTENER: Once = Once::new();
+static NEXT_CAPTURER_ID: AtomicU64 = AtomicU64::new(1);
+
 /// 输入捕获器，封装 rdev::listen
+///
+/// `stop()` 或 drop 后不再转发事件
 pub struct InputCapturer {
-    _private: (),
+    id: u64,
+}
+
+impl Default for InputCapturer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InputCapturer {
     pub fn new() -> Self {
-        Self { _private: () }
+        Self { id: NEXT_CAPTURER_ID.fetch_add(1, Ordering::Relaxed) }
     }
 
     /// 启动全局输入监听，将事件发送到 channel
+    ///
+    /// 会接管转发目标，此前启动的捕获器不再收到事件
     pub fn start(&self, tx: mpsc::UnboundedSender<rdev::Event>) -> Result<()> {
         info!("starting input capture");
+        self.attach(tx);
 
-        std::thread::spawn(move || {
-            rdev::listen(move |event| {
-                let _ = tx.send(event);
-            })
-            .expect("failed to listen for input events");
+        LISTENER.call_once(|| {
+            std::thread::spawn(|| {
+                rdev::listen(forward_event).expect("failed to listen for input events");
+            });
         });
 
         Ok(())
     }
+
+    /// 把转发目标设为本捕获器的 channel
+    fn attach(&self, tx: mpsc::UnboundedSender<rdev::Event>) {
+        *FORWARD.lock().unwrap() = Some((self.id, tx));
+    }
+
+    /// 停止转发输入事件（监听线程保留，供下次 start 复用）
+    pub fn stop(&self) {
+        let mut forward = FORWARD.lock().unwrap();
+        if matches!(forward.as_ref(), Some((id, _)) if *id == self.id) {
+            *forward = None;
+            info!("input capture stopped");
+        }
+    }
+}
+
+impl Drop for InputCapturer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// ── 自身注入的事件 ──
+//
+// rdev 不区分真实输入与本程序模拟的输入：本地回显在本机模拟的按键、鼠标按键与滚动
+// 会被监听线程再次捕获并转发，随后再次回显，形成回环。模拟前先登记，
+// 监听线程捕获到匹配的事件时消耗登记并丢弃该事件
+
+/// 登记后未被捕获到的注入在此时间后作废，避免吞掉之后真实的同类输入
+const INJECTED_TTL: Duration = Duration::from_millis(500);
+
+static INJECTED: Mutex<Vec<(Injected, Instant)>> = Mutex::new(Vec::new());
+
+/// 即将由本程序模拟的一次输入
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Injected {
+    Key(KeyCode, KeyAction),
+    Button(MouseButton, ButtonAction),
+    /// 各轴滚动的行数；不同平台注入与捕获的滚动方向约定不一，只按轴与刻度数匹配
+    Scroll { dx: f64, dy: f64 },
+}
+
+/// 登记即将模拟的输入，监听线程捕获到对应事件时不再转发
+pub fn mark_injected(injected: Injected) {
+    let injected = match injected {
+        Injected::Scroll { dx, dy } if dx == 0.0 && dy == 0.0 => return,
+        Injected::Scroll { dx, dy } => Injected::Scroll { dx: dx.abs(), dy: dy.abs() },
+        injected => injected,
+    };
+    if let Ok(mut pending) = INJECTED.lock() {
+        pending.push((injected, Instant::now()));
+    }
+}
+
+/// 捕获到的事件是否为登记过的注入；是则消耗对应的登记
+fn take_injected(event: &rdev::EventType) -> bool {
+    let Ok(mut pending) = INJECTED.lock() else { return false };
+    let now = Instant::now();
+    pending.retain(|(_, at)| now.duration_since(*at) < INJECTED_TTL);
+    let captured = match event {
+        rdev::EventType::KeyPress(key) => Injected::Key(rdev_key_to_proto(key), KeyAction::Press),
+        rdev::EventType::KeyRelease(key) => {
+            Injected::Key(rdev_key_to_proto(key), KeyAction::Release)
+        }
+        rdev::EventType::ButtonPress(btn) => {
+            Injected::Button(rdev_button_to_proto(btn), ButtonAction::Press)
+        }
+        rdev::EventType::ButtonRelease(btn) => {
+            Injected::Button(rdev_button_to_proto(btn), ButtonAction::Release)
+        }
+        rdev::EventType::Wheel { delta_x, delta_y } => {
+            let (dx, dy) = ((*delta_x as f64).abs(), (*delta_y as f64).abs());
+            let Some(index) = pending.iter().position(|(injected, _)| {
+                matches!(injected, Injected::Scroll { dx: x, dy: y }
+                    if (dx == 0.0 || *x > 0.0) && (dy == 0.0 || *y > 0.0))
+            }) else {
+                return false;
+            };
+            // 一次滚动可能产生多个刻度事件，余量耗尽后才移除登记
+            if let Injected::Scroll { dx: x, dy: y } = &mut pending[index].0 {
+                *x = (x.abs() - dx).max(0.0);
+                *y = (y.abs() - dy).max(0.0);
+                if *x == 0.0 && *y == 0.0 {
+                    pending.remove(index);
+                }
+            }
+            return true;
+        }
+        rdev::EventType::MouseMove { .. } => return false,
+    };
+    match pending.iter().position(|(injected, _)| *injected == captured) {
+        Some(index) => {
+            pending.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 监听线程的回调：把事件转发给当前目标，本程序自身注入的事件除外
+fn forward_event(event: rdev::Event) {
+    if take_injected(&event.event_type) {
+        return;
+    }
+    if let Ok(mut forward) = FORWARD.lock() {
+        let closed = match forward.as_ref() {
+            Some((_, tx)) => tx.send(event).is_err(),
+            None => false,
+        };
+        // 接收端已关闭，停止转发
+        if closed {
+            *forward = None;
+        }
+    }
 }
 
 /// 将 rdev 原始事件转换为协议 Message
@@ -55,21 +265,51 @@ pub fn rdev_event_to_message(event: &rdev::Event) -> Option<Message> {
             key: rdev_key_to_proto(key),
             action: KeyAction::Release,
         }),
+        // rdev 只报告整数的滚轮刻度，不区分高精度滚动；高精度与否由服务端按本机滚动单位判定
         rdev::EventType::Wheel { delta_x, delta_y } => Some(Message::MouseScroll {
             dx: *delta_x as f64,
             dy: *delta_y as f64,
+            precise: false,
         }),
     }
 }
 
-fn rdev_button_to_proto(btn: &rdev::Button) -> MouseButton {
+/// 布局感知的转换：可打印按键的按下携带其产生的字符（[`Message::KeyChar`]），
+/// 使接收方按字符键入而非按物理键位；其余事件与 [`rdev_event_to_message`] 相同
+pub fn rdev_event_to_layout_message(event: &rdev::Event) -> Option<Message> {
+    if let rdev::EventType::KeyPress(key) = &event.event_type {
+        if let Some(text) = event.name.as_deref().filter(|s| is_printable(s)) {
+            return Some(Message::KeyChar {
+                key: rdev_key_to_proto(key),
+                text: text.to_string(),
+            });
+        }
+    }
+    rdev_event_to_message(event)
+}
+
+/// 按键产生的是否为可打印字符（Ctrl 组合等产生的控制字符不算）
+fn is_printable(text: &str) -> bool {
+    !text.is_empty() && !text.chars().any(char::is_control)
+}
+
+/// rdev 报告侧键（后退、前进）时的原始按键码
+///
+/// X11 上按键 4–7 已被 rdev 报告为滚轮（含水平滚动的倾斜键），侧键为 8、9；
+/// Windows 上为 XBUTTON1、XBUTTON2；macOS 上 rdev 不报告侧键
+#[cfg(not(target_os = "windows"))]
+const SIDE_BUTTONS: (u8, u8) = (8, 9);
+#[cfg(target_os = "windows")]
+const SIDE_BUTTONS: (u8, u8) = (1, 2);
+
+pub(crate) fn rdev_button_to_proto(btn: &rdev::Button) -> MouseButton {
     match btn {
         rdev::Button::Left => MouseButton::Left,
         rdev::Button::Right => MouseButton::Right,
         rdev::Button::Middle => MouseButton::Middle,
-        rdev::Button::Unknown(4) => MouseButton::Back,
-        rdev::Button::Unknown(5) => MouseButton::Forward,
-        _ => MouseButton::Left,
+        rdev::Button::Unknown(code) if *code == SIDE_BUTTONS.0 => MouseButton::Back,
+        rdev::Button::Unknown(code) if *code == SIDE_BUTTONS.1 => MouseButton::Forward,
+        rdev::Button::Unknown(code) => MouseButton::Other(*code),
     }
 }
 
@@ -150,7 +390,142 @@ fn rdev_key_to_proto(key: &rdev::Key) -> KeyCode {
         rdev::Key::PrintScreen => KeyCode::PrintScreen,
         rdev::Key::ScrollLock => KeyCode::ScrollLock,
         rdev::Key::Pause => KeyCode::Pause,
-        rdev::Key::Unknown(code) => KeyCode::Unknown(*code as u32),
+        rdev::Key::Unknown(code) => KeyCode::Unknown(*code),
         _ => KeyCode::Unknown(0),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event_type: rdev::EventType, name: Option<&str>) -> rdev::Event {
+        rdev::Event { time: std::time::SystemTime::now(), name: name.map(String::from), event_type }
+    }
+
+    fn key_press() -> rdev::Event {
+        event(rdev::EventType::KeyPress(rdev::Key::KeyA), None)
+    }
+
+    /// 转发目标与注入登记是进程级的，涉及它们的测试串行执行
+    static GLOBALS: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn wheel_events_are_tagged_as_notches() {
+        let wheel = event(rdev::EventType::Wheel { delta_x: 0, delta_y: -2 }, None);
+        assert!(matches!(
+            rdev_event_to_message(&wheel),
+            Some(Message::MouseScroll { dx, dy, precise: false }) if dx == 0.0 && dy == -2.0
+        ));
+    }
+
+    #[test]
+    fn unnamed_displays_get_stable_names() {
+        assert_eq!(screen_name(0, "DELL U2720Q"), "DELL U2720Q");
+        assert_eq!(screen_name(0, ""), "Display-1");
+        assert_eq!(screen_name(2, "  "), "Display-3");
+    }
+
+    #[test]
+    fn layout_symbols_travel_as_characters() {
+        // 德语布局下 Shift+2 产生 '"'，AltGr+E 产生 '€'：按字符转发而不是物理键位
+        let quote = event(rdev::EventType::KeyPress(rdev::Key::Num2), Some("\""));
+        assert!(matches!(
+            rdev_event_to_layout_message(&quote),
+            Some(Message::KeyChar { key: KeyCode::Num2, text }) if text == "\""
+        ));
+        let euro = event(rdev::EventType::KeyPress(rdev::Key::KeyE), Some("€"));
+        assert!(matches!(
+            rdev_event_to_layout_message(&euro),
+            Some(Message::KeyChar { key: KeyCode::KeyE, text }) if text == "€"
+        ));
+
+        // Ctrl 组合产生的控制字符、无字符的按键与释放事件仍走物理键位
+        let ctrl_c = event(rdev::EventType::KeyPress(rdev::Key::KeyC), Some("\u{3}"));
+        assert!(matches!(
+            rdev_event_to_layout_message(&ctrl_c),
+            Some(Message::KeyEvent { key: KeyCode::KeyC, action: KeyAction::Press })
+        ));
+        let shift = event(rdev::EventType::KeyPress(rdev::Key::ShiftLeft), None);
+        assert!(matches!(
+            rdev_event_to_layout_message(&shift),
+            Some(Message::KeyEvent { key: KeyCode::LeftShift, action: KeyAction::Press })
+        ));
+        let release = event(rdev::EventType::KeyRelease(rdev::Key::Num2), Some("\""));
+        assert!(matches!(
+            rdev_event_to_layout_message(&release),
+            Some(Message::KeyEvent { key: KeyCode::Num2, action: KeyAction::Release })
+        ));
+    }
+
+    #[test]
+    fn stopped_capturer_forwards_nothing() {
+        let _globals = GLOBALS.lock().unwrap();
+        let first = InputCapturer::new();
+        let (tx, mut first_rx) = mpsc::unbounded_channel();
+        first.attach(tx);
+        forward_event(key_press());
+        assert!(first_rx.try_recv().is_ok());
+
+        // 新的捕获器接管转发，旧捕获器的 stop 不影响它
+        let second = InputCapturer::new();
+        let (tx, mut second_rx) = mpsc::unbounded_channel();
+        second.attach(tx);
+        first.stop();
+        forward_event(key_press());
+        assert!(first_rx.try_recv().is_err());
+        assert!(second_rx.try_recv().is_ok());
+
+        drop(second);
+        forward_event(key_press());
+        assert!(second_rx.try_recv().is_err());
+        assert!(FORWARD.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn injected_events_are_not_forwarded_again() {
+        let _globals = GLOBALS.lock().unwrap();
+        let capturer = InputCapturer::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        capturer.attach(tx);
+
+        // 回显的按下被再次捕获时丢弃，之后真实的同一按键照常转发
+        mark_injected(Injected::Key(KeyCode::KeyA, KeyAction::Press));
+        forward_event(key_press());
+        assert!(rx.try_recv().is_err());
+        forward_event(key_press());
+        assert!(rx.try_recv().is_ok());
+
+        // 只丢弃与登记相符的事件
+        mark_injected(Injected::Button(MouseButton::Left, ButtonAction::Press));
+        forward_event(event(rdev::EventType::ButtonPress(rdev::Button::Right), None));
+        assert!(rx.try_recv().is_ok());
+        forward_event(event(rdev::EventType::ButtonPress(rdev::Button::Left), None));
+        assert!(rx.try_recv().is_err());
+
+        // 一次回显的滚动产生多个刻度事件，余量耗尽前都丢弃
+        mark_injected(Injected::Scroll { dx: 0.0, dy: -2.0 });
+        let notch = || event(rdev::EventType::Wheel { delta_x: 0, delta_y: -1 }, None);
+        forward_event(notch());
+        forward_event(notch());
+        assert!(rx.try_recv().is_err());
+        forward_event(notch());
+        assert!(rx.try_recv().is_ok());
+        assert!(INJECTED.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn stale_injections_expire() {
+        let _globals = GLOBALS.lock().unwrap();
+        let capturer = InputCapturer::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        capturer.attach(tx);
+
+        // 未被捕获到的登记过期后不再吞掉真实输入
+        let stale = Instant::now() - INJECTED_TTL;
+        INJECTED.lock().unwrap().push((Injected::Key(KeyCode::KeyA, KeyAction::Press), stale));
+        forward_event(key_press());
+        assert!(rx.try_recv().is_ok());
+        assert!(INJECTED.lock().unwrap().is_empty());
+    }
+}
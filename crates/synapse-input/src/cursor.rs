@@ -0,0 +1,195 @@
+use tracing::debug;
+
+/// 本机系统光标的显示与隐藏
+///
+/// X11 下通过 XFixes 隐藏根窗口上的光标；隐藏状态绑定在打开的 X 连接上，drop 时连接关闭、光标随之恢复。
+/// 其他平台无法隐藏系统光标，降级为无操作
+pub struct CursorVisibility {
+    /// 首次隐藏时才打开，打开失败后不再重试
+    backend: Option<Option<platform::Backend>>,
+    hidden: bool,
+}
+
+impl CursorVisibility {
+    pub fn new() -> Self {
+        Self { backend: None, hidden: false }
+    }
+
+    /// 显示或隐藏光标；重复设置为同一状态时不做任何事
+    pub fn set_visible(&mut self, visible: bool) {
+        if self.hidden != visible {
+            return;
+        }
+        let backend = self.backend.get_or_insert_with(platform::Backend::open);
+        match backend {
+            Some(backend) => {
+                backend.set_visible(visible);
+                self.hidden = !visible;
+            }
+            None => {
+                debug!(visible, "cursor visibility control not supported on this platform, ignoring");
+            }
+        }
+    }
+}
+
+impl Default for CursorVisibility {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 锁定本机指针：光标停在原处，本机程序收不到鼠标事件，原始运动（见 [`relative`](crate::relative)）照常送达
+///
+/// X11 下抓取指针并将其限制在光标处 1x1 的不可见窗口内，随抓取的 X 连接关闭而解除。
+/// 其他平台无法锁定，降级为无操作
+pub struct PointerLock {
+    /// 首次锁定时才打开，打开失败后不再重试
+    backend: Option<Option<platform::Backend>>,
+    locked: bool,
+}
+
+impl PointerLock {
+    pub fn new() -> Self {
+        Self { backend: None, locked: false }
+    }
+
+    /// 锁定或解锁指针；重复设置为同一状态时不做任何事
+    pub fn set_locked(&mut self, locked: bool) {
+        if self.locked == locked {
+            return;
+        }
+        let backend = self.backend.get_or_insert_with(platform::Backend::open);
+        match backend {
+            Some(backend) => self.locked = backend.set_locked(locked),
+            None => {
+                debug!(locked, "pointer lock not supported on this platform, ignoring");
+            }
+        }
+    }
+}
+
+impl Default for PointerLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::ptr;
+
+    use tracing::debug;
+    use x11::{xfixes, xlib};
+
+    pub(super) struct Backend {
+        display: *mut xlib::Display,
+        root: xlib::Window,
+        /// 锁定期间限制指针的窗口
+        confine: Option<xlib::Window>,
+    }
+
+    // 连接只在持有者所在线程使用，移动到其他线程后不会被并发访问
+    unsafe impl Send for Backend {}
+
+    impl Backend {
+        pub(super) fn open() -> Option<Self> {
+            unsafe {
+                let display = xlib::XOpenDisplay(ptr::null());
+                if display.is_null() {
+                    debug!("no X display, cannot hide the cursor");
+                    return None;
+                }
+                let (mut event_base, mut error_base) = (0, 0);
+                if xfixes::XFixesQueryExtension(display, &mut event_base, &mut error_base) == 0 {
+                    debug!("X server lacks the XFixes extension, cannot hide the cursor");
+                    xlib::XCloseDisplay(display);
+                    return None;
+                }
+                let root = xlib::XDefaultRootWindow(display);
+                Some(Self { display, root, confine: None })
+            }
+        }
+
+        pub(super) fn set_visible(&mut self, visible: bool) {
+            unsafe {
+                if visible {
+                    xfixes::XFixesShowCursor(self.display, self.root);
+                } else {
+                    xfixes::XFixesHideCursor(self.display, self.root);
+                }
+                xlib::XFlush(self.display);
+            }
+        }
+
+        /// 返回操作后是否处于锁定状态
+        pub(super) fn set_locked(&mut self, locked: bool) -> bool {
+            unsafe {
+                if !locked {
+                    xlib::XUngrabPointer(self.display, xlib::CurrentTime);
+                    if let Some(window) = self.confine.take() {
+                        xlib::XDestroyWindow(self.display, window);
+                    }
+                    xlib::XFlush(self.display);
+                    return false;
+                }
+
+                let (mut root, mut child) = (0, 0);
+                let (mut x, mut y, mut win_x, mut win_y, mut buttons) = (0, 0, 0, 0, 0);
+                xlib::XQueryPointer(
+                    self.display, self.root, &mut root, &mut child,
+                    &mut x, &mut y, &mut win_x, &mut win_y, &mut buttons,
+                );
+                // 不经窗口管理器直接映射的不可见窗口
+                let mut attributes: xlib::XSetWindowAttributes = std::mem::zeroed();
+                attributes.override_redirect = xlib::True;
+                let window = xlib::XCreateWindow(
+                    self.display, self.root, x, y, 1, 1, 0, 0,
+                    xlib::InputOnly as u32, ptr::null_mut(),
+                    xlib::CWOverrideRedirect, &mut attributes,
+                );
+                xlib::XMapWindow(self.display, window);
+                // 不向任何窗口报告鼠标事件，本机程序收不到点击
+                let status = xlib::XGrabPointer(
+                    self.display, self.root, xlib::False, 0,
+                    xlib::GrabModeAsync, xlib::GrabModeAsync,
+                    window, 0, xlib::CurrentTime,
+                );
+                if status != xlib::GrabSuccess {
+                    debug!(status, "failed to grab the pointer, leaving it unlocked");
+                    xlib::XDestroyWindow(self.display, window);
+                    xlib::XFlush(self.display);
+                    return false;
+                }
+                self.confine = Some(window);
+                xlib::XFlush(self.display);
+                true
+            }
+        }
+    }
+
+    impl Drop for Backend {
+        fn drop(&mut self) {
+            unsafe {
+                xlib::XCloseDisplay(self.display);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    pub(super) struct Backend;
+
+    impl Backend {
+        pub(super) fn open() -> Option<Self> {
+            None
+        }
+
+        pub(super) fn set_visible(&mut self, _visible: bool) {}
+
+        pub(super) fn set_locked(&mut self, _locked: bool) -> bool {
+            false
+        }
+    }
+}
@@ -1,5 +1,14 @@
 pub mod capture;
+pub mod cursor;
+pub mod lock;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod relative;
 pub mod simulate;
 
 pub use capture::InputCapturer;
-pub use simulate::InputSimulator;
+pub use lock::lock_screen;
+pub use simulate::{
+    apply_guarded, create_with_retry, FailureTracker, InputSimulator, Positioning, Simulator,
+    SimulatorPanic,
+};
@@ -0,0 +1,43 @@
+use std::process::Command;
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+/// 锁定本机屏幕
+///
+/// 通过各平台的系统命令实现。命令在后台线程中等待结束，不阻塞调用方（异步任务或模拟线程）；
+/// 命令无法启动时返回错误，由调用方记录，执行失败只记录日志
+pub fn lock_screen() -> Result<()> {
+    info!("locking screen");
+    let mut child = lock_command().spawn()?;
+    std::thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => warn!("screen lock command failed: {status}"),
+        Err(e) => warn!("screen lock command failed: {e}"),
+        Ok(_) => {}
+    });
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn lock_command() -> Command {
+    let mut cmd = Command::new("rundll32.exe");
+    cmd.arg("user32.dll,LockWorkStation");
+    cmd
+}
+
+#[cfg(target_os = "macos")]
+fn lock_command() -> Command {
+    let mut cmd = Command::new("osascript");
+    cmd.args([
+        "-e",
+        r#"tell application "System Events" to keystroke "q" using {control down, command down}"#,
+    ]);
+    cmd
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn lock_command() -> Command {
+    let mut cmd = Command::new("loginctl");
+    cmd.arg("lock-session");
+    cmd
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use synapse_protocol::input::{ButtonAction, GestureKind, KeyAction, KeyCode, MouseButton};
+
+use crate::simulate::Simulator;
+
+/// [`RecordingSimulator`] 记录的一次调用
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimulatorCall {
+    MoveMouse(i32, i32),
+    MoveMouseRelative(i32, i32),
+    MouseButton(MouseButton, ButtonAction),
+    DoubleClick(MouseButton),
+    KeyEvent(KeyCode, KeyAction),
+    KeyChar(KeyCode, String),
+    KeyCombo(Vec<KeyCode>),
+    TypeText(String),
+    Scroll(f64, f64, bool),
+    Gesture(GestureKind, f64),
+    LockScreen,
+    ReleaseAll,
+}
+
+/// 只记录调用、不产生真实输入的模拟器
+#[derive(Debug, Default)]
+pub struct RecordingSimulator {
+    pub calls: Vec<SimulatorCall>,
+    /// 设置后每次调用仍被记录，但返回该错误，模拟权限被撤销等持续性故障
+    pub fail_with: Option<String>,
+}
+
+impl RecordingSimulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 每次调用都失败的模拟器
+    pub fn failing(error: impl Into<String>) -> Self {
+        Self { calls: Vec::new(), fail_with: Some(error.into()) }
+    }
+
+    fn record(&mut self, call: SimulatorCall) -> Result<()> {
+        self.calls.push(call);
+        match &self.fail_with {
+            Some(error) => Err(anyhow::anyhow!("{error}")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Simulator for RecordingSimulator {
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<()> {
+        self.record(SimulatorCall::MoveMouse(x, y))
+    }
+
+    fn move_mouse_relative(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.record(SimulatorCall::MoveMouseRelative(dx, dy))
+    }
+
+    fn mouse_button(&mut self, button: MouseButton, action: ButtonAction) -> Result<()> {
+        self.record(SimulatorCall::MouseButton(button, action))
+    }
+
+    fn double_click(&mut self, button: MouseButton) -> Result<()> {
+        self.record(SimulatorCall::DoubleClick(button))
+    }
+
+    fn key_event(&mut self, key: KeyCode, action: KeyAction) -> Result<()> {
+        self.record(SimulatorCall::KeyEvent(key, action))
+    }
+
+    fn key_char(&mut self, key: KeyCode, text: &str) -> Result<()> {
+        self.record(SimulatorCall::KeyChar(key, text.to_string()))
+    }
+
+    fn key_combo(&mut self, keys: &[KeyCode]) -> Result<()> {
+        self.record(SimulatorCall::KeyCombo(keys.to_vec()))
+    }
+
+    fn type_text(&mut self, text: &str) -> Result<()> {
+        self.record(SimulatorCall::TypeText(text.to_string()))
+    }
+
+    fn scroll(&mut self, dx: f64, dy: f64, precise: bool) -> Result<()> {
+        self.record(SimulatorCall::Scroll(dx, dy, precise))
+    }
+
+    fn gesture(&mut self, kind: GestureKind, magnitude: f64) -> Result<()> {
+        self.record(SimulatorCall::Gesture(kind, magnitude))
+    }
+
+    fn lock_screen(&mut self) -> Result<()> {
+        self.record(SimulatorCall::LockScreen)
+    }
+
+    fn release_all(&mut self) -> Result<()> {
+        self.record(SimulatorCall::ReleaseAll)
+    }
+}
@@ -0,0 +1,214 @@
+use synapse_protocol::Message;
+use tokio::sync::mpsc;
+
+/// 开始捕获鼠标的原始相对运动，以 `MouseDelta` 发送到 channel；返回当前平台是否支持
+///
+/// 原始运动不受光标是否移动、是否被 [`PointerLock`](crate::cursor::PointerLock) 锁定的影响，
+/// 也不包含程序移动光标产生的位移（XWarpPointer 不产生原始事件，XTest 注入的运动按来源设备丢弃），
+/// 服务端据此可以不再把光标锁回屏幕中心。
+/// 目前只支持 X11（XInput 2.1 及以上的 RawMotion 事件），其他平台返回 `false`，
+/// 调用方应退回基于绝对坐标的方式。
+/// 与 [`InputCapturer`](crate::InputCapturer) 相同，读取线程随进程存活，接收端关闭后在下一个事件时退出
+pub fn start(tx: mpsc::UnboundedSender<Message>) -> bool {
+    platform::start(tx)
+}
+
+/// 给定版本的 XInput 能否用于捕获：RawMotion 自 2.0 起提供，
+/// 但直到 2.1 才在指针被抓取时继续送达根窗口
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn raw_motion_usable(major: i32, minor: i32) -> bool {
+    (major, minor) >= (2, 1)
+}
+
+/// 是否为 X 服务器为 XTest 注入创建的从设备（如 "Virtual core XTEST pointer"）
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn is_xtest_device(name: &str) -> bool {
+    name.contains("XTEST")
+}
+
+/// 从原始事件的有效轴掩码与按轴序紧凑排列的值中取出 x（轴 0）与 y（轴 1）的位移
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn motion_delta(mask: &[u8], values: &[f64]) -> (f64, f64) {
+    let mut delta = (0.0, 0.0);
+    let mut values = values.iter();
+    for axis in 0..2 {
+        let set = mask.get(axis / 8).is_some_and(|byte| byte & (1 << (axis % 8)) != 0);
+        if !set {
+            continue;
+        }
+        let Some(value) = values.next() else { break };
+        match axis {
+            0 => delta.0 = *value,
+            _ => delta.1 = *value,
+        }
+    }
+    delta
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::ffi::CStr;
+    use std::ptr;
+
+    use synapse_protocol::Message;
+    use tokio::sync::mpsc;
+    use tracing::{debug, info};
+    use x11::{xinput2, xlib};
+
+    /// 只在读取线程中使用的 X 连接
+    struct Connection(*mut xlib::Display);
+
+    // 连接创建后只移交给读取线程，不会被并发访问
+    unsafe impl Send for Connection {}
+
+    pub(super) fn start(tx: mpsc::UnboundedSender<Message>) -> bool {
+        unsafe {
+            let display = xlib::XOpenDisplay(ptr::null());
+            if display.is_null() {
+                debug!("no X display, raw pointer motion unavailable");
+                return false;
+            }
+            let (mut opcode, mut event_base, mut error_base) = (0, 0, 0);
+            let found = xlib::XQueryExtension(
+                display,
+                c"XInputExtension".as_ptr(),
+                &mut opcode,
+                &mut event_base,
+                &mut error_base,
+            );
+            let (mut major, mut minor) = (2, 1);
+            if found == 0
+                || xinput2::XIQueryVersion(display, &mut major, &mut minor) != xlib::Success as i32
+                || !super::raw_motion_usable(major, minor)
+            {
+                debug!(major, minor, "X server lacks XInput 2.1, raw pointer motion unavailable");
+                xlib::XCloseDisplay(display);
+                return false;
+            }
+
+            let mut mask = [0u8; xinput2::XI_LASTEVENT as usize / 8 + 1];
+            xinput2::XISetMask(&mut mask, xinput2::XI_RawMotion);
+            let mut event_mask = xinput2::XIEventMask {
+                deviceid: xinput2::XIAllMasterDevices,
+                mask_len: mask.len() as i32,
+                mask: mask.as_mut_ptr(),
+            };
+            xinput2::XISelectEvents(display, xlib::XDefaultRootWindow(display), &mut event_mask, 1);
+            xlib::XFlush(display);
+
+            let xtest = xtest_pointers(display);
+            info!(major, minor, ?xtest, "capturing raw pointer motion");
+            let connection = Connection(display);
+            std::thread::spawn(move || read_motion(connection, opcode, xtest, tx));
+            true
+        }
+    }
+
+    /// XTest 从设备的 ID：enigo 等通过 XTest 注入的运动以其为来源，同样产生 RawMotion 事件
+    unsafe fn xtest_pointers(display: *mut xlib::Display) -> Vec<i32> {
+        let mut count = 0;
+        let info = xinput2::XIQueryDevice(display, xinput2::XIAllDevices, &mut count);
+        if info.is_null() {
+            return Vec::new();
+        }
+        let ids = std::slice::from_raw_parts(info, count.max(0) as usize)
+            .iter()
+            .filter(|device| {
+                device._use == xinput2::XISlavePointer
+                    && !device.name.is_null()
+                    && super::is_xtest_device(&CStr::from_ptr(device.name).to_string_lossy())
+            })
+            .map(|device| device.deviceid)
+            .collect();
+        xinput2::XIFreeDeviceInfo(info);
+        ids
+    }
+
+    /// 阻塞读取 RawMotion 事件，接收端关闭后关闭连接退出；来自 `xtest` 设备的事件是程序自身的注入，丢弃
+    fn read_motion(
+        connection: Connection,
+        opcode: i32,
+        xtest: Vec<i32>,
+        tx: mpsc::UnboundedSender<Message>,
+    ) {
+        let display = connection.0;
+        loop {
+            let delta = unsafe {
+                let mut event: xlib::XEvent = std::mem::zeroed();
+                xlib::XNextEvent(display, &mut event);
+                let cookie = &mut event.generic_event_cookie;
+                if cookie.type_ != xlib::GenericEvent
+                    || cookie.extension != opcode
+                    || xlib::XGetEventData(display, cookie) == 0
+                {
+                    continue;
+                }
+                let delta = (cookie.evtype == xinput2::XI_RawMotion)
+                    .then(|| &*(cookie.data as *const xinput2::XIRawEvent))
+                    .filter(|raw| !xtest.contains(&raw.sourceid))
+                    .map(|raw| {
+                        // 使用加速后的值，与本机光标的手感一致
+                        let mask = std::slice::from_raw_parts(
+                            raw.valuators.mask,
+                            raw.valuators.mask_len as usize,
+                        );
+                        let count = mask.iter().map(|byte| byte.count_ones() as usize).sum();
+                        let values = std::slice::from_raw_parts(raw.valuators.values, count);
+                        super::motion_delta(mask, values)
+                    });
+                xlib::XFreeEventData(display, cookie);
+                delta
+            };
+            let Some((dx, dy)) = delta else { continue };
+            if (dx, dy) != (0.0, 0.0) && tx.send(Message::MouseDelta { dx, dy }).is_err() {
+                break;
+            }
+        }
+        debug!("raw pointer motion receiver closed");
+        unsafe {
+            xlib::XCloseDisplay(display);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use synapse_protocol::Message;
+    use tokio::sync::mpsc;
+    use tracing::debug;
+
+    pub(super) fn start(_tx: mpsc::UnboundedSender<Message>) -> bool {
+        debug!("raw pointer motion not supported on this platform");
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_xinput_2_1() {
+        assert!(!raw_motion_usable(1, 5));
+        assert!(!raw_motion_usable(2, 0));
+        assert!(raw_motion_usable(2, 1));
+        assert!(raw_motion_usable(2, 4));
+    }
+
+    #[test]
+    fn recognizes_xtest_devices() {
+        assert!(is_xtest_device("Virtual core XTEST pointer"));
+        assert!(!is_xtest_device("Logitech USB Receiver Mouse"));
+        assert!(!is_xtest_device("Virtual core pointer"));
+    }
+
+    #[test]
+    fn reads_axes_from_packed_values() {
+        assert_eq!(motion_delta(&[0b11], &[3.0, -2.5]), (3.0, -2.5));
+        // 只有 y 轴变化时值数组中只有 y
+        assert_eq!(motion_delta(&[0b10], &[4.0]), (0.0, 4.0));
+        // 滚轮等其他轴不计入
+        assert_eq!(motion_delta(&[0b101], &[1.5, 7.0]), (1.5, 0.0));
+        assert_eq!(motion_delta(&[], &[]), (0.0, 0.0));
+    }
+}
@@ -1,23 +1,233 @@
+use std::collections::HashSet;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
 use anyhow::Result;
 use enigo::{Enigo, Keyboard, Mouse, Settings};
-use synapse_protocol::input::{ButtonAction, KeyAction, KeyCode, MouseButton};
-use tracing::debug;
+use synapse_protocol::input::{
+    pinch_lines, ButtonAction, GestureKind, KeyAction, KeyCode, MouseButton, ScrollUnit,
+};
+use synapse_protocol::Message;
+use tracing::{debug, info, warn};
+
+use crate::cursor::{CursorVisibility, PointerLock};
+
+/// 无法查询光标位置时，向左上角移动的距离（足以让光标贴住屏幕角落）
+const HOMING_DISTANCE: i32 = 100_000;
+
+/// 捏合缩放退化为滚轮时按住的修饰键
+#[cfg(target_os = "macos")]
+const ZOOM_MODIFIER: KeyCode = KeyCode::LeftMeta;
+#[cfg(not(target_os = "macos"))]
+const ZOOM_MODIFIER: KeyCode = KeyCode::LeftCtrl;
+
+/// 鼠标绝对定位策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Positioning {
+    /// 直接使用系统的绝对坐标定位
+    Absolute,
+    /// 记录模拟器认定的光标位置，通过相对移动到达目标（Wayland 等不支持绝对定位的会话）
+    Relative,
+}
+
+impl Positioning {
+    /// 根据当前会话类型选择定位策略
+    pub fn detect() -> Self {
+        if cfg!(target_os = "linux") && is_wayland_session() {
+            Positioning::Relative
+        } else {
+            Positioning::Absolute
+        }
+    }
+}
+
+fn is_wayland_session() -> bool {
+    std::env::var("XDG_SESSION_TYPE")
+        .map(|t| t.eq_ignore_ascii_case("wayland"))
+        .unwrap_or(false)
+        || std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+/// 相对定位模式下，从 `from` 移动到 `to` 所需的相对位移
+pub fn relative_step(from: (i32, i32), to: (i32, i32)) -> (i32, i32) {
+    (to.0 - from.0, to.1 - from.1)
+}
+
+/// 将以 `unit` 计的滚动量换算为整行，不足一行的部分保留在 `remainder` 中
+pub fn scroll_lines(unit: ScrollUnit, delta: (f64, f64), remainder: &mut (f64, f64)) -> (i32, i32) {
+    let x = remainder.0 + unit.convert(delta.0, ScrollUnit::Lines);
+    let y = remainder.1 + unit.convert(delta.1, ScrollUnit::Lines);
+    let lines = (x.trunc(), y.trunc());
+    *remainder = (x - lines.0, y - lines.1);
+    (lines.0 as i32, lines.1 as i32)
+}
+
+/// 滚轮刻度：换算为行后每条消息至少滚动一行（换算结果不足一行时不会被累计吞掉），不保留余量
+pub fn scroll_notches(unit: ScrollUnit, delta: (f64, f64)) -> (i32, i32) {
+    let notch = |delta: f64| {
+        let lines = unit.convert(delta, ScrollUnit::Lines);
+        if lines == 0.0 {
+            0
+        } else {
+            (lines.abs().round().max(1.0) * lines.signum()) as i32
+        }
+    };
+    (notch(delta.0), notch(delta.1))
+}
+
+/// 连续模拟失败达到该次数时告警
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 10;
+
+/// 创建模拟器的默认尝试次数
+pub const DEFAULT_CREATE_ATTEMPTS: u32 = 5;
+/// 创建模拟器失败后的默认重试间隔
+pub const DEFAULT_CREATE_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// 调用 `create` 直到成功，最多尝试 `attempts` 次，每次失败后等待 `delay`
+///
+/// 刚登录的会话中显示服务可能尚未就绪，创建模拟器会暂时失败。每次失败调用
+/// `on_error(已尝试次数, 错误)`；全部失败时返回最后一次的错误。会阻塞当前线程
+pub fn create_with_retry<T>(
+    attempts: u32,
+    delay: Duration,
+    mut create: impl FnMut() -> Result<T>,
+    mut on_error: impl FnMut(u32, &anyhow::Error),
+) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match create() {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                on_error(attempt, &e);
+                if attempt >= attempts.max(1) {
+                    return Err(e);
+                }
+                std::thread::sleep(delay);
+            }
+        }
+    }
+}
+
+/// 连续模拟失败计数，用于发现权限被撤销、显示断开等持续性故障
+pub struct FailureTracker {
+    consecutive: u32,
+    threshold: u32,
+}
+
+impl FailureTracker {
+    pub fn new(threshold: u32) -> Self {
+        Self { consecutive: 0, threshold }
+    }
+
+    /// 记录一次模拟结果，成功时清零；连续失败恰好达到阈值时返回 `true`
+    pub fn record<T>(&mut self, result: &Result<T>) -> bool {
+        match result {
+            Ok(_) => {
+                self.consecutive = 0;
+                false
+            }
+            Err(_) => {
+                self.consecutive += 1;
+                self.consecutive == self.threshold
+            }
+        }
+    }
+
+    /// 当前连续失败次数
+    pub fn consecutive(&self) -> u32 {
+        self.consecutive
+    }
+}
 
 /// 输入模拟器，封装 enigo
 pub struct InputSimulator {
     enigo: Enigo,
+    positioning: Positioning,
+    /// 模拟器认定的光标位置；相对定位模式下用于换算绝对移动
+    cursor: Option<(i32, i32)>,
+    /// 以字符方式键入、尚未收到释放事件的键
+    char_keys: HashSet<KeyCode>,
+    /// 收到的滚动量单位
+    scroll_unit: ScrollUnit,
+    /// 尚未凑满一行的滚动量（行）
+    scroll_remainder: (f64, f64),
+    /// 已模拟按下、尚未释放的键与鼠标按键
+    held_keys: HashSet<KeyCode>,
+    held_buttons: HashSet<MouseButton>,
+    /// 粘滞键模式：单独敲击的修饰键暂存，作用于下一个非修饰键后释放
+    sticky_keys: bool,
+    sticky: StickyKeys,
+    cursor_visibility: CursorVisibility,
+    pointer_lock: PointerLock,
 }
 
 impl InputSimulator {
     pub fn new() -> Result<Self> {
         let enigo = Enigo::new(&Settings::default())?;
-        Ok(Self { enigo })
+        let positioning = Positioning::detect();
+        if positioning == Positioning::Relative {
+            info!("absolute pointer positioning unavailable in this session, using relative fallback");
+        }
+        Ok(Self {
+            enigo,
+            positioning,
+            cursor: None,
+            char_keys: HashSet::new(),
+            scroll_unit: ScrollUnit::default(),
+            scroll_remainder: (0.0, 0.0),
+            held_keys: HashSet::new(),
+            held_buttons: HashSet::new(),
+            sticky_keys: false,
+            sticky: StickyKeys::default(),
+            cursor_visibility: CursorVisibility::new(),
+            pointer_lock: PointerLock::new(),
+        })
+    }
+
+    /// 设置收到的滚动量单位，应与 Client 在握手中声明的单位一致
+    pub fn with_scroll_unit(mut self, unit: ScrollUnit) -> Self {
+        self.scroll_unit = unit;
+        self
+    }
+
+    /// 启用粘滞键模拟：单独按下并松开的修饰键暂存，作用于下一个非修饰键或鼠标按键后释放；
+    /// 按住修饰键时照常转发
+    pub fn with_sticky_keys(mut self, enabled: bool) -> Self {
+        self.sticky_keys = enabled;
+        self
+    }
+
+    /// 当前使用的定位策略
+    pub fn positioning(&self) -> Positioning {
+        self.positioning
     }
 
     /// 模拟鼠标移动到绝对坐标
+    ///
+    /// 绝对定位失败时自动切换为相对定位模式
     pub fn move_mouse(&mut self, x: i32, y: i32) -> Result<()> {
         debug!(x, y, "simulating mouse move");
-        self.enigo.move_mouse(x, y, enigo::Coordinate::Abs)?;
+        if self.positioning == Positioning::Absolute {
+            match self.enigo.move_mouse(x, y, enigo::Coordinate::Abs) {
+                Ok(()) => {
+                    self.cursor = Some((x, y));
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("absolute mouse move failed ({e}), falling back to relative positioning");
+                    self.positioning = Positioning::Relative;
+                    self.cursor = None;
+                }
+            }
+        }
+
+        let from = self.believed_cursor()?;
+        let (dx, dy) = relative_step(from, (x, y));
+        if dx != 0 || dy != 0 {
+            self.enigo.move_mouse(dx, dy, enigo::Coordinate::Rel)?;
+        }
+        self.cursor = Some((x, y));
         Ok(())
     }
 
@@ -25,34 +235,211 @@ impl InputSimulator {
     pub fn move_mouse_relative(&mut self, dx: i32, dy: i32) -> Result<()> {
         debug!(dx, dy, "simulating relative mouse move");
         self.enigo.move_mouse(dx, dy, enigo::Coordinate::Rel)?;
+        if let Some((x, y)) = self.cursor {
+            self.cursor = Some((x + dx, y + dy));
+        }
         Ok(())
     }
 
+    /// 模拟器认定的光标位置，首次使用时向系统查询；查询失败则将光标推到左上角作为已知起点
+    fn believed_cursor(&mut self) -> Result<(i32, i32)> {
+        if let Some(pos) = self.cursor {
+            return Ok(pos);
+        }
+        let pos = match self.enigo.location() {
+            Ok(pos) => pos,
+            Err(_) => {
+                self.enigo.move_mouse(-HOMING_DISTANCE, -HOMING_DISTANCE, enigo::Coordinate::Rel)?;
+                (0, 0)
+            }
+        };
+        self.cursor = Some(pos);
+        Ok(pos)
+    }
+
     /// 模拟鼠标按键
     pub fn mouse_button(&mut self, button: MouseButton, action: ButtonAction) -> Result<()> {
         debug!(?button, ?action, "simulating mouse button");
-        let btn = to_enigo_button(button);
+        let btn = match to_enigo_button(button) {
+            Some(btn) => btn,
+            None => {
+                debug!(?button, "mouse button not supported by simulator, ignored");
+                return Ok(());
+            }
+        };
         match action {
-            ButtonAction::Press => self.enigo.button(btn, enigo::Direction::Press)?,
-            ButtonAction::Release => self.enigo.button(btn, enigo::Direction::Release)?,
+            ButtonAction::Press => {
+                let latched = self.press_latched()?;
+                let pressed = self.enigo.button(btn, enigo::Direction::Press);
+                if pressed.is_ok() {
+                    self.held_buttons.insert(button);
+                }
+                let released = self.release_latched(latched);
+                pressed?;
+                released?;
+            }
+            ButtonAction::Release => {
+                self.held_buttons.remove(&button);
+                self.enigo.button(btn, enigo::Direction::Release)?;
+            }
         }
         Ok(())
     }
 
+    /// 模拟双击：连续两次按下/释放，间隔远小于系统的双击判定时间
+    pub fn double_click(&mut self, button: MouseButton) -> Result<()> {
+        debug!(?button, "simulating double click");
+        click_twice(button, |button, action| self.mouse_button(button, action))
+    }
+
     /// 模拟键盘事件
+    ///
+    /// 以字符方式键入的键已完整输入，其释放事件被忽略。
+    /// 粘滞键模式下修饰键照常转发，单独敲击（按下后未按其他键即松开）时暂存，再次敲击取消暂存
     pub fn key_event(&mut self, key: KeyCode, action: KeyAction) -> Result<()> {
+        if action == KeyAction::Release && self.char_keys.remove(&key) {
+            return Ok(());
+        }
+        let modifier = is_modifier(key);
+        if self.sticky_keys && modifier {
+            match action {
+                KeyAction::Press => self.sticky.modifier_down(key),
+                KeyAction::Release => self.sticky.modifier_up(key),
+            }
+        }
         debug!(?key, ?action, "simulating key event");
         let enigo_key = to_enigo_key(key);
         match action {
-            KeyAction::Press => self.enigo.key(enigo_key, enigo::Direction::Press)?,
-            KeyAction::Release => self.enigo.key(enigo_key, enigo::Direction::Release)?,
+            KeyAction::Press => {
+                let latched = if modifier { Vec::new() } else { self.press_latched()? };
+                let pressed = self.enigo.key(enigo_key, enigo::Direction::Press);
+                if pressed.is_ok() {
+                    self.held_keys.insert(key);
+                }
+                let released = self.release_latched(latched);
+                pressed?;
+                released?;
+            }
+            KeyAction::Release => {
+                self.held_keys.remove(&key);
+                self.enigo.key(enigo_key, enigo::Direction::Release)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 按下粘滞键模式下暂存的修饰键并清空暂存，返回实际按下的键
+    fn press_latched(&mut self) -> Result<Vec<KeyCode>> {
+        let keys = self.sticky.take_latched();
+        let mut pressed = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Err(e) = self.enigo.key(to_enigo_key(key), enigo::Direction::Press) {
+                // 已按下的修饰键仍需释放，避免卡住
+                let _ = self.release_latched(pressed);
+                return Err(e.into());
+            }
+            self.held_keys.insert(key);
+            pressed.push(key);
+        }
+        Ok(pressed)
+    }
+
+    /// 逆序释放 [`Self::press_latched`] 按下的修饰键
+    fn release_latched(&mut self, keys: Vec<KeyCode>) -> Result<()> {
+        let mut result = Ok(());
+        for key in keys.into_iter().rev() {
+            self.held_keys.remove(&key);
+            if let Err(e) = self.enigo.key(to_enigo_key(key), enigo::Direction::Release) {
+                result = result.and(Err(e.into()));
+            }
+        }
+        result
+    }
+
+    /// 释放所有已模拟按下、尚未释放的键与鼠标按键
+    ///
+    /// 连接中断时释放事件不会再到达，不释放会导致本机修饰键等卡住
+    pub fn release_all(&mut self) -> Result<()> {
+        // 暂存的修饰键尚未实际按下，直接丢弃
+        self.sticky = StickyKeys::default();
+        if self.held_keys.is_empty() && self.held_buttons.is_empty() {
+            return Ok(());
+        }
+        info!(
+            keys = self.held_keys.len(),
+            buttons = self.held_buttons.len(),
+            "releasing held keys and buttons"
+        );
+        let mut result = Ok(());
+        for key in std::mem::take(&mut self.held_keys) {
+            if let Err(e) = self.enigo.key(to_enigo_key(key), enigo::Direction::Release) {
+                result = result.and(Err(e.into()));
+            }
         }
+        for button in std::mem::take(&mut self.held_buttons) {
+            if let Some(btn) = to_enigo_button(button) {
+                if let Err(e) = self.enigo.button(btn, enigo::Direction::Release) {
+                    result = result.and(Err(e.into()));
+                }
+            }
+        }
+        result
+    }
+
+    /// 模拟组合键：依次按下 `keys`，再逆序释放
+    ///
+    /// 中途按下失败时仍会释放已按下的键，避免修饰键卡住
+    pub fn key_combo(&mut self, keys: &[KeyCode]) -> Result<()> {
+        debug!(?keys, "simulating key combo");
+        press_combo(keys, |key, action| self.key_event(key, action))
+    }
+
+    /// 以键入方式输入文本
+    pub fn type_text(&mut self, text: &str) -> Result<()> {
+        debug!(len = text.len(), "simulating text input");
+        self.enigo.text(text)?;
         Ok(())
     }
 
-    /// 模拟滚轮
-    pub fn scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
-        debug!(dx, dy, "simulating scroll");
+    /// 布局感知模式下的按键：键入 `text`，并记住 `key` 以忽略其后的释放事件
+    pub fn key_char(&mut self, key: KeyCode, text: &str) -> Result<()> {
+        debug!(?key, "simulating key as character");
+        self.char_keys.insert(key);
+        let latched = self.press_latched()?;
+        let typed = self.type_text(text);
+        let released = self.release_latched(latched);
+        typed?;
+        released
+    }
+
+    /// 显示或隐藏本机系统光标
+    ///
+    /// 目前只支持 X11（XFixes），其他平台降级为无操作，见 [`CursorVisibility`]
+    pub fn set_cursor_visible(&mut self, visible: bool) -> Result<()> {
+        self.cursor_visibility.set_visible(visible);
+        Ok(())
+    }
+
+    /// 锁定或解锁本机指针
+    ///
+    /// 目前只支持 X11，其他平台降级为无操作，见 [`PointerLock`]
+    pub fn set_pointer_locked(&mut self, locked: bool) -> Result<()> {
+        self.pointer_lock.set_locked(locked);
+        Ok(())
+    }
+
+    /// 模拟滚轮，`dx`/`dy` 以配置的滚动单位计
+    ///
+    /// 系统滚轮按行滚动：高精度滚动（`precise`）换算为行后不足一行的部分累计到下次，
+    /// 滚轮刻度每次至少滚动一行
+    pub fn scroll(&mut self, dx: f64, dy: f64, precise: bool) -> Result<()> {
+        let (dx, dy) = if precise {
+            scroll_lines(self.scroll_unit, (dx, dy), &mut self.scroll_remainder)
+        } else {
+            self.scroll_remainder = (0.0, 0.0);
+            scroll_notches(self.scroll_unit, (dx, dy))
+        };
+        debug!(dx, dy, precise, "simulating scroll");
         if dy != 0 {
             self.enigo.scroll(dy, enigo::Axis::Vertical)?;
         }
@@ -61,15 +448,293 @@ impl InputSimulator {
         }
         Ok(())
     }
+
+    /// 模拟触控板手势
+    ///
+    /// 系统不提供合成捏合手势的接口：捏合缩放退化为"缩放修饰键 + 滚轮"（多数应用的缩放方式，
+    /// macOS 上为 Cmd，其他平台为 Ctrl）；旋转没有通用的对应操作，忽略
+    pub fn gesture(&mut self, kind: GestureKind, magnitude: f64) -> Result<()> {
+        debug!(?kind, magnitude, "simulating gesture");
+        match kind {
+            GestureKind::Pinch => pinch_zoom(magnitude, |step| {
+                match step {
+                    ZoomStep::Modifier(KeyAction::Press) => {
+                        self.enigo.key(to_enigo_key(ZOOM_MODIFIER), enigo::Direction::Press)?
+                    }
+                    ZoomStep::Modifier(KeyAction::Release) => {
+                        self.enigo.key(to_enigo_key(ZOOM_MODIFIER), enigo::Direction::Release)?
+                    }
+                    ZoomStep::Scroll(lines) => self.enigo.scroll(lines, enigo::Axis::Vertical)?,
+                }
+                Ok(())
+            })?,
+            GestureKind::Rotate => debug!("rotate gesture has no simulated equivalent, ignored"),
+        }
+        Ok(())
+    }
 }
 
-fn to_enigo_button(button: MouseButton) -> enigo::Button {
+/// 输入模拟过程中发生的 panic（部分平台遇到某些按键时 panic 而不是返回错误）
+#[derive(Debug)]
+pub struct SimulatorPanic(pub String);
+
+impl std::fmt::Display for SimulatorPanic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "input simulation panicked: {}", self.0)
+    }
+}
+
+impl std::error::Error for SimulatorPanic {}
+
+/// 同 [`Simulator::apply`]，但将模拟器中的 panic 转为 [`SimulatorPanic`] 错误，
+/// 一条消息的失败不会终止整个消息循环
+pub fn apply_guarded<S: Simulator + ?Sized>(simulator: &mut S, msg: &Message) -> Result<bool> {
+    std::panic::catch_unwind(AssertUnwindSafe(|| simulator.apply(msg))).unwrap_or_else(|payload| {
+        let reason = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".into());
+        Err(SimulatorPanic(reason).into())
+    })
+}
+
+/// 输入模拟的抽象，使客户端消息处理可以脱离真实的系统输入进行测试
+pub trait Simulator {
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<()>;
+    fn move_mouse_relative(&mut self, dx: i32, dy: i32) -> Result<()>;
+    fn mouse_button(&mut self, button: MouseButton, action: ButtonAction) -> Result<()>;
+    fn double_click(&mut self, button: MouseButton) -> Result<()>;
+    fn key_event(&mut self, key: KeyCode, action: KeyAction) -> Result<()>;
+    fn key_char(&mut self, key: KeyCode, text: &str) -> Result<()>;
+    fn key_combo(&mut self, keys: &[KeyCode]) -> Result<()>;
+    fn type_text(&mut self, text: &str) -> Result<()>;
+    fn scroll(&mut self, dx: f64, dy: f64, precise: bool) -> Result<()>;
+    fn gesture(&mut self, kind: GestureKind, magnitude: f64) -> Result<()>;
+    fn lock_screen(&mut self) -> Result<()>;
+    fn release_all(&mut self) -> Result<()>;
+
+    /// 将协议中的输入消息应用到本机
+    ///
+    /// 返回 `Ok(false)` 表示该消息不是输入类消息，由调用方自行处理
+    fn apply(&mut self, msg: &Message) -> Result<bool> {
+        match msg {
+            Message::MouseMove { x, y } => self.move_mouse(*x as i32, *y as i32)?,
+            Message::MouseDelta { dx, dy } => self.move_mouse_relative(*dx as i32, *dy as i32)?,
+            Message::MouseButtonEvent { button, action } => self.mouse_button(*button, *action)?,
+            Message::MouseDoubleClick { button } => self.double_click(*button)?,
+            Message::KeyEvent { key, action } => self.key_event(*key, *action)?,
+            Message::KeyChar { key, text } => self.key_char(*key, text)?,
+            Message::KeyCombo { keys } => self.key_combo(keys)?,
+            Message::TypeText { text } => self.type_text(text)?,
+            Message::MouseScroll { dx, dy, precise } => self.scroll(*dx, *dy, *precise)?,
+            Message::Gesture { kind, magnitude } => self.gesture(*kind, *magnitude)?,
+            Message::LockScreen => self.lock_screen()?,
+            // 焦点离开或会话结束后不会再收到释放事件
+            Message::LeaveScreen { .. } | Message::Bye { .. } | Message::ReleaseAll => {
+                self.release_all()?
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+impl Simulator for InputSimulator {
+    fn move_mouse(&mut self, x: i32, y: i32) -> Result<()> {
+        InputSimulator::move_mouse(self, x, y)
+    }
+
+    fn move_mouse_relative(&mut self, dx: i32, dy: i32) -> Result<()> {
+        InputSimulator::move_mouse_relative(self, dx, dy)
+    }
+
+    fn mouse_button(&mut self, button: MouseButton, action: ButtonAction) -> Result<()> {
+        InputSimulator::mouse_button(self, button, action)
+    }
+
+    fn key_event(&mut self, key: KeyCode, action: KeyAction) -> Result<()> {
+        InputSimulator::key_event(self, key, action)
+    }
+
+    fn key_char(&mut self, key: KeyCode, text: &str) -> Result<()> {
+        InputSimulator::key_char(self, key, text)
+    }
+
+    fn key_combo(&mut self, keys: &[KeyCode]) -> Result<()> {
+        InputSimulator::key_combo(self, keys)
+    }
+
+    fn double_click(&mut self, button: MouseButton) -> Result<()> {
+        InputSimulator::double_click(self, button)
+    }
+
+    fn type_text(&mut self, text: &str) -> Result<()> {
+        InputSimulator::type_text(self, text)
+    }
+
+    fn scroll(&mut self, dx: f64, dy: f64, precise: bool) -> Result<()> {
+        InputSimulator::scroll(self, dx, dy, precise)
+    }
+
+    fn gesture(&mut self, kind: GestureKind, magnitude: f64) -> Result<()> {
+        InputSimulator::gesture(self, kind, magnitude)
+    }
+
+    fn lock_screen(&mut self) -> Result<()> {
+        crate::lock::lock_screen()
+    }
+
+    fn release_all(&mut self) -> Result<()> {
+        InputSimulator::release_all(self)
+    }
+}
+
+impl Drop for InputSimulator {
+    fn drop(&mut self) {
+        if let Err(e) = self.release_all() {
+            warn!("failed to release held input on drop: {e}");
+        }
+    }
+}
+
+/// 捏合缩放退化成的一步操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ZoomStep {
+    /// 按下或释放 [`ZOOM_MODIFIER`]
+    Modifier(KeyAction),
+    /// 垂直滚动的行数
+    Scroll(i32),
+}
+
+/// 将捏合幅度转换为"按住缩放修饰键滚动"；滚动失败时修饰键仍被释放，幅度不足一行时不操作
+fn pinch_zoom(magnitude: f64, mut step: impl FnMut(ZoomStep) -> Result<()>) -> Result<()> {
+    let lines = pinch_lines(magnitude);
+    if lines == 0 {
+        return Ok(());
+    }
+    step(ZoomStep::Modifier(KeyAction::Press))?;
+    let scrolled = step(ZoomStep::Scroll(lines));
+    step(ZoomStep::Modifier(KeyAction::Release))?;
+    scrolled
+}
+
+/// 连续两次按下/释放 `button`，中途失败时停止
+fn click_twice(
+    button: MouseButton,
+    mut mouse_button: impl FnMut(MouseButton, ButtonAction) -> Result<()>,
+) -> Result<()> {
+    for _ in 0..2 {
+        mouse_button(button, ButtonAction::Press)?;
+        mouse_button(button, ButtonAction::Release)?;
+    }
+    Ok(())
+}
+
+/// 依次按下 `keys` 再逆序释放；某个键按下失败时停止按下，仍逆序释放已按下的键
+fn press_combo(
+    keys: &[KeyCode],
+    mut key_event: impl FnMut(KeyCode, KeyAction) -> Result<()>,
+) -> Result<()> {
+    let mut pressed = Vec::with_capacity(keys.len());
+    let mut result = Ok(());
+    for key in keys {
+        if let Err(e) = key_event(*key, KeyAction::Press) {
+            result = Err(e);
+            break;
+        }
+        pressed.push(*key);
+    }
+    for key in pressed.into_iter().rev() {
+        let released = key_event(key, KeyAction::Release);
+        if result.is_ok() {
+            result = released;
+        }
+    }
+    result
+}
+
+/// 粘滞键状态：单独敲击的修饰键暂存，作用于下一个非修饰键或鼠标按键
+///
+/// 按住修饰键期间按下其他键属于普通组合键，松开后不暂存
+#[derive(Debug, Default)]
+struct StickyKeys {
+    /// 当前按住的修饰键
+    held: Vec<KeyCode>,
+    /// 按下后尚未有其他键参与的修饰键，松开时仍在其中即为单独敲击
+    tapping: Vec<KeyCode>,
+    /// 已暂存、尚未作用的修饰键（按敲击顺序）
+    latched: Vec<KeyCode>,
+}
+
+impl StickyKeys {
+    fn modifier_down(&mut self, key: KeyCode) {
+        // 自动重复的按下不重新开始敲击
+        if !self.held.contains(&key) {
+            self.held.push(key);
+            self.tapping.push(key);
+        }
+    }
+
+    fn modifier_up(&mut self, key: KeyCode) {
+        self.held.retain(|k| *k != key);
+        let Some(i) = self.tapping.iter().position(|k| *k == key) else {
+            return;
+        };
+        self.tapping.remove(i);
+        match self.latched.iter().position(|k| *k == key) {
+            Some(i) => {
+                self.latched.remove(i);
+                debug!(?key, "sticky modifier unlatched");
+            }
+            None => {
+                self.latched.push(key);
+                debug!(?key, "sticky modifier latched");
+            }
+        }
+    }
+
+    /// 非修饰键或鼠标按键按下：取出需要临时按下的暂存修饰键（已被按住的除外），
+    /// 并使按住的修饰键不再算作单独敲击
+    fn take_latched(&mut self) -> Vec<KeyCode> {
+        self.tapping.clear();
+        let held = &self.held;
+        std::mem::take(&mut self.latched)
+            .into_iter()
+            .filter(|key| !held.contains(key))
+            .collect()
+    }
+}
+
+fn is_modifier(key: KeyCode) -> bool {
+    matches!(
+        key,
+        KeyCode::LeftShift
+            | KeyCode::RightShift
+            | KeyCode::LeftCtrl
+            | KeyCode::RightCtrl
+            | KeyCode::LeftAlt
+            | KeyCode::RightAlt
+            | KeyCode::LeftMeta
+            | KeyCode::RightMeta
+    )
+}
+
+fn to_enigo_button(button: MouseButton) -> Option<enigo::Button> {
     match button {
-        MouseButton::Left => enigo::Button::Left,
-        MouseButton::Right => enigo::Button::Right,
-        MouseButton::Middle => enigo::Button::Middle,
-        // Back/Forward 并非所有平台都支持，回退到 Left
-        MouseButton::Back | MouseButton::Forward => enigo::Button::Left,
+        MouseButton::Left => Some(enigo::Button::Left),
+        MouseButton::Right => Some(enigo::Button::Right),
+        MouseButton::Middle => Some(enigo::Button::Middle),
+        #[cfg(any(target_os = "windows", all(unix, not(target_os = "macos"))))]
+        MouseButton::Back => Some(enigo::Button::Back),
+        #[cfg(any(target_os = "windows", all(unix, not(target_os = "macos"))))]
+        MouseButton::Forward => Some(enigo::Button::Forward),
+        // enigo 在 macOS 上不支持侧键，跳过而不是误按其他键
+        #[cfg(target_os = "macos")]
+        MouseButton::Back | MouseButton::Forward => None,
+        MouseButton::TiltLeft => Some(enigo::Button::ScrollLeft),
+        MouseButton::TiltRight => Some(enigo::Button::ScrollRight),
+        MouseButton::Other(_) => None,
     }
 }
 
@@ -145,3 +810,377 @@ fn to_enigo_key(key: KeyCode) -> enigo::Key {
         _ => enigo::Key::Unicode('\0'),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tap(sticky: &mut StickyKeys, key: KeyCode) {
+        sticky.modifier_down(key);
+        sticky.modifier_up(key);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn apply_dispatches_each_input_message() {
+        use synapse_protocol::screen::{Edge, ScreenId, ScreenPosition};
+        use synapse_protocol::DeviceId;
+
+        use crate::mock::{RecordingSimulator, SimulatorCall};
+
+        let mut simulator = RecordingSimulator::new();
+        let input = [
+            Message::MouseMove { x: 10.7, y: 20.0 },
+            Message::MouseDelta { dx: -3.0, dy: 4.0 },
+            Message::MouseButtonEvent { button: MouseButton::Right, action: ButtonAction::Press },
+            Message::MouseDoubleClick { button: MouseButton::Left },
+            Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Release },
+            Message::KeyChar { key: KeyCode::KeyE, text: "€".into() },
+            Message::KeyCombo { keys: vec![KeyCode::LeftCtrl, KeyCode::KeyC] },
+            Message::TypeText { text: "hi".into() },
+            Message::MouseScroll { dx: 0.0, dy: -2.0, precise: true },
+            Message::Gesture { kind: GestureKind::Pinch, magnitude: 0.5 },
+            Message::LockScreen,
+        ];
+        for msg in &input {
+            assert!(simulator.apply(msg).unwrap(), "{msg:?} should be simulated");
+        }
+        assert_eq!(
+            simulator.calls,
+            vec![
+                SimulatorCall::MoveMouse(10, 20),
+                SimulatorCall::MoveMouseRelative(-3, 4),
+                SimulatorCall::MouseButton(MouseButton::Right, ButtonAction::Press),
+                SimulatorCall::DoubleClick(MouseButton::Left),
+                SimulatorCall::KeyEvent(KeyCode::KeyA, KeyAction::Release),
+                SimulatorCall::KeyChar(KeyCode::KeyE, "€".into()),
+                SimulatorCall::KeyCombo(vec![KeyCode::LeftCtrl, KeyCode::KeyC]),
+                SimulatorCall::TypeText("hi".into()),
+                SimulatorCall::Scroll(0.0, -2.0, true),
+                SimulatorCall::Gesture(GestureKind::Pinch, 0.5),
+                SimulatorCall::LockScreen,
+            ]
+        );
+
+        // 焦点离开、会话结束与显式请求都释放按住的键
+        simulator.calls.clear();
+        let position = ScreenPosition { x: 0.0, y: 0.0 };
+        for msg in [
+            Message::LeaveScreen { screen_id: ScreenId(0), edge: Edge::Left, position },
+            Message::Bye { device_id: DeviceId("server".into()) },
+            Message::ReleaseAll,
+        ] {
+            assert!(simulator.apply(&msg).unwrap());
+        }
+        assert_eq!(simulator.calls, vec![SimulatorCall::ReleaseAll; 3]);
+
+        // 非输入消息交还调用方处理
+        simulator.calls.clear();
+        assert!(!simulator.apply(&Message::Ping(1)).unwrap());
+        assert!(!simulator.apply(&Message::ClipboardText { text: "x".into(), transfer_id: 1 }).unwrap());
+        assert!(simulator.calls.is_empty());
+    }
+
+    #[test]
+    fn failure_tracker_fires_once_at_threshold() {
+        let mut tracker = FailureTracker::new(3);
+        let failed: Result<()> = Err(anyhow::anyhow!("permission denied"));
+
+        // 恰好在第三次连续失败时触发，之后继续失败不再重复触发
+        assert!(!tracker.record(&failed));
+        assert!(!tracker.record(&failed));
+        assert!(tracker.record(&failed));
+        assert!(!tracker.record(&failed));
+        assert_eq!(tracker.consecutive(), 4);
+
+        // 一次成功清零，再次连续失败到阈值时重新触发
+        assert!(!tracker.record(&Ok(())));
+        assert_eq!(tracker.consecutive(), 0);
+        assert!(!tracker.record(&failed));
+        assert!(!tracker.record(&failed));
+        assert!(!tracker.record(&Ok(())));
+        assert!(!tracker.record(&failed));
+        assert!(!tracker.record(&failed));
+        assert!(tracker.record(&failed));
+    }
+
+    #[test]
+    fn precise_scroll_accumulates_partial_lines() {
+        let mut remainder = (0.0, 0.0);
+        // 像素单位：半行累计到下次，两次凑满一行
+        assert_eq!(scroll_lines(ScrollUnit::Pixels, (0.0, 10.0), &mut remainder), (0, 0));
+        assert_eq!(scroll_lines(ScrollUnit::Pixels, (0.0, 10.0), &mut remainder), (0, 1));
+        assert_eq!(remainder, (0.0, 0.0));
+        // 行单位：整行部分立即滚动，余量保留
+        assert_eq!(scroll_lines(ScrollUnit::Lines, (-2.5, 1.0), &mut remainder), (-2, 1));
+        assert_eq!(remainder, (-0.5, 0.0));
+    }
+
+    #[test]
+    fn notched_scroll_moves_at_least_one_line() {
+        // 像素单位的小幅刻度不会被取整为零
+        assert_eq!(scroll_notches(ScrollUnit::Pixels, (0.0, 5.0)), (0, 1));
+        assert_eq!(scroll_notches(ScrollUnit::Pixels, (-60.0, 0.0)), (-3, 0));
+        assert_eq!(scroll_notches(ScrollUnit::Lines, (0.0, -2.0)), (0, -2));
+        assert_eq!(scroll_notches(ScrollUnit::Lines, (0.0, 0.0)), (0, 0));
+    }
+
+    #[test]
+    fn extra_buttons_round_trip_from_capture_to_simulation() {
+        use crate::capture::rdev_button_to_proto;
+
+        // rdev 在 X11 上把按键 4–7 报告为滚轮，侧键为 8、9；Windows 上侧键为 XBUTTON1、XBUTTON2
+        #[cfg(not(target_os = "windows"))]
+        let (back, forward, other) = (8, 9, 10);
+        #[cfg(target_os = "windows")]
+        let (back, forward, other) = (1, 2, 3);
+        #[cfg(not(target_os = "macos"))]
+        let (back_sim, forward_sim) = (Some(enigo::Button::Back), Some(enigo::Button::Forward));
+        #[cfg(target_os = "macos")]
+        let (back_sim, forward_sim) = (None, None);
+
+        let cases = [
+            (rdev::Button::Unknown(back), MouseButton::Back, back_sim),
+            (rdev::Button::Unknown(forward), MouseButton::Forward, forward_sim),
+            // enigo 无法模拟的按键被跳过，而不是误按左键
+            (rdev::Button::Unknown(other), MouseButton::Other(other), None),
+            (rdev::Button::Middle, MouseButton::Middle, Some(enigo::Button::Middle)),
+        ];
+        for (captured, proto, simulated) in cases {
+            assert_eq!(rdev_button_to_proto(&captured), proto);
+            assert_eq!(to_enigo_button(proto), simulated);
+        }
+    }
+
+    #[test]
+    fn relative_fallback_reaches_absolute_targets() {
+        // 从认定位置出发，逐个绝对目标换算的相对位移累加后正好落在最后的目标上
+        let targets = [(960, 540), (0, 0), (1919, 1079), (1919, 1079), (-100, 300)];
+        let mut cursor = (100, 200);
+        for target in targets {
+            let (dx, dy) = relative_step(cursor, target);
+            cursor = (cursor.0 + dx, cursor.1 + dy);
+            assert_eq!(cursor, target);
+        }
+        assert_eq!(relative_step((100, 200), (960, 540)), (860, 340));
+        assert_eq!(relative_step((5, 5), (5, 5)), (0, 0));
+    }
+
+    #[test]
+    fn pinch_scrolls_with_zoom_modifier_held() {
+        use ZoomStep::{Modifier, Scroll};
+
+        let mut steps = Vec::new();
+        pinch_zoom(0.3, |step| {
+            steps.push(step);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(steps, [Modifier(KeyAction::Press), Scroll(3), Modifier(KeyAction::Release)]);
+
+        // 捏合收拢为反向滚动；滚动失败时修饰键仍被释放
+        steps.clear();
+        let result = pinch_zoom(-0.2, |step| {
+            steps.push(step);
+            match step {
+                Scroll(_) => anyhow::bail!("scroll rejected"),
+                _ => Ok(()),
+            }
+        });
+        assert!(result.is_err());
+        assert_eq!(steps, [Modifier(KeyAction::Press), Scroll(-2), Modifier(KeyAction::Release)]);
+
+        // 不足一行的幅度不操作
+        pinch_zoom(0.01, |_| panic!("nothing to simulate")).unwrap();
+    }
+
+    /// 遇到未知键码时 panic 的模拟器，记录成功模拟的按键
+    #[derive(Default)]
+    struct Fragile {
+        keys: Vec<KeyCode>,
+    }
+
+    impl Simulator for Fragile {
+        fn move_mouse(&mut self, _x: i32, _y: i32) -> Result<()> {
+            Ok(())
+        }
+        fn move_mouse_relative(&mut self, _dx: i32, _dy: i32) -> Result<()> {
+            Ok(())
+        }
+        fn mouse_button(&mut self, _button: MouseButton, _action: ButtonAction) -> Result<()> {
+            Ok(())
+        }
+        fn double_click(&mut self, _button: MouseButton) -> Result<()> {
+            Ok(())
+        }
+        fn key_event(&mut self, key: KeyCode, _action: KeyAction) -> Result<()> {
+            if let KeyCode::Unknown(code) = key {
+                panic!("no mapping for key {code}");
+            }
+            self.keys.push(key);
+            Ok(())
+        }
+        fn key_char(&mut self, _key: KeyCode, _text: &str) -> Result<()> {
+            Ok(())
+        }
+        fn key_combo(&mut self, _keys: &[KeyCode]) -> Result<()> {
+            Ok(())
+        }
+        fn type_text(&mut self, _text: &str) -> Result<()> {
+            Ok(())
+        }
+        fn scroll(&mut self, _dx: f64, _dy: f64, _precise: bool) -> Result<()> {
+            Ok(())
+        }
+        fn gesture(&mut self, _kind: GestureKind, _magnitude: f64) -> Result<()> {
+            Ok(())
+        }
+        fn lock_screen(&mut self) -> Result<()> {
+            Ok(())
+        }
+        fn release_all(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn simulator_panic_becomes_error_and_loop_continues() {
+        let press = |key| Message::KeyEvent { key, action: KeyAction::Press };
+        let mut simulator = Fragile::default();
+        let results: Vec<_> = [press(KeyCode::KeyA), press(KeyCode::Unknown(0x1fe)), press(KeyCode::KeyB)]
+            .iter()
+            .map(|msg| apply_guarded(&mut simulator, msg))
+            .collect();
+
+        assert!(matches!(results[0], Ok(true)));
+        let panic = results[1].as_ref().unwrap_err().downcast_ref::<SimulatorPanic>().unwrap();
+        assert_eq!(panic.0, "no mapping for key 510");
+        // panic 之后的消息照常模拟
+        assert!(matches!(results[2], Ok(true)));
+        assert_eq!(simulator.keys, [KeyCode::KeyA, KeyCode::KeyB]);
+    }
+
+    #[test]
+    fn create_retries_until_display_is_ready() {
+        let mut attempts = 0;
+        let mut failures = Vec::new();
+        let created = create_with_retry(
+            5,
+            Duration::from_millis(1),
+            || {
+                attempts += 1;
+                if attempts < 3 {
+                    anyhow::bail!("display not ready");
+                }
+                Ok("simulator")
+            },
+            |attempt, e| failures.push((attempt, e.to_string())),
+        );
+        assert_eq!(created.unwrap(), "simulator");
+        assert_eq!(attempts, 3);
+        assert_eq!(failures, [(1, "display not ready".to_string()), (2, "display not ready".to_string())]);
+
+        // 全部失败时在最后一次尝试后返回其错误，不再等待
+        let mut attempts = 0;
+        let created: Result<()> = create_with_retry(
+            2,
+            Duration::from_millis(1),
+            || {
+                attempts += 1;
+                anyhow::bail!("attempt {attempts} failed")
+            },
+            |_, _| {},
+        );
+        assert_eq!(created.unwrap_err().to_string(), "attempt 2 failed");
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn double_click_emits_two_press_release_pairs() {
+        use ButtonAction::{Press, Release};
+
+        let mut events = Vec::new();
+        click_twice(MouseButton::Left, |button, action| {
+            events.push((button, action));
+            Ok(())
+        })
+        .unwrap();
+        let left = MouseButton::Left;
+        assert_eq!(events, [(left, Press), (left, Release), (left, Press), (left, Release)]);
+    }
+
+    #[test]
+    fn combo_presses_in_order_and_releases_in_reverse() {
+        use KeyAction::{Press, Release};
+        use KeyCode::{Delete, LeftAlt, LeftCtrl};
+
+        let mut events = Vec::new();
+        let keys = [LeftCtrl, LeftAlt, Delete];
+        press_combo(&keys, |key, action| {
+            events.push((key, action));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            events,
+            [
+                (LeftCtrl, Press),
+                (LeftAlt, Press),
+                (Delete, Press),
+                (Delete, Release),
+                (LeftAlt, Release),
+                (LeftCtrl, Release),
+            ]
+        );
+
+        // 中途按下失败：不再按下后续键，已按下的键仍被释放
+        events.clear();
+        let result = press_combo(&keys, |key, action| {
+            if key == Delete {
+                anyhow::bail!("key rejected");
+            }
+            events.push((key, action));
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(events, [(LeftCtrl, Press), (LeftAlt, Press), (LeftAlt, Release), (LeftCtrl, Release)]);
+    }
+
+    #[test]
+    fn sticky_tap_latches_for_next_key() {
+        let mut sticky = StickyKeys::default();
+        tap(&mut sticky, KeyCode::LeftShift);
+        // Shift 敲击后按 a：Shift 只作用于这一次
+        assert_eq!(sticky.take_latched(), [KeyCode::LeftShift]);
+        assert!(sticky.take_latched().is_empty());
+    }
+
+    #[test]
+    fn sticky_held_modifier_passes_through() {
+        let mut sticky = StickyKeys::default();
+        // 按住 Shift 再按 a 是普通组合键，松开后不暂存
+        sticky.modifier_down(KeyCode::LeftShift);
+        assert!(sticky.take_latched().is_empty());
+        sticky.modifier_up(KeyCode::LeftShift);
+        assert!(sticky.take_latched().is_empty());
+    }
+
+    #[test]
+    fn sticky_second_tap_unlatches() {
+        let mut sticky = StickyKeys::default();
+        tap(&mut sticky, KeyCode::LeftCtrl);
+        tap(&mut sticky, KeyCode::LeftCtrl);
+        assert!(sticky.take_latched().is_empty());
+    }
+
+    #[test]
+    fn sticky_skips_latched_key_that_is_held() {
+        let mut sticky = StickyKeys::default();
+        tap(&mut sticky, KeyCode::LeftShift);
+        tap(&mut sticky, KeyCode::LeftCtrl);
+        // 自动重复的按下不影响
+        sticky.modifier_down(KeyCode::LeftShift);
+        sticky.modifier_down(KeyCode::LeftShift);
+        assert_eq!(sticky.take_latched(), [KeyCode::LeftCtrl]);
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::HashSet;
+use std::future::Future;
+
+use synapse_input::capture::{self, Injected};
+use synapse_input::simulate::DEFAULT_FAILURE_THRESHOLD;
+use synapse_input::{apply_guarded, FailureTracker, Simulator, SimulatorPanic};
+use synapse_protocol::Message;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, warn};
+
+use crate::transfer::transfer_id;
+use crate::{ClientEvent, LocalAction};
+
+/// 把收到的剪贴板消息写入本机剪贴板
+///
+/// 返回 `Ok(true)` 表示已写入（随后向服务端回执），`Ok(false)` 表示按策略跳过
+pub type ClipboardWriter = Box<dyn FnMut(&Message) -> anyhow::Result<bool> + Send>;
+
+/// 去掉同一批中随后被取消的剪贴板传输
+fn discard_cancelled(mut batch: Vec<Message>) -> Vec<Message> {
+    let cancelled: HashSet<u64> = batch
+        .iter()
+        .filter_map(|msg| match msg {
+            Message::ClipboardCancel { transfer_id } => Some(*transfer_id),
+            _ => None,
+        })
+        .collect();
+    if !cancelled.is_empty() {
+        batch.retain(|msg| match transfer_id(msg) {
+            Some(id) if cancelled.contains(&id) => {
+                debug!(transfer_id = id, "discarding cancelled clipboard transfer");
+                false
+            }
+            _ => true,
+        });
+    }
+    batch
+}
+
+/// 在本机执行服务端产生的 [`LocalAction`]，CLI 与 GUI 共用
+///
+/// 光标显隐与指针锁定依赖具体平台实现，返回 `Ok(false)` 交由调用方处理。
+/// 按键、鼠标按键与滚动（本地回显）模拟前先登记为自身注入，本机捕获到后不会再次转发
+pub fn apply_local_action<S: Simulator + ?Sized>(
+    simulator: &mut S,
+    action: &LocalAction,
+) -> anyhow::Result<bool> {
+    match action {
+        LocalAction::MoveMouse(x, y) => simulator.move_mouse(*x, *y)?,
+        LocalAction::MoveMouseRelative(dx, dy) => simulator.move_mouse_relative(*dx, *dy)?,
+        LocalAction::TypeText(text) => simulator.type_text(text)?,
+        LocalAction::LockScreen => simulator.lock_screen()?,
+        LocalAction::Key(key, action) => {
+            capture::mark_injected(Injected::Key(*key, *action));
+            simulator.key_event(*key, *action)?
+        }
+        LocalAction::MouseButton(button, action) => {
+            capture::mark_injected(Injected::Button(*button, *action));
+            simulator.mouse_button(*button, *action)?
+        }
+        LocalAction::Scroll { dx, dy, precise } => {
+            capture::mark_injected(Injected::Scroll { dx: *dx, dy: *dy });
+            simulator.scroll(*dx, *dy, *precise)?
+        }
+        LocalAction::HideCursor
+        | LocalAction::ShowCursor
+        | LocalAction::LockPointer
+        | LocalAction::UnlockPointer => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// 客户端对服务端消息的处理：剪贴板写入本机并回执，输入交给模拟器
+///
+/// CLI 与 GUI 共用，模拟器可替换为 `synapse_input::mock::RecordingSimulator` 进行测试
+pub struct MessageApplier<S> {
+    simulator: S,
+    failures: FailureTracker,
+    clipboard: Option<ClipboardWriter>,
+    server_tx: mpsc::UnboundedSender<Message>,
+    event_tx: mpsc::UnboundedSender<ClientEvent>,
+}
+
+impl<S: Simulator> MessageApplier<S> {
+    pub fn new(
+        simulator: S,
+        server_tx: mpsc::UnboundedSender<Message>,
+        event_tx: mpsc::UnboundedSender<ClientEvent>,
+    ) -> Self {
+        Self {
+            simulator,
+            failures: FailureTracker::new(DEFAULT_FAILURE_THRESHOLD),
+            clipboard: None,
+            server_tx,
+            event_tx,
+        }
+    }
+
+    /// 设置剪贴板写入方式；未设置时忽略剪贴板消息，也不回执
+    pub fn with_clipboard(
+        mut self,
+        writer: impl FnMut(&Message) -> anyhow::Result<bool> + Send + 'static,
+    ) -> Self {
+        self.clipboard = Some(Box::new(writer));
+        self
+    }
+
+    pub fn simulator(&self) -> &S {
+        &self.simulator
+    }
+
+    /// 处理一条消息，返回不由本处理器负责的消息（如剪贴板历史）
+    pub fn apply(&mut self, msg: Message) -> Option<Message> {
+        match msg {
+            Message::ClipboardText { .. }
+            | Message::ClipboardImage { .. }
+            | Message::ClipboardClear { .. } => {
+                self.write_clipboard(&msg);
+                None
+            }
+            Message::ClipboardHistory { .. } => Some(msg),
+            // 已写入的内容无法撤回；尚未写入的在 `run` 中整批丢弃
+            Message::ClipboardCancel { transfer_id } => {
+                debug!(transfer_id, "transfer cancelled after it was applied");
+                None
+            }
+            msg => {
+                self.simulate(&msg);
+                None
+            }
+        }
+    }
+
+    /// 持续处理消息直到取消或通道关闭，`on_other` 处理 [`apply`](Self::apply) 返回的消息
+    ///
+    /// 每次取出通道中已到达的全部消息，其中被 [`Message::ClipboardCancel`] 取消的剪贴板不再写入
+    pub async fn run<F, Fut>(
+        &mut self,
+        message_rx: &mut mpsc::UnboundedReceiver<Message>,
+        cancel: CancellationToken,
+        mut on_other: F,
+    ) where
+        F: FnMut(Message) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                msg = message_rx.recv() => match msg {
+                    Some(msg) => {
+                        let mut batch = vec![msg];
+                        while let Ok(msg) = message_rx.try_recv() {
+                            batch.push(msg);
+                        }
+                        for msg in discard_cancelled(batch) {
+                            if let Some(other) = self.apply(msg) {
+                                on_other(other).await;
+                            }
+                        }
+                    }
+                    None => break,
+                },
+            }
+        }
+    }
+
+    fn write_clipboard(&mut self, msg: &Message) {
+        let Some(writer) = self.clipboard.as_mut() else {
+            debug!("clipboard support is not enabled, ignoring");
+            return;
+        };
+        match writer(msg) {
+            Ok(true) => {
+                if let Some(transfer_id) = transfer_id(msg) {
+                    let _ = self.server_tx.send(Message::ClipboardAck { transfer_id });
+                }
+            }
+            Ok(false) => debug!("clipboard message skipped by policy"),
+            Err(e) => {
+                warn!(transfer_id = ?transfer_id(msg), "failed to write clipboard: {e}");
+                let _ = self.event_tx.send(ClientEvent::Log(format!("Rejected clipboard data: {e}")));
+            }
+        }
+    }
+
+    fn simulate(&mut self, msg: &Message) {
+        let result = apply_guarded(&mut self.simulator, msg);
+        if let Some(panic) = result.as_ref().err().and_then(|e| e.downcast_ref::<SimulatorPanic>()) {
+            error!("{panic}");
+            debug!(?msg, "message that panicked the simulator");
+            let _ = self.event_tx.send(ClientEvent::Log(format!("{panic}; continuing")));
+        }
+        if self.failures.record(&result) {
+            let error = result.err().map(|e| e.to_string()).unwrap_or_default();
+            let _ = self.event_tx.send(ClientEvent::SimulationFailing {
+                consecutive_failures: self.failures.consecutive(),
+                error: error.clone(),
+            });
+            let _ = self.event_tx.send(ClientEvent::Log(format!(
+                "Input simulation keeps failing ({error}); check input permissions"
+            )));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use synapse_input::mock::{RecordingSimulator, SimulatorCall};
+    use synapse_protocol::input::{KeyAction, KeyCode};
+
+    use super::*;
+
+    type Channels = (
+        mpsc::UnboundedReceiver<Message>,
+        mpsc::UnboundedReceiver<ClientEvent>,
+    );
+
+    fn applier() -> (MessageApplier<RecordingSimulator>, Channels) {
+        let (server_tx, server_rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let applier = MessageApplier::new(RecordingSimulator::new(), server_tx, event_tx);
+        (applier, (server_rx, event_rx))
+    }
+
+    #[test]
+    fn persistent_simulation_failure_is_reported_once() {
+        let (server_tx, _server_rx) = mpsc::unbounded_channel();
+        let (event_tx, mut events) = mpsc::unbounded_channel();
+        let mut applier =
+            MessageApplier::new(RecordingSimulator::failing("permission denied"), server_tx, event_tx);
+
+        let key = Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press };
+        for _ in 0..DEFAULT_FAILURE_THRESHOLD - 1 {
+            applier.apply(key.clone());
+        }
+        assert!(events.try_recv().is_err());
+
+        // 达到阈值时报告一次，并附带可读的提示
+        applier.apply(key.clone());
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ClientEvent::SimulationFailing { consecutive_failures, error })
+                if consecutive_failures == DEFAULT_FAILURE_THRESHOLD && error == "permission denied"
+        ));
+        assert!(matches!(events.try_recv(), Ok(ClientEvent::Log(msg)) if msg.contains("permission denied")));
+        applier.apply(key);
+        assert!(events.try_recv().is_err());
+        // 失败的消息仍交给了模拟器
+        assert_eq!(applier.simulator().calls.len(), DEFAULT_FAILURE_THRESHOLD as usize + 1);
+    }
+
+    #[test]
+    fn input_reaches_simulator() {
+        let (mut applier, _channels) = applier();
+        applier.apply(Message::MouseDelta { dx: 3.0, dy: -2.0 });
+        applier.apply(Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press });
+        applier.apply(Message::ReleaseAll);
+        assert_eq!(
+            applier.simulator().calls,
+            vec![
+                SimulatorCall::MoveMouseRelative(3, -2),
+                SimulatorCall::KeyEvent(KeyCode::KeyA, KeyAction::Press),
+                SimulatorCall::ReleaseAll,
+            ]
+        );
+    }
+
+    #[test]
+    fn scroll_keeps_precise_flag() {
+        let (mut applier, _channels) = applier();
+        applier.apply(Message::MouseScroll { dx: 0.0, dy: 0.25, precise: true });
+        applier.apply(Message::MouseScroll { dx: -1.0, dy: 0.0, precise: false });
+        assert_eq!(
+            applier.simulator().calls,
+            vec![SimulatorCall::Scroll(0.0, 0.25, true), SimulatorCall::Scroll(-1.0, 0.0, false)]
+        );
+    }
+
+    #[test]
+    fn local_actions_map_to_simulator_calls() {
+        use synapse_protocol::input::{ButtonAction, MouseButton};
+
+        let mut simulator = RecordingSimulator::new();
+        let cases = [
+            (LocalAction::MoveMouse(960, 540), SimulatorCall::MoveMouse(960, 540)),
+            (LocalAction::MoveMouseRelative(-4, 7), SimulatorCall::MoveMouseRelative(-4, 7)),
+            (
+                LocalAction::MouseButton(MouseButton::Right, ButtonAction::Press),
+                SimulatorCall::MouseButton(MouseButton::Right, ButtonAction::Press),
+            ),
+            (
+                LocalAction::Key(KeyCode::KeyA, KeyAction::Release),
+                SimulatorCall::KeyEvent(KeyCode::KeyA, KeyAction::Release),
+            ),
+            (
+                LocalAction::Scroll { dx: 0.0, dy: -1.5, precise: true },
+                SimulatorCall::Scroll(0.0, -1.5, true),
+            ),
+            (LocalAction::TypeText("hello".into()), SimulatorCall::TypeText("hello".into())),
+            (LocalAction::LockScreen, SimulatorCall::LockScreen),
+        ];
+        for (action, call) in cases {
+            simulator.calls.clear();
+            assert!(apply_local_action(&mut simulator, &action).unwrap(), "{action:?}");
+            assert_eq!(simulator.calls, [call]);
+        }
+
+        // 光标显示与指针锁定由调用方处理
+        simulator.calls.clear();
+        for action in [
+            LocalAction::HideCursor,
+            LocalAction::ShowCursor,
+            LocalAction::LockPointer,
+            LocalAction::UnlockPointer,
+        ] {
+            assert!(!apply_local_action(&mut simulator, &action).unwrap(), "{action:?}");
+        }
+        assert!(simulator.calls.is_empty());
+    }
+
+    #[test]
+    fn written_clipboard_is_acked() {
+        let (applier, (mut server_rx, _)) = applier();
+        let mut applier = applier.with_clipboard(|msg| {
+            Ok(matches!(msg, Message::ClipboardText { text, .. } if text != "skip"))
+        });
+        applier.apply(Message::ClipboardText { text: "hello".into(), transfer_id: 7 });
+        applier.apply(Message::ClipboardText { text: "skip".into(), transfer_id: 8 });
+        assert!(matches!(server_rx.try_recv(), Ok(Message::ClipboardAck { transfer_id: 7 })));
+        assert!(server_rx.try_recv().is_err());
+        assert!(applier.simulator().calls.is_empty());
+    }
+
+    #[test]
+    fn clipboard_without_writer_is_not_acked() {
+        let (mut applier, (mut server_rx, _)) = applier();
+        applier.apply(Message::ClipboardClear { transfer_id: 1 });
+        assert!(server_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn clipboard_write_error_is_logged() {
+        let (applier, (mut server_rx, mut event_rx)) = applier();
+        let mut applier = applier.with_clipboard(|_| Err(anyhow::anyhow!("too large")));
+        applier.apply(Message::ClipboardClear { transfer_id: 1 });
+        assert!(server_rx.try_recv().is_err());
+        assert!(matches!(event_rx.try_recv(), Ok(ClientEvent::Log(log)) if log.contains("too large")));
+    }
+
+    #[tokio::test]
+    async fn cancelled_transfer_is_not_written() {
+        let (applier, (mut server_rx, _)) = applier();
+        let written = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut applier = applier.with_clipboard({
+            let written = written.clone();
+            move |msg| {
+                written.lock().unwrap().push(transfer_id(msg));
+                Ok(true)
+            }
+        });
+        // 取消到达时 7 仍在通道中等待写入，8 不受影响
+        let (message_tx, mut message_rx) = mpsc::unbounded_channel();
+        message_tx.send(Message::ClipboardText { text: "big".into(), transfer_id: 7 }).unwrap();
+        message_tx.send(Message::ClipboardText { text: "next".into(), transfer_id: 8 }).unwrap();
+        message_tx.send(Message::ClipboardCancel { transfer_id: 7 }).unwrap();
+        drop(message_tx);
+        applier.run(&mut message_rx, CancellationToken::new(), |_| async {}).await;
+
+        assert_eq!(*written.lock().unwrap(), vec![Some(8)]);
+        assert!(matches!(server_rx.try_recv(), Ok(Message::ClipboardAck { transfer_id: 8 })));
+        assert!(server_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn run_hands_history_to_caller_and_stops_on_cancel() {
+        let (mut applier, _channels) = applier();
+        let (message_tx, mut message_rx) = mpsc::unbounded_channel();
+        message_tx.send(Message::MouseMove { x: 10.0, y: 20.0 }).unwrap();
+        message_tx.send(Message::ClipboardHistory { items: vec![] }).unwrap();
+        let cancel = CancellationToken::new();
+        let mut others = Vec::new();
+        let run = applier.run(&mut message_rx, cancel.clone(), |msg| {
+            others.push(msg);
+            cancel.cancel();
+            async {}
+        });
+        tokio::time::timeout(std::time::Duration::from_secs(5), run).await.unwrap();
+        assert!(matches!(others.as_slice(), [Message::ClipboardHistory { .. }]));
+        assert_eq!(applier.simulator().calls, vec![SimulatorCall::MoveMouse(10, 20)]);
+    }
+
+    #[tokio::test]
+    async fn key_held_when_server_drops_is_released() {
+        use futures::{SinkExt, StreamExt};
+        use synapse_protocol::{Capabilities, Compression, DeviceId, PROTOCOL_VERSION};
+
+        let (client_side, mut server) = crate::transport::duplex_pair(1 << 16);
+        // 服务端握手后按下一个键，在释放前断开
+        let server = tokio::spawn(async move {
+            assert!(matches!(server.next().await, Some(Ok(Message::Hello { .. }))));
+            server
+                .send(Message::Welcome {
+                    protocol_version: PROTOCOL_VERSION,
+                    device_id: DeviceId("server".into()),
+                    device_name: "server".into(),
+                    screens: Vec::new(),
+                    capabilities: Capabilities::local(),
+                    compression: Compression::None,
+                })
+                .await
+                .unwrap();
+            server.send(Message::KeyEvent { key: KeyCode::LeftShift, action: KeyAction::Press }).await.unwrap();
+        });
+        let (message_tx, mut message_rx) = mpsc::unbounded_channel();
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let client = crate::Client::new("127.0.0.1:0");
+        let session = client.run_session(
+            client_side,
+            "client".into(),
+            "client".into(),
+            (1920, 1080),
+            message_tx,
+            event_tx,
+            CancellationToken::new(),
+        );
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(5), session).await.unwrap();
+        server.await.unwrap();
+
+        let (mut applier, _channels) = applier();
+        while let Ok(msg) = message_rx.try_recv() {
+            applier.apply(msg);
+        }
+        let keys: Vec<_> = applier
+            .simulator()
+            .calls
+            .iter()
+            .filter(|call| matches!(call, SimulatorCall::KeyEvent(..) | SimulatorCall::ReleaseAll))
+            .cloned()
+            .collect();
+        assert_eq!(
+            keys,
+            vec![SimulatorCall::KeyEvent(KeyCode::LeftShift, KeyAction::Press), SimulatorCall::ReleaseAll]
+        );
+    }
+}
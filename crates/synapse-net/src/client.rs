@@ -1,23 +1,267 @@
-use anyhow::Result;
+use std::net::IpAddr;
+use std::time::Duration;
+
 use futures::{SinkExt, StreamExt};
-use synapse_protocol::screen::{ScreenId, ScreenInfo, ScreenRect};
-use synapse_protocol::{DeviceId, Message, MessageCodec};
+use serde::{Deserialize, Serialize};
+use synapse_protocol::input::ScrollUnit;
+use synapse_protocol::screen::{Edge, ScreenId, ScreenInfo, ScreenRect};
+use synapse_protocol::image;
+use synapse_protocol::{
+    Capabilities, Capability, Compression, CompressionHandle, DeviceId, Message, MessageCodec,
+    PROTOCOL_VERSION,
+};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio_util::codec::Framed;
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
+use crate::error::{SynapseError, SynapseResult};
+use crate::jitter::JitterBuffer;
+use crate::transfer::transfer_id;
+use crate::transport::Transport;
+use crate::udp::UdpReceiver;
 use crate::ClientEvent;
 
+/// 建立 TCP 连接的默认超时
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 发出 Hello 后等待 Welcome 的默认超时，需容纳服务端等待用户批准配对的时间
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(120);
+/// 发往服务端的剪贴板消息默认合并窗口
+pub const DEFAULT_CLIPBOARD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// 探测连接时使用的设备 ID 前缀，服务端日志中可据此区分探测与正常连接
+const PROBE_DEVICE_PREFIX: &str = "synapse-probe";
+
+/// [`Client::probe`] 从服务端 Welcome 中得到的信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub device_id: String,
+    pub device_name: String,
+    pub screens: Vec<ScreenInfo>,
+    /// 服务端支持的协议功能
+    pub capabilities: Capabilities,
+}
+
+/// 服务端在 Welcome 中声明的协议版本与本机不同时拒绝继续会话
+fn check_version(remote: u32) -> SynapseResult<()> {
+    if remote != PROTOCOL_VERSION {
+        warn!(local = PROTOCOL_VERSION, remote, "incompatible server protocol version");
+        return Err(SynapseError::ProtocolVersion { local: PROTOCOL_VERSION, remote });
+    }
+    Ok(())
+}
+
 /// TCP 客户端
 pub struct Client {
     addr: String,
+    connect_timeout: Duration,
+    handshake_timeout: Duration,
+    jitter_depth: u32,
+    clipboard_debounce: Duration,
+    relay_session: Option<String>,
+    preferred_edge: Option<Edge>,
+    clipboard_only: bool,
+    scroll_unit: ScrollUnit,
+    absolute_pointer: bool,
+    screens: Vec<ScreenInfo>,
+    compression: Vec<Compression>,
+    udp_motion: bool,
+    pairing_key: String,
+    outgoing_tx: mpsc::UnboundedSender<Message>,
+    outgoing_rx: std::sync::Mutex<Option<mpsc::UnboundedReceiver<Message>>>,
 }
 
 impl Client {
     pub fn new(addr: impl Into<String>) -> Self {
-        Self { addr: addr.into() }
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
+        Self {
+            addr: addr.into(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            jitter_depth: 0,
+            clipboard_debounce: DEFAULT_CLIPBOARD_DEBOUNCE,
+            relay_session: None,
+            preferred_edge: None,
+            clipboard_only: false,
+            scroll_unit: ScrollUnit::default(),
+            absolute_pointer: false,
+            screens: Vec::new(),
+            compression: vec![Compression::Lz4],
+            udp_motion: false,
+            pairing_key: crate::pairing::generate_key(),
+            outgoing_tx,
+            outgoing_rx: std::sync::Mutex::new(Some(outgoing_rx)),
+        }
+    }
+
+    /// 建立连接（含加入中继会话）的超时，默认 10 秒
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// 发出 Hello 后等待 Welcome 的超时，默认 120 秒；对端接受连接却不响应时据此失败并重试
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// 通过中继连接：`addr` 视为中继地址，加入 `session` 会话后与服务端握手
+    pub fn with_relay_session(mut self, session: impl Into<String>) -> Self {
+        self.relay_session = Some(session.into());
+        self
+    }
+
+    /// 请求位于服务端屏幕的指定边缘（该边缘空闲时服务端采纳）
+    pub fn with_preferred_edge(mut self, edge: Edge) -> Self {
+        self.preferred_edge = Some(edge);
+        self
+    }
+
+    /// 声明为仅剪贴板设备：只接收剪贴板同步，服务端不会把焦点切换到本机
+    pub fn with_clipboard_only(mut self, clipboard_only: bool) -> Self {
+        self.clipboard_only = clipboard_only;
+        self
+    }
+
+    /// 希望收到的滚动量单位，服务端转发滚动时按此换算；应与本机模拟器配置的单位一致
+    ///
+    /// `synapse-input` 的模拟器最终按整行滚动，像素单位下高精度滚动先按像素累计再换算为行
+    pub fn with_scroll_unit(mut self, unit: ScrollUnit) -> Self {
+        self.scroll_unit = unit;
+        self
+    }
+
+    /// 要求服务端以绝对坐标定位光标（游戏、设计软件等不适合累积相对位移的场景）
+    pub fn with_absolute_pointer(mut self, enabled: bool) -> Self {
+        self.absolute_pointer = enabled;
+        self
+    }
+
+    /// 握手时报告的本机屏幕（名称、主屏标记等）；未设置时按 `screen_size` 生成一个
+    pub fn with_screens(mut self, screens: Vec<ScreenInfo>) -> Self {
+        self.screens = screens;
+        self
+    }
+
+    /// 握手时声明支持的帧压缩算法，默认支持 LZ4；为空时不压缩
+    pub fn with_compression(mut self, compression: Vec<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// 服务端也启用时经 UDP 接收鼠标运动，丢包时后续运动不必等待 TCP 重传；通过中继连接时无效
+    pub fn with_udp_motion(mut self, enabled: bool) -> Self {
+        self.udp_motion = enabled;
+        self
+    }
+
+    /// 配对密钥，服务端按其指纹记住已确认的设备；未设置时每个 `Client` 随机生成一个，
+    /// 需要跨进程保持信任时由调用方用 [`pairing::generate_key`](crate::pairing::generate_key) 生成并保存
+    pub fn with_pairing_key(mut self, key: impl Into<String>) -> Self {
+        self.pairing_key = key.into();
+        self
+    }
+
+    /// 本机配对密钥的指纹，服务端确认配对时显示同一指纹
+    pub fn pairing_fingerprint(&self) -> String {
+        crate::pairing::fingerprint(&self.pairing_key)
+    }
+
+    /// 发往服务端的剪贴板消息在该时长内没有新变化后才发送，期间的多次变化只发送最后一次
+    ///
+    /// 避免快速连续复制（如宏批量粘贴）逐条发送；`Duration::ZERO` 表示立即发送
+    pub fn with_clipboard_debounce(mut self, window: Duration) -> Self {
+        self.clipboard_debounce = window;
+        self
+    }
+
+    /// 获取向服务端发送消息的通道（如剪贴板确认），连接建立前发送的消息会在握手后送出
+    pub fn sender(&self) -> mpsc::UnboundedSender<Message> {
+        self.outgoing_tx.clone()
+    }
+
+    /// 启用鼠标运动抖动缓冲，`depth` 为缓冲的消息数（0 表示关闭）
+    ///
+    /// 以少量延迟换取网络抖动下更平滑的光标移动；点击与按键不受影响
+    pub fn with_jitter_buffer(mut self, depth: u32) -> Self {
+        self.jitter_depth = depth;
+        self
+    }
+
+    /// 测试服务端是否可达：完成 Hello/Welcome 握手后立即发送 `Bye` 断开，不进入消息循环
+    ///
+    /// 以仅剪贴板设备身份握手，服务端不会为其分配边缘；`timeout` 覆盖连接与握手全过程
+    pub async fn probe(addr: &str, timeout: Duration) -> SynapseResult<ServerInfo> {
+        let hostname = hostname::get()
+            .map(|h| h.to_string_lossy().to_string())
+            .unwrap_or_else(|_| "client".into());
+        let device_id =
+            DeviceId(format!("{PROBE_DEVICE_PREFIX}-{hostname}-{}", std::process::id()));
+
+        let handshake = async {
+            let mut framed = Framed::new(TcpStream::connect(addr).await?, MessageCodec::new());
+            framed.send(Message::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                device_id: device_id.clone(),
+                device_name: format!("{hostname} (probe)"),
+                screens: vec![ScreenInfo {
+                    id: ScreenId(0),
+                    name: "Probe".into(),
+                    rect: ScreenRect { x: 0, y: 0, width: 1, height: 1 },
+                    is_primary: true,
+                }],
+                preferred_edge: None,
+                clipboard_only: true,
+                scroll_unit: ScrollUnit::default(),
+                // 探测只收 Welcome，不切换帧格式
+                capabilities: {
+                    let mut capabilities = Capabilities::local();
+                    capabilities.remove(Capability::FrameSync);
+                    capabilities.remove(Capability::UdpMotion);
+                    capabilities
+                },
+                absolute_pointer: false,
+                compression: Vec::new(),
+                pairing_key: String::new(),
+            }).await.map_err(SynapseError::transport)?;
+
+            let info = loop {
+                match framed.next().await {
+                    Some(Ok(Message::Welcome {
+                        protocol_version, device_id, device_name, screens, capabilities, ..
+                    })) => {
+                        check_version(protocol_version)?;
+                        break ServerInfo { device_id: device_id.0, device_name, screens, capabilities };
+                    }
+                    Some(Ok(Message::Bye { .. })) => {
+                        return Err(SynapseError::Auth("server rejected the probe".into()));
+                    }
+                    Some(Ok(msg)) => warn!("expected Welcome, got {:?}", msg),
+                    Some(Err(e)) => return Err(SynapseError::transport(e)),
+                    None => {
+                        return Err(SynapseError::Handshake(
+                            "connection closed before Welcome".into(),
+                        ));
+                    }
+                }
+            };
+            let _ = framed.send(Message::Bye { device_id }).await;
+            SynapseResult::Ok(info)
+        };
+        match tokio::time::timeout(timeout, handshake).await {
+            Ok(result) => {
+                if let Ok(info) = &result {
+                    info!(%addr, server = %info.device_name, "probe succeeded");
+                }
+                result
+            }
+            Err(_) => {
+                warn!(%addr, ?timeout, "probe timed out");
+                Err(SynapseError::Timeout(timeout))
+            }
+        }
     }
 
     /// 连接到服务端，进入完整消息循环
@@ -29,45 +273,163 @@ impl Client {
         message_tx: mpsc::UnboundedSender<Message>,
         event_tx: mpsc::UnboundedSender<ClientEvent>,
         cancel: CancellationToken,
-    ) -> Result<()> {
+    ) -> SynapseResult<()> {
         let _ = event_tx.send(ClientEvent::Log(format!(
             "Connecting to {}...", self.addr
         )));
 
-        let stream = TcpStream::connect(&self.addr).await?;
+        let dial = async {
+            SynapseResult::Ok(match &self.relay_session {
+                Some(session) => crate::relay::join(&self.addr, session).await?,
+                None => Framed::new(TcpStream::connect(&self.addr).await?, MessageCodec::new()),
+            })
+        };
+        let framed = match tokio::time::timeout(self.connect_timeout, dial).await {
+            Ok(result) => result?,
+            Err(_) => {
+                warn!(addr = %self.addr, timeout = ?self.connect_timeout, "connect timed out");
+                let _ = event_tx.send(ClientEvent::Log(format!(
+                    "Connection to {} timed out after {:?}",
+                    self.addr,
+                    self.connect_timeout
+                )));
+                return Err(SynapseError::Timeout(self.connect_timeout));
+            }
+        };
         info!(addr = %self.addr, "connected to server");
 
-        let mut framed = Framed::new(stream, MessageCodec);
+        let compression = framed.codec().compression_handle();
+        // UDP 旁路发往 TCP 连接的对端，经中继时对端不是服务端
+        let udp_peer = match (&self.relay_session, self.udp_motion) {
+            (None, true) => framed.get_ref().peer_addr().ok().map(|addr| addr.ip()),
+            _ => None,
+        };
+        self.session(
+            framed,
+            Some(compression),
+            udp_peer,
+            device_id,
+            device_name,
+            screen_size,
+            message_tx,
+            event_tx,
+            cancel,
+        ).await
+    }
+
+    /// 在已建立的传输上完成握手并进入消息循环（该传输不协商压缩）
+    #[allow(clippy::too_many_arguments)]
+    pub async fn run_session<T: Transport>(
+        &self,
+        framed: T,
+        device_id: String,
+        device_name: String,
+        screen_size: (u32, u32),
+        message_tx: mpsc::UnboundedSender<Message>,
+        event_tx: mpsc::UnboundedSender<ClientEvent>,
+        cancel: CancellationToken,
+    ) -> SynapseResult<()> {
+        self.session(
+            framed, None, None, device_id, device_name, screen_size, message_tx, event_tx, cancel,
+        ).await
+    }
 
+    /// `compression` 为传输编解码器的压缩句柄，只有能切换压缩时才在 Hello 中声明支持的算法；
+    /// `udp_peer` 为 UDP 旁路的服务端地址，为 `None` 时不声明支持 UDP 运动
+    #[allow(clippy::too_many_arguments)]
+    async fn session<T: Transport>(
+        &self,
+        mut framed: T,
+        compression: Option<CompressionHandle>,
+        udp_peer: Option<IpAddr>,
+        device_id: String,
+        device_name: String,
+        screen_size: (u32, u32),
+        message_tx: mpsc::UnboundedSender<Message>,
+        event_tx: mpsc::UnboundedSender<ClientEvent>,
+        cancel: CancellationToken,
+    ) -> SynapseResult<()> {
         // 发送 Hello 握手（携带屏幕信息）
-        framed.send(Message::Hello {
-            device_id: DeviceId(device_id.clone()),
-            device_name: device_name.clone(),
-            screens: vec![ScreenInfo {
+        let screens = if self.screens.is_empty() {
+            vec![ScreenInfo {
                 id: ScreenId(0),
-                name: "primary".into(),
+                name: "Display-1".into(),
                 rect: ScreenRect {
                     x: 0, y: 0,
                     width: screen_size.0,
                     height: screen_size.1,
                 },
                 is_primary: true,
-            }],
-        }).await?;
+            }]
+        } else {
+            self.screens.clone()
+        };
+        // 帧同步需要在握手后切换编解码器，无法切换时不声明支持
+        let mut capabilities = Capabilities::local();
+        if compression.is_none() {
+            capabilities.remove(Capability::FrameSync);
+        }
+        if udp_peer.is_none() {
+            capabilities.remove(Capability::UdpMotion);
+        }
+        framed.send(Message::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            device_id: DeviceId(device_id.clone()),
+            device_name: device_name.clone(),
+            screens,
+            preferred_edge: self.preferred_edge,
+            clipboard_only: self.clipboard_only,
+            scroll_unit: self.scroll_unit,
+            capabilities,
+            absolute_pointer: self.absolute_pointer,
+            compression: match compression {
+                Some(_) => self.compression.clone(),
+                None => Vec::new(),
+            },
+            pairing_key: self.pairing_key.clone(),
+        }).await.map_err(SynapseError::transport)?;
 
         // 等待 Welcome
+        let deadline = tokio::time::sleep(self.handshake_timeout);
+        tokio::pin!(deadline);
         let welcome = loop {
             let msg = tokio::select! {
-                _ = cancel.cancelled() => return Ok(()),
+                _ = cancel.cancelled() => return Err(SynapseError::Cancelled),
+                _ = &mut deadline => {
+                    warn!(timeout = ?self.handshake_timeout, "no Welcome from server");
+                    let _ = event_tx.send(ClientEvent::Log(format!(
+                        "Server did not complete the handshake within {:?}",
+                        self.handshake_timeout
+                    )));
+                    return Err(SynapseError::Timeout(self.handshake_timeout));
+                }
                 result = framed.next() => match result {
                     Some(Ok(msg)) => msg,
-                    Some(Err(e)) => return Err(e.into()),
-                    None => return Err(anyhow::anyhow!("connection closed before Welcome")),
+                    Some(Err(e)) => return Err(SynapseError::transport(e)),
+                    None => {
+                        return Err(SynapseError::Handshake(
+                            "connection closed before Welcome".into(),
+                        ));
+                    }
                 },
             };
             match msg {
-                Message::Welcome { device_id, device_name, .. } => {
-                    break (device_id.0, device_name);
+                Message::Welcome {
+                    protocol_version, device_id, device_name, capabilities, compression: chosen, ..
+                } => {
+                    check_version(protocol_version)?;
+                    let shared = Capabilities::local().intersection(capabilities);
+                    info!(?shared, ?chosen, "negotiated capabilities");
+                    if let Some(handle) = &compression {
+                        handle.set(chosen);
+                        handle.set_frame_sync(shared.contains(Capability::FrameSync));
+                    }
+                    break (device_id.0, device_name, shared);
+                }
+                // 握手阶段收到 Bye：服务端拒绝了本设备（未通过配对、不在布局白名单等）
+                Message::Bye { .. } => {
+                    let _ = event_tx.send(ClientEvent::Log("Server rejected the connection".into()));
+                    return Err(SynapseError::Auth("server rejected the connection".into()));
                 }
                 _ => {
                     warn!("expected Welcome, got {:?}", msg);
@@ -75,23 +437,101 @@ impl Client {
             }
         };
 
+        let shared = welcome.2;
         info!(server_id = %welcome.0, server_name = %welcome.1, "handshake complete");
         let _ = event_tx.send(ClientEvent::Connected {
-            server_device_id: welcome.0,
+            server_device_id: welcome.0.clone(),
             server_device_name: welcome.1,
         });
         let _ = event_tx.send(ClientEvent::Log("Connected to server".into()));
 
         // 消息接收循环
+        let mut outgoing_rx = self.outgoing_rx.lock().unwrap().take();
+        let mut jitter = (self.jitter_depth > 0).then(|| JitterBuffer::new(self.jitter_depth));
+        // 等待合并窗口结束的最新剪贴板消息及其发送时间
+        let mut pending_clipboard: Option<(Message, tokio::time::Instant)> = None;
+        // 服务端发来 UdpOffer 后打开的 UDP 运动旁路
+        let mut udp: Option<UdpReceiver> = None;
         loop {
+            let clipboard_due = pending_clipboard.as_ref().map(|(_, due)| *due);
+            let flush_clipboard = async move {
+                match clipboard_due {
+                    Some(due) => tokio::time::sleep_until(due).await,
+                    None => std::future::pending().await,
+                }
+            };
+            let deadline = jitter.as_ref().and_then(JitterBuffer::next_deadline);
+            let release = async move {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
             let msg = tokio::select! {
+                _ = release => {
+                    if let Some(jitter) = jitter.as_mut() {
+                        for msg in jitter.pop_due(tokio::time::Instant::now()) {
+                            let _ = message_tx.send(msg);
+                        }
+                    }
+                    continue;
+                }
+                _ = flush_clipboard => {
+                    if let Some((msg, _)) = pending_clipboard.take() {
+                        let _ = framed.send(msg).await;
+                    }
+                    continue;
+                }
+                Some(msg) = async {
+                    match outgoing_rx.as_mut() {
+                        Some(rx) => rx.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    // 服务端不支持的消息不发送；不支持 PNG 时图片退回原始 RGBA
+                    let msg = match msg {
+                        msg if shared.permits(&msg) => msg,
+                        msg @ Message::ClipboardImage { .. }
+                            if shared.contains(Capability::ClipboardImage) =>
+                        {
+                            match image::png_to_raw(msg) {
+                                Ok(msg) => msg,
+                                Err(e) => {
+                                    warn!("failed to decode clipboard image for server: {e}");
+                                    continue;
+                                }
+                            }
+                        }
+                        msg => {
+                            debug!(cap = ?msg.required_capability(), "server does not support message, skipped");
+                            continue;
+                        }
+                    };
+                    if transfer_id(&msg).is_some() && !self.clipboard_debounce.is_zero() {
+                        // 新变化替换尚未发送的旧内容，并重新开始计时
+                        let due = tokio::time::Instant::now() + self.clipboard_debounce;
+                        pending_clipboard = Some((msg, due));
+                    } else {
+                        let _ = framed.send(msg).await;
+                    }
+                    continue;
+                }
                 _ = cancel.cancelled() => {
-                    // 发送 Bye
+                    // 先发出仍在合并窗口中的剪贴板，再发送 Bye
+                    if let Some((msg, _)) = pending_clipboard.take() {
+                        let _ = framed.send(msg).await;
+                    }
                     let _ = framed.send(Message::Bye {
                         device_id: DeviceId(device_id.clone()),
                     }).await;
                     break;
                 }
+                msg = async {
+                    match udp.as_mut() {
+                        Some(udp) => udp.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => msg,
                 result = framed.next() => match result {
                     Some(Ok(msg)) => msg,
                     Some(Err(e)) => {
@@ -110,15 +550,267 @@ impl Client {
                     let _ = framed.send(Message::Pong(*seq)).await;
                 }
                 Message::Pong(_) => {}
-                _ => {
-                    // 转发给上层处理（输入模拟、剪贴板等）
-                    let _ = message_tx.send(msg);
+                Message::UdpOffer { port, token } => {
+                    let Some(ip) = udp_peer else { continue };
+                    match UdpReceiver::connect((ip, *port).into(), *token).await {
+                        Ok(receiver) => udp = Some(receiver),
+                        // 服务端收不到注册时继续经 TCP 发送运动
+                        Err(e) => warn!("UDP motion unavailable, staying on TCP: {e}"),
+                    }
                 }
+                Message::UdpBarrier { seq } => {
+                    // 之后的 TCP 消息（如点击）须在此前经 UDP 发出的运动之后应用
+                    let caught_up = match udp.as_mut() {
+                        Some(udp) => udp.catch_up(*seq).await,
+                        None => Vec::new(),
+                    };
+                    for motion in caught_up {
+                        forward(motion, &mut jitter, &message_tx);
+                    }
+                }
+                _ => forward(msg, &mut jitter, &message_tx),
             }
         }
 
+        // 通知上层会话已结束（如释放仍按下的键）
+        let _ = message_tx.send(Message::Bye { device_id: DeviceId(welcome.0.clone()) });
+
+        // 归还发送通道，供下一次连接使用
+        if let Some(rx) = outgoing_rx {
+            *self.outgoing_rx.lock().unwrap() = Some(rx);
+        }
+
         let _ = event_tx.send(ClientEvent::Disconnected);
         let _ = event_tx.send(ClientEvent::Log("Disconnected from server".into()));
         Ok(())
     }
 }
+
+/// 把服务端消息转发给上层处理（输入模拟、剪贴板等），启用抖动缓冲时运动先进入缓冲
+fn forward(msg: Message, jitter: &mut Option<JitterBuffer>, message_tx: &mpsc::UnboundedSender<Message>) {
+    match jitter.as_mut() {
+        Some(jitter) if JitterBuffer::is_motion(&msg) => {
+            jitter.push(msg, tokio::time::Instant::now());
+        }
+        Some(jitter) => {
+            // 非运动消息立即转发，先放出缓冲中的运动以保持顺序
+            for motion in jitter.drain() {
+                let _ = message_tx.send(motion);
+            }
+            let _ = message_tx.send(msg);
+        }
+        None => {
+            let _ = message_tx.send(msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::duplex_pair;
+
+    /// 运行一次会话，`server` 扮演服务端读取 Hello 后的行为
+    async fn session_with(
+        cancel: CancellationToken,
+        server: impl FnOnce(crate::transport::DuplexTransport) -> tokio::task::JoinHandle<()>,
+    ) -> SynapseResult<()> {
+        let (client_side, server_side) = duplex_pair(1 << 16);
+        let server = server(server_side);
+        let (message_tx, _message_rx) = mpsc::unbounded_channel();
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let result = Client::new("127.0.0.1:0")
+            .run_session(
+                client_side,
+                "client".into(),
+                "client".into(),
+                (1920, 1080),
+                message_tx,
+                event_tx,
+                cancel,
+            )
+            .await;
+        server.abort();
+        result
+    }
+
+    #[tokio::test]
+    async fn bye_before_welcome_is_an_auth_error() {
+        let result = session_with(CancellationToken::new(), |mut server| {
+            tokio::spawn(async move {
+                assert!(matches!(server.next().await, Some(Ok(Message::Hello { .. }))));
+                server.send(Message::Bye { device_id: DeviceId("server".into()) }).await.unwrap();
+                std::future::pending::<()>().await;
+            })
+        })
+        .await;
+        let err = result.unwrap_err();
+        assert!(matches!(err, SynapseError::Auth(_)), "{err:?}");
+        assert!(!err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn cancel_before_welcome_is_cancelled() {
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        let result = session_with(cancel, |mut server| {
+            tokio::spawn(async move {
+                assert!(matches!(server.next().await, Some(Ok(Message::Hello { .. }))));
+                trigger.cancel();
+                std::future::pending::<()>().await;
+            })
+        })
+        .await;
+        assert!(matches!(result, Err(SynapseError::Cancelled)), "{result:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_peer_times_out_handshake() {
+        // 对端保持连接但从不响应
+        let (client_side, _server_side) = duplex_pair(1 << 16);
+        let (message_tx, _message_rx) = mpsc::unbounded_channel();
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let started = tokio::time::Instant::now();
+        let result = Client::new("127.0.0.1:0")
+            .with_handshake_timeout(Duration::from_secs(5))
+            .run_session(
+                client_side,
+                "client".into(),
+                "client".into(),
+                (1920, 1080),
+                message_tx,
+                event_tx,
+                CancellationToken::new(),
+            )
+            .await;
+        let err = result.unwrap_err();
+        assert!(matches!(err, SynapseError::Timeout(timeout) if timeout == Duration::from_secs(5)), "{err:?}");
+        assert!(err.is_retryable());
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+        let logs: Vec<_> = std::iter::from_fn(|| event_rx.try_recv().ok()).collect();
+        assert!(logs.iter().any(|event| matches!(event, ClientEvent::Log(msg) if msg.contains("handshake"))));
+    }
+
+    #[tokio::test]
+    async fn probe_reports_server_info_and_leaves_no_peer() {
+        let screen = ScreenRect { x: 0, y: 0, width: 1920, height: 1080 };
+        let server = std::sync::Arc::new(crate::Server::new("127.0.0.1:0"));
+        let (_input_tx, input_rx) = mpsc::unbounded_channel();
+        let (_clipboard_tx, clipboard_rx) = mpsc::unbounded_channel();
+        let (action_tx, _action_rx) = mpsc::unbounded_channel();
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let cancel = CancellationToken::new();
+        let run = tokio::spawn({
+            let server = server.clone();
+            let cancel = cancel.clone();
+            async move {
+                server.run(input_rx, clipboard_rx, action_tx, event_tx, screen, Edge::Right, cancel).await
+            }
+        });
+
+        let scenario = async {
+            let addr = loop {
+                match server.local_addr() {
+                    Some(addr) => break addr,
+                    None => tokio::time::sleep(Duration::from_millis(5)).await,
+                }
+            };
+            let info = Client::probe(&addr.to_string(), Duration::from_secs(5)).await.unwrap();
+            let hostname = hostname::get().unwrap().to_string_lossy().to_string();
+            assert_eq!(info.device_name, hostname);
+            assert!(matches!(info.screens.as_slice(), [s] if (s.rect.width, s.rect.height) == (1920, 1080)));
+            // 未启用帧同步与 UDP 旁路的服务端不声明这两项
+            let mut expected = Capabilities::local();
+            expected.remove(Capability::FrameSync);
+            expected.remove(Capability::UdpMotion);
+            assert_eq!(info.capabilities, expected);
+
+            // 探测连接随 Bye 注销，不留下设备
+            loop {
+                if let crate::ServerEvent::DeviceDisconnected { device_id } = event_rx.recv().await.unwrap() {
+                    assert!(device_id.starts_with(PROBE_DEVICE_PREFIX));
+                    break;
+                }
+            }
+            assert!(server.peers_snapshot().await.is_empty());
+        };
+        tokio::time::timeout(Duration::from_secs(10), scenario).await.expect("scenario timed out");
+        cancel.cancel();
+        run.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rapid_clipboard_changes_send_only_the_latest() {
+        const WINDOW: Duration = Duration::from_millis(250);
+        let (client_side, mut server) = duplex_pair(1 << 16);
+        let client = Client::new("127.0.0.1:0").with_clipboard_debounce(WINDOW);
+        let clipboard = client.sender();
+        let (message_tx, _message_rx) = mpsc::unbounded_channel();
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let cancel = CancellationToken::new();
+        let session = tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                client
+                    .run_session(
+                        client_side,
+                        "client".into(),
+                        "client".into(),
+                        (1920, 1080),
+                        message_tx,
+                        event_tx,
+                        cancel,
+                    )
+                    .await
+            }
+        });
+
+        assert!(matches!(server.next().await, Some(Ok(Message::Hello { .. }))));
+        server
+            .send(Message::Welcome {
+                protocol_version: PROTOCOL_VERSION,
+                device_id: DeviceId("server".into()),
+                device_name: "server".into(),
+                screens: Vec::new(),
+                capabilities: Capabilities::local(),
+                compression: Compression::None,
+            })
+            .await
+            .unwrap();
+
+        // 每次变化都在窗口内，计时随之重新开始
+        for (i, text) in ["one", "two", "three"].into_iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(WINDOW / 2).await;
+            }
+            clipboard.send(Message::ClipboardText { text: text.into(), transfer_id: 0 }).unwrap();
+        }
+        let last_change = tokio::time::Instant::now();
+
+        let mut sent = Vec::new();
+        loop {
+            match server.next().await {
+                Some(Ok(Message::ClipboardText { text, .. })) => {
+                    sent.push(text);
+                    assert!(last_change.elapsed() >= WINDOW, "sent after {:?}", last_change.elapsed());
+                    cancel.cancel();
+                }
+                Some(Ok(Message::Bye { .. })) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => panic!("{e}"),
+            }
+        }
+        assert_eq!(sent, ["three"]);
+        session.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn other_protocol_version_is_rejected() {
+        assert!(check_version(PROTOCOL_VERSION).is_ok());
+        let err = check_version(PROTOCOL_VERSION + 1).unwrap_err();
+        assert!(matches!(
+            err,
+            SynapseError::ProtocolVersion { local: PROTOCOL_VERSION, remote } if remote == PROTOCOL_VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+use thiserror::Error;
+
+/// synapse-net 公共 API 返回的错误
+///
+/// 内部实现仍使用 anyhow，在 API 边界转换为可匹配的错误类型
+#[derive(Debug, Error)]
+pub enum SynapseError {
+    /// 网络 I/O 错误（连接被拒绝、断开、地址不可用等）
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// 连接在限定时间内未能建立
+    #[error("connection timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    /// 握手未能完成（对端提前关闭、收到非预期消息等）
+    #[error("handshake failed: {0}")]
+    Handshake(String),
+    /// 对端拒绝认证
+    #[error("authentication failed: {0}")]
+    Auth(String),
+    /// 双方协议版本不兼容
+    #[error("protocol version mismatch: local {local}, remote {remote}")]
+    ProtocolVersion { local: u32, remote: u32 },
+    /// 消息编解码失败
+    #[error("codec error: {0}")]
+    Codec(String),
+    /// 操作被取消
+    #[error("cancelled")]
+    Cancelled,
+    /// 服务端已在运行
+    #[error("server is already running")]
+    AlreadyRunning,
+    /// 服务端尚未运行
+    #[error("server is not running")]
+    NotRunning,
+    /// 本机屏幕尺寸无效（如无显示器或显示尚未就绪时为 0）
+    #[error("invalid local screen size {width}x{height}")]
+    InvalidScreen { width: u32, height: u32 },
+    /// 监听地址无效
+    #[error("invalid bind address {0:?}: expected host:port, e.g. 0.0.0.0:24800")]
+    InvalidAddress(String),
+    /// 指定设备未连接
+    #[error("device not connected: {0}")]
+    UnknownDevice(String),
+    /// 其他内部错误
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl SynapseError {
+    /// 将传输层（`Transport`）的错误区分为 I/O 错误与编解码错误
+    pub(crate) fn transport(e: anyhow::Error) -> Self {
+        match e.downcast::<std::io::Error>() {
+            Ok(io) => SynapseError::Io(io),
+            Err(e) => SynapseError::Codec(e.to_string()),
+        }
+    }
+
+    /// 是否值得重试（网络波动、连接超时、握手中断）；认证失败、版本不兼容等重试无意义
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            SynapseError::Io(_) | SynapseError::Timeout(_) | SynapseError::Handshake(_)
+        )
+    }
+}
+
+/// synapse-net 公共 API 的返回类型
+pub type SynapseResult<T> = std::result::Result<T, SynapseError>;
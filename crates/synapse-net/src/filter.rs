@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+
+use synapse_protocol::input::KeyCode;
+
+/// 针对单台设备的按键过滤：被屏蔽的键与组合键不转发给该设备
+///
+/// 通过 [`Server::set_key_filter`](crate::Server::set_key_filter) 设置。
+/// 按下被屏蔽时其释放同样不转发，已转发按下的键总会收到释放，不会造成按键卡住
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyFilter {
+    keys: HashSet<KeyCode>,
+    combos: Vec<Vec<KeyCode>>,
+}
+
+impl KeyFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从不转发该键（如 Meta 键，避免在受控机器上打开开始菜单）
+    pub fn with_key(mut self, key: KeyCode) -> Self {
+        self.keys.insert(key);
+        self
+    }
+
+    /// 不转发使组合键完整按下的那个键；组合中先按下的修饰键照常转发
+    pub fn with_combo(mut self, combo: Vec<KeyCode>) -> Self {
+        if !combo.is_empty() {
+            self.combos.push(combo);
+        }
+        self
+    }
+
+    /// 是否不屏蔽任何键
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.combos.is_empty()
+    }
+
+    /// 新按下 `key`（`pressed` 为包含它在内的当前按下的键）时是否屏蔽
+    pub(crate) fn blocks(&self, key: KeyCode, pressed: &HashSet<KeyCode>) -> bool {
+        self.keys.contains(&key)
+            || self.combos.iter().any(|combo| {
+                combo.contains(&key) && combo.iter().all(|k| *k == key || pressed.contains(k))
+            })
+    }
+}
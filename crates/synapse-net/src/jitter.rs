@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use synapse_protocol::Message;
+use tokio::time::Instant;
+
+/// 到达间隔估计的上下限
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+const MAX_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 到达间隔的 EWMA 平滑系数
+const INTERVAL_ALPHA: f64 = 0.125;
+
+/// 鼠标运动抖动缓冲
+///
+/// 仅缓冲运动类消息（移动/相对移动/滚轮），按平滑后的到达间隔匀速释放；
+/// `depth` 为目标缓冲的消息数，决定额外引入的延迟
+pub(crate) struct JitterBuffer {
+    depth: u32,
+    queue: VecDeque<(Instant, Message)>,
+    interval: Duration,
+    last_arrival: Option<Instant>,
+    last_release: Option<Instant>,
+}
+
+impl JitterBuffer {
+    pub(crate) fn new(depth: u32) -> Self {
+        Self {
+            depth,
+            queue: VecDeque::new(),
+            interval: Duration::from_millis(8),
+            last_arrival: None,
+            last_release: None,
+        }
+    }
+
+    /// 是否需要缓冲该消息
+    pub(crate) fn is_motion(msg: &Message) -> bool {
+        matches!(
+            msg,
+            Message::MouseMove { .. } | Message::MouseDelta { .. } | Message::MouseScroll { .. }
+        )
+    }
+
+    /// 放入一条运动消息并为其安排释放时间
+    pub(crate) fn push(&mut self, msg: Message, now: Instant) {
+        if let Some(last) = self.last_arrival {
+            let gap = now.saturating_duration_since(last).clamp(MIN_INTERVAL, MAX_INTERVAL);
+            let smoothed = self.interval.as_secs_f64() * (1.0 - INTERVAL_ALPHA)
+                + gap.as_secs_f64() * INTERVAL_ALPHA;
+            self.interval = Duration::from_secs_f64(smoothed);
+        }
+        self.last_arrival = Some(now);
+
+        // 目标释放时间：到达后延迟 depth 个间隔，且与上一条至少相隔一个间隔；
+        // 缓冲延迟不超过 2 * depth 个间隔，避免积压导致延迟持续增长
+        let target = now + self.interval * self.depth;
+        let spaced = self
+            .queue
+            .back()
+            .map(|(at, _)| *at)
+            .or(self.last_release)
+            .map(|prev| prev + self.interval)
+            .unwrap_or(target);
+        let release_at = target.max(spaced).min(now + self.interval * (2 * self.depth));
+        self.queue.push_back((release_at, msg));
+    }
+
+    /// 下一条消息的释放时间
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.queue.front().map(|(at, _)| *at)
+    }
+
+    /// 取出所有已到释放时间的消息
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Vec<Message> {
+        let mut due = Vec::new();
+        while let Some((at, _)) = self.queue.front() {
+            if *at > now {
+                break;
+            }
+            if let Some((at, msg)) = self.queue.pop_front() {
+                self.last_release = Some(at);
+                due.push(msg);
+            }
+        }
+        due
+    }
+
+    /// 立即取出全部缓冲消息（非运动消息到达前调用，以保持先后顺序）
+    pub(crate) fn drain(&mut self) -> Vec<Message> {
+        if let Some((at, _)) = self.queue.back() {
+            self.last_release = Some(*at);
+        }
+        self.queue.drain(..).map(|(_, msg)| msg).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(dx: f64) -> Message {
+        Message::MouseDelta { dx, dy: 0.0 }
+    }
+
+    fn dx(msgs: Vec<Message>) -> Vec<f64> {
+        msgs.into_iter()
+            .map(|msg| match msg {
+                Message::MouseDelta { dx, .. } => dx,
+                other => panic!("unexpected message: {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spreads_a_burst_over_smoothed_interval() {
+        let mut buffer = JitterBuffer::new(2);
+        let start = Instant::now();
+        // 同时到达的一串运动被拉开，按顺序逐条释放
+        for i in 0..3 {
+            buffer.push(delta(i as f64), start);
+        }
+        assert!(buffer.pop_due(start).is_empty());
+
+        let mut releases = Vec::new();
+        while let Some(at) = buffer.next_deadline() {
+            releases.push(at);
+            assert_eq!(buffer.pop_due(at).len(), 1);
+        }
+        assert_eq!(releases.len(), 3);
+        assert!(releases.windows(2).all(|pair| pair[0] < pair[1]));
+        // 首条延迟 depth 个初始间隔，后续不超过 2 * depth 个间隔
+        assert_eq!(releases[0], start + Duration::from_millis(16));
+        assert!(releases[2] <= start + Duration::from_millis(32));
+    }
+
+    #[test]
+    fn drain_releases_everything_in_order() {
+        let mut buffer = JitterBuffer::new(3);
+        let start = Instant::now();
+        for i in 0..4 {
+            buffer.push(delta(i as f64), start + Duration::from_millis(i * 8));
+        }
+        assert_eq!(dx(buffer.drain()), [0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(buffer.next_deadline(), None);
+
+        // 排空后新到的运动不会早于上次释放
+        let last = start + Duration::from_millis(24);
+        buffer.push(delta(4.0), last);
+        assert!(buffer.next_deadline().unwrap() > last);
+    }
+
+    #[test]
+    fn buffers_only_motion() {
+        assert!(JitterBuffer::is_motion(&delta(1.0)));
+        assert!(JitterBuffer::is_motion(&Message::MouseMove { x: 1.0, y: 2.0 }));
+        assert!(!JitterBuffer::is_motion(&Message::LockScreen));
+    }
+}
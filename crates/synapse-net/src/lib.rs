@@ -1,18 +1,81 @@
+#[cfg(feature = "simulate")]
+pub mod apply;
 pub mod client;
 pub mod discovery;
+pub mod error;
+pub mod filter;
+mod jitter;
+pub mod metrics;
+pub mod pairing;
+pub mod queue;
+pub mod record;
+pub mod relay;
+mod sequence;
 pub mod server;
+mod throttle;
+pub mod transfer;
+pub mod transform;
+pub mod transport;
+mod udp;
 
-pub use client::Client;
+#[cfg(feature = "simulate")]
+pub use apply::{apply_local_action, ClipboardWriter, MessageApplier};
+pub use client::{Client, ServerInfo, DEFAULT_CLIPBOARD_DEBOUNCE};
 pub use discovery::Discovery;
-pub use server::Server;
+pub use error::{SynapseError, SynapseResult};
+pub use filter::KeyFilter;
+pub use metrics::Metrics;
+pub use queue::{Overflow, QueueLimit, QueuePolicy};
+pub use record::MessageRecorder;
+pub use relay::Relay;
+pub use sequence::Sequenced;
+pub use server::{
+    parse_bind_addr, ClipboardHistoryReader, ClipboardReader, DeviceLayout, DisconnectPolicy,
+    EdgeTrigger, LocalEcho, PasteStrategy, PeerSnapshot, ReplayDevice, ReplayOutcome, Server, ServerConfig,
+    ServerControl, DEFAULT_BIND,
+    DEFAULT_PORT, DEFAULT_RETURN_COOLDOWN, DEFAULT_SWITCH_COOLDOWN,
+};
+pub use transfer::{TransferKind, TransferState, TransferStatus};
+pub use transform::{InputTransform, Rotation};
+pub use transport::{duplex_pair, DuplexTransport, Transport};
 
 use serde::{Deserialize, Serialize};
+use synapse_protocol::input::{ButtonAction, KeyAction, KeyCode, MouseButton};
+use synapse_protocol::screen::Edge;
 
 /// Server 端需要在本地执行的动作
 #[derive(Debug, Clone)]
 pub enum LocalAction {
     /// 将鼠标移动到指定绝对坐标（用于焦点在远程时锁定鼠标到屏幕中心）
     MoveMouse(i32, i32),
+    /// 将鼠标相对当前位置移动
+    MoveMouseRelative(i32, i32),
+    /// 在本机以键入方式输入文本
+    TypeText(String),
+    /// 隐藏本地光标（焦点进入远程）
+    HideCursor,
+    /// 恢复显示本地光标（焦点回到本地）
+    ShowCursor,
+    /// 锁定本地指针，光标不动、本机程序收不到鼠标事件（相对运动模式下焦点进入远程）
+    LockPointer,
+    /// 解除本地指针锁定（焦点回到本地）
+    UnlockPointer,
+    /// 锁定本机屏幕
+    LockScreen,
+    /// 在本机模拟按键（本地回显，见 [`LocalEcho`]）
+    Key(KeyCode, KeyAction),
+    /// 在本机模拟鼠标按键（本地回显）
+    MouseButton(MouseButton, ButtonAction),
+    /// 在本机滚动，滚动量以本机捕获的单位计（[`ServerConfig::scroll_unit`](crate::ServerConfig)）
+    Scroll { dx: f64, dy: f64, precise: bool },
+}
+
+/// 焦点所在远程设备的信息（握手时协商的主屏尺寸）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FocusTarget {
+    pub device_name: String,
+    pub width: u32,
+    pub height: u32,
 }
 
 /// 服务端产生的事件，用于通知上层（GUI/CLI）
@@ -31,6 +94,59 @@ pub enum ServerEvent {
     FocusChanged {
         target: String,
     },
+    /// 焦点切换详情，与 `FocusChanged` 同时发送
+    FocusChangedDetailed {
+        /// 切换前的焦点（设备 ID 或 "local"）
+        from: String,
+        /// 切换后的焦点（设备 ID 或 "local"）
+        to: String,
+        /// 触发切换的本地屏幕边缘；设备断开等非边缘触发时为 `None`
+        edge: Option<Edge>,
+        /// 切换到的远程设备的名称与屏幕尺寸；回到本地时为 `None`
+        target: Option<FocusTarget>,
+    },
+    /// 未知设备请求接入，需通过 [`ServerControl::ApprovePairing`] 或
+    /// [`ServerControl::RejectPairing`] 答复
+    PairingRequest {
+        device_id: String,
+        device_name: String,
+        /// 设备配对密钥的指纹，应与设备上显示的一致；设备未提供密钥时为 `None`
+        fingerprint: Option<String>,
+    },
+    /// 收到设备无法解码的数据（数据损坏或协议不兼容），该设备随即断开
+    DecodeError {
+        device_id: String,
+        error: String,
+    },
+    /// 鼠标越过屏幕边缘完成了一次焦点切换（进入远程或回到本地各一次），
+    /// 供界面提示音或闪烁等反馈；外部控制、设备断开等非越界切换不发送
+    EdgeCrossed {
+        /// 越过的本地屏幕边缘
+        edge: Edge,
+        /// 切换后的焦点（设备 ID 或 "local"）
+        target: String,
+    },
+    /// 设备的显示名称已通过 [`Server::set_device_label`](crate::Server::set_device_label) 更改
+    DeviceRenamed {
+        device_id: String,
+        device_name: String,
+    },
+    /// 剪贴板传输已被 [`Server::cancel_transfer`](crate::Server::cancel_transfer) 取消
+    TransferCancelled {
+        transfer_id: u64,
+        device_id: String,
+    },
+    /// 设备确认已应用剪贴板内容
+    ClipboardSynced {
+        device_id: String,
+        transfer_id: u64,
+    },
+    /// 设备所在的边缘被其他设备占用，设备改到剩余的边缘；`None` 表示设备已不在任何边缘，
+    /// 焦点无法再越界切换到它
+    DeviceEdgeChanged {
+        device_id: String,
+        edge: Option<Edge>,
+    },
     /// 日志消息
     Log(String),
 }
@@ -45,6 +161,16 @@ pub enum ClientEvent {
     },
     /// 与服务端断开
     Disconnected,
+    /// 输入模拟持续失败（可能是辅助功能权限被撤销或显示器断开）
+    SimulationFailing {
+        consecutive_failures: u32,
+        error: String,
+    },
+    /// 多次重试后仍无法创建输入模拟器（如显示服务未就绪），本次会话不会模拟任何输入
+    SimulatorUnavailable {
+        attempts: u32,
+        error: String,
+    },
     /// 日志消息
     Log(String),
 }
@@ -0,0 +1,270 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 服务端运行指标，供健康检查与监控抓取
+#[derive(Debug)]
+pub struct Metrics {
+    running: AtomicBool,
+    devices: Mutex<HashSet<String>>,
+    forwarded: AtomicU64,
+    /// 上一次计算速率时的 (时间, 转发计数)
+    last_sample: Mutex<(Instant, u64)>,
+    /// 各设备最近一次测得的往返时延
+    rtts: Mutex<HashMap<String, Duration>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            running: AtomicBool::new(false),
+            devices: Mutex::new(HashSet::new()),
+            forwarded: AtomicU64::new(0),
+            last_sample: Mutex::new((Instant::now(), 0)),
+            rtts: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Metrics {
+    /// 服务端主循环是否在运行
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// 标记主循环开始运行，返回的守卫被 drop 时（含出错提前返回）标记为停止
+    pub(crate) fn running_guard(self: &Arc<Self>) -> RunningGuard {
+        self.running.store(true, Ordering::Relaxed);
+        RunningGuard(self.clone())
+    }
+
+    pub(crate) fn device_connected(&self, device_id: &str) {
+        self.devices.lock().unwrap().insert(device_id.to_string());
+    }
+
+    pub(crate) fn device_disconnected(&self, device_id: &str) {
+        self.devices.lock().unwrap().remove(device_id);
+        self.rtts.lock().unwrap().remove(device_id);
+    }
+
+    pub(crate) fn record_rtt(&self, device_id: &str, rtt: Duration) {
+        self.rtts.lock().unwrap().insert(device_id.to_string(), rtt);
+    }
+
+    /// 设备最近一次测得的往返时延，尚未测得时为 `None`
+    pub fn device_rtt(&self, device_id: &str) -> Option<Duration> {
+        self.rtts.lock().unwrap().get(device_id).copied()
+    }
+
+    pub(crate) fn record_forwarded(&self) {
+        self.forwarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 当前连接的设备数
+    pub fn connected_devices(&self) -> usize {
+        self.devices.lock().unwrap().len()
+    }
+
+    /// 累计转发给客户端的消息数
+    pub fn messages_forwarded(&self) -> u64 {
+        self.forwarded.load(Ordering::Relaxed)
+    }
+
+    /// 自上次调用以来的平均转发速率（条/秒）
+    pub fn messages_per_second(&self) -> f64 {
+        let now = Instant::now();
+        let total = self.messages_forwarded();
+        let mut last = self.last_sample.lock().unwrap();
+        let elapsed = now.duration_since(last.0).as_secs_f64();
+        let rate = if elapsed > 0.0 { (total - last.1) as f64 / elapsed } else { 0.0 };
+        *last = (now, total);
+        rate
+    }
+
+    /// Prometheus 文本格式
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE synapse_up gauge");
+        let _ = writeln!(out, "synapse_up {}", self.is_running() as u8);
+        let _ = writeln!(out, "# TYPE synapse_connected_devices gauge");
+        let _ = writeln!(out, "synapse_connected_devices {}", self.connected_devices());
+        for device_id in self.devices.lock().unwrap().iter() {
+            let _ = writeln!(out, "synapse_device_connected{{device_id=\"{}\"}} 1", escape_label(device_id));
+        }
+        let _ = writeln!(out, "# TYPE synapse_messages_forwarded_total counter");
+        let _ = writeln!(out, "synapse_messages_forwarded_total {}", self.messages_forwarded());
+        let _ = writeln!(out, "# TYPE synapse_messages_per_second gauge");
+        let _ = writeln!(out, "synapse_messages_per_second {:.2}", self.messages_per_second());
+        let _ = writeln!(out, "# TYPE synapse_device_rtt_seconds gauge");
+        for (device_id, rtt) in self.rtts.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "synapse_device_rtt_seconds{{device_id=\"{}\"}} {:.6}",
+                escape_label(device_id),
+                rtt.as_secs_f64()
+            );
+        }
+        out
+    }
+}
+
+pub(crate) struct RunningGuard(Arc<Metrics>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// ── HTTP 端点 ──
+
+#[cfg(feature = "http-metrics")]
+pub use http::serve;
+
+#[cfg(feature = "http-metrics")]
+mod http {
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_util::sync::CancellationToken;
+    use tracing::{debug, info};
+
+    use super::Metrics;
+    use crate::error::SynapseResult;
+
+    /// 提供 `/health` 与 `/metrics` 的最小 HTTP 服务，直到 `cancel` 触发
+    ///
+    /// `/health` 在服务端主循环运行时返回 200，否则 503；`/metrics` 为 Prometheus 文本格式
+    pub async fn serve(addr: &str, metrics: Arc<Metrics>, cancel: CancellationToken) -> SynapseResult<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!(%addr, "metrics endpoint listening");
+        serve_on(listener, metrics, cancel).await
+    }
+
+    async fn serve_on(listener: TcpListener, metrics: Arc<Metrics>, cancel: CancellationToken) -> SynapseResult<()> {
+        loop {
+            let (stream, _) = tokio::select! {
+                _ = cancel.cancelled() => break,
+                result = listener.accept() => result?,
+            };
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                if let Err(e) = respond(stream, &metrics).await {
+                    debug!("metrics request failed: {e}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+        let (status, body) = match path {
+            "/health" if metrics.is_running() => ("200 OK", "ok\n".to_string()),
+            "/health" => ("503 Service Unavailable", "not running\n".to_string()),
+            "/metrics" => ("200 OK", metrics.render_prometheus()),
+            _ => ("404 Not Found", "not found\n".to_string()),
+        };
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use synapse_protocol::screen::{Edge, ScreenRect};
+        use tokio::sync::mpsc;
+
+        use super::*;
+        use crate::Server;
+
+        async fn get(addr: std::net::SocketAddr, path: &str) -> String {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        }
+
+        #[tokio::test]
+        async fn serves_health_and_metrics_of_running_server() {
+            let server = Arc::new(Server::new("127.0.0.1:0"));
+            let (_input_tx, input_rx) = mpsc::unbounded_channel();
+            let (_clipboard_tx, clipboard_rx) = mpsc::unbounded_channel();
+            let (action_tx, _action_rx) = mpsc::unbounded_channel();
+            let (event_tx, _event_rx) = mpsc::unbounded_channel();
+            let cancel = CancellationToken::new();
+            let screen = ScreenRect { x: 0, y: 0, width: 1920, height: 1080 };
+            let run = tokio::spawn({
+                let server = server.clone();
+                let cancel = cancel.clone();
+                async move {
+                    server.run(input_rx, clipboard_rx, action_tx, event_tx, screen, Edge::Right, cancel).await
+                }
+            });
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let http = tokio::spawn(serve_on(listener, server.metrics(), cancel.clone()));
+
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while !server.metrics().is_running() {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+                let health = get(addr, "/health").await;
+                assert!(health.starts_with("HTTP/1.1 200 OK\r\n"), "{health}");
+                assert!(health.ends_with("\r\n\r\nok\n"), "{health}");
+
+                let metrics = get(addr, "/metrics").await;
+                assert!(metrics.starts_with("HTTP/1.1 200 OK\r\n"), "{metrics}");
+                assert!(metrics.contains("\nsynapse_up 1\n"), "{metrics}");
+                assert!(metrics.contains("\nsynapse_connected_devices 0\n"), "{metrics}");
+
+                assert!(get(addr, "/nope").await.starts_with("HTTP/1.1 404"));
+            })
+            .await
+            .expect("metrics endpoint did not answer");
+
+            cancel.cancel();
+            run.await.unwrap().unwrap();
+            http.await.unwrap().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_rtt_per_device() {
+        let metrics = Metrics::default();
+        metrics.device_connected("laptop");
+        metrics.record_rtt("laptop", Duration::from_micros(1500));
+        assert_eq!(metrics.device_rtt("laptop"), Some(Duration::from_micros(1500)));
+
+        let rendered = metrics.render_prometheus();
+        assert!(rendered.contains("synapse_device_rtt_seconds{device_id=\"laptop\"} 0.001500"));
+        assert!(rendered.contains("synapse_device_connected{device_id=\"laptop\"} 1"));
+
+        metrics.device_disconnected("laptop");
+        assert_eq!(metrics.device_rtt("laptop"), None);
+        assert!(!metrics.render_prometheus().contains("synapse_device_rtt_seconds{"));
+    }
+}
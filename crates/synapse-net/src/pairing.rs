@@ -0,0 +1,37 @@
+use sha2::{Digest, Sha256};
+
+/// 生成新的配对密钥（32 字节随机数的十六进制），由设备保存并在每次 Hello 中发送
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).expect("system random source is unavailable");
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// 配对密钥的指纹，形如 `1a2b-3c4d-5e6f-7a8b`
+///
+/// 服务端只保存指纹；确认配对时双方界面都显示指纹，用户核对一致后再允许接入
+pub fn fingerprint(key: &str) -> String {
+    let digest = Sha256::digest(key.as_bytes());
+    digest[..8]
+        .chunks(2)
+        .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_stable_and_key_specific() {
+        let key = generate_key();
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, generate_key());
+
+        let print = fingerprint(&key);
+        assert_eq!(print, fingerprint(&key));
+        assert_eq!(print.len(), 19);
+        assert_ne!(print, fingerprint(&generate_key()));
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use synapse_protocol::{Message, Priority};
+use tokio::sync::Notify;
+use tracing::debug;
+
+use crate::transfer::{transfer_id, TransferMap};
+
+/// 队列满时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// 丢弃队列中最旧的同类消息（鼠标运动：只有最新位置有意义）
+    DropOldest,
+    /// 丢弃新到达的消息（剪贴板：保留已排队的传输）
+    DropNewest,
+    /// 不限制容量，从不丢弃（按键、按钮：丢失会导致按键卡住）
+    Unbounded,
+}
+
+/// 一类消息的队列上限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueLimit {
+    /// 该类消息最多排队的条数，`Overflow::Unbounded` 时忽略
+    pub capacity: usize,
+    pub overflow: Overflow,
+}
+
+/// 每个设备发送队列的背压策略，按消息类别分别限制
+///
+/// 鼠标运动与其他输入共用一个有序队列（保证移动与点击的先后顺序），只是各自计数；
+/// 剪贴板等大块数据在单独的低优先级队列中，高优先级队列空闲时才发送
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuePolicy {
+    /// 鼠标运动（[`Message::MouseMove`]、[`Message::MouseDelta`]）
+    pub motion: QueueLimit,
+    /// 其他高优先级消息（按键、按钮、滚动、焦点切换等）
+    pub input: QueueLimit,
+    /// 低优先级消息（剪贴板）
+    pub bulk: QueueLimit,
+}
+
+impl Default for QueuePolicy {
+    fn default() -> Self {
+        Self {
+            motion: QueueLimit { capacity: 256, overflow: Overflow::DropOldest },
+            input: QueueLimit { capacity: usize::MAX, overflow: Overflow::Unbounded },
+            bulk: QueueLimit { capacity: 16, overflow: Overflow::DropNewest },
+        }
+    }
+}
+
+fn is_motion(msg: &Message) -> bool {
+    matches!(msg, Message::MouseMove { .. } | Message::MouseDelta { .. })
+}
+
+#[derive(Default)]
+struct Queues {
+    high: VecDeque<Message>,
+    /// `high` 中鼠标运动消息的条数
+    motion: usize,
+    bulk: VecDeque<Message>,
+    closed: bool,
+}
+
+/// 按 [`QueuePolicy`] 限制的设备发送队列，替代无界 channel
+pub(crate) struct PeerQueue {
+    policy: QueuePolicy,
+    device_id: String,
+    /// 剪贴板消息入队时在此登记，被丢弃时注销
+    transfers: Arc<TransferMap>,
+    queues: Mutex<Queues>,
+    high_ready: Notify,
+    bulk_ready: Notify,
+}
+
+impl PeerQueue {
+    pub(crate) fn new(policy: QueuePolicy, device_id: String, transfers: Arc<TransferMap>) -> Self {
+        Self {
+            policy,
+            device_id,
+            transfers,
+            queues: Mutex::new(Queues::default()),
+            high_ready: Notify::new(),
+            bulk_ready: Notify::new(),
+        }
+    }
+
+    /// 按策略入队；队列已关闭（连接已结束）时返回 `false`，因策略丢弃消息仍返回 `true`
+    pub(crate) fn push(&self, msg: Message) -> bool {
+        let mut queues = self.queues.lock().unwrap();
+        if queues.closed {
+            return false;
+        }
+        match msg.priority() {
+            Priority::High => {
+                let motion = is_motion(&msg);
+                let limit = if motion { self.policy.motion } else { self.policy.input };
+                let queued = if motion {
+                    queues.motion
+                } else {
+                    queues.high.len() - queues.motion
+                };
+                if limit.overflow != Overflow::Unbounded && queued >= limit.capacity {
+                    match limit.overflow {
+                        Overflow::DropNewest => {
+                            debug!(?msg, "send queue full, dropping message");
+                            return true;
+                        }
+                        _ => {
+                            let oldest = queues.high.iter().position(|m| is_motion(m) == motion);
+                            if let Some(index) = oldest {
+                                queues.high.remove(index);
+                                if motion {
+                                    queues.motion -= 1;
+                                }
+                            }
+                        }
+                    }
+                }
+                if motion {
+                    queues.motion += 1;
+                }
+                queues.high.push_back(msg);
+                self.high_ready.notify_one();
+            }
+            Priority::Low => {
+                let limit = self.policy.bulk;
+                if limit.overflow != Overflow::Unbounded && queues.bulk.len() >= limit.capacity {
+                    match limit.overflow {
+                        Overflow::DropNewest => {
+                            debug!("bulk send queue full, dropping message");
+                            return true;
+                        }
+                        _ => {
+                            if let Some(id) = queues.bulk.pop_front().as_ref().and_then(transfer_id) {
+                                self.transfers.remove(id);
+                            }
+                        }
+                    }
+                }
+                let msg = self.transfers.register(&self.device_id, msg);
+                queues.bulk.push_back(msg);
+                self.bulk_ready.notify_one();
+            }
+        }
+        true
+    }
+
+    /// 将剪贴板消息放入高优先级队列末尾，使其先于之后入队的输入与焦点切换消息发送
+    ///
+    /// 不受容量限制，仍登记为传输以便确认
+    pub(crate) fn push_ahead(&self, msg: Message) -> bool {
+        let mut queues = self.queues.lock().unwrap();
+        if queues.closed {
+            return false;
+        }
+        let msg = self.transfers.register(&self.device_id, msg);
+        queues.high.push_back(msg);
+        self.high_ready.notify_one();
+        true
+    }
+
+    /// 取出一条高优先级消息，队列为空时返回 `None`
+    pub(crate) fn try_recv_high(&self) -> Option<Message> {
+        let mut queues = self.queues.lock().unwrap();
+        let msg = queues.high.pop_front()?;
+        if is_motion(&msg) {
+            queues.motion -= 1;
+        }
+        Some(msg)
+    }
+
+    /// 取出一条低优先级消息，队列为空时返回 `None`
+    pub(crate) fn try_recv_bulk(&self) -> Option<Message> {
+        self.queues.lock().unwrap().bulk.pop_front()
+    }
+
+    /// 从低优先级队列中移除尚未发送的剪贴板传输，已发送或不存在时返回 `false`
+    pub(crate) fn remove_bulk(&self, id: u64) -> bool {
+        let mut queues = self.queues.lock().unwrap();
+        match queues.bulk.iter().position(|msg| transfer_id(msg) == Some(id)) {
+            Some(index) => {
+                queues.bulk.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 等待下一条高优先级消息；队列关闭后返回 `None`
+    pub(crate) async fn recv_high(&self) -> Option<Message> {
+        loop {
+            if let Some(msg) = self.try_recv_high() {
+                return Some(msg);
+            }
+            if self.queues.lock().unwrap().closed {
+                return None;
+            }
+            self.high_ready.notified().await;
+        }
+    }
+
+    /// 等待下一条低优先级消息；队列关闭后返回 `None`
+    pub(crate) async fn recv_bulk(&self) -> Option<Message> {
+        loop {
+            {
+                let mut queues = self.queues.lock().unwrap();
+                if let Some(msg) = queues.bulk.pop_front() {
+                    return Some(msg);
+                }
+                if queues.closed {
+                    return None;
+                }
+            }
+            self.bulk_ready.notified().await;
+        }
+    }
+
+    /// 连接结束或开始排空：之后的入队返回 `false`；已排队的消息仍可取出，取完后接收返回 `None`
+    pub(crate) fn close(&self) {
+        self.queues.lock().unwrap().closed = true;
+        // 每个队列只有一个接收方，notify_one 在接收方尚未等待时也会保留通知
+        self.high_ready.notify_one();
+        self.bulk_ready.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use synapse_protocol::input::{KeyAction, KeyCode};
+
+    use super::*;
+
+    fn queue(policy: QueuePolicy) -> PeerQueue {
+        PeerQueue::new(policy, "laptop".into(), Arc::new(TransferMap::default()))
+    }
+
+    fn motion(x: f64) -> Message {
+        Message::MouseMove { x, y: 0.0 }
+    }
+
+    fn key(key: KeyCode) -> Message {
+        Message::KeyEvent { key, action: KeyAction::Press }
+    }
+
+    fn clipboard(text: &str) -> Message {
+        Message::ClipboardText { text: text.into(), transfer_id: 0 }
+    }
+
+    fn drain_high(queue: &PeerQueue) -> Vec<Message> {
+        std::iter::from_fn(|| queue.try_recv_high()).collect()
+    }
+
+    #[test]
+    fn keystroke_gets_through_a_full_motion_queue() {
+        let queue = queue(QueuePolicy::default());
+        for x in 0..300 {
+            assert!(queue.push(motion(x as f64)));
+        }
+        queue.push(key(KeyCode::KeyA));
+
+        let sent = drain_high(&queue);
+        // 默认保留最新的 256 条运动，按键不受影响且排在其后
+        assert_eq!(sent.len(), 257);
+        assert!(matches!(sent[0], Message::MouseMove { x, .. } if x == 44.0));
+        assert!(matches!(sent[255], Message::MouseMove { x, .. } if x == 299.0));
+        assert!(matches!(sent[256], Message::KeyEvent { key: KeyCode::KeyA, .. }));
+    }
+
+    #[test]
+    fn keys_are_never_dropped() {
+        let queue = queue(QueuePolicy::default());
+        for _ in 0..10_000 {
+            queue.push(key(KeyCode::KeyB));
+        }
+        assert_eq!(drain_high(&queue).len(), 10_000);
+    }
+
+    #[test]
+    fn full_clipboard_queue_keeps_queued_transfers() {
+        let transfers = Arc::new(TransferMap::default());
+        let queue = PeerQueue::new(QueuePolicy::default(), "laptop".into(), transfers.clone());
+        for i in 0..20 {
+            queue.push(clipboard(&i.to_string()));
+        }
+        // 默认容量 16，之后到达的被丢弃且不登记为传输
+        let queued: Vec<_> = std::iter::from_fn(|| queue.try_recv_bulk()).collect();
+        assert_eq!(queued.len(), 16);
+        assert!(matches!(&queued[15], Message::ClipboardText { text, .. } if text == "15"));
+        assert_eq!(transfers.list().len(), 16);
+    }
+
+    #[test]
+    fn drop_oldest_clipboard_forgets_evicted_transfer() {
+        let transfers = Arc::new(TransferMap::default());
+        let policy = QueuePolicy {
+            bulk: QueueLimit { capacity: 2, overflow: Overflow::DropOldest },
+            ..QueuePolicy::default()
+        };
+        let queue = PeerQueue::new(policy, "laptop".into(), transfers.clone());
+        for text in ["a", "b", "c"] {
+            queue.push(clipboard(text));
+        }
+        let queued: Vec<_> = std::iter::from_fn(|| queue.try_recv_bulk()).collect();
+        assert!(matches!(queued.as_slice(), [
+            Message::ClipboardText { text: b, .. },
+            Message::ClipboardText { text: c, .. },
+        ] if b == "b" && c == "c"));
+        assert_eq!(transfers.list().len(), 2);
+    }
+
+    #[test]
+    fn closed_queue_rejects_new_messages_but_drains() {
+        let queue = queue(QueuePolicy::default());
+        queue.push(key(KeyCode::KeyC));
+        queue.close();
+        assert!(!queue.push(key(KeyCode::KeyD)));
+        assert_eq!(drain_high(&queue).len(), 1);
+    }
+}
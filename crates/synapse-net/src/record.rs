@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bytes::{BufMut, BytesMut};
+use synapse_protocol::{Message, MessageCodec};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_util::codec::{Decoder, Encoder};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::error::{SynapseError, SynapseResult};
+use crate::sequence::Sequenced;
+
+/// 录制文件的刷盘间隔，进程被强制结束时最多丢失这段时间内的记录
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 输入消息录制器，用于把现场的焦点问题转成可复现的用例
+///
+/// 文件由连续的记录组成，每条记录为 `[u64 BE 距录制开始的微秒数][MessageCodec 帧]`
+pub struct MessageRecorder {
+    path: PathBuf,
+}
+
+impl MessageRecorder {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// 接管输入 channel：每条消息写入录制文件后原样转发（保留采集序号）
+    ///
+    /// 返回的接收端替代原接收端传给 [`Server::run`](crate::Server::run)。
+    /// 文件定期刷盘，`cancel` 触发或输入 channel 关闭时刷盘并停止
+    pub async fn tap(
+        self,
+        mut input_rx: mpsc::UnboundedReceiver<Sequenced>,
+        cancel: CancellationToken,
+    ) -> SynapseResult<mpsc::UnboundedReceiver<Sequenced>> {
+        let mut writer = BufWriter::new(File::create(&self.path).await?);
+        info!(path = %self.path.display(), "recording input messages");
+        let (tx, rx) = mpsc::unbounded_channel();
+        let start = Instant::now();
+
+        tokio::spawn(async move {
+            let mut codec = MessageCodec::new();
+            let mut buf = BytesMut::new();
+            let mut recording = true;
+            let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                let item = tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = flush.tick() => {
+                        if recording {
+                            if let Err(e) = writer.flush().await {
+                                warn!("input recording stopped: {e}");
+                                recording = false;
+                            }
+                        }
+                        continue;
+                    }
+                    item = input_rx.recv() => match item {
+                        Some(item) => item,
+                        None => break,
+                    },
+                };
+                if recording {
+                    buf.clear();
+                    buf.put_u64(start.elapsed().as_micros() as u64);
+                    let written = match codec.encode(item.msg.clone(), &mut buf) {
+                        Ok(()) => writer.write_all(&buf).await.map_err(anyhow::Error::from),
+                        Err(e) => Err(e),
+                    };
+                    // 写入失败只停止录制，不影响输入转发
+                    if let Err(e) = written {
+                        warn!("input recording stopped: {e}");
+                        recording = false;
+                    }
+                }
+                if tx.send(item).is_err() {
+                    break;
+                }
+            }
+            let _ = writer.flush().await;
+        });
+        Ok(rx)
+    }
+}
+
+/// 读取录制文件中的下一条记录，文件结束时返回 `None`
+async fn read_record(
+    reader: &mut (impl AsyncRead + Unpin),
+    codec: &mut MessageCodec,
+) -> SynapseResult<Option<(Duration, Message)>> {
+    let offset = match reader.read_u64().await {
+        Ok(offset) => offset,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let len = reader.read_u32().await?;
+    let mut frame = BytesMut::with_capacity(4 + len as usize);
+    frame.put_u32(len);
+    frame.resize(4 + len as usize, 0);
+    reader.read_exact(&mut frame[4..]).await?;
+    let msg = codec
+        .decode(&mut frame)
+        .map_err(SynapseError::transport)?
+        .ok_or_else(|| SynapseError::Codec("truncated recording".into()))?;
+    Ok(Some((Duration::from_micros(offset), msg)))
+}
+
+/// 读出录制文件中的全部记录（距录制开始的时间, 消息），供
+/// [`replay_focus`](crate::server::replay_focus) 离线回放
+pub async fn read_recording(path: impl AsRef<Path>) -> SynapseResult<Vec<(Duration, Message)>> {
+    let mut reader = BufReader::new(File::open(path.as_ref()).await?);
+    let mut codec = MessageCodec::new();
+    let mut records = Vec::new();
+    while let Some(record) = read_record(&mut reader, &mut codec).await? {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// 将录制文件中的消息按顺序送入 `input_tx`，返回回放的消息数
+///
+/// `realtime` 为 `true` 时按录制时的时间间隔送入，停留触发、切换冷却等依赖时间的逻辑才能复现
+pub async fn replay(
+    path: impl AsRef<Path>,
+    input_tx: &mpsc::UnboundedSender<Sequenced>,
+    realtime: bool,
+) -> SynapseResult<usize> {
+    let path = path.as_ref();
+    let mut reader = BufReader::new(File::open(path).await?);
+    info!(path = %path.display(), realtime, "replaying recorded input");
+    let mut codec = MessageCodec::new();
+    let start = Instant::now();
+    let mut count = 0;
+
+    while let Some((offset, msg)) = read_record(&mut reader, &mut codec).await? {
+        if realtime {
+            tokio::time::sleep_until(start + offset).await;
+        }
+        if input_tx.send(Sequenced::new(msg)).is_err() {
+            break;
+        }
+        count += 1;
+    }
+    debug!(count, "replay finished");
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{replay_focus, ReplayDevice};
+    use crate::ServerConfig;
+    use synapse_protocol::screen::{Edge, ScreenRect};
+
+    #[tokio::test]
+    async fn recorded_crossing_replays_deterministically() {
+        let path = std::env::temp_dir().join(format!("synapse-record-{}.bin", std::process::id()));
+        let (input_tx, input_rx) = mpsc::unbounded_channel();
+        let cancel = CancellationToken::new();
+        let mut forwarded = MessageRecorder::new(&path).tap(input_rx, cancel.clone()).await.unwrap();
+        for (x, y) in [(1000.0, 540.0), (1919.0, 540.0), (1919.0, 540.0)] {
+            input_tx.send(Message::MouseMove { x, y }.into()).unwrap();
+            forwarded.recv().await.unwrap();
+        }
+        // 取消后录制任务刷盘退出，转发端随之关闭
+        cancel.cancel();
+        assert!(forwarded.recv().await.is_none());
+        let records = read_recording(&path).await.unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(records.len(), 3);
+
+        tokio::time::pause();
+        let screen = ScreenRect { x: 0, y: 0, width: 1920, height: 1080 };
+        let devices = [ReplayDevice { device_id: "laptop".into(), edge: Edge::Right, width: 1280, height: 720 }];
+        let first = replay_focus(records.clone(), screen, ServerConfig::default(), &devices).await;
+        let second = replay_focus(records, screen, ServerConfig::default(), &devices).await;
+        assert!(first.sent.iter().any(|(id, msg)| id == "laptop" && matches!(msg, Message::EnterScreen { .. })));
+        assert_eq!(format!("{first:?}"), format!("{second:?}"));
+    }
+}
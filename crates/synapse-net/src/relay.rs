@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::{SinkExt, StreamExt};
+use synapse_protocol::{Message, MessageCodec};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::error::{SynapseError, SynapseResult};
+use crate::server::{classify_accept_error, AcceptErrorKind, ACCEPT_BACKOFF, ACCEPT_ERROR_BACKOFF};
+
+type RelayStream = Framed<TcpStream, MessageCodec>;
+
+/// 等待配对的连接：会话码 → (先到达的一端, 到达时间)
+type WaitingMap = Arc<Mutex<HashMap<String, (RelayStream, Instant)>>>;
+
+/// 默认的等待时限，超时未配对的连接被关闭
+pub const DEFAULT_WAITING_TTL: Duration = Duration::from_secs(300);
+
+/// 中继服务器，用于双方都无法直连（如分处不同 NAT 之后）的场景
+///
+/// Server 与 Client 都主动连接中继，首帧发送 [`Message::RelayJoin`]；
+/// 会话码相同的两条连接配对后，中继在二者之间原样转发消息
+pub struct Relay {
+    addr: String,
+    waiting_ttl: Duration,
+}
+
+impl Relay {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self { addr: addr.into(), waiting_ttl: DEFAULT_WAITING_TTL }
+    }
+
+    /// 等待配对的时限，超时的连接被关闭（按时限的一半定期检查，实际关闭最多晚半个时限）
+    pub fn with_waiting_ttl(mut self, ttl: Duration) -> Self {
+        self.waiting_ttl = ttl;
+        self
+    }
+
+    /// 运行中继，直到 `cancel` 触发
+    pub async fn run(&self, cancel: CancellationToken) -> SynapseResult<()> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        info!(addr = %self.addr, "relay listening");
+        self.serve(listener, cancel).await
+    }
+
+    /// 监听 socket 本身不可用时返回错误，其他 accept 错误只记录并重试
+    async fn serve(&self, listener: TcpListener, cancel: CancellationToken) -> SynapseResult<()> {
+        let waiting: WaitingMap = Arc::new(Mutex::new(HashMap::new()));
+        let mut sweep = tokio::time::interval((self.waiting_ttl / 2).max(Duration::from_millis(10)));
+
+        loop {
+            let result = tokio::select! {
+                _ = cancel.cancelled() => {
+                    info!("relay shutting down");
+                    break;
+                }
+                _ = sweep.tick() => {
+                    let ttl = self.waiting_ttl;
+                    waiting.lock().await.retain(|session, (_, since)| {
+                        let alive = since.elapsed() < ttl;
+                        if !alive {
+                            info!(%session, "relay session expired without a partner");
+                        }
+                        alive
+                    });
+                    continue;
+                }
+                result = listener.accept() => result,
+            };
+            // accept 失败只记录并重试，不影响已配对的会话；监听 socket 本身不可用时停止
+            let (stream, peer_addr) = match result {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    let delay = match classify_accept_error(&e) {
+                        AcceptErrorKind::Connection => {
+                            debug!("relay connection failed during accept: {e}");
+                            continue;
+                        }
+                        AcceptErrorKind::Resources => {
+                            warn!("relay accept failed, retrying in {:?}: {e}", ACCEPT_BACKOFF);
+                            ACCEPT_BACKOFF
+                        }
+                        AcceptErrorKind::Other => {
+                            error!("relay accept failed, retrying in {:?}: {e}", ACCEPT_ERROR_BACKOFF);
+                            ACCEPT_ERROR_BACKOFF
+                        }
+                        AcceptErrorKind::Fatal => {
+                            error!("relay listener failed: {e}");
+                            return Err(e.into());
+                        }
+                    };
+                    tokio::select! {
+                        _ = cancel.cancelled() => {}
+                        _ = tokio::time::sleep(delay) => {}
+                    }
+                    continue;
+                }
+            };
+            debug!(%peer_addr, "relay connection");
+
+            let waiting = waiting.clone();
+            let cancel = cancel.clone();
+            tokio::spawn(async move {
+                let framed = Framed::new(stream, MessageCodec::new());
+                if let Err(e) = handle_join(framed, waiting, cancel).await {
+                    warn!(%peer_addr, "relay connection error: {e}");
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 读取会话码并配对；配对成功则转发，否则进入等待
+async fn handle_join(mut framed: RelayStream, waiting: WaitingMap, cancel: CancellationToken) -> Result<()> {
+    let session = match framed.next().await {
+        Some(Ok(Message::RelayJoin { session })) => session,
+        Some(Ok(msg)) => anyhow::bail!("expected RelayJoin, got {:?}", msg),
+        Some(Err(e)) => return Err(e),
+        None => return Ok(()),
+    };
+
+    let partner = {
+        let mut waiting = waiting.lock().await;
+        match waiting.remove(&session) {
+            Some((partner, _)) => partner,
+            None => {
+                info!(%session, "relay session waiting for partner");
+                waiting.insert(session, (framed, Instant::now()));
+                return Ok(());
+            }
+        }
+    };
+
+    info!(%session, "relay session paired");
+    forward(partner, framed, cancel).await;
+    info!(%session, "relay session closed");
+    Ok(())
+}
+
+/// 在两条连接之间双向转发消息，任一端关闭即结束
+async fn forward(a: RelayStream, b: RelayStream, cancel: CancellationToken) {
+    let (mut a_tx, mut a_rx) = a.split();
+    let (mut b_tx, mut b_rx) = b.split();
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            msg = a_rx.next() => match msg {
+                Some(Ok(msg)) => {
+                    if b_tx.send(msg).await.is_err() {
+                        break;
+                    }
+                }
+                _ => break,
+            },
+            msg = b_rx.next() => match msg {
+                Some(Ok(msg)) => {
+                    if a_tx.send(msg).await.is_err() {
+                        break;
+                    }
+                }
+                _ => break,
+            },
+        }
+    }
+}
+
+/// 连接中继并加入会话，返回可直接用于握手的传输
+pub(crate) async fn join(relay_addr: &str, session: &str) -> SynapseResult<RelayStream> {
+    let stream = TcpStream::connect(relay_addr).await?;
+    let mut framed = Framed::new(stream, MessageCodec::new());
+    framed
+        .send(Message::RelayJoin { session: session.to_string() })
+        .await
+        .map_err(SynapseError::transport)?;
+    info!(relay = %relay_addr, %session, "joined relay session");
+    Ok(framed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unpaired_session_expires() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let relay = Relay::new(addr.clone()).with_waiting_ttl(Duration::from_millis(50));
+        let cancel = CancellationToken::new();
+
+        let scenario = async {
+            let mut lonely = join(&addr, "lonely").await.unwrap();
+            // 超时后中继关闭等待中的连接
+            let closed = tokio::time::timeout(Duration::from_secs(5), lonely.next()).await;
+            assert!(matches!(closed, Ok(None | Some(Err(_)))));
+
+            // 之后加入的会话仍能正常配对
+            let mut a = join(&addr, "pair").await.unwrap();
+            let mut b = join(&addr, "pair").await.unwrap();
+            a.send(Message::Ping(7)).await.unwrap();
+            assert!(matches!(b.next().await, Some(Ok(Message::Ping(7)))));
+            cancel.cancel();
+        };
+        let (served, ()) = tokio::join!(relay.serve(listener, cancel.clone()), scenario);
+        served.unwrap();
+    }
+
+    #[tokio::test]
+    async fn forwards_between_server_and_client() {
+        use synapse_protocol::screen::{Edge, ScreenRect};
+        use tokio::sync::mpsc;
+
+        use crate::{Client, ClientEvent, Sequenced, Server, ServerEvent};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay_addr = listener.local_addr().unwrap().to_string();
+        let relay = Relay::new(relay_addr.clone());
+        let cancel = CancellationToken::new();
+
+        let scenario = async {
+            let server = Arc::new(Server::new("127.0.0.1:0"));
+            let (input_tx, input_rx) = mpsc::unbounded_channel();
+            let (_clipboard_tx, clipboard_rx) = mpsc::unbounded_channel();
+            let (action_tx, _action_rx) = mpsc::unbounded_channel();
+            let (server_event_tx, mut server_event_rx) = mpsc::unbounded_channel();
+            let screen = ScreenRect { x: 0, y: 0, width: 1920, height: 1080 };
+            let server_run = tokio::spawn({
+                let server = server.clone();
+                let cancel = cancel.clone();
+                async move {
+                    server
+                        .run(input_rx, clipboard_rx, action_tx, server_event_tx, screen, Edge::Right, cancel)
+                        .await
+                }
+            });
+            server.connect_relay(&relay_addr, "wan").await.unwrap();
+
+            let client = Client::new(relay_addr.clone()).with_relay_session("wan");
+            let (message_tx, mut message_rx) = mpsc::unbounded_channel();
+            let (client_event_tx, mut client_event_rx) = mpsc::unbounded_channel();
+            let client_run = tokio::spawn({
+                let cancel = cancel.clone();
+                async move {
+                    client
+                        .connect("laptop".into(), "Laptop".into(), (1280, 720), message_tx, client_event_tx, cancel)
+                        .await
+                }
+            });
+
+            // 握手经中继完成
+            while !matches!(client_event_rx.recv().await, Some(ClientEvent::Connected { .. })) {}
+            while !matches!(server_event_rx.recv().await, Some(ServerEvent::DeviceConnected { .. })) {}
+
+            // 推过右边缘后，焦点切换经中继送达客户端
+            for msg in [Message::MouseMove { x: 1000.0, y: 540.0 }, Message::MouseMove { x: 1919.0, y: 540.0 }] {
+                input_tx.send(Sequenced::new(msg)).unwrap();
+            }
+            loop {
+                match message_rx.recv().await {
+                    Some(Message::EnterScreen { .. }) => break,
+                    Some(_) => {}
+                    None => panic!("client stopped before focus arrived"),
+                }
+            }
+
+            cancel.cancel();
+            client_run.await.unwrap().unwrap();
+            server_run.await.unwrap().unwrap();
+        };
+        let (served, ()) = tokio::time::timeout(Duration::from_secs(10), async {
+            tokio::join!(relay.serve(listener, cancel.clone()), scenario)
+        })
+        .await
+        .expect("relay scenario timed out");
+        served.unwrap();
+    }
+}
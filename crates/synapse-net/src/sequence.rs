@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use synapse_protocol::Message;
+
+/// 采集序号分配器（进程内单调递增，跨输入与剪贴板通道共享）
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// 带采集序号的本地消息
+///
+/// 输入与剪贴板经由不同通道到达服务端，序号用于在开启
+/// [`ServerConfig::ordered_input`](crate::ServerConfig::ordered_input) 时按采集顺序合并两个通道
+#[derive(Debug, Clone)]
+pub struct Sequenced {
+    pub seq: u64,
+    pub msg: Message,
+}
+
+impl Sequenced {
+    /// 以下一个序号标记消息，应在采集时立即调用
+    pub fn new(msg: Message) -> Self {
+        Self { seq: Self::reserve(), msg }
+    }
+
+    /// 预先分配一个序号，用于采集后还需耗时转换才能得到消息的内容（如剪贴板图片编码）
+    pub fn reserve() -> u64 {
+        NEXT_SEQ.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// 以 [`Sequenced::reserve`] 预先分配的序号标记消息
+    pub fn with_seq(seq: u64, msg: Message) -> Self {
+        Self { seq, msg }
+    }
+}
+
+impl From<Message> for Sequenced {
+    fn from(msg: Message) -> Self {
+        Sequenced::new(msg)
+    }
+}
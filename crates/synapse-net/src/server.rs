@@ -1,33 +1,457 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
 use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use synapse_protocol::image;
+use synapse_protocol::input::{
+    pinch_lines, ButtonAction, GestureKind, KeyAction, KeyCode, MouseButton, ScrollUnit,
+};
 use synapse_protocol::screen::{Edge, ScreenId, ScreenInfo, ScreenPosition, ScreenRect};
-use synapse_protocol::{Message, MessageCodec};
-use tokio::net::TcpListener;
-use tokio::sync::{mpsc, RwLock};
+use synapse_protocol::{
+    encoded_len, Capabilities, Capability, CodecError, Compression, CompressionHandle, Message,
+    MessageCodec, Priority, MAX_FRAME_SIZE, PROTOCOL_VERSION,
+};
+use tokio::net::{TcpListener, TcpSocket};
+use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::time::Instant;
 use tokio_util::codec::Framed;
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info, warn};
+use tokio_util::task::TaskTracker;
+use tracing::{debug, error, info, warn};
 
-use crate::{LocalAction, ServerEvent};
+use crate::error::{SynapseError, SynapseResult};
+use crate::filter::KeyFilter;
+use crate::metrics::Metrics;
+use crate::queue::{PeerQueue, QueuePolicy};
+use crate::sequence::Sequenced;
+use crate::throttle;
+use crate::transfer::{transfer_id, TransferMap, TransferState, TransferStatus};
+use crate::transform::InputTransform;
+use crate::transport::Transport;
+use crate::udp::{self, UdpMotion, UdpPath};
+use crate::{FocusTarget, LocalAction, ServerEvent};
 
 type PeerMap = Arc<RwLock<HashMap<String, PeerInfo>>>;
 
+/// 读取本机当前剪贴板并转换为协议消息，剪贴板为空时返回 `None`
+pub type ClipboardReader = Arc<dyn Fn() -> Option<Message> + Send + Sync>;
+
+/// 读取本机剪贴板历史（从新到旧的剪贴板消息），用于回复 [`Message::ClipboardHistoryRequest`]
+pub type ClipboardHistoryReader = Arc<dyn Fn() -> Vec<Message> + Send + Sync>;
+
 struct PeerInfo {
-    tx: mpsc::UnboundedSender<Message>,
-    #[allow(dead_code)]
+    /// 发送队列（高优先级的输入、焦点切换与低优先级的剪贴板）
+    queue: Arc<PeerQueue>,
     screen_w: u32,
-    #[allow(dead_code)]
     screen_h: u32,
+    device_name: String,
+    /// 服务端为设备设置的显示名称，覆盖 `device_name`
+    label: Option<String>,
+    /// 设备所在的本地屏幕边缘；仅剪贴板设备为 `None`
+    edge: Option<Edge>,
+    screens: Vec<ScreenInfo>,
+    /// 仅接收剪贴板同步，不参与焦点切换
+    clipboard_only: bool,
+    /// 设备希望收到的滚动量单位
+    scroll_unit: ScrollUnit,
+    /// 双方都支持的可选功能
+    capabilities: Capabilities,
+    /// 设备要求以绝对坐标定位光标
+    absolute_pointer: bool,
+    /// 已协商的 UDP 运动旁路
+    udp: Option<Arc<UdpPath>>,
+}
+
+/// 已连接设备的当前状态
+#[derive(Debug, Clone)]
+pub struct PeerSnapshot {
+    pub device_id: String,
+    pub device_name: String,
+    /// 设备所在的本地屏幕边缘；仅剪贴板设备为 `None`
+    pub edge: Option<Edge>,
+    /// 仅接收剪贴板同步，不参与焦点切换
+    pub clipboard_only: bool,
+    /// 设备在 Hello 中上报的屏幕
+    pub screens: Vec<ScreenInfo>,
+    /// 最近一次测得的往返时延，尚未收到 Pong 时为 `None`
+    pub rtt: Option<Duration>,
+}
+
+impl PeerInfo {
+    /// 剪贴板消息插队到输入队列，保证先于之后的焦点切换送达；设备不支持该内容时不发送
+    fn send_ahead(&self, msg: Message) -> bool {
+        if !self.capabilities.permits(&msg) {
+            return true;
+        }
+        self.queue.push_ahead(msg)
+    }
+
+    /// 事件与快照中显示的名称：有标签时为标签，否则为设备在 Hello 中上报的名称
+    fn display_name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.device_name)
+    }
+
+    /// 按消息优先级与队列策略放入发送队列，连接已结束时返回 `false`
+    ///
+    /// 对端不支持的消息被跳过（仍返回 `true`）；不支持按字符键入时退回按物理键位
+    fn send(&self, msg: Message) -> bool {
+        // 运动消息优先经 UDP 旁路发送，旁路尚未就绪时仍走 TCP 队列；
+        // 之后经 TCP 的第一条输入前加屏障，客户端先应用此前的 UDP 运动
+        if let Some(path) = &self.udp {
+            if udp::is_motion(&msg) {
+                if path.send(&msg) {
+                    return true;
+                }
+            } else if msg.priority() == Priority::High {
+                if let Some(seq) = path.take_barrier() {
+                    self.queue.push(Message::UdpBarrier { seq });
+                }
+            }
+        }
+        let msg = match msg.required_capability() {
+            Some(cap) if !self.capabilities.contains(cap) => match msg {
+                Message::KeyChar { key, .. } => Message::KeyEvent { key, action: KeyAction::Press },
+                // 旧版本客户端退回两次单击，能否被识别为双击取决于网络时延
+                Message::MouseDoubleClick { button } => {
+                    return [ButtonAction::Press, ButtonAction::Release]
+                        .repeat(2)
+                        .into_iter()
+                        .map(|action| Message::MouseButtonEvent { button, action })
+                        .all(|msg| self.queue.push(msg));
+                }
+                // 不支持手势的设备：捏合缩放退化为 Ctrl+滚轮
+                Message::Gesture { kind: GestureKind::Pinch, magnitude } => {
+                    let lines = pinch_lines(magnitude);
+                    if lines == 0 {
+                        return true;
+                    }
+                    let dy = ScrollUnit::Lines.convert(lines as f64, self.scroll_unit);
+                    return [
+                        Message::KeyEvent { key: KeyCode::LeftCtrl, action: KeyAction::Press },
+                        Message::MouseScroll { dx: 0.0, dy, precise: false },
+                        Message::KeyEvent { key: KeyCode::LeftCtrl, action: KeyAction::Release },
+                    ]
+                    .into_iter()
+                    .all(|msg| self.queue.push(msg));
+                }
+                // 不支持 PNG 的设备：解码为原始 RGBA 发送
+                msg @ Message::ClipboardImage { .. }
+                    if self.capabilities.contains(Capability::ClipboardImage) =>
+                {
+                    match image::png_to_raw(msg) {
+                        Ok(msg) => msg,
+                        Err(e) => {
+                            warn!("failed to decode clipboard image for peer: {e}");
+                            return true;
+                        }
+                    }
+                }
+                _ => {
+                    debug!(?cap, "peer does not support message, skipped");
+                    return true;
+                }
+            },
+            _ => msg,
+        };
+        self.queue.push(msg)
+    }
 }
 
 // ── 边缘检测阈值 ──
 const EDGE_THRESHOLD: f64 = 2.0;
 
+/// 跟随模式下映射区域距本地屏幕边缘的距离，使光标在任何位置都还能继续向外移动
+const FOLLOW_MARGIN: i32 = 50;
+
+/// 键入粘贴策略下默认的粘贴键：左右 Ctrl+V，macOS 上另加左右 Cmd+V
+fn default_type_paste_hotkeys() -> Vec<Vec<KeyCode>> {
+    let mut modifiers = vec![KeyCode::LeftCtrl, KeyCode::RightCtrl];
+    if cfg!(target_os = "macos") {
+        modifiers.extend([KeyCode::LeftMeta, KeyCode::RightMeta]);
+    }
+    modifiers.into_iter().map(|modifier| vec![modifier, KeyCode::KeyV]).collect()
+}
+
+/// 快捷键键入文本的默认最大字符数
+const DEFAULT_MAX_TYPE_TEXT_LEN: usize = 4096;
+
+/// 焦点切换后默认不响应边缘触发的时长
+pub const DEFAULT_SWITCH_COOLDOWN: Duration = Duration::from_millis(150);
+
+/// 切回本地后默认不响应边缘触发的时长
+pub const DEFAULT_RETURN_COOLDOWN: Duration = Duration::from_millis(200);
+
+/// 剪贴板传输等待接收方确认的时限，超时记录为同步失败
+const CLIPBOARD_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 向设备发送 Ping 测量往返时延的间隔
+const RTT_PING_INTERVAL: Duration = Duration::from_secs(5);
+
+// ── 连接限制默认值 ──
+const DEFAULT_LISTEN_BACKLOG: u32 = 128;
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+const DEFAULT_MAX_CONNECTIONS_PER_IP: usize = 8;
+
+/// 资源耗尽导致 accept 失败后的重试间隔
+pub(crate) const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+/// 其他 accept 错误后的重试间隔，监听 socket 持续不可用时避免刷屏
+pub(crate) const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// 一次合并写出的高优先级消息上限，避免持续的输入流长时间不 flush
+const MAX_SEND_BATCH: usize = 64;
+
+// ── 配置 ──
+
+/// 焦点所在设备断开时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisconnectPolicy {
+    /// 立即切回本地，并将鼠标恢复到切换前的位置
+    #[default]
+    ReturnLocal,
+    /// 冻结焦点，在宽限期内等待设备重连；超时后切回本地
+    WaitForReconnect(Duration),
+}
+
+/// 焦点设备上粘贴本机剪贴板内容的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PasteStrategy {
+    /// 同步剪贴板，由设备上的粘贴操作读取
+    #[default]
+    Clipboard,
+    /// 不向焦点设备同步剪贴板；焦点在远程时按下 Ctrl+V 将剪贴板文本直接键入（沙盒等无法读写剪贴板的设备）
+    TypeText,
+}
+
+/// 焦点在远程时，转发给设备的输入是否同时在本机模拟（教学演示：键入同时出现在两台机器上）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LocalEcho {
+    /// 只转发给焦点设备
+    #[default]
+    Off,
+    /// 所有按键、鼠标按键与滚动
+    All,
+    /// 仅列出的键
+    Keys(HashSet<KeyCode>),
+}
+
+impl LocalEcho {
+    /// 转发的消息需要在本机回显时对应的本地动作
+    fn action_for(&self, msg: &Message) -> Option<LocalAction> {
+        let (key, action) = match (self, msg) {
+            (LocalEcho::Off, _) => return None,
+            (LocalEcho::All, Message::MouseButtonEvent { button, action }) => {
+                return Some(LocalAction::MouseButton(*button, *action));
+            }
+            (LocalEcho::All, Message::MouseScroll { dx, dy, precise }) => {
+                return Some(LocalAction::Scroll { dx: *dx, dy: *dy, precise: *precise });
+            }
+            (_, Message::KeyEvent { key, action }) => (*key, *action),
+            (_, Message::KeyChar { key, .. }) => (*key, KeyAction::Press),
+            _ => return None,
+        };
+        match self {
+            LocalEcho::Keys(keys) if !keys.contains(&key) => None,
+            _ => Some(LocalAction::Key(key, action)),
+        }
+    }
+}
+
+/// 鼠标到达屏幕边缘后触发焦点切换的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EdgeTrigger {
+    /// 到达边缘立即切换
+    #[default]
+    Immediate,
+    /// 持续推向边缘超过给定时长后切换
+    Dwell(Duration),
+    /// 在给定时间窗内两次推向同一边缘（中间离开边缘）才切换，避免移向角落按钮时误触发
+    DoublePush(Duration),
+}
+
+/// 固定布局中一台设备的位置与行为，按设备 ID 或设备名匹配
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceLayout {
+    /// 设备 ID 或设备名
+    pub device: String,
+    /// 设备所在的本地屏幕边缘，优先于设备请求的边缘与默认方向
+    pub edge: Edge,
+    /// 指定时覆盖设备在 Hello 中声明的定位方式（`true` 为绝对坐标）
+    #[serde(default)]
+    pub absolute_pointer: Option<bool>,
+    /// 是否向该设备同步剪贴板
+    #[serde(default = "default_true")]
+    pub clipboard: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 服务端行为配置
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// 焦点设备断开时的处理策略
+    pub disconnect_policy: DisconnectPolicy,
+    /// 不参与剪贴板同步的设备 ID
+    pub clipboard_opt_out: HashSet<String>,
+    /// 焦点在远程时无输入超过该时长则自动切回本地；`None` 表示禁用
+    pub idle_timeout: Option<Duration>,
+    /// 将当前剪贴板文本以键入方式发送到焦点设备的快捷键；`None` 表示禁用
+    pub paste_hotkey: Option<Vec<KeyCode>>,
+    /// 快捷键键入文本的最大字符数，超出时拒绝键入
+    pub max_type_text_len: usize,
+    /// 焦点设备上粘贴的方式
+    pub paste_strategy: PasteStrategy,
+    /// 键入粘贴策略下触发键入的粘贴键，任一组合按下即触发
+    pub type_paste_hotkeys: Vec<Vec<KeyCode>>,
+    /// 每个设备发送队列的容量与溢出处理
+    pub queue_policy: QueuePolicy,
+    /// 焦点在远程时按下即立即切回本地的组合键（远程无响应时的紧急出口）；`None` 表示禁用
+    pub escape_hotkey: Option<Vec<KeyCode>>,
+    /// 边缘切换的触发方式
+    pub edge_trigger: EdgeTrigger,
+    /// 输入源在焦点位于远程时直接提供相对运动（`MouseDelta`，如 `synapse_input::relative`
+    /// 捕获的原始运动）；此时不再把鼠标锁回屏幕中心，而是在进入远程时发出
+    /// [`LocalAction::LockPointer`]、回到本地时发出 [`LocalAction::UnlockPointer`]。
+    /// 平台不支持原始运动时应保持关闭，退回锁回中心的方式
+    pub relative_motion: bool,
+    /// 焦点在远程时隐藏本地光标（[`LocalAction::HideCursor`]），切回本地时恢复
+    pub hide_cursor: bool,
+    /// 各边缘的"缓冲带"宽度（像素）：到达边缘后需继续向外推动累计该距离才切换；未配置的边缘不启用
+    pub edge_gutter: HashMap<Edge, f64>,
+    /// 视为仅剪贴板的设备 ID（不论其 Hello 是否声明）：只同步剪贴板，从不转发输入
+    pub clipboard_only_devices: HashSet<String>,
+    /// 未知设备首次连接时需用户确认（[`ServerControl::ApprovePairing`]）后才接入
+    pub require_pairing: bool,
+    /// 已确认可信设备的配对密钥指纹（[`pairing::fingerprint`](crate::pairing::fingerprint)），
+    /// 出示对应密钥的设备连接时无需确认
+    pub trusted_devices: HashSet<String>,
+    /// 本机捕获的滚动量单位，转发时换算为各设备声明的单位
+    pub scroll_unit: ScrollUnit,
+    /// 监听队列长度
+    pub listen_backlog: u32,
+    /// 同时存在的连接数上限（含未完成握手的连接）
+    pub max_connections: usize,
+    /// 单个 IP 同时存在的连接数上限
+    pub max_connections_per_ip: usize,
+    /// 按采集序号合并输入与剪贴板通道，严格保持两者的先后顺序（脚本化场景）；
+    /// 关闭时输入优先处理，剪贴板可能被推迟到之后的输入之后
+    pub ordered_input: bool,
+    /// 在 Welcome 中向设备报告的本机屏幕（名称、主屏标记等）；为空时按本地屏幕区域生成一个
+    pub screens: Vec<ScreenInfo>,
+    /// 固定的多设备布局（如配置文件中的"笔记本在左、平板在右"），设备连接时按 ID 或名称套用
+    pub layout: Vec<DeviceLayout>,
+    /// 拒绝 `layout` 中未列出的设备；关闭时未列出的设备按默认方向放置
+    pub reject_unlisted: bool,
+    /// 停止时的排空时限：取消后不再接受新输入，在该时限内发完各设备队列中的消息并发送 `Bye`
+    /// 再断开；`None` 表示立即断开，丢弃未发送的消息
+    pub drain_timeout: Option<Duration>,
+    /// 按偏好顺序排列的帧压缩算法，握手时选出客户端也支持的第一个；为空时不压缩
+    pub compression: Vec<Compression>,
+    /// 客户端也支持时使用带同步字与校验和的帧格式：数据损坏或流错位时跳过损坏部分继续，
+    /// 而不是断开连接；每帧多 8 字节开销
+    pub frame_sync: bool,
+    /// 跟随模式：焦点在远程时本地光标按比例映射到远程虚拟光标的位置，而非锁定在屏幕中心
+    ///
+    /// 用于演示等需要在本机屏幕上看到光标位置的场景；开启后不再隐藏本地光标
+    pub follow_cursor: bool,
+    /// 设备 ID → 服务端设置的显示名称，在事件与快照中代替设备上报的名称（不影响协议）
+    pub device_labels: HashMap<String, String>,
+    /// 每次焦点切换（进入或离开远程）后不响应边缘触发的时长，防止在边界处来回切换
+    pub switch_cooldown: Duration,
+    /// 从远程切回本地后不响应边缘触发的时长，避免光标仍停在边缘附近时立即再次切出；
+    /// `Duration::ZERO` 表示只保留 `switch_cooldown`
+    pub return_cooldown: Duration,
+    /// 客户端也支持时鼠标运动改经 UDP 发送（与 TCP 监听同一端口），避免丢包重传时后续运动被阻塞；
+    /// 握手、按键、剪贴板等仍走 TCP，其前加 [`Message::UdpBarrier`]，客户端先应用此前的运动，
+    /// 点击不会早于运动落下；点击之后的运动仍可能先于点击送达。仅中继以外的直连有效
+    pub udp_motion: bool,
+    /// 焦点在远程时同时在本机模拟的输入
+    pub local_echo: LocalEcho,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            disconnect_policy: DisconnectPolicy::default(),
+            clipboard_opt_out: HashSet::new(),
+            idle_timeout: None,
+            paste_hotkey: None,
+            max_type_text_len: DEFAULT_MAX_TYPE_TEXT_LEN,
+            paste_strategy: PasteStrategy::default(),
+            type_paste_hotkeys: default_type_paste_hotkeys(),
+            queue_policy: QueuePolicy::default(),
+            escape_hotkey: Some(vec![KeyCode::LeftShift, KeyCode::RightShift]),
+            edge_trigger: EdgeTrigger::default(),
+            relative_motion: false,
+            hide_cursor: false,
+            edge_gutter: HashMap::new(),
+            clipboard_only_devices: HashSet::new(),
+            require_pairing: false,
+            trusted_devices: HashSet::new(),
+            scroll_unit: ScrollUnit::default(),
+            listen_backlog: DEFAULT_LISTEN_BACKLOG,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            max_connections_per_ip: DEFAULT_MAX_CONNECTIONS_PER_IP,
+            ordered_input: false,
+            follow_cursor: false,
+            screens: Vec::new(),
+            layout: Vec::new(),
+            reject_unlisted: false,
+            drain_timeout: None,
+            compression: vec![Compression::Lz4],
+            frame_sync: false,
+            switch_cooldown: DEFAULT_SWITCH_COOLDOWN,
+            return_cooldown: DEFAULT_RETURN_COOLDOWN,
+            device_labels: HashMap::new(),
+            udp_motion: false,
+            local_echo: LocalEcho::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// 设备在固定布局中的条目，ID 匹配优先于名称匹配
+    pub fn layout_for(&self, device_id: &str, device_name: &str) -> Option<&DeviceLayout> {
+        self.layout
+            .iter()
+            .find(|entry| entry.device == device_id)
+            .or_else(|| self.layout.iter().find(|entry| entry.device == device_name))
+    }
+
+    /// 指定设备是否参与剪贴板同步
+    pub fn clipboard_enabled_for(&self, device_id: &str) -> bool {
+        !self.clipboard_opt_out.contains(device_id)
+    }
+
+    /// 出示该密钥指纹的设备连接时是否需要用户确认；没有密钥（`None`）的设备总是需要
+    pub fn needs_pairing(&self, fingerprint: Option<&str>) -> bool {
+        self.require_pairing && !fingerprint.is_some_and(|print| self.trusted_devices.contains(print))
+    }
+}
+
 // ── FocusManager ──
 
+/// 边缘上 `start`..`end`（边长的比例）一段通往的设备
+#[derive(Debug, Clone, PartialEq)]
+struct EdgePortal {
+    edge: Edge,
+    start: f64,
+    end: f64,
+    device_id: String,
+}
+
+impl EdgePortal {
+    fn contains(&self, fraction: f64) -> bool {
+        // 区段终点为 1 时包含边缘的末端
+        fraction >= self.start && (fraction < self.end || self.end >= 1.0)
+    }
+}
+
 #[derive(Debug, Clone)]
 enum FocusState {
     Local,
@@ -38,43 +462,367 @@ enum FocusState {
         remote_w: u32,
         remote_h: u32,
         entered_edge: Edge,
+        /// 切换前本地鼠标所在位置，切回本地时用于恢复
+        return_x: i32,
+        return_y: i32,
     },
 }
 
+/// 鼠标与边缘的接触记录（用于 Dwell / DoublePush 触发）
+#[derive(Debug, Clone, Copy)]
+struct EdgeContact {
+    edge: Edge,
+    /// 首次到达边缘的时间
+    since: Instant,
+    /// 首次到达后是否已离开过边缘
+    left: bool,
+}
+
 struct FocusManager {
+    config: ServerConfig,
     state: FocusState,
+    /// 本地屏幕在虚拟桌面中的原点（主屏不在原点时可能为负）
+    origin_x: i32,
+    origin_y: i32,
     screen_w: u32,
     screen_h: u32,
     center_x: i32,
     center_y: i32,
+    /// 焦点在远程时本地光标被放置的位置，下一次移动的位移相对它计算
+    anchor: (i32, i32),
     /// 边缘方向 → (device_id, 远程屏幕宽, 高)
     edge_devices: HashMap<Edge, (String, u32, u32)>,
+    /// 只覆盖边缘一段的设备，越过该段时优先于 `edge_devices`；设备重连后沿用
+    edge_portals: Vec<EdgePortal>,
+    /// 已连接（非仅剪贴板）设备的屏幕尺寸，供边缘传送门查找
+    device_screens: HashMap<String, (u32, u32)>,
+    /// 焦点在本地时最后一次观测到的鼠标位置
+    last_local: (f64, f64),
+    /// 当前处于按下状态的键
+    pressed_keys: HashSet<KeyCode>,
+    edge_contact: Option<EdgeContact>,
+    /// 最近一次发出的焦点切换目标（设备 ID 或 "local"）
+    last_focus_target: String,
+    /// 最近一次焦点切换的时间，用于抑制边界处的来回切换
+    last_switch: Option<Instant>,
+    /// 最近一次从远程切回本地的时间，`return_cooldown` 内不响应边缘触发
+    last_return: Option<Instant>,
+    /// 缓冲带中正在累计推动的边缘及已累计的距离
+    gutter_push: Option<(Edge, f64)>,
+    /// 等待用户确认的设备 → 确认结果发送端
+    pending_pairings: HashMap<String, oneshot::Sender<bool>>,
+    /// 通过 [`Server::set_device_edges`] 指定的多边缘布局，设备重连后沿用
+    device_edges: HashMap<String, Vec<Edge>>,
+    /// 通过 [`Server::set_input_transform`] 指定的各设备输入变换
+    input_transforms: HashMap<String, InputTransform>,
+    /// 通过 [`Server::set_motion_scale`] 指定的各设备鼠标运动倍率
+    motion_scales: HashMap<String, f64>,
+    /// 通过 [`Server::set_edge_gap`] 指定的各设备与本机屏幕之间的物理间隙（像素）
+    edge_gaps: HashMap<String, f64>,
+    /// 通过 [`Server::set_key_filter`] 指定的各设备按键过滤
+    key_filters: HashMap<String, KeyFilter>,
+    /// 按下时被过滤的键，其释放同样不转发
+    blocked_keys: HashSet<KeyCode>,
+    /// 最近一次本机剪贴板变化，焦点进入尚未收到它的设备时先补发
+    last_clipboard: Option<Message>,
+    /// 已收到 `last_clipboard` 的设备
+    clipboard_delivered: HashSet<String>,
+}
+
+/// 把零宽或零高的屏幕区域撑到至少 1 像素，避免边缘换算除以零
+fn non_empty(screen: ScreenRect) -> ScreenRect {
+    ScreenRect { width: screen.width.max(1), height: screen.height.max(1), ..screen }
 }
 
 impl FocusManager {
-    fn new(screen_w: u32, screen_h: u32) -> Self {
+    fn new(screen: ScreenRect, config: ServerConfig) -> Self {
+        let screen = non_empty(screen);
+        let center_x = screen.x + screen.width as i32 / 2;
+        let center_y = screen.y + screen.height as i32 / 2;
         Self {
+            config,
             state: FocusState::Local,
-            screen_w,
-            screen_h,
-            center_x: screen_w as i32 / 2,
-            center_y: screen_h as i32 / 2,
+            origin_x: screen.x,
+            origin_y: screen.y,
+            screen_w: screen.width,
+            screen_h: screen.height,
+            center_x,
+            center_y,
+            anchor: (center_x, center_y),
             edge_devices: HashMap::new(),
+            edge_portals: Vec::new(),
+            device_screens: HashMap::new(),
+            last_local: (center_x as f64, center_y as f64),
+            pressed_keys: HashSet::new(),
+            edge_contact: None,
+            gutter_push: None,
+            last_focus_target: "local".into(),
+            last_switch: None,
+            last_return: None,
+            pending_pairings: HashMap::new(),
+            device_edges: HashMap::new(),
+            input_transforms: HashMap::new(),
+            motion_scales: HashMap::new(),
+            edge_gaps: HashMap::new(),
+            key_filters: HashMap::new(),
+            blocked_keys: HashSet::new(),
+            last_clipboard: None,
+            clipboard_delivered: HashSet::new(),
+        }
+    }
+
+    /// 本地屏幕区域变化（接入显示器、改变分辨率或旋转）后更新边缘检测与光标放置使用的尺寸
+    fn set_screen(&mut self, screen: ScreenRect) {
+        let screen = non_empty(screen);
+        self.origin_x = screen.x;
+        self.origin_y = screen.y;
+        self.screen_w = screen.width;
+        self.screen_h = screen.height;
+        self.center_x = screen.x + screen.width as i32 / 2;
+        self.center_y = screen.y + screen.height as i32 / 2;
+        self.edge_contact = None;
+        self.gutter_push = None;
+        // 切回本地时的位置由 return_position 按新尺寸收回到屏幕内
+    }
+
+    /// 记录按键状态，返回该键是否为新按下（自动重复的按下返回 `false`）
+    fn track_key(&mut self, key: KeyCode, action: KeyAction) -> bool {
+        match action {
+            KeyAction::Press => self.pressed_keys.insert(key),
+            KeyAction::Release => {
+                self.pressed_keys.remove(&key);
+                false
+            }
         }
     }
 
+    /// 新按下 `key` 后 `combo` 中的键是否已全部按下
+    fn combo_completed(&self, key: KeyCode, combo: &[KeyCode]) -> bool {
+        !combo.is_empty()
+            && combo.contains(&key)
+            && combo.iter().all(|k| self.pressed_keys.contains(k))
+    }
+
+    /// 按下 `key` 是否完成了任一键入粘贴组合
+    fn type_paste_completed(&self, key: KeyCode) -> bool {
+        self.config.type_paste_hotkeys.iter().any(|combo| self.combo_completed(key, combo))
+    }
+
     fn set_edge_device(&mut self, edge: Edge, device_id: String, w: u32, h: u32) {
-        self.edge_devices.insert(edge, (device_id, w, h));
+        self.edge_devices.insert(edge, (device_id, w.max(1), h.max(1)));
+    }
+
+    /// 按设备的输入变换改写将要转发的消息
+    fn transform_for(&self, device_id: &str, msg: Message, screen: (u32, u32)) -> Message {
+        match self.input_transforms.get(device_id) {
+            Some(transform) => transform.apply(msg, screen),
+            None => msg,
+        }
+    }
+
+    /// 按设备的按键过滤判断是否丢弃将要转发的按键消息；按下被丢弃的键，其自动重复与释放一并丢弃
+    fn key_blocked(&mut self, device_id: &str, msg: &Message, newly_pressed: bool) -> bool {
+        let filter = self.key_filters.get(device_id);
+        match msg {
+            Message::KeyEvent { key, action: KeyAction::Press } if newly_pressed => {
+                let blocked = filter.is_some_and(|f| f.blocks(*key, &self.pressed_keys));
+                if blocked {
+                    self.blocked_keys.insert(*key);
+                } else {
+                    self.blocked_keys.remove(key);
+                }
+                blocked
+            }
+            Message::KeyEvent { key, action: KeyAction::Press } => self.blocked_keys.contains(key),
+            Message::KeyEvent { key, action: KeyAction::Release } => self.blocked_keys.remove(key),
+            Message::KeyChar { key, .. } => {
+                filter.is_some_and(|f| f.blocks(*key, &self.pressed_keys))
+            }
+            _ => false,
+        }
+    }
+
+    /// 按设备的运动倍率缩放相对位移，虚拟光标与转发的位移使用同一结果
+    fn scale_motion(&self, device_id: &str, dx: f64, dy: f64) -> (f64, f64) {
+        match self.motion_scales.get(device_id) {
+            Some(scale) => (dx * scale, dy * scale),
+            None => (dx, dy),
+        }
+    }
+
+    /// 设备与本机屏幕之间的物理间隙，未设置时为 0
+    fn edge_gap(&self, device_id: &str) -> f64 {
+        self.edge_gaps.get(device_id).copied().unwrap_or(0.0)
+    }
+
+    /// 占用 `edges` 中任一边缘的其他设备
+    fn edge_owners(&self, edges: &[Edge], except: &str) -> Vec<String> {
+        let mut owners: Vec<String> = Vec::new();
+        for (owner, _, _) in edges.iter().filter_map(|edge| self.edge_devices.get(edge)) {
+            if owner != except && !owners.contains(owner) {
+                owners.push(owner.clone());
+            }
+        }
+        owners
+    }
+
+    /// 设备的部分边缘被其他设备取代后仍占用的边缘，优先按其布局的顺序；已失去的边缘从布局中去掉
+    fn remaining_edge(&mut self, device_id: &str) -> Option<Edge> {
+        let held: Vec<Edge> = self
+            .edge_devices
+            .iter()
+            .filter(|(_, (owner, _, _))| owner == device_id)
+            .map(|(edge, _)| *edge)
+            .collect();
+        if let Some(layout) = self.device_edges.get_mut(device_id) {
+            layout.retain(|edge| held.contains(edge));
+            if layout.is_empty() {
+                self.device_edges.remove(device_id);
+            } else {
+                return Some(layout[0]);
+            }
+        }
+        held.first().copied()
+    }
+
+    /// 将设备放到多条边缘上，原先占用这些边缘的设备被替换
+    fn set_device_edges(&mut self, device_id: &str, edges: &[Edge], w: u32, h: u32) {
+        self.remove_device(device_id);
+        for edge in edges {
+            self.set_edge_device(*edge, device_id.to_string(), w, h);
+        }
     }
 
     fn remove_device(&mut self, device_id: &str) {
         self.edge_devices.retain(|_, (id, _, _)| id != device_id);
-        // 如果焦点在被移除的设备上，切回本地
-        if let FocusState::Remote { device_id: ref fid, .. } = self.state {
+    }
+
+    /// 设置边缘传送门，与之重叠的传送门被替换
+    fn set_edge_portal(&mut self, portal: EdgePortal) {
+        self.edge_portals.retain(|p| {
+            p.edge != portal.edge || p.end <= portal.start || p.start >= portal.end
+        });
+        self.edge_portals.push(portal);
+    }
+
+    /// `(x, y)` 沿 `edge` 方向在本地屏幕边长上的比例（0 为左端或上端）
+    fn edge_fraction(&self, edge: Edge, x: f64, y: f64) -> f64 {
+        let fraction = match edge {
+            Edge::Left | Edge::Right => (y - self.origin_y as f64) / self.screen_h as f64,
+            Edge::Top | Edge::Bottom => (x - self.origin_x as f64) / self.screen_w as f64,
+        };
+        fraction.clamp(0.0, 1.0)
+    }
+
+    /// `(x, y)` 处所在的、设备已连接的边缘传送门
+    fn portal_at(&self, edge: Edge, x: f64, y: f64) -> Option<&EdgePortal> {
+        let fraction = self.edge_fraction(edge, x, y);
+        self.edge_portals.iter().find(|p| {
+            p.edge == edge
+                && p.contains(fraction)
+                && self.device_screens.contains_key(&p.device_id)
+        })
+    }
+
+    /// 越过 `edge` 上的 `(x, y)` 时进入的设备及其屏幕尺寸，以及用于计算进入位置的本地坐标
+    ///
+    /// 传送门的区段被拉伸到整条边缘，使区段两端对应远程屏幕的两端
+    fn target_at(&self, edge: Edge, x: f64, y: f64) -> Option<(String, u32, u32, f64, f64)> {
+        if let Some(portal) = self.portal_at(edge, x, y) {
+            let (rw, rh) = self.device_screens[&portal.device_id];
+            let fraction = self.edge_fraction(edge, x, y);
+            let along = (fraction - portal.start) / (portal.end - portal.start);
+            let (x, y) = match edge {
+                Edge::Left | Edge::Right => {
+                    (x, self.origin_y as f64 + along * self.screen_h as f64)
+                }
+                Edge::Top | Edge::Bottom => {
+                    (self.origin_x as f64 + along * self.screen_w as f64, y)
+                }
+            };
+            return Some((portal.device_id.clone(), rw, rh, x, y));
+        }
+        self.edge_devices
+            .get(&edge)
+            .map(|(device_id, rw, rh)| (device_id.clone(), *rw, *rh, x, y))
+    }
+
+    /// 设备某个传送门区段中点所在的边缘位置，用于外部命令切换焦点
+    fn portal_entry(&self, device_id: &str) -> Option<(Edge, (f64, f64))> {
+        let portal = self.edge_portals.iter().find(|p| p.device_id == device_id)?;
+        let middle = (portal.start + portal.end) / 2.0;
+        let left = self.origin_x as f64;
+        let top = self.origin_y as f64;
+        let right = left + self.screen_w as f64;
+        let bottom = top + self.screen_h as f64;
+        let point = match portal.edge {
+            Edge::Left => (left, top + middle * self.screen_h as f64),
+            Edge::Right => (right, top + middle * self.screen_h as f64),
+            Edge::Top => (left + middle * self.screen_w as f64, top),
+            Edge::Bottom => (left + middle * self.screen_w as f64, bottom),
+        };
+        Some((portal.edge, point))
+    }
+
+    /// 焦点当前是否在指定设备上
+    fn is_focused_on(&self, device_id: &str) -> bool {
+        matches!(&self.state, FocusState::Remote { device_id: fid, .. } if fid == device_id)
+    }
+
+    /// 若焦点在指定设备上则切回本地，返回需要恢复的本地鼠标位置
+    fn release_focus(&mut self, device_id: &str) -> Option<(i32, i32)> {
+        if let FocusState::Remote { device_id: ref fid, return_x, return_y, .. } = self.state {
             if fid == device_id {
+                let pos = self.return_position(return_x, return_y);
                 self.state = FocusState::Local;
+                return Some(pos);
             }
         }
+        None
+    }
+
+    /// 切回本地时的鼠标位置：切换前的位置，向内收回到边缘阈值之外以免立即再次触发切换
+    fn return_position(&self, x: i32, y: i32) -> (i32, i32) {
+        let margin = EDGE_THRESHOLD as i32 + 1;
+        let min_x = self.origin_x + margin;
+        let min_y = self.origin_y + margin;
+        let max_x = (self.origin_x + self.screen_w as i32 - margin).max(min_x);
+        let max_y = (self.origin_y + self.screen_h as i32 - margin).max(min_y);
+        (x.clamp(min_x, max_x), y.clamp(min_y, max_y))
+    }
+
+    /// 焦点在远程时本地光标应放置的位置：跟随模式下为虚拟光标的映射位置，否则为屏幕中心
+    fn park_position(&self, vx: f64, vy: f64, remote_w: u32, remote_h: u32) -> (i32, i32) {
+        if self.config.follow_cursor {
+            self.follow_position(vx, vy, remote_w, remote_h)
+        } else {
+            (self.center_x, self.center_y)
+        }
+    }
+
+    /// 将远程虚拟光标位置按比例映射到本地屏幕内缩 [`FOLLOW_MARGIN`] 的区域
+    ///
+    /// 映射区域不贴边，光标在边界处仍能产生向外的位移，虚拟光标才能到达远程边缘
+    fn follow_position(&self, vx: f64, vy: f64, remote_w: u32, remote_h: u32) -> (i32, i32) {
+        let margin_x = FOLLOW_MARGIN.min(self.screen_w as i32 / 4);
+        let margin_y = FOLLOW_MARGIN.min(self.screen_h as i32 / 4);
+        let span_x = (self.screen_w as i32 - 2 * margin_x) as f64;
+        let span_y = (self.screen_h as i32 - 2 * margin_y) as f64;
+        let fx = (vx / remote_w.max(1) as f64).clamp(0.0, 1.0);
+        let fy = (vy / remote_h.max(1) as f64).clamp(0.0, 1.0);
+        (
+            self.origin_x + margin_x + (fx * span_x).round() as i32,
+            self.origin_y + margin_y + (fy * span_y).round() as i32,
+        )
+    }
+
+    /// 虚拟光标位置换算为设备屏幕内的绝对坐标
+    ///
+    /// 虚拟光标可以停在 `remote_w`/`remote_h`（刚好越过边缘），此时收回到最后一个像素
+    fn client_position(vx: f64, vy: f64, remote_w: u32, remote_h: u32) -> (f64, f64) {
+        let max_x = remote_w.saturating_sub(1) as f64;
+        let max_y = remote_h.saturating_sub(1) as f64;
+        (vx.clamp(0.0, max_x), vy.clamp(0.0, max_y))
     }
 
     /// 反向边缘
@@ -89,13 +837,146 @@ impl FocusManager {
 
     /// 检测绝对坐标是否到达屏幕边缘，返回对应 Edge
     fn check_edge(&self, x: f64, y: f64) -> Option<Edge> {
-        if x <= EDGE_THRESHOLD { return Some(Edge::Left); }
-        if x >= self.screen_w as f64 - EDGE_THRESHOLD { return Some(Edge::Right); }
-        if y <= EDGE_THRESHOLD { return Some(Edge::Top); }
-        if y >= self.screen_h as f64 - EDGE_THRESHOLD { return Some(Edge::Bottom); }
+        let left = self.origin_x as f64;
+        let top = self.origin_y as f64;
+        let right = left + self.screen_w as f64;
+        let bottom = top + self.screen_h as f64;
+        if x <= left + EDGE_THRESHOLD { return Some(Edge::Left); }
+        if x >= right - EDGE_THRESHOLD { return Some(Edge::Right); }
+        if y <= top + EDGE_THRESHOLD { return Some(Edge::Top); }
+        if y >= bottom - EDGE_THRESHOLD { return Some(Edge::Bottom); }
         None
     }
 
+    /// 鼠标从 `prev` 移动到 `cur` 时与 `edge` 触发线的交点
+    ///
+    /// 快速移动时单个采样可能越过边缘很远，用交点代替终点计算进入位置，避免跨屏位置偏移
+    fn edge_crossing(&self, edge: Edge, prev: (f64, f64), cur: (f64, f64)) -> (f64, f64) {
+        let left = self.origin_x as f64 + EDGE_THRESHOLD;
+        let top = self.origin_y as f64 + EDGE_THRESHOLD;
+        let right = self.origin_x as f64 + self.screen_w as f64 - EDGE_THRESHOLD;
+        let bottom = self.origin_y as f64 + self.screen_h as f64 - EDGE_THRESHOLD;
+        let (px, py) = prev;
+        let (cx, cy) = cur;
+        // 交点在线段上的比例；起点已在触发线之外或未跨越时退回终点
+        let t = match edge {
+            Edge::Left if px > left && cx < px => (px - left) / (px - cx),
+            Edge::Right if px < right && cx > px => (right - px) / (cx - px),
+            Edge::Top if py > top && cy < py => (py - top) / (py - cy),
+            Edge::Bottom if py < bottom && cy > py => (bottom - py) / (cy - py),
+            _ => return cur,
+        };
+        let t = t.clamp(0.0, 1.0);
+        (px + (cx - px) * t, py + (cy - py) * t)
+    }
+
+    /// 缓冲带：在边缘处累计向外推动的距离，达到该边缘配置的宽度后才放行
+    ///
+    /// 只累计向外的分量，向内移动时清零；沿边缘滑动不计入。光标被系统钳制在边缘时
+    /// 坐标不变但仍有事件到达，这样的事件按向外推动 1 像素计
+    fn gutter_passed(&mut self, edge: Option<Edge>, prev: (f64, f64), cur: (f64, f64)) -> Option<Edge> {
+        let Some(edge) = edge else {
+            self.gutter_push = None;
+            return None;
+        };
+        let Some(&width) = self.config.edge_gutter.get(&edge) else {
+            return Some(edge);
+        };
+        let push = match edge {
+            Edge::Left => prev.0 - cur.0,
+            Edge::Right => cur.0 - prev.0,
+            Edge::Top => prev.1 - cur.1,
+            Edge::Bottom => cur.1 - prev.1,
+        };
+        let push = if prev == cur { 1.0 } else { push };
+        if push < 0.0 {
+            self.gutter_push = None;
+            return None;
+        }
+        let total = match self.gutter_push {
+            Some((pushing, total)) if pushing == edge => total + push,
+            _ => push,
+        };
+        if total >= width {
+            self.gutter_push = None;
+            Some(edge)
+        } else {
+            self.gutter_push = Some((edge, total));
+            None
+        }
+    }
+
+    /// 按配置的触发方式判断是否切换；`edge` 为本次鼠标事件所在的（有设备的）边缘
+    fn edge_triggered(&mut self, edge: Option<Edge>, now: Instant) -> Option<Edge> {
+        match self.config.edge_trigger {
+            EdgeTrigger::Immediate => edge,
+            EdgeTrigger::Dwell(dwell) => {
+                let Some(edge) = edge else {
+                    self.edge_contact = None;
+                    return None;
+                };
+                match self.edge_contact {
+                    Some(contact) if contact.edge == edge => {
+                        if now.duration_since(contact.since) >= dwell {
+                            self.edge_contact = None;
+                            return Some(edge);
+                        }
+                    }
+                    _ => {
+                        self.edge_contact = Some(EdgeContact { edge, since: now, left: false });
+                    }
+                }
+                None
+            }
+            EdgeTrigger::DoublePush(window) => {
+                // 超出时间窗的接触记录作废
+                if let Some(contact) = self.edge_contact {
+                    if now.duration_since(contact.since) > window {
+                        self.edge_contact = None;
+                    }
+                }
+                let Some(edge) = edge else {
+                    if let Some(contact) = self.edge_contact.as_mut() {
+                        contact.left = true;
+                    }
+                    return None;
+                };
+                match self.edge_contact {
+                    Some(contact) if contact.edge == edge && contact.left => {
+                        self.edge_contact = None;
+                        Some(edge)
+                    }
+                    Some(contact) if contact.edge == edge => None,
+                    _ => {
+                        self.edge_contact = Some(EdgeContact { edge, since: now, left: false });
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// 存在物理间隙时虚拟光标在进入一侧越出远程屏幕的距离：光标先穿过间隙才出现在远程屏幕上
+    fn gap_offset(edge: &Edge, gap: f64) -> (f64, f64) {
+        match edge {
+            Edge::Right => (-gap, 0.0),
+            Edge::Left => (gap, 0.0),
+            Edge::Bottom => (0.0, -gap),
+            Edge::Top => (0.0, gap),
+        }
+    }
+
+    /// 虚拟光标的取值范围 `((min_x, max_x), (min_y, max_y))`，进入一侧向外延伸 `gap`
+    fn virtual_bounds(entered_edge: &Edge, rw: u32, rh: u32, gap: f64) -> ((f64, f64), (f64, f64)) {
+        let (rw, rh) = (rw as f64, rh as f64);
+        match entered_edge {
+            Edge::Right => ((-gap, rw), (0.0, rh)),
+            Edge::Left => ((0.0, rw + gap), (0.0, rh)),
+            Edge::Bottom => ((0.0, rw), (-gap, rh)),
+            Edge::Top => ((0.0, rw), (0.0, rh + gap)),
+        }
+    }
+
     /// 计算进入远程屏幕时的初始虚拟光标位置
     fn entry_position(edge: &Edge, x: f64, y: f64, sw: u32, sh: u32, rw: u32, rh: u32) -> (f64, f64) {
         match edge {
@@ -106,156 +987,928 @@ impl FocusManager {
         }
     }
 
-    /// 检测虚拟光标是否到达远程屏幕的反向边缘
-    fn check_virtual_edge(vx: f64, vy: f64, rw: u32, rh: u32, entered_edge: &Edge) -> bool {
+    /// 检测虚拟光标是否到达远程屏幕的反向边缘（存在间隙时需穿过整个间隙）
+    fn check_virtual_edge(
+        vx: f64,
+        vy: f64,
+        rw: u32,
+        rh: u32,
+        entered_edge: &Edge,
+        gap: f64,
+    ) -> bool {
         let exit_edge = Self::opposite_edge(entered_edge);
         match exit_edge {
-            Edge::Left => vx <= 0.0,
-            Edge::Right => vx >= rw as f64,
-            Edge::Top => vy <= 0.0,
-            Edge::Bottom => vy >= rh as f64,
+            Edge::Left => vx <= -gap,
+            Edge::Right => vx >= rw as f64 + gap,
+            Edge::Top => vy <= -gap,
+            Edge::Bottom => vy >= rh as f64 + gap,
         }
     }
 }
 
-/// TCP 服务端
-pub struct Server {
-    addr: String,
+/// 并发连接计数，限制总连接数与单个 IP 的连接数
+#[derive(Clone)]
+struct ConnectionLimiter {
+    inner: Arc<std::sync::Mutex<ConnectionCounts>>,
+    max_total: usize,
+    max_per_ip: usize,
 }
 
-impl Server {
-    pub fn new(addr: impl Into<String>) -> Self {
-        Self { addr: addr.into() }
-    }
+#[derive(Default)]
+struct ConnectionCounts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
 
-    /// 启动服务端完整消息循环（焦点驱动模式）
-    pub async fn run(
-        &self,
-        input_rx: mpsc::UnboundedReceiver<Message>,
-        clipboard_rx: mpsc::UnboundedReceiver<Message>,
-        local_action_tx: mpsc::UnboundedSender<LocalAction>,
-        event_tx: mpsc::UnboundedSender<ServerEvent>,
-        screen_size: (u32, u32),
-        client_direction: Edge,
-        cancel: CancellationToken,
-    ) -> Result<()> {
-        let listener = TcpListener::bind(&self.addr).await?;
-        info!(addr = %self.addr, "server listening");
-        let _ = event_tx.send(ServerEvent::Log(format!("Listening on {}", self.addr)));
+/// 占用一个连接名额，drop 时归还
+struct ConnectionSlot {
+    limiter: ConnectionLimiter,
+    ip: Option<IpAddr>,
+}
 
-        let peers: PeerMap = Arc::new(RwLock::new(HashMap::new()));
-        let focus = Arc::new(tokio::sync::Mutex::new(
-            FocusManager::new(screen_size.0, screen_size.1),
-        ));
-        let client_direction = Arc::new(client_direction);
+impl ConnectionLimiter {
+    fn new(max_total: usize, max_per_ip: usize) -> Self {
+        Self { inner: Arc::default(), max_total, max_per_ip }
+    }
 
-        // 焦点驱动的输入处理任务
-        let peers_input = peers.clone();
-        let focus_input = focus.clone();
-        let cancel_input = cancel.clone();
-        let event_tx_input = event_tx.clone();
-        let local_action = local_action_tx.clone();
-        tokio::spawn(async move {
-            let mut input_rx = input_rx;
-            let mut clipboard_rx = clipboard_rx;
-            loop {
-                let msg = tokio::select! {
-                    _ = cancel_input.cancelled() => break,
-                    Some(msg) = input_rx.recv() => msg,
-                    Some(msg) = clipboard_rx.recv() => msg,
-                    else => break,
-                };
-                // PLACEHOLDER_INPUT_HANDLER
-                handle_input_message(
-                    msg,
-                    &focus_input,
-                    &peers_input,
-                    &local_action,
-                    &event_tx_input,
-                ).await;
+    /// 尝试占用一个名额；`ip` 为 `None`（如进程内传输、中继）时只计入总数
+    fn try_acquire(&self, ip: Option<IpAddr>) -> Option<ConnectionSlot> {
+        let mut counts = self.inner.lock().unwrap();
+        if counts.total >= self.max_total {
+            return None;
+        }
+        if let Some(ip) = ip {
+            let count = counts.per_ip.entry(ip).or_default();
+            if *count >= self.max_per_ip {
+                return None;
             }
-        });
+            *count += 1;
+        }
+        counts.total += 1;
+        Some(ConnectionSlot { limiter: self.clone(), ip })
+    }
+}
 
-        // Accept 循环
-        let client_dir = client_direction.clone();
-        loop {
-            let (stream, peer_addr) = tokio::select! {
-                _ = cancel.cancelled() => {
-                    info!("server shutting down");
-                    break;
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut counts = self.limiter.inner.lock().unwrap();
+        counts.total -= 1;
+        if let Some(ip) = self.ip {
+            if let Some(count) = counts.per_ip.get_mut(&ip) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.per_ip.remove(&ip);
                 }
-                result = listener.accept() => result?,
-            };
+            }
+        }
+    }
+}
 
-            info!(%peer_addr, "new connection");
-            let _ = event_tx.send(ServerEvent::Log(format!("New connection from {peer_addr}")));
+/// accept 失败的类别
+pub(crate) enum AcceptErrorKind {
+    /// 单个连接在握手阶段失败，不影响监听
+    Connection,
+    /// 系统资源暂时耗尽，退避后重试
+    Resources,
+    /// 其他错误，记录后以较长间隔重试，不终止服务
+    Other,
+    /// 监听 socket 本身已不可用（EBADF / EINVAL / ENOTSOCK），重试没有意义
+    Fatal,
+}
 
-            let peers = peers.clone();
-            let focus = focus.clone();
-            let event_tx = event_tx.clone();
-            let cancel = cancel.clone();
-            let client_dir = client_dir.clone();
+pub(crate) fn classify_accept_error(e: &std::io::Error) -> AcceptErrorKind {
+    use std::io::ErrorKind;
+    match e.kind() {
+        ErrorKind::ConnectionAborted
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::Interrupted
+        | ErrorKind::WouldBlock
+        | ErrorKind::TimedOut => AcceptErrorKind::Connection,
+        ErrorKind::OutOfMemory => AcceptErrorKind::Resources,
+        _ if is_resource_exhaustion(e) => AcceptErrorKind::Resources,
+        _ if is_listener_invalid(e) => AcceptErrorKind::Fatal,
+        _ => AcceptErrorKind::Other,
+    }
+}
 
-            tokio::spawn(async move {
-                if let Err(e) = handle_client(
-                    stream, peer_addr, peers, focus, event_tx, cancel, &client_dir,
-                ).await {
-                    warn!(%peer_addr, "client handler error: {e}");
-                }
-            });
-        }
+/// 监听 socket 已关闭或不再是可 accept 的 socket（EBADF / EINVAL / ENOTSOCK）
+fn is_listener_invalid(e: &std::io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    const CODES: &[i32] = &[9, 22, 88];
+    #[cfg(all(unix, not(target_os = "linux")))]
+    const CODES: &[i32] = &[9, 22, 38];
+    #[cfg(windows)]
+    const CODES: &[i32] = &[10009, 10022, 10038];
+    #[cfg(not(any(unix, windows)))]
+    const CODES: &[i32] = &[];
+    e.raw_os_error().is_some_and(|code| CODES.contains(&code))
+}
 
-        Ok(())
-    }
+/// 文件描述符或缓冲区耗尽（EMFILE / ENFILE / ENOBUFS / ENOMEM）
+fn is_resource_exhaustion(e: &std::io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    const CODES: &[i32] = &[24, 23, 105, 12];
+    #[cfg(all(unix, not(target_os = "linux")))]
+    const CODES: &[i32] = &[24, 23, 55, 12];
+    #[cfg(windows)]
+    const CODES: &[i32] = &[10024, 10055];
+    #[cfg(not(any(unix, windows)))]
+    const CODES: &[i32] = &[];
+    e.raw_os_error().is_some_and(|code| CODES.contains(&code))
 }
 
-async fn handle_input_message(
-    msg: Message,
-    focus: &tokio::sync::Mutex<FocusManager>,
+/// 记录 accept 错误并返回重试前的等待时间；监听 socket 已不可用时返回 `None`，由调用方终止监听
+fn accept_retry_delay(
+    e: &std::io::Error,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+) -> Option<Duration> {
+    match classify_accept_error(e) {
+        AcceptErrorKind::Connection => {
+            debug!("connection failed during accept: {e}");
+            Some(Duration::ZERO)
+        }
+        AcceptErrorKind::Resources => {
+            // 文件描述符等资源暂时耗尽，稍后重试
+            warn!("accept failed, retrying in {:?}: {e}", ACCEPT_BACKOFF);
+            Some(ACCEPT_BACKOFF)
+        }
+        AcceptErrorKind::Other => {
+            error!("accept failed, retrying in {:?}: {e}", ACCEPT_ERROR_BACKOFF);
+            let _ = event_tx.send(ServerEvent::Log(format!("Accept failed: {e}")));
+            Some(ACCEPT_ERROR_BACKOFF)
+        }
+        AcceptErrorKind::Fatal => {
+            error!("listener failed: {e}");
+            let _ = event_tx.send(ServerEvent::Log(format!("Listener failed: {e}")));
+            None
+        }
+    }
+}
+
+/// 服务端默认端口
+pub const DEFAULT_PORT: u16 = 24800;
+
+/// 服务端默认监听地址（所有 IPv4 网卡）
+pub const DEFAULT_BIND: &str = "0.0.0.0:24800";
+
+/// 校验并规范化监听地址
+///
+/// 接受 `host:port`、`[v6]:port`、只有端口（`24800` 或 `:24800`，监听所有网卡）以及只有 IP（使用默认端口）
+pub fn parse_bind_addr(addr: &str) -> SynapseResult<SocketAddr> {
+    let trimmed = addr.trim();
+    let invalid = || SynapseError::InvalidAddress(addr.to_string());
+    if trimmed.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(addr) = trimmed.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if let Ok(port) = trimmed.trim_start_matches(':').parse::<u16>() {
+        return Ok(SocketAddr::from(([0, 0, 0, 0], port)));
+    }
+    let ip = trimmed.trim_start_matches('[').trim_end_matches(']');
+    match ip.parse::<IpAddr>() {
+        Ok(ip) => Ok(SocketAddr::new(ip, DEFAULT_PORT)),
+        Err(_) => Err(invalid()),
+    }
+}
+
+/// 绑定监听地址，使用指定的监听队列长度
+async fn bind_listener(addr: &str, backlog: u32) -> std::io::Result<TcpListener> {
+    let addr = tokio::net::lookup_host(addr).await?.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("cannot resolve {addr}"))
+    })?;
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    #[cfg(not(windows))]
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(backlog)
+}
+
+/// 待接入的传输及其对端描述
+type PendingTransport = (Box<dyn Transport>, String);
+
+/// 外部控制命令，用于脚本化地驱动焦点切换
+#[derive(Debug, Clone)]
+pub enum ServerControl {
+    /// 将焦点切换到指定设备
+    SwitchFocus { device_id: String },
+    /// 将焦点切回本地
+    ReturnLocal,
+    /// 允许等待确认的设备接入，并记为可信设备
+    ApprovePairing { device_id: String },
+    /// 拒绝等待确认的设备
+    RejectPairing { device_id: String },
+    /// 锁定所有已连接设备及本机的屏幕
+    LockAll,
+    /// 将设备放到多条边缘上（如左右两侧环绕同一台设备）
+    SetDeviceEdges { device_id: String, edges: Vec<Edge> },
+    /// 设置转发给设备的输入变换（交换左右键、反转或旋转运动）
+    SetInputTransform { device_id: String, transform: InputTransform },
+    /// 本地屏幕区域已变化（接入显示器、改变分辨率或旋转）
+    UpdateScreen { rect: ScreenRect },
+    /// 取消仍在发送队列中的剪贴板传输
+    CancelTransfer { transfer_id: u64 },
+    /// 设置本地回显
+    SetLocalEcho(LocalEcho),
+    /// 设置设备与本机屏幕之间的物理间隙（像素），0 表示无间隙
+    SetEdgeGap { device_id: String, gap: u32 },
+    /// 设置设备的按键过滤，空过滤器表示全部转发
+    SetKeyFilter { device_id: String, filter: KeyFilter },
+    /// 设置设备的鼠标运动倍率
+    SetMotionScale { device_id: String, scale: f64 },
+    /// 设置或清除（`None`）设备的显示名称
+    SetDeviceLabel { device_id: String, label: Option<String> },
+    /// 将设备放到边缘的一段上（`start`..`end` 为边长的比例）
+    SetEdgePortal { edge: Edge, start: f64, end: f64, device_id: String },
+}
+
+/// TCP 服务端
+pub struct Server {
+    addr: String,
+    config: ServerConfig,
+    peers: PeerMap,
+    metrics: Arc<Metrics>,
+    clipboard_reader: Option<ClipboardReader>,
+    clipboard_history: Option<ClipboardHistoryReader>,
+    control_tx: mpsc::UnboundedSender<ServerControl>,
+    control_rx: std::sync::Mutex<Option<mpsc::UnboundedReceiver<ServerControl>>>,
+    transport_tx: mpsc::UnboundedSender<PendingTransport>,
+    transport_rx: std::sync::Mutex<Option<mpsc::UnboundedReceiver<PendingTransport>>>,
+    local_addr: std::sync::Mutex<Option<SocketAddr>>,
+    transfers: Arc<TransferMap>,
+}
+
+impl Server {
+    pub fn new(addr: impl Into<String>) -> Self {
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        let (transport_tx, transport_rx) = mpsc::unbounded_channel();
+        Self {
+            addr: addr.into(),
+            config: ServerConfig::default(),
+            peers: Arc::new(RwLock::new(HashMap::new())),
+            metrics: Arc::new(Metrics::default()),
+            clipboard_reader: None,
+            clipboard_history: None,
+            control_tx,
+            control_rx: std::sync::Mutex::new(Some(control_rx)),
+            transport_tx,
+            transport_rx: std::sync::Mutex::new(Some(transport_rx)),
+            local_addr: std::sync::Mutex::new(None),
+            transfers: Arc::new(TransferMap::default()),
+        }
+    }
+
+    /// 接入一条已建立的传输（如进程内的内存管道），与 TCP 连接走相同的会话流程
+    ///
+    /// `peer` 为对端描述，仅用于日志
+    pub fn attach(&self, transport: impl Transport, peer: impl Into<String>) -> SynapseResult<()> {
+        self.transport_tx
+            .send((Box::new(transport), peer.into()))
+            .map_err(|_| SynapseError::NotRunning)
+    }
+
+    /// 通过中继接入一个客户端：连接中继并加入 `session` 会话，配对后的连接按普通客户端处理
+    pub async fn connect_relay(&self, relay_addr: &str, session: &str) -> SynapseResult<()> {
+        let framed = crate::relay::join(relay_addr, session).await?;
+        self.attach(framed, format!("relay:{session}"))
+    }
+
+    /// 当前已连接设备的快照，供晚于连接事件订阅的界面渲染当前状态
+    pub async fn peers_snapshot(&self) -> Vec<PeerSnapshot> {
+        self.peers
+            .read()
+            .await
+            .iter()
+            .map(|(device_id, peer)| PeerSnapshot {
+                device_id: device_id.clone(),
+                device_name: peer.display_name().to_string(),
+                edge: peer.edge,
+                clipboard_only: peer.clipboard_only,
+                screens: peer.screens.clone(),
+                rtt: self.metrics.device_rtt(device_id),
+            })
+            .collect()
+    }
+
+    /// 运行指标（连接设备数、转发速率等）
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// 进行中的剪贴板传输（排队中或等待确认）
+    pub fn active_transfers(&self) -> Vec<TransferStatus> {
+        self.transfers.list()
+    }
+
+    /// 取消剪贴板传输，结果以 [`ServerEvent::TransferCancelled`] 通知
+    ///
+    /// 排队中的传输直接移出队列；已写出、等待确认的传输向设备发送 [`Message::ClipboardCancel`]，
+    /// 设备丢弃尚未写入剪贴板的内容，之后的回执不再计为同步成功
+    pub fn cancel_transfer(&self, transfer_id: u64) {
+        let _ = self.control_tx.send(ServerControl::CancelTransfer { transfer_id });
+    }
+
+    /// 允许等待确认的设备接入
+    pub fn approve(&self, device_id: impl Into<String>) {
+        let _ = self.control_tx.send(ServerControl::ApprovePairing { device_id: device_id.into() });
+    }
+
+    /// 拒绝等待确认的设备
+    pub fn reject(&self, device_id: impl Into<String>) {
+        let _ = self.control_tx.send(ServerControl::RejectPairing { device_id: device_id.into() });
+    }
+
+    /// 锁定所有已连接设备及本机的屏幕
+    pub fn lock_all(&self) {
+        let _ = self.control_tx.send(ServerControl::LockAll);
+    }
+
+    /// 将设备放到多条边缘上，从任一边缘越过都进入该设备，进入位置按实际越过的边缘计算
+    ///
+    /// 设备未连接时在其连接后生效
+    pub fn set_device_edges(&self, device_id: impl Into<String>, edges: Vec<Edge>) {
+        let _ = self.control_tx.send(ServerControl::SetDeviceEdges {
+            device_id: device_id.into(),
+            edges,
+        });
+    }
+
+    /// 将设备放到 `edge` 的一段上，`start`..`end` 为该边长度的比例（0 为左端或上端）
+    ///
+    /// 同一条边缘可分给多台设备（如右边缘上半段通往显示器、下半段通往笔记本）；
+    /// 区段外仍进入占用整条边缘的设备。设备未连接时在其连接后生效
+    pub fn set_edge_portal(&self, edge: Edge, start: f64, end: f64, device_id: impl Into<String>) {
+        let _ = self.control_tx.send(ServerControl::SetEdgePortal {
+            edge,
+            start,
+            end,
+            device_id: device_id.into(),
+        });
+    }
+
+    /// 为设备设置仅在服务端显示的名称（如多台同名主机分别标为"厨房电脑""工作室 Mac"）
+    ///
+    /// 之后的连接、快照与焦点事件使用该名称，设备上报的名称不变；`label` 为空时恢复上报的名称。
+    /// 需要跨重启保留时由调用方写入 [`ServerConfig::device_labels`]
+    pub fn set_device_label(&self, device_id: impl Into<String>, label: impl Into<String>) {
+        let label = label.into();
+        let _ = self.control_tx.send(ServerControl::SetDeviceLabel {
+            device_id: device_id.into(),
+            label: (!label.trim().is_empty()).then(|| label.trim().to_string()),
+        });
+    }
+
+    /// 为设备设置输入变换（如左手使用时交换左右键、旋转安装的显示器旋转运动方向）
+    ///
+    /// 只影响该设备；设备未连接时在其连接后生效
+    pub fn set_input_transform(&self, device_id: impl Into<String>, transform: InputTransform) {
+        let _ = self.control_tx.send(ServerControl::SetInputTransform {
+            device_id: device_id.into(),
+            transform,
+        });
+    }
+
+    /// 设置设备的鼠标运动倍率（指针速度），如 2.0 使远程光标移动本地位移的两倍距离
+    ///
+    /// 用于两台显示器物理尺寸差异较大的情况；1.0 恢复默认，非正数或非有限值被忽略。
+    /// 设备未连接时在其连接后生效
+    pub fn set_motion_scale(&self, device_id: impl Into<String>, scale: f64) {
+        let _ = self.control_tx.send(ServerControl::SetMotionScale {
+            device_id: device_id.into(),
+            scale,
+        });
+    }
+
+    /// 设置焦点在远程时哪些转发的输入同时在本机模拟，如演示时键入同时出现在两台机器上
+    pub fn set_local_echo(&self, echo: LocalEcho) {
+        let _ = self.control_tx.send(ServerControl::SetLocalEcho(echo));
+    }
+
+    /// 设置设备与本机屏幕之间的物理间隙（显示器边框与缝隙，按像素计），0 表示无间隙
+    ///
+    /// 越过边缘后光标先在远程屏幕外穿过该距离才出现，切回本地时同样需要穿过间隙，
+    /// 使光标在两块屏幕之间的移动与物理位置一致。设备未连接时在其连接后生效
+    pub fn set_edge_gap(&self, device_id: impl Into<String>, gap: u32) {
+        let _ = self.control_tx.send(ServerControl::SetEdgeGap {
+            device_id: device_id.into(),
+            gap,
+        });
+    }
+
+    /// 设置不转发给设备的键与组合键（如 Meta 键、Alt+F4），焦点在该设备时这些键直接丢弃
+    ///
+    /// 按下时被过滤的键，其释放同样不转发；空的 [`KeyFilter`] 恢复全部转发。设备未连接时在其连接后生效
+    pub fn set_key_filter(&self, device_id: impl Into<String>, filter: KeyFilter) {
+        let _ = self.control_tx.send(ServerControl::SetKeyFilter {
+            device_id: device_id.into(),
+            filter,
+        });
+    }
+
+    /// 本地屏幕区域变化后调用（如前端检测到接入显示器、分辨率或方向改变），之后的边缘检测按新区域进行
+    pub fn update_screen(&self, rect: ScreenRect) {
+        let _ = self.control_tx.send(ServerControl::UpdateScreen { rect });
+    }
+
+    /// 获取控制命令发送端，可在 `run` 之前或运行期间使用
+    pub fn control_sender(&self) -> mpsc::UnboundedSender<ServerControl> {
+        self.control_tx.clone()
+    }
+
+    /// 使用自定义配置
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 限制同时存在的连接总数与单个 IP 的连接数，超出的连接在接受时即被关闭
+    pub fn with_connection_limits(mut self, max_connections: usize, per_ip: usize) -> Self {
+        self.config.max_connections = max_connections;
+        self.config.max_connections_per_ip = per_ip;
+        self
+    }
+
+    /// 设置每个设备发送队列的背压策略（默认：鼠标运动丢弃最旧、按键不丢弃、剪贴板丢弃最新）
+    pub fn with_queue_policy(mut self, policy: QueuePolicy) -> Self {
+        self.config.queue_policy = policy;
+        self
+    }
+
+    /// 设置剪贴板读取器，新设备握手完成后会收到当前剪贴板快照
+    pub fn with_clipboard_reader(
+        mut self,
+        reader: impl Fn() -> Option<Message> + Send + Sync + 'static,
+    ) -> Self {
+        self.clipboard_reader = Some(Arc::new(reader));
+        self
+    }
+
+    /// 设置剪贴板历史读取器，设备请求剪贴板历史时调用；未设置时回复空历史
+    pub fn with_clipboard_history(
+        mut self,
+        reader: impl Fn() -> Vec<Message> + Send + Sync + 'static,
+    ) -> Self {
+        self.clipboard_history = Some(Arc::new(reader));
+        self
+    }
+
+    /// 向指定设备发送组合键（如 Ctrl+Alt+Del），由 Client 一次性执行
+    pub async fn send_combo(&self, device_id: &str, keys: Vec<KeyCode>) -> SynapseResult<()> {
+        let peers = self.peers.read().await;
+        let peer = peers
+            .get(device_id)
+            .ok_or_else(|| SynapseError::UnknownDevice(device_id.to_string()))?;
+        if !peer.send(Message::KeyCombo { keys }) {
+            return Err(SynapseError::UnknownDevice(device_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// 向指定设备发送一次双击，由 Client 作为整体执行
+    pub async fn send_double_click(
+        &self,
+        device_id: &str,
+        button: MouseButton,
+    ) -> SynapseResult<()> {
+        let peers = self.peers.read().await;
+        let peer = peers
+            .get(device_id)
+            .ok_or_else(|| SynapseError::UnknownDevice(device_id.to_string()))?;
+        if !peer.send(Message::MouseDoubleClick { button }) {
+            return Err(SynapseError::UnknownDevice(device_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// 让指定设备释放所有已按下的键与鼠标按键，用于远程修饰键卡住时无需重连即可恢复
+    pub async fn release_all(&self, device_id: &str) -> SynapseResult<()> {
+        let peers = self.peers.read().await;
+        let peer = peers
+            .get(device_id)
+            .ok_or_else(|| SynapseError::UnknownDevice(device_id.to_string()))?;
+        if !peer.send(Message::ReleaseAll) {
+            return Err(SynapseError::UnknownDevice(device_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// 实际监听的地址，`run` 绑定端口之前为 `None`
+    ///
+    /// 监听端口为 0 时由系统分配端口，可通过该地址得知（如测试中绑定 `127.0.0.1:0`）
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        *self.local_addr.lock().unwrap()
+    }
+
+    /// 启动服务端完整消息循环（焦点驱动模式）
+    ///
+    /// `input_rx` 与 `clipboard_rx` 接收带采集序号的 [`Sequenced`] 消息，
+    /// 调用方应在采集时以 `Sequenced::new` 或 `msg.into()` 标记
+    #[allow(clippy::too_many_arguments)]
+    pub async fn run(
+        &self,
+        input_rx: mpsc::UnboundedReceiver<Sequenced>,
+        clipboard_rx: mpsc::UnboundedReceiver<Sequenced>,
+        local_action_tx: mpsc::UnboundedSender<LocalAction>,
+        event_tx: mpsc::UnboundedSender<ServerEvent>,
+        screen: ScreenRect,
+        client_direction: Edge,
+        cancel: CancellationToken,
+    ) -> SynapseResult<()> {
+        // 零尺寸屏幕会让边缘检测每次都触发、坐标换算除以零，拒绝启动
+        if screen.width == 0 || screen.height == 0 {
+            error!(?screen, "invalid local screen size, refusing to start");
+            return Err(SynapseError::InvalidScreen { width: screen.width, height: screen.height });
+        }
+        let mut control_rx = self
+            .control_rx
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or(SynapseError::AlreadyRunning)?;
+        let mut transport_rx = self
+            .transport_rx
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or(SynapseError::AlreadyRunning)?;
+        let listener = bind_listener(&self.addr, self.config.listen_backlog).await?;
+        let local_addr = listener.local_addr()?;
+        *self.local_addr.lock().unwrap() = Some(local_addr);
+        info!(addr = %local_addr, "server listening");
+        let _running = self.metrics.running_guard();
+        let event_tx = throttle::throttled(event_tx);
+        let _ = event_tx.send(ServerEvent::Log(format!("Listening on {local_addr}")));
+        let udp = if self.config.udp_motion {
+            match UdpMotion::bind(local_addr).await {
+                Ok(udp) => {
+                    info!(addr = %local_addr, "UDP motion channel listening");
+                    tokio::spawn(udp.clone().run(cancel.clone()));
+                    Some(udp)
+                }
+                Err(e) => {
+                    warn!(addr = %local_addr, "UDP motion disabled, failed to bind: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let peers = self.peers.clone();
+        let focus = Arc::new(tokio::sync::Mutex::new(
+            FocusManager::new(screen, self.config.clone()),
+        ));
+        let client_direction = Arc::new(client_direction);
+
+        // 焦点驱动的输入处理任务
+        let peers_input = peers.clone();
+        let transfers_input = self.transfers.clone();
+        let focus_input = focus.clone();
+        let cancel_input = cancel.clone();
+        let event_tx_input = event_tx.clone();
+        let local_action = local_action_tx.clone();
+        let idle_timeout = self.config.idle_timeout;
+        let clipboard_input = self.clipboard_reader.clone();
+        let ordered_input = self.config.ordered_input;
+        tokio::spawn(async move {
+            let mut input_rx = input_rx;
+            let mut clipboard_rx = clipboard_rx;
+            let mut idle_deadline = idle_timeout.map(|t| tokio::time::Instant::now() + t);
+            loop {
+                let deadline = idle_deadline;
+                let idle = async move {
+                    match deadline {
+                        Some(deadline) => tokio::time::sleep_until(deadline).await,
+                        None => std::future::pending().await,
+                    }
+                };
+                // 优先处理控制命令与输入，剪贴板只在输入空闲时处理
+                let msg = tokio::select! {
+                    biased;
+                    _ = cancel_input.cancelled() => break,
+                    Some(control) = control_rx.recv() => {
+                        handle_control(
+                            control,
+                            &focus_input,
+                            &peers_input,
+                            &transfers_input,
+                            &local_action,
+                            &event_tx_input,
+                        ).await;
+                        continue;
+                    }
+                    Some(msg) = input_rx.recv() => {
+                        // 有输入活动，重置空闲计时
+                        idle_deadline = idle_timeout.map(|t| tokio::time::Instant::now() + t);
+                        msg
+                    }
+                    Some(msg) = clipboard_rx.recv() => msg,
+                    _ = idle => {
+                        handle_idle_timeout(
+                            &focus_input,
+                            &peers_input,
+                            &local_action,
+                            &event_tx_input,
+                        ).await;
+                        idle_deadline = idle_timeout.map(|t| tokio::time::Instant::now() + t);
+                        continue;
+                    }
+                    else => break,
+                };
+                let batch = drain_batch(msg, &mut input_rx, &mut clipboard_rx, ordered_input);
+                for item in batch {
+                    handle_input_message(
+                        item.msg,
+                        &focus_input,
+                        &peers_input,
+                        &local_action,
+                        &event_tx_input,
+                        clipboard_input.as_ref(),
+                    ).await;
+                }
+            }
+        });
+
+        // Accept 循环
+        let client_dir = client_direction.clone();
+        let limiter = ConnectionLimiter::new(
+            self.config.max_connections,
+            self.config.max_connections_per_ip,
+        );
+        let clients = TaskTracker::new();
+        loop {
+            // 只有直接接入的 TCP 连接能切换编解码器的压缩，中继与外部传输不协商压缩
+            let (transport, peer_addr, ip, compression): (
+                Box<dyn Transport>, String, Option<IpAddr>, Option<CompressionHandle>,
+            ) = tokio::select! {
+                _ = cancel.cancelled() => {
+                    info!("server shutting down");
+                    break;
+                }
+                result = listener.accept() => {
+                    let (stream, peer_addr) = match result {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            let Some(delay) = accept_retry_delay(&e, &event_tx) else {
+                                return Err(e.into());
+                            };
+                            // 已有连接不受影响，退避后继续监听；退避期间停止服务不必等待退避结束
+                            tokio::select! {
+                                _ = cancel.cancelled() => {}
+                                _ = tokio::time::sleep(delay) => {}
+                            }
+                            continue;
+                        }
+                    };
+                    let codec = MessageCodec::new();
+                    let compression = codec.compression_handle();
+                    let transport = Box::new(Framed::new(stream, codec));
+                    (transport, peer_addr.to_string(), Some(peer_addr.ip()), Some(compression))
+                }
+                Some((transport, peer_addr)) = transport_rx.recv() => {
+                    (transport, peer_addr, None, None)
+                }
+            };
+
+            // 超出连接上限时直接关闭，不为其创建处理任务
+            let slot = match limiter.try_acquire(ip) {
+                Some(slot) => slot,
+                None => {
+                    warn!(%peer_addr, "connection limit reached, refusing connection");
+                    let _ = event_tx.send(ServerEvent::Log(format!(
+                        "Refused connection from {peer_addr}: too many connections"
+                    )));
+                    continue;
+                }
+            };
+
+            info!(%peer_addr, "new connection");
+            let _ = event_tx.send(ServerEvent::Log(format!("New connection from {peer_addr}")));
+
+            let peers = peers.clone();
+            let focus = focus.clone();
+            let event_tx = event_tx.clone();
+            let cancel = cancel.clone();
+            let client_dir = client_dir.clone();
+            let local_action_tx = local_action_tx.clone();
+            let clipboard_reader = self.clipboard_reader.clone();
+            let clipboard_history = self.clipboard_history.clone();
+            let metrics = self.metrics.clone();
+            let transfers = self.transfers.clone();
+            let udp = udp.clone();
+
+            clients.spawn(async move {
+                let _slot = slot;
+                if let Err(e) = handle_client(
+                    transport, peer_addr.clone(), compression, peers, transfers, focus,
+                    local_action_tx, event_tx, cancel, &client_dir, clipboard_reader,
+                    clipboard_history, metrics, udp,
+                ).await {
+                    warn!(%peer_addr, "client handler error: {e}");
+                }
+            });
+        }
+
+        // 优雅停止：等待各连接发完队列中的消息（每个连接受 drain_timeout 限制）
+        clients.close();
+        if self.config.drain_timeout.is_some() {
+            clients.wait().await;
+        }
+
+        Ok(())
+    }
+}
+
+/// 离线回放时虚拟接入的设备
+#[derive(Debug, Clone)]
+pub struct ReplayDevice {
+    pub device_id: String,
+    pub edge: Edge,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// 离线回放产生的结果
+#[derive(Debug, Default)]
+pub struct ReplayOutcome {
+    /// 按产生顺序排列的本机动作
+    pub actions: Vec<LocalAction>,
+    /// 按发送顺序排列的 (设备 ID, 消息)
+    pub sent: Vec<(String, Message)>,
+    pub events: Vec<ServerEvent>,
+}
+
+/// 不经网络与输入 channel，把录制的消息（见 [`read_recording`](crate::record::read_recording)）
+/// 逐条送入焦点逻辑，收集产生的本机动作、发往设备的消息与事件
+///
+/// 消息之间按录制的时间间隔等待。在暂停时钟（`tokio::time::pause`）的运行时中调用时时间瞬间推进，
+/// 停留触发、切换冷却等依赖时间的逻辑每次回放的结果都相同
+pub async fn replay_focus(
+    recording: impl IntoIterator<Item = (Duration, Message)>,
+    screen: ScreenRect,
+    config: ServerConfig,
+    devices: &[ReplayDevice],
+) -> ReplayOutcome {
+    let transfers = Arc::new(TransferMap::default());
+    let mut fm = FocusManager::new(screen, config.clone());
+    let mut peers = HashMap::new();
+    for device in devices {
+        fm.set_edge_device(device.edge, device.device_id.clone(), device.width, device.height);
+        let queue = Arc::new(PeerQueue::new(
+            config.queue_policy,
+            device.device_id.clone(),
+            transfers.clone(),
+        ));
+        peers.insert(device.device_id.clone(), PeerInfo {
+            queue,
+            screen_w: device.width,
+            screen_h: device.height,
+            device_name: device.device_id.clone(),
+            label: None,
+            edge: Some(device.edge),
+            screens: Vec::new(),
+            clipboard_only: false,
+            scroll_unit: ScrollUnit::Lines,
+            capabilities: Capabilities::local(),
+            absolute_pointer: false,
+            udp: None,
+        });
+    }
+    let focus = tokio::sync::Mutex::new(fm);
+    let peers: PeerMap = Arc::new(RwLock::new(peers));
+    let (action_tx, mut action_rx) = mpsc::unbounded_channel();
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let mut outcome = ReplayOutcome::default();
+    let start = Instant::now();
+
+    for (offset, msg) in recording {
+        tokio::time::sleep_until(start + offset).await;
+        handle_input_message(msg, &focus, &peers, &action_tx, &event_tx, None).await;
+        outcome.actions.extend(std::iter::from_fn(|| action_rx.try_recv().ok()));
+        outcome.events.extend(std::iter::from_fn(|| event_rx.try_recv().ok()));
+        for (device_id, peer) in peers.read().await.iter() {
+            let drained = std::iter::from_fn(|| peer.queue.try_recv_high().or_else(|| peer.queue.try_recv_bulk()));
+            outcome.sent.extend(drained.map(|msg| (device_id.clone(), msg)));
+        }
+    }
+    outcome
+}
+
+/// 边缘被其他设备取代后，更新原设备的 [`PeerInfo::edge`] 并以 [`ServerEvent::DeviceEdgeChanged`] 通知
+fn update_displaced(
+    fm: &mut FocusManager,
+    peers: &mut HashMap<String, PeerInfo>,
+    displaced: Vec<String>,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+) {
+    for device_id in displaced {
+        let edge = fm.remaining_edge(&device_id);
+        if let Some(peer) = peers.get_mut(&device_id) {
+            peer.edge = edge;
+        }
+        info!(%device_id, ?edge, "device displaced from its edge");
+        let _ = event_tx.send(ServerEvent::DeviceEdgeChanged { device_id, edge });
+    }
+}
+
+/// 焦点在远程时 span 的 `target` 字段记录接收输入的设备
+#[tracing::instrument(level = "debug", skip_all, fields(target = tracing::field::Empty))]
+async fn handle_input_message(
+    msg: Message,
+    focus: &tokio::sync::Mutex<FocusManager>,
     peers: &PeerMap,
     local_action_tx: &mpsc::UnboundedSender<LocalAction>,
     event_tx: &mpsc::UnboundedSender<ServerEvent>,
+    clipboard_reader: Option<&ClipboardReader>,
 ) {
     let mut fm = focus.lock().await;
 
+    // 仅剪贴板设备不参与焦点切换，剪贴板变更总是同步给它们
+    if matches!(
+        msg,
+        Message::ClipboardText { .. } | Message::ClipboardImage { .. } | Message::ClipboardClear { .. }
+    ) {
+        for (device_id, peer) in peers.read().await.iter() {
+            if peer.clipboard_only && fm.config.clipboard_enabled_for(device_id) {
+                peer.send(msg.clone());
+            }
+        }
+    }
+
+    let key_action = match &msg {
+        Message::KeyEvent { key, action } => Some((*key, *action)),
+        Message::KeyChar { key, .. } => Some((*key, KeyAction::Press)),
+        _ => None,
+    };
+    let newly_pressed = key_action.is_some_and(|(key, action)| fm.track_key(key, action));
+    if let Some((key, _)) = key_action {
+        let paste_hotkey = fm.config.paste_hotkey.clone().unwrap_or_default();
+        if newly_pressed && fm.combo_completed(key, &paste_hotkey) {
+            type_clipboard(&mut fm, peers, local_action_tx, event_tx, clipboard_reader).await;
+            return;
+        }
+        // 键入粘贴策略：远程设备上的粘贴改为直接键入剪贴板文本，粘贴键本身不转发
+        let type_paste = fm.config.paste_strategy == PasteStrategy::TypeText
+            && matches!(fm.state, FocusState::Remote { .. });
+        if type_paste && newly_pressed && fm.type_paste_completed(key) {
+            type_clipboard(&mut fm, peers, local_action_tx, event_tx, clipboard_reader).await;
+            return;
+        }
+        let escape_hotkey = fm.config.escape_hotkey.clone().unwrap_or_default();
+        if newly_pressed && fm.combo_completed(key, &escape_hotkey) {
+            if let FocusState::Remote { device_id, virtual_x, virtual_y, .. } = fm.state.clone() {
+                info!(%device_id, "escape hotkey pressed, returning focus to local");
+                // 先释放远程已按下的键，避免切回后远程修饰键卡住
+                if !release_remote_keys(&fm, peers, &device_id).await {
+                    drop_dead_peer(&mut fm, peers, &device_id, local_action_tx, event_tx).await;
+                    return;
+                }
+                leave_remote(&mut fm, peers, virtual_x, virtual_y, None, local_action_tx, event_tx)
+                    .await;
+                return;
+            }
+        }
+    }
+
     match &fm.state.clone() {
         FocusState::Local => {
+            // 焦点在本地时的剪贴板变化留到焦点进入设备时再发送
+            if matches!(
+                msg,
+                Message::ClipboardText { .. }
+                    | Message::ClipboardImage { .. }
+                    | Message::ClipboardClear { .. }
+            ) {
+                fm.last_clipboard = Some(msg.clone());
+                fm.clipboard_delivered.clear();
+            }
             // 焦点在本地：只关心 MouseMove 的边缘检测
             if let Message::MouseMove { x, y } = &msg {
-                if let Some(edge) = fm.check_edge(*x, *y) {
-                    // 检查该边缘是否有设备
-                    if let Some((device_id, rw, rh)) = fm.edge_devices.get(&edge).cloned() {
-                        let (vx, vy) = FocusManager::entry_position(
-                            &edge, *x, *y, fm.screen_w, fm.screen_h, rw, rh,
-                        );
-                        info!(
-                            %device_id, ?edge, vx, vy,
-                            "focus switching to remote device"
-                        );
-                        fm.state = FocusState::Remote {
-                            device_id: device_id.clone(),
-                            virtual_x: vx,
-                            virtual_y: vy,
-                            remote_w: rw,
-                            remote_h: rh,
-                            entered_edge: edge.clone(),
-                        };
-                        // 锁定鼠标到屏幕中心
-                        let _ = local_action_tx.send(LocalAction::MoveMouse(
-                            fm.center_x, fm.center_y,
-                        ));
-                        // 通知 Client 进入屏幕
-                        let peers_r = peers.read().await;
-                        if let Some(peer) = peers_r.get(&device_id) {
-                            let _ = peer.tx.send(Message::EnterScreen {
-                                screen_id: ScreenId(0),
-                                position: ScreenPosition { x: vx, y: vy },
-                            });
-                            // 发送初始绝对定位
-                            let _ = peer.tx.send(Message::MouseMove { x: vx, y: vy });
-                        }
-                        let _ = event_tx.send(ServerEvent::FocusChanged {
-                            target: device_id,
+                let prev = fm.last_local;
+                fm.last_local = (*x, *y);
+                // 只考虑有设备的边缘
+                let edge = fm
+                    .check_edge(*x, *y)
+                    .filter(|edge| fm.target_at(*edge, *x, *y).is_some());
+                let edge = fm.gutter_passed(edge, prev, (*x, *y));
+                let now = Instant::now();
+                let cooling_down = fm
+                    .last_switch
+                    .is_some_and(|t| now.duration_since(t) < fm.config.switch_cooldown)
+                    || fm
+                        .last_return
+                        .is_some_and(|t| now.duration_since(t) < fm.config.return_cooldown);
+                let edge = edge.filter(|_| !cooling_down);
+                if let Some(edge) = fm.edge_triggered(edge, now) {
+                    let (cross_x, cross_y) = fm.edge_crossing(edge, prev, (*x, *y));
+                    enter_remote(&mut fm, peers, edge, cross_x, cross_y, local_action_tx, event_tx)
+                        .await;
+                    if let FocusState::Remote { device_id, .. } = &fm.state {
+                        let _ = event_tx.send(ServerEvent::EdgeCrossed {
+                            edge,
+                            target: device_id.clone(),
                         });
                     }
                 }
@@ -269,81 +1922,146 @@ async fn handle_input_message(
             remote_w,
             remote_h,
             entered_edge,
+            ..
         } => {
             let device_id = device_id.clone();
+            tracing::Span::current().record("target", device_id.as_str());
             let remote_w = *remote_w;
             let remote_h = *remote_h;
-            let entered_edge = entered_edge.clone();
+            let entered_edge = *entered_edge;
 
             match &msg {
-                Message::MouseMove { x, y } => {
-                    // 计算 delta（相对于屏幕中心）
-                    let dx = *x as f64 - fm.center_x as f64;
-                    let dy = *y as f64 - fm.center_y as f64;
+                Message::MouseMove { .. } | Message::MouseDelta { .. } => {
+                    let relative = fm.config.relative_motion;
+                    let (dx, dy) = match &msg {
+                        // 计算 delta（相对于光标被放置的位置）
+                        Message::MouseMove { x, y } if !relative => {
+                            (*x - fm.anchor.0 as f64, *y - fm.anchor.1 as f64)
+                        }
+                        // 输入源直接提供相对运动，本地光标不动
+                        Message::MouseDelta { dx, dy } if relative => (*dx, *dy),
+                        _ => return,
+                    };
                     if dx == 0.0 && dy == 0.0 {
                         return; // 忽略锁回中心产生的事件
                     }
+                    let (dx, dy) = fm.scale_motion(&device_id, dx, dy);
 
                     // 更新虚拟光标
-                    let new_vx = (*virtual_x + dx).clamp(0.0, remote_w as f64);
-                    let new_vy = (*virtual_y + dy).clamp(0.0, remote_h as f64);
+                    let gap = fm.edge_gap(&device_id);
+                    let ((min_x, max_x), (min_y, max_y)) =
+                        FocusManager::virtual_bounds(&entered_edge, remote_w, remote_h, gap);
+                    let new_vx = (*virtual_x + dx).clamp(min_x, max_x);
+                    let new_vy = (*virtual_y + dy).clamp(min_y, max_y);
 
                     // 检测是否到达反向边缘（切回本地）
                     if FocusManager::check_virtual_edge(
-                        new_vx, new_vy, remote_w, remote_h, &entered_edge,
+                        new_vx, new_vy, remote_w, remote_h, &entered_edge, gap,
                     ) {
-                        info!(%device_id, "focus switching back to local");
-                        // 发送 LeaveScreen 给 Client
-                        let peers_r = peers.read().await;
-                        if let Some(peer) = peers_r.get(&device_id) {
-                            let _ = peer.tx.send(Message::LeaveScreen {
-                                screen_id: ScreenId(0),
-                                edge: FocusManager::opposite_edge(&entered_edge),
-                                position: ScreenPosition { x: new_vx, y: new_vy },
-                            });
-                        }
-                        fm.state = FocusState::Local;
-                        let _ = event_tx.send(ServerEvent::FocusChanged {
-                            target: "local".into(),
-                        });
+                        let exit_edge = FocusManager::opposite_edge(&entered_edge);
+                        leave_remote(
+                            &mut fm, peers, new_vx, new_vy, Some(exit_edge),
+                            local_action_tx, event_tx,
+                        ).await;
                         return;
                     }
 
                     // 更新虚拟光标位置
-                    fm.state = FocusState::Remote {
-                        device_id: device_id.clone(),
-                        virtual_x: new_vx,
-                        virtual_y: new_vy,
-                        remote_w,
-                        remote_h,
-                        entered_edge,
-                    };
+                    if let FocusState::Remote { virtual_x, virtual_y, .. } = &mut fm.state {
+                        *virtual_x = new_vx;
+                        *virtual_y = new_vy;
+                    }
 
-                    // 发送 MouseDelta 给焦点设备
-                    let peers_r = peers.read().await;
-                    if let Some(peer) = peers_r.get(&device_id) {
-                        let _ = peer.tx.send(Message::MouseDelta { dx, dy });
+                    // 发送 MouseDelta 给焦点设备；要求绝对定位的设备改为发送虚拟光标的坐标
+                    let absolute = peers
+                        .read()
+                        .await
+                        .get(&device_id)
+                        .is_some_and(|peer| peer.absolute_pointer);
+                    let motion = if absolute {
+                        let (x, y) =
+                            FocusManager::client_position(new_vx, new_vy, remote_w, remote_h);
+                        Message::MouseMove { x, y }
+                    } else if gap > 0.0 {
+                        // 只转发落在远程屏幕内的那部分位移，光标在间隙中时远程光标不动
+                        let visible = |v: f64, max: u32| v.clamp(0.0, max as f64);
+                        Message::MouseDelta {
+                            dx: visible(new_vx, remote_w) - visible(*virtual_x, remote_w),
+                            dy: visible(new_vy, remote_h) - visible(*virtual_y, remote_h),
+                        }
+                    } else {
+                        Message::MouseDelta { dx, dy }
+                    };
+                    let in_gap =
+                        matches!(motion, Message::MouseDelta { dx, dy } if dx == 0.0 && dy == 0.0);
+                    let motion = fm.transform_for(&device_id, motion, (remote_w, remote_h));
+                    if !in_gap && !send_to_peer(peers, &device_id, motion).await {
+                        drop_dead_peer(&mut fm, peers, &device_id, local_action_tx, event_tx).await;
+                        return;
                     }
 
-                    // 锁回鼠标到屏幕中心
-                    let _ = local_action_tx.send(LocalAction::MoveMouse(
-                        fm.center_x, fm.center_y,
-                    ));
+                    // 锁回鼠标到屏幕中心（跟随模式下移到映射位置）；
+                    // 移动本身产生的事件位移为零，不会被当作用户输入
+                    if !relative || fm.config.follow_cursor {
+                        let (ax, ay) = fm.park_position(new_vx, new_vy, remote_w, remote_h);
+                        fm.anchor = (ax, ay);
+                        let _ = local_action_tx.send(LocalAction::MoveMouse(ax, ay));
+                    }
                 }
                 Message::KeyEvent { .. }
+                | Message::KeyChar { .. }
+                | Message::KeyCombo { .. }
                 | Message::MouseButtonEvent { .. }
-                | Message::MouseScroll { .. } => {
+                | Message::MouseDoubleClick { .. }
+                | Message::Gesture { .. } => {
+                    if fm.key_blocked(&device_id, &msg, newly_pressed) {
+                        debug!(%device_id, ?msg, "key blocked by filter, not forwarding");
+                        return;
+                    }
+                    // 本地回显按本机的键位与按键模拟，不经过设备的输入变换
+                    if let Some(action) = fm.config.local_echo.action_for(&msg) {
+                        let _ = local_action_tx.send(action);
+                    }
                     // 转发给焦点设备
-                    let peers_r = peers.read().await;
-                    if let Some(peer) = peers_r.get(&device_id) {
-                        let _ = peer.tx.send(msg);
+                    let msg = fm.transform_for(&device_id, msg, (remote_w, remote_h));
+                    if !send_to_peer(peers, &device_id, msg).await {
+                        drop_dead_peer(&mut fm, peers, &device_id, local_action_tx, event_tx).await;
+                    }
+                }
+                Message::MouseScroll { dx, dy, precise } => {
+                    if let Some(action) = fm.config.local_echo.action_for(&msg) {
+                        let _ = local_action_tx.send(action);
+                    }
+                    // 换算为焦点设备声明的滚动单位；本机按像素报告的滚动视为高精度滚动
+                    let source = fm.config.scroll_unit;
+                    let target = peers
+                        .read()
+                        .await
+                        .get(&device_id)
+                        .map_or(source, |peer| peer.scroll_unit);
+                    let scroll = Message::MouseScroll {
+                        dx: source.convert(*dx, target),
+                        dy: source.convert(*dy, target),
+                        precise: *precise || source == ScrollUnit::Pixels,
+                    };
+                    if !send_to_peer(peers, &device_id, scroll).await {
+                        drop_dead_peer(&mut fm, peers, &device_id, local_action_tx, event_tx).await;
                     }
                 }
-                Message::ClipboardText { .. } | Message::ClipboardImage { .. } => {
-                    // 剪贴板同步给焦点设备
-                    let peers_r = peers.read().await;
-                    if let Some(peer) = peers_r.get(&device_id) {
-                        let _ = peer.tx.send(msg);
+                Message::ClipboardText { .. }
+                | Message::ClipboardImage { .. }
+                | Message::ClipboardClear { .. } => {
+                    fm.last_clipboard = Some(msg.clone());
+                    fm.clipboard_delivered.clear();
+                    // 剪贴板同步给焦点设备（键入粘贴策略下不同步）
+                    if !fm.config.clipboard_enabled_for(&device_id)
+                        || fm.config.paste_strategy == PasteStrategy::TypeText
+                    {
+                        return;
+                    }
+                    fm.clipboard_delivered.insert(device_id.clone());
+                    if !send_to_peer(peers, &device_id, msg).await {
+                        drop_dead_peer(&mut fm, peers, &device_id, local_action_tx, event_tx).await;
                     }
                 }
                 _ => {}
@@ -352,115 +2070,1067 @@ async fn handle_input_message(
     }
 }
 
-async fn handle_client(
-    stream: tokio::net::TcpStream,
-    peer_addr: std::net::SocketAddr,
-    peers: PeerMap,
-    focus: Arc<tokio::sync::Mutex<FocusManager>>,
-    event_tx: mpsc::UnboundedSender<ServerEvent>,
-    cancel: CancellationToken,
-    client_direction: &Edge,
-) -> Result<()> {
-    let mut framed = Framed::new(stream, MessageCodec);
+/// 取出输入与剪贴板通道中已到达的全部消息，与 `first` 合并为一批待处理的消息
+///
+/// 开启 `ordered` 时按采集序号合并两个通道；否则已到达的剪贴板变化先于输入处理，
+/// 越过边缘时焦点设备能拿到最新内容
+fn drain_batch(
+    first: Sequenced,
+    input_rx: &mut mpsc::UnboundedReceiver<Sequenced>,
+    clipboard_rx: &mut mpsc::UnboundedReceiver<Sequenced>,
+    ordered: bool,
+) -> Vec<Sequenced> {
+    let mut batch = vec![first];
+    if ordered {
+        while let Ok(msg) = input_rx.try_recv() {
+            batch.push(msg);
+        }
+        while let Ok(msg) = clipboard_rx.try_recv() {
+            batch.push(msg);
+        }
+        batch.sort_by_key(|item| item.seq);
+    } else {
+        while let Ok(item) = clipboard_rx.try_recv() {
+            batch.push(item);
+        }
+        batch.sort_by_key(|item| item.msg.priority() == Priority::High);
+    }
+    batch
+}
 
-    // 等待 Hello 握手
-    let (device_id, device_name, screens) = loop {
-        let msg = tokio::select! {
-            _ = cancel.cancelled() => return Ok(()),
-            result = framed.next() => match result {
+/// 处理外部控制命令，与边缘跨越走相同的焦点切换流程
+async fn handle_control(
+    control: ServerControl,
+    focus: &tokio::sync::Mutex<FocusManager>,
+    peers: &PeerMap,
+    transfers: &TransferMap,
+    local_action_tx: &mpsc::UnboundedSender<LocalAction>,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+) {
+    let mut fm = focus.lock().await;
+
+    match control {
+        ServerControl::SwitchFocus { device_id } => {
+            if fm.is_focused_on(&device_id) {
+                return;
+            }
+            let entry = fm.edge_devices
+                .iter()
+                .find(|(_, (id, _, _))| *id == device_id)
+                .map(|(edge, _)| (*edge, fm.last_local))
+                .or_else(|| fm.portal_entry(&device_id).filter(|_| {
+                    fm.device_screens.contains_key(&device_id)
+                }));
+            let (edge, (x, y)) = match entry {
+                Some(entry) => entry,
+                None => {
+                    warn!(%device_id, "cannot switch focus: device not assigned to an edge");
+                    let _ = event_tx.send(ServerEvent::Log(format!(
+                        "Cannot switch focus to {device_id}: device not connected"
+                    )));
+                    return;
+                }
+            };
+            if let FocusState::Remote { virtual_x, virtual_y, .. } = fm.state {
+                leave_remote(&mut fm, peers, virtual_x, virtual_y, None, local_action_tx, event_tx)
+                    .await;
+            }
+            enter_remote(&mut fm, peers, edge, x, y, local_action_tx, event_tx).await;
+        }
+        ServerControl::ReturnLocal => {
+            if let FocusState::Remote { virtual_x, virtual_y, .. } = fm.state {
+                leave_remote(&mut fm, peers, virtual_x, virtual_y, None, local_action_tx, event_tx)
+                    .await;
+            }
+        }
+        ServerControl::ApprovePairing { device_id } => {
+            if let Some(decision) = fm.pending_pairings.remove(&device_id) {
+                let _ = decision.send(true);
+            }
+        }
+        ServerControl::RejectPairing { device_id } => {
+            if let Some(decision) = fm.pending_pairings.remove(&device_id) {
+                let _ = decision.send(false);
+            }
+        }
+        ServerControl::LockAll => {
+            info!("locking all devices");
+            // 先切回本地，避免锁屏后本地鼠标仍被锁在屏幕中心
+            if let FocusState::Remote { virtual_x, virtual_y, .. } = fm.state {
+                leave_remote(&mut fm, peers, virtual_x, virtual_y, None, local_action_tx, event_tx)
+                    .await;
+            }
+            // 仅剪贴板设备不接受控制，不锁定
+            for peer in peers.read().await.values().filter(|peer| !peer.clipboard_only) {
+                peer.send(Message::LockScreen);
+            }
+            let _ = local_action_tx.send(LocalAction::LockScreen);
+            let _ = event_tx.send(ServerEvent::Log("Locking all devices".into()));
+        }
+        ServerControl::SetDeviceEdges { device_id, edges } => {
+            if edges.is_empty() {
+                warn!(%device_id, "ignoring empty edge list");
+                return;
+            }
+            info!(%device_id, ?edges, "assigning device to edges");
+            let mut peers = peers.write().await;
+            if let Some(peer) = peers.get_mut(&device_id).filter(|peer| !peer.clipboard_only) {
+                let displaced = fm.edge_owners(&edges, &device_id);
+                fm.set_device_edges(&device_id, &edges, peer.screen_w, peer.screen_h);
+                peer.edge = Some(edges[0]);
+                update_displaced(&mut fm, &mut peers, displaced, event_tx);
+            }
+            fm.device_edges.insert(device_id, edges);
+        }
+        ServerControl::UpdateScreen { rect } => {
+            if rect.width == 0 || rect.height == 0 {
+                warn!(?rect, "ignoring empty screen rect");
+                return;
+            }
+            info!(?rect, "local screen changed");
+            fm.set_screen(rect);
+            // 焦点在远程时光标锁定位置随之变化，立即移过去，否则下一次位移会按旧位置计算
+            if let FocusState::Remote { virtual_x, virtual_y, remote_w, remote_h, .. } = fm.state {
+                let (ax, ay) = fm.park_position(virtual_x, virtual_y, remote_w, remote_h);
+                fm.anchor = (ax, ay);
+                let _ = local_action_tx.send(LocalAction::MoveMouse(ax, ay));
+            }
+            let _ = event_tx.send(ServerEvent::Log(format!(
+                "Local screen changed to {}x{}",
+                rect.width, rect.height
+            )));
+        }
+        ServerControl::SetInputTransform { device_id, transform } => {
+            info!(%device_id, ?transform, "setting input transform");
+            if transform.is_identity() {
+                fm.input_transforms.remove(&device_id);
+            } else {
+                fm.input_transforms.insert(device_id, transform);
+            }
+        }
+        ServerControl::SetLocalEcho(echo) => {
+            info!(?echo, "setting local echo");
+            fm.config.local_echo = echo;
+        }
+        ServerControl::SetEdgeGap { device_id, gap } => {
+            info!(%device_id, gap, "setting edge gap");
+            if gap == 0 {
+                fm.edge_gaps.remove(&device_id);
+            } else {
+                fm.edge_gaps.insert(device_id, gap as f64);
+            }
+        }
+        ServerControl::SetKeyFilter { device_id, filter } => {
+            info!(%device_id, ?filter, "setting key filter");
+            if filter.is_empty() {
+                fm.key_filters.remove(&device_id);
+            } else {
+                fm.key_filters.insert(device_id, filter);
+            }
+        }
+        ServerControl::SetMotionScale { device_id, scale } => {
+            if !scale.is_finite() || scale <= 0.0 {
+                warn!(%device_id, scale, "ignoring invalid motion scale");
+                return;
+            }
+            info!(%device_id, scale, "setting motion scale");
+            if scale == 1.0 {
+                fm.motion_scales.remove(&device_id);
+            } else {
+                fm.motion_scales.insert(device_id, scale);
+            }
+        }
+        ServerControl::SetDeviceLabel { device_id, label } => {
+            info!(%device_id, ?label, "setting device label");
+            match &label {
+                Some(label) => fm.config.device_labels.insert(device_id.clone(), label.clone()),
+                None => fm.config.device_labels.remove(&device_id),
+            };
+            if let Some(peer) = peers.write().await.get_mut(&device_id) {
+                peer.label = label;
+                let _ = event_tx.send(ServerEvent::DeviceRenamed {
+                    device_id,
+                    device_name: peer.display_name().to_string(),
+                });
+            }
+        }
+        ServerControl::SetEdgePortal { edge, start, end, device_id } => {
+            if !(0.0..1.0).contains(&start) || end <= start || end > 1.0 {
+                warn!(?edge, start, end, "ignoring invalid edge portal range");
+                return;
+            }
+            info!(%device_id, ?edge, start, end, "assigning device to edge portal");
+            // 传送门取代设备原先占用的整条边缘
+            fm.remove_device(&device_id);
+            fm.set_edge_portal(EdgePortal { edge, start, end, device_id: device_id.clone() });
+            if let Some(peer) = peers.write().await.get_mut(&device_id) {
+                peer.edge = Some(edge);
+            }
+        }
+        ServerControl::CancelTransfer { transfer_id } => {
+            let Some(status) = transfers.get(transfer_id) else {
+                debug!(transfer_id, "cancel requested for unknown transfer");
+                return;
+            };
+            let removed = peers.read().await.get(&status.device_id).is_some_and(|peer| {
+                match status.state {
+                    TransferState::Queued => peer.queue.remove_bulk(transfer_id),
+                    // 已写出：通知设备丢弃，不支持取消的设备只停止跟踪回执
+                    TransferState::AwaitingAck => {
+                        peer.send_ahead(Message::ClipboardCancel { transfer_id });
+                        true
+                    }
+                }
+            });
+            if removed {
+                transfers.remove(transfer_id);
+                info!(transfer_id, device_id = %status.device_id, "clipboard transfer cancelled");
+                let _ = event_tx.send(ServerEvent::TransferCancelled {
+                    transfer_id,
+                    device_id: status.device_id,
+                });
+            }
+        }
+    }
+}
+
+/// 粘贴快捷键：读取当前剪贴板文本，以键入方式输入到焦点设备（焦点在本地时在本机键入）
+async fn type_clipboard(
+    fm: &mut FocusManager,
+    peers: &PeerMap,
+    local_action_tx: &mpsc::UnboundedSender<LocalAction>,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+    clipboard_reader: Option<&ClipboardReader>,
+) {
+    let reader = match clipboard_reader {
+        Some(reader) => reader.clone(),
+        None => return,
+    };
+    let text = match tokio::task::spawn_blocking(move || reader()).await {
+        Ok(Some(Message::ClipboardText { text, .. })) => text,
+        _ => {
+            let _ = event_tx.send(ServerEvent::Log("Paste hotkey: clipboard has no text".into()));
+            return;
+        }
+    };
+    let len = text.chars().count();
+    if len > fm.config.max_type_text_len {
+        let _ = event_tx.send(ServerEvent::Log(format!(
+            "Paste hotkey: clipboard text too long to type ({len} chars, max {})",
+            fm.config.max_type_text_len
+        )));
+        return;
+    }
+
+    match &fm.state {
+        FocusState::Local => {
+            // 快捷键仍按着，先释放，否则键入的字符会与修饰键组成快捷键
+            for key in fm.pressed_keys.iter().copied() {
+                let _ = local_action_tx.send(LocalAction::Key(key, KeyAction::Release));
+            }
+            let _ = local_action_tx.send(LocalAction::TypeText(text));
+        }
+        FocusState::Remote { device_id, .. } => {
+            let device_id = device_id.clone();
+            // 先释放远程已按下的快捷键，避免键入的字符被当作组合键
+            let delivered = release_remote_keys(fm, peers, &device_id).await
+                && send_to_peer(peers, &device_id, Message::TypeText { text }).await;
+            if !delivered {
+                drop_dead_peer(fm, peers, &device_id, local_action_tx, event_tx).await;
+            }
+        }
+    }
+}
+
+/// 向远程设备发送当前所有已按下键的释放事件，通道已关闭时返回 `false`
+async fn release_remote_keys(fm: &FocusManager, peers: &PeerMap, device_id: &str) -> bool {
+    for key in fm.pressed_keys.iter().copied() {
+        let release = Message::KeyEvent { key, action: KeyAction::Release };
+        if !send_to_peer(peers, device_id, release).await {
+            return false;
+        }
+    }
+    true
+}
+
+/// 空闲超时：焦点在远程时自动切回本地
+async fn handle_idle_timeout(
+    focus: &tokio::sync::Mutex<FocusManager>,
+    peers: &PeerMap,
+    local_action_tx: &mpsc::UnboundedSender<LocalAction>,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+) {
+    let mut fm = focus.lock().await;
+    if let FocusState::Remote { virtual_x, virtual_y, .. } = fm.state {
+        info!("idle timeout, returning focus to local");
+        let _ = event_tx.send(ServerEvent::Log(
+            "No input activity, control returned to local".into(),
+        ));
+        leave_remote(&mut fm, peers, virtual_x, virtual_y, None, local_action_tx, event_tx).await;
+    }
+}
+
+/// 焦点切换到 `edge` 上的远程设备：锁定本地鼠标并通知设备进入屏幕
+///
+/// `(x, y)` 为触发切换时的本地鼠标位置
+async fn enter_remote(
+    fm: &mut FocusManager,
+    peers: &PeerMap,
+    edge: Edge,
+    x: f64,
+    y: f64,
+    local_action_tx: &mpsc::UnboundedSender<LocalAction>,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+) {
+    let (device_id, rw, rh, entry_x, entry_y) = match fm.target_at(edge, x, y) {
+        Some(target) => target,
+        None => return,
+    };
+    let (vx, vy) = FocusManager::entry_position(
+        &edge,
+        entry_x - fm.origin_x as f64,
+        entry_y - fm.origin_y as f64,
+        fm.screen_w,
+        fm.screen_h,
+        rw,
+        rh,
+    );
+    let (gap_x, gap_y) = FocusManager::gap_offset(&edge, fm.edge_gap(&device_id));
+    let (vx, vy) = (vx + gap_x, vy + gap_y);
+    info!(
+        %device_id, ?edge, vx, vy,
+        "focus switching to remote device"
+    );
+    fm.state = FocusState::Remote {
+        device_id: device_id.clone(),
+        virtual_x: vx,
+        virtual_y: vy,
+        remote_w: rw,
+        remote_h: rh,
+        entered_edge: edge,
+        return_x: x as i32,
+        return_y: y as i32,
+    };
+    // 锁定鼠标到屏幕中心（跟随模式下为映射位置）
+    let (ax, ay) = fm.park_position(vx, vy, rw, rh);
+    fm.anchor = (ax, ay);
+    let _ = local_action_tx.send(LocalAction::MoveMouse(ax, ay));
+    if fm.config.hide_cursor && !fm.config.follow_cursor {
+        let _ = local_action_tx.send(LocalAction::HideCursor);
+    }
+    // 位移来自原始运动，锁住本地指针使其停在锚点，本机程序也收不到点击
+    if fm.config.relative_motion && !fm.config.follow_cursor {
+        let _ = local_action_tx.send(LocalAction::LockPointer);
+    }
+    // 切换前的复制尚未同步到该设备时先补发，插在 EnterScreen 之前，
+    // 使控制权到达时远程剪贴板已是最新
+    let stale_clipboard = fm.last_clipboard.clone().filter(|_| {
+        fm.config.clipboard_enabled_for(&device_id)
+            && fm.config.paste_strategy != PasteStrategy::TypeText
+            && !fm.clipboard_delivered.contains(&device_id)
+    });
+    if let Some(clipboard) = stale_clipboard {
+        if let Some(peer) = peers.read().await.get(&device_id) {
+            debug!(%device_id, "flushing clipboard ahead of EnterScreen");
+            peer.send_ahead(clipboard);
+        }
+        fm.clipboard_delivered.insert(device_id.clone());
+    }
+    // 通知 Client 进入屏幕，并发送初始绝对定位
+    let delivered = send_to_peer(peers, &device_id, Message::EnterScreen {
+        screen_id: ScreenId(0),
+        position: ScreenPosition { x: vx, y: vy },
+    }).await && send_to_peer(
+        peers, &device_id, Message::MouseMove { x: vx, y: vy },
+    ).await;
+    if !delivered {
+        drop_dead_peer(fm, peers, &device_id, local_action_tx, event_tx).await;
+        return;
+    }
+    fm.last_switch = Some(Instant::now());
+    let target = peers.read().await.get(&device_id).map(|peer| FocusTarget {
+        device_name: peer.display_name().to_string(),
+        width: rw,
+        height: rh,
+    });
+    emit_focus_changed(fm, event_tx, "local".into(), device_id, Some(edge), target);
+}
+
+/// 焦点从远程设备切回本地，`(vx, vy)` 为离开时的虚拟光标位置
+///
+/// `exit_edge` 为 `None` 表示非边缘触发（如外部控制），此时将本地鼠标恢复到切换前的位置
+async fn leave_remote(
+    fm: &mut FocusManager,
+    peers: &PeerMap,
+    vx: f64,
+    vy: f64,
+    exit_edge: Option<Edge>,
+    local_action_tx: &mpsc::UnboundedSender<LocalAction>,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+) {
+    let (device_id, entered_edge, return_x, return_y) = match &fm.state {
+        FocusState::Remote { device_id, entered_edge, return_x, return_y, .. } => {
+            (device_id.clone(), *entered_edge, *return_x, *return_y)
+        }
+        FocusState::Local => return,
+    };
+    info!(%device_id, "focus switching back to local");
+    // 发送 LeaveScreen 给 Client
+    let delivered = send_to_peer(peers, &device_id, Message::LeaveScreen {
+        screen_id: ScreenId(0),
+        edge: FocusManager::opposite_edge(&entered_edge),
+        position: ScreenPosition { x: vx, y: vy },
+    }).await;
+    if !delivered {
+        drop_dead_peer(fm, peers, &device_id, local_action_tx, event_tx).await;
+        return;
+    }
+    fm.state = FocusState::Local;
+    if fm.config.relative_motion {
+        let _ = local_action_tx.send(LocalAction::UnlockPointer);
+    }
+    if fm.config.hide_cursor {
+        let _ = local_action_tx.send(LocalAction::ShowCursor);
+    }
+    if exit_edge.is_none() {
+        let (x, y) = fm.return_position(return_x, return_y);
+        let _ = local_action_tx.send(LocalAction::MoveMouse(x, y));
+    }
+    fm.last_switch = Some(Instant::now());
+    fm.last_return = fm.last_switch;
+    emit_focus_changed(fm, event_tx, device_id, "local".into(), exit_edge, None);
+    if exit_edge.is_some() {
+        let _ = event_tx.send(ServerEvent::EdgeCrossed {
+            edge: entered_edge,
+            target: "local".into(),
+        });
+    }
+}
+
+/// 向设备发送消息，仅在其发送通道已关闭时返回 `false`
+///
+/// 设备未注册（例如正在等待重连）时消息被丢弃，视为成功
+async fn send_to_peer(peers: &PeerMap, device_id: &str, msg: Message) -> bool {
+    match peers.read().await.get(device_id) {
+        Some(peer) => peer.send(msg),
+        None => true,
+    }
+}
+
+/// 设备发送通道已关闭：立即移除该设备，焦点在其上时切回本地
+async fn drop_dead_peer(
+    fm: &mut FocusManager,
+    peers: &PeerMap,
+    device_id: &str,
+    local_action_tx: &mpsc::UnboundedSender<LocalAction>,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+) {
+    warn!(%device_id, "peer channel closed, dropping device");
+    peers.write().await.remove(device_id);
+    fm.remove_device(device_id);
+    force_return_local(fm, device_id, local_action_tx, event_tx);
+}
+
+/// 焦点设备异常断开：强制切回本地并恢复鼠标位置
+fn force_return_local(
+    fm: &mut FocusManager,
+    device_id: &str,
+    local_action_tx: &mpsc::UnboundedSender<LocalAction>,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+) {
+    if let Some((x, y)) = fm.release_focus(device_id) {
+        warn!(%device_id, "focused device lost, forcing focus back to local");
+        fm.last_return = Some(Instant::now());
+        if fm.config.relative_motion {
+            let _ = local_action_tx.send(LocalAction::UnlockPointer);
+        }
+        let _ = local_action_tx.send(LocalAction::MoveMouse(x, y));
+        if fm.config.hide_cursor {
+            let _ = local_action_tx.send(LocalAction::ShowCursor);
+        }
+        let _ = event_tx.send(ServerEvent::Log(format!(
+            "Device {device_id} disconnected while focused, control returned to local"
+        )));
+        emit_focus_changed(fm, event_tx, device_id.to_string(), "local".into(), None, None);
+    }
+}
+
+/// 发送焦点切换事件（简单版与详细版各一条）
+///
+/// 目标与上次发出的相同时不重复发送
+fn emit_focus_changed(
+    fm: &mut FocusManager,
+    event_tx: &mpsc::UnboundedSender<ServerEvent>,
+    from: String,
+    to: String,
+    edge: Option<Edge>,
+    target: Option<FocusTarget>,
+) {
+    if fm.last_focus_target == to {
+        return;
+    }
+    fm.last_focus_target = to.clone();
+    let _ = event_tx.send(ServerEvent::FocusChanged { target: to.clone() });
+    let _ = event_tx.send(ServerEvent::FocusChangedDetailed { from, to, edge, target });
+}
+
+/// 剪贴板历史回复的条目：去掉对端不支持的条目，并从最旧的一端截断，使整条回复不超过单帧上限
+fn history_reply(items: Vec<Message>, capabilities: Capabilities) -> Vec<Message> {
+    // 预留消息标签与条目数的编码开销
+    let mut budget = MAX_FRAME_SIZE as u64 - 64;
+    items
+        .into_iter()
+        .filter(|item| capabilities.permits(item))
+        .take_while(|item| match budget.checked_sub(encoded_len(item)) {
+            Some(rest) => {
+                budget = rest;
+                true
+            }
+            None => false,
+        })
+        .collect()
+}
+
+/// 校验客户端在 Hello 中上报的屏幕列表：非空、ID 不重复、尺寸非零
+fn validate_screens(screens: &[ScreenInfo]) -> Result<()> {
+    if screens.is_empty() {
+        anyhow::bail!("client reported no screens");
+    }
+    let mut ids = HashSet::new();
+    for screen in screens {
+        if !ids.insert(screen.id.0) {
+            anyhow::bail!("duplicate screen id {}", screen.id.0);
+        }
+        if screen.rect.width == 0 || screen.rect.height == 0 {
+            anyhow::bail!(
+                "screen {} has zero size ({}x{})",
+                screen.id.0, screen.rect.width, screen.rect.height
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 优雅停止单个连接：关闭队列不再接受新消息，在时限内发完已排队的消息后发送 `Bye`
+async fn drain_peer<T: Transport>(
+    framed: &mut T,
+    queue: &PeerQueue,
+    metrics: &Metrics,
+    server_id: synapse_protocol::DeviceId,
+    timeout: Duration,
+) -> Result<()> {
+    queue.close();
+    let flush = async {
+        while let Some(msg) = queue.try_recv_high() {
+            framed.feed(msg).await?;
+            metrics.record_forwarded();
+        }
+        while let Some(msg) = queue.try_recv_bulk() {
+            framed.feed(msg).await?;
+            metrics.record_forwarded();
+        }
+        framed.send(Message::Bye { device_id: server_id }).await
+    };
+    match tokio::time::timeout(timeout, flush).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!(?timeout, "send queue not drained in time, closing connection");
+            Ok(())
+        }
+    }
+}
+
+/// 整个会话处于 `client` span 中，握手后记录 `device_id`，会话内的日志都带有设备上下文
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(name = "client", skip_all, fields(%peer_addr, device_id = tracing::field::Empty))]
+async fn handle_client<T: Transport>(
+    mut framed: T,
+    peer_addr: String,
+    compression: Option<CompressionHandle>,
+    peers: PeerMap,
+    transfers: Arc<TransferMap>,
+    focus: Arc<tokio::sync::Mutex<FocusManager>>,
+    local_action_tx: mpsc::UnboundedSender<LocalAction>,
+    event_tx: mpsc::UnboundedSender<ServerEvent>,
+    cancel: CancellationToken,
+    client_direction: &Edge,
+    clipboard_reader: Option<ClipboardReader>,
+    clipboard_history: Option<ClipboardHistoryReader>,
+    metrics: Arc<Metrics>,
+    udp: Option<Arc<UdpMotion>>,
+) -> Result<()> {
+    let hostname = hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "server".into());
+
+    // 等待 Hello 握手
+    let (
+        device_id, device_name, screens, preferred_edge, clipboard_only, scroll_unit, capabilities,
+        absolute_pointer, client_compression, pairing_key,
+    ) = loop {
+        let msg = tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            result = framed.next() => match result {
                 Some(Ok(msg)) => msg,
-                Some(Err(e)) => return Err(e.into()),
+                Some(Err(e)) => return Err(e),
                 None => return Ok(()),
             },
         };
         match msg {
-            Message::Hello { device_id, device_name, screens } => {
-                break (device_id.0.clone(), device_name.clone(), screens);
+            Message::Hello {
+                protocol_version, device_id, device_name, screens, preferred_edge, clipboard_only,
+                scroll_unit, capabilities, absolute_pointer, compression, pairing_key,
+            } => {
+                if protocol_version != PROTOCOL_VERSION {
+                    warn!(%peer_addr, protocol_version, "incompatible protocol version, rejecting");
+                    let _ = event_tx.send(ServerEvent::Log(format!(
+                        "Rejected {device_name}: protocol version {protocol_version}, \
+                         expected {PROTOCOL_VERSION}"
+                    )));
+                    let _ = framed.send(Message::Bye {
+                        device_id: synapse_protocol::DeviceId(hostname),
+                    }).await;
+                    return Err(SynapseError::ProtocolVersion {
+                        local: PROTOCOL_VERSION,
+                        remote: protocol_version,
+                    }
+                    .into());
+                }
+                break (
+                    device_id.0, device_name, screens, preferred_edge, clipboard_only, scroll_unit,
+                    Capabilities::local().intersection(capabilities), absolute_pointer, compression,
+                    pairing_key,
+                );
             }
             _ => {
                 warn!(%peer_addr, "expected Hello, got {:?}", msg);
             }
         }
     };
+    tracing::Span::current().record("device_id", device_id.as_str());
+    let clipboard_only =
+        clipboard_only || focus.lock().await.config.clipboard_only_devices.contains(&device_id);
+
+    // 固定布局：套用设备的条目，启用白名单时拒绝未列出的设备
+    let (layout, reject_unlisted) = {
+        let fm = focus.lock().await;
+        (fm.config.layout_for(&device_id, &device_name).cloned(), fm.config.reject_unlisted)
+    };
+    if layout.is_none() && reject_unlisted {
+        warn!(%peer_addr, %device_id, "rejecting device not in layout");
+        let _ = event_tx.send(ServerEvent::Log(format!(
+            "Rejected {device_name}: not listed in the device layout"
+        )));
+        let _ = framed.send(Message::Bye {
+            device_id: synapse_protocol::DeviceId(hostname),
+        }).await;
+        return Ok(());
+    }
+    let absolute_pointer = layout
+        .as_ref()
+        .and_then(|entry| entry.absolute_pointer)
+        .unwrap_or(absolute_pointer);
+    if layout.as_ref().is_some_and(|entry| !entry.clipboard) {
+        focus.lock().await.config.clipboard_opt_out.insert(device_id.clone());
+    }
+
+    // 屏幕信息无效时拒绝握手，避免按错误尺寸映射坐标
+    if let Err(e) = validate_screens(&screens) {
+        warn!(%peer_addr, %device_id, "rejecting client: {e}");
+        let _ = event_tx.send(ServerEvent::Log(format!(
+            "Rejected {device_name}: {e}"
+        )));
+        let _ = framed.send(Message::Bye {
+            device_id: synapse_protocol::DeviceId(hostname),
+        }).await;
+        return Err(e);
+    }
+
+    // 未知设备需用户确认后才接入，确认前不回复 Welcome，也不转发任何输入；
+    // 信任按配对密钥的指纹记录，仅冒用设备 ID 的连接仍需确认
+    // 检查与登记在同一把锁内完成，避免两个同 ID 的连接互相覆盖等待中的确认
+    let fingerprint = (!pairing_key.is_empty()).then(|| crate::pairing::fingerprint(&pairing_key));
+    let pairing = {
+        let mut fm = focus.lock().await;
+        if !fm.config.needs_pairing(fingerprint.as_deref()) {
+            None
+        } else if fm.pending_pairings.contains_key(&device_id) {
+            Some(None)
+        } else {
+            let (decision_tx, decision_rx) = oneshot::channel();
+            fm.pending_pairings.insert(device_id.clone(), decision_tx);
+            Some(Some(decision_rx))
+        }
+    };
+    if let Some(None) = pairing {
+        warn!(%peer_addr, %device_id, "pairing already pending for this device, rejecting");
+        let _ = event_tx.send(ServerEvent::Log(format!(
+            "Pairing with {device_name} already pending, rejected duplicate connection"
+        )));
+        let _ = framed.send(Message::Bye {
+            device_id: synapse_protocol::DeviceId(hostname),
+        }).await;
+        return Ok(());
+    }
+    if let Some(Some(decision_rx)) = pairing {
+        info!(%peer_addr, %device_id, "waiting for pairing approval");
+        let _ = event_tx.send(ServerEvent::PairingRequest {
+            device_id: device_id.clone(),
+            device_name: device_name.clone(),
+            fingerprint: fingerprint.clone(),
+        });
+        let approved = tokio::select! {
+            _ = cancel.cancelled() => false,
+            decision = decision_rx => decision.unwrap_or(false),
+            // 等待期间对端断开
+            _ = async { while let Some(Ok(_)) = framed.next().await {} } => false,
+        };
+        let mut fm = focus.lock().await;
+        fm.pending_pairings.remove(&device_id);
+        if !approved {
+            drop(fm);
+            info!(%peer_addr, %device_id, "pairing rejected");
+            let _ = event_tx.send(ServerEvent::Log(format!("Pairing with {device_name} rejected")));
+            let _ = framed.send(Message::Bye {
+                device_id: synapse_protocol::DeviceId(hostname),
+            }).await;
+            return Ok(());
+        }
+        // 没有密钥的设备无法识别，只允许本次接入
+        if let Some(fingerprint) = fingerprint {
+            fm.config.trusted_devices.insert(fingerprint);
+        }
+        let _ = event_tx.send(ServerEvent::Log(format!("Pairing with {device_name} approved")));
+    }
 
     // 回复 Welcome（携带 Server 屏幕信息）
-    let hostname = hostname::get()
-        .map(|h| h.to_string_lossy().to_string())
-        .unwrap_or_else(|_| "server".into());
     let fm = focus.lock().await;
-    let server_screen = ScreenInfo {
-        id: ScreenId(0),
-        name: "primary".into(),
-        rect: ScreenRect {
-            x: 0, y: 0,
-            width: fm.screen_w,
-            height: fm.screen_h,
-        },
-        is_primary: true,
+    let server_screens = if fm.config.screens.is_empty() {
+        vec![ScreenInfo {
+            id: ScreenId(0),
+            name: "Display-1".into(),
+            rect: ScreenRect {
+                x: fm.origin_x,
+                y: fm.origin_y,
+                width: fm.screen_w,
+                height: fm.screen_h,
+            },
+            is_primary: true,
+        }]
+    } else {
+        fm.config.screens.clone()
+    };
+    // 按服务端偏好选出双方都支持的压缩算法；无法切换该连接的编解码器时不压缩
+    let chosen = match compression {
+        Some(_) => Compression::negotiate(&fm.config.compression, &client_compression),
+        None => Compression::None,
+    };
+    // 帧同步同样需要能切换该连接的编解码器，未启用时不在 Welcome 中声明
+    let mut server_capabilities = Capabilities::local();
+    if !fm.config.frame_sync || compression.is_none() {
+        server_capabilities.remove(Capability::FrameSync);
+    }
+    let frame_sync = server_capabilities.contains(Capability::FrameSync)
+        && capabilities.contains(Capability::FrameSync);
+    // UDP 旁路需要知道对端 IP，同样只用于直接接入的 TCP 连接；仅剪贴板设备不收运动
+    let peer_ip = peer_addr.parse::<SocketAddr>().ok().map(|addr| addr.ip());
+    let udp = match (udp, peer_ip) {
+        (Some(udp), Some(ip)) if compression.is_some() && !clipboard_only => Some((udp, ip)),
+        _ => {
+            server_capabilities.remove(Capability::UdpMotion);
+            None
+        }
     };
     drop(fm);
 
+    let server_id = synapse_protocol::DeviceId(hostname.clone());
     framed.send(Message::Welcome {
-        device_id: synapse_protocol::DeviceId(hostname.clone()),
+        protocol_version: PROTOCOL_VERSION,
+        device_id: server_id.clone(),
         device_name: hostname,
-        screens: vec![server_screen],
+        screens: server_screens,
+        capabilities: server_capabilities,
+        compression: chosen,
     }).await?;
+    // Welcome 本身不压缩，之后的帧按协商结果压缩
+    if let Some(handle) = &compression {
+        handle.set(chosen);
+        handle.set_frame_sync(frame_sync);
+    }
+    debug!(%device_id, ?chosen, frame_sync, "negotiated frame format");
+    let udp_path = udp
+        .filter(|_| capabilities.contains(Capability::UdpMotion))
+        .map(|(udp, ip)| {
+            let path = udp.register(&device_id, ip);
+            (udp, path)
+        });
+    if let Some((udp, path)) = &udp_path {
+        let offer = Message::UdpOffer { port: udp.port(), token: path.token() };
+        if let Err(e) = framed.send(offer).await {
+            udp.unregister(path.token());
+            return Err(e);
+        }
+        debug!(%device_id, port = udp.port(), "offered UDP motion channel");
+    }
 
     info!(%peer_addr, %device_id, %device_name, "client handshake complete");
+    metrics.device_connected(&device_id);
+    let label = focus.lock().await.config.device_labels.get(&device_id).cloned();
     let _ = event_tx.send(ServerEvent::DeviceConnected {
         device_id: device_id.clone(),
-        device_name: device_name.clone(),
+        device_name: label.clone().unwrap_or_else(|| device_name.clone()),
     });
 
-    // 从 Client 的 Hello.screens 获取屏幕尺寸
-    let (client_w, client_h) = if let Some(s) = screens.first() {
-        (s.rect.width, s.rect.height)
-    } else {
-        (1920, 1080) // 默认值
-    };
+    // 从 Client 的 Hello.screens 获取屏幕尺寸（已校验非空），优先使用主屏
+    let client_screen = screens.iter().find(|s| s.is_primary).unwrap_or(&screens[0]);
+    let (client_w, client_h) = (client_screen.rect.width, client_screen.rect.height);
 
     // 注册到 peer map 并设置边缘设备
-    let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<Message>();
+    let (queue_policy, drain_timeout) = {
+        let fm = focus.lock().await;
+        (fm.config.queue_policy, fm.config.drain_timeout)
+    };
+    let queue = Arc::new(PeerQueue::new(queue_policy, device_id.clone(), transfers.clone()));
     {
         let mut peers_w = peers.write().await;
         peers_w.insert(device_id.clone(), PeerInfo {
-            tx: outgoing_tx,
+            queue: queue.clone(),
             screen_w: client_w,
             screen_h: client_h,
+            device_name: device_name.clone(),
+            label,
+            edge: None,
+            screens: screens.clone(),
+            clipboard_only,
+            scroll_unit,
+            capabilities,
+            absolute_pointer,
+            udp: udp_path.as_ref().map(|(_, path)| path.clone()),
         });
     }
-    {
+    if clipboard_only {
+        info!(%device_id, "clipboard-only device, not assigning an edge");
+        let _ = event_tx.send(ServerEvent::Log(format!(
+            "{device_name} connected as a clipboard-only device"
+        )));
+    } else {
         let mut fm = focus.lock().await;
-        fm.set_edge_device(client_direction.clone(), device_id.clone(), client_w, client_h);
+        // 固定布局中的边缘优先；其次采用客户端请求的边缘，该边缘已被其他设备占用时退回服务端配置
+        let edge = match (layout.as_ref(), preferred_edge) {
+            (Some(entry), _) => {
+                let _ = event_tx.send(ServerEvent::Log(format!(
+                    "{device_name} placed on the {:?} edge per the device layout",
+                    entry.edge
+                )));
+                entry.edge
+            }
+            (None, Some(preferred)) => match fm.edge_devices.get(&preferred) {
+                Some((owner, _, _)) if *owner != device_id => {
+                    let _ = event_tx.send(ServerEvent::Log(format!(
+                        "{device_name} requested the {preferred:?} edge, which is taken by {owner}; \
+                         placing it on {client_direction:?}"
+                    )));
+                    *client_direction
+                }
+                _ => {
+                    let _ = event_tx.send(ServerEvent::Log(format!(
+                        "{device_name} placed on the {preferred:?} edge as requested"
+                    )));
+                    preferred
+                }
+            },
+            (None, None) => *client_direction,
+        };
+        fm.device_screens.insert(device_id.clone(), (client_w, client_h));
+        // 已通过 set_edge_portal 或 set_device_edges 指定布局的设备沿用该布局
+        let portal_edge = fm.edge_portals.iter().find(|p| p.device_id == device_id).map(|p| p.edge);
+        let (edge, displaced) = match (portal_edge, fm.device_edges.get(&device_id).cloned()) {
+            (Some(edge), _) => (edge, Vec::new()),
+            (None, Some(edges)) => {
+                let displaced = fm.edge_owners(&edges, &device_id);
+                fm.set_device_edges(&device_id, &edges, client_w, client_h);
+                (edges[0], displaced)
+            }
+            (None, None) => {
+                // 退回的边缘（服务端配置或布局指定）同样可能已被占用，新设备会取代原设备
+                let displaced = fm.edge_owners(&[edge], &device_id);
+                if let Some(owner) = displaced.first() {
+                    warn!(%device_id, %owner, ?edge, "edge already occupied, replacing its device");
+                    let _ = event_tx.send(ServerEvent::Log(format!(
+                        "{device_name} placed on the {edge:?} edge, which was taken by {owner}"
+                    )));
+                }
+                fm.set_edge_device(edge, device_id.clone(), client_w, client_h);
+                (edge, displaced)
+            }
+        };
+        {
+            let mut peers = peers.write().await;
+            if let Some(peer) = peers.get_mut(&device_id) {
+                peer.edge = Some(edge);
+            }
+            update_displaced(&mut fm, &mut peers, displaced, &event_tx);
+        }
         info!(
-            %device_id, ?client_direction, client_w, client_h,
+            %device_id, ?edge, ?preferred_edge, client_w, client_h,
             "registered edge device"
         );
+        // 宽限期内重连：恢复焦点
+        if let FocusState::Remote { virtual_x, virtual_y, .. } = fm.state {
+            if fm.is_focused_on(&device_id) {
+                info!(%device_id, "focused device reconnected, resuming control");
+                queue.push(Message::EnterScreen {
+                    screen_id: ScreenId(0),
+                    position: ScreenPosition { x: virtual_x, y: virtual_y },
+                });
+                let (x, y) = FocusManager::client_position(virtual_x, virtual_y, client_w, client_h);
+                queue.push(Message::MouseMove { x, y });
+            }
+        }
+    }
+
+    // 发送当前剪贴板快照，使新设备从一致的剪贴板状态开始
+    let clipboard_enabled = focus.lock().await.config.clipboard_enabled_for(&device_id);
+    if let (true, Some(reader)) = (clipboard_enabled, clipboard_reader) {
+        if let Ok(Some(snapshot)) = tokio::task::spawn_blocking(move || reader()).await {
+            if capabilities.permits(&snapshot) {
+                queue.push(snapshot);
+                focus.lock().await.clipboard_delivered.insert(device_id.clone());
+            }
+        }
     }
 
     // 消息循环
+    let mut pending_acks: HashMap<u64, tokio::time::Instant> = HashMap::new();
+    // 定期 Ping 设备，收到对应的 Pong 时记录往返时延；只跟踪最近发出的 Ping
+    let mut rtt_ping = tokio::time::interval(RTT_PING_INTERVAL);
+    rtt_ping.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut ping_seq = 0u64;
+    let mut ping_in_flight: Option<(u64, Instant)> = None;
     let result: Result<()> = async {
         loop {
+            let ack_deadline = pending_acks.values().min().copied();
+            let ack_timeout = async move {
+                match ack_deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+            // 先处理对端消息：发送队列持续有运动时，Ping/Bye 等控制消息不会被饿死；
+            // 高优先级队列先于剪贴板队列发送，避免大块剪贴板数据阻塞输入
             tokio::select! {
-                _ = cancel.cancelled() => break,
+                biased;
+                _ = cancel.cancelled() => {
+                    if let Some(timeout) = drain_timeout {
+                        drain_peer(&mut framed, &queue, &metrics, server_id.clone(), timeout).await?;
+                    }
+                    break;
+                }
                 incoming = framed.next() => {
                     match incoming {
                         Some(Ok(Message::Ping(seq))) => {
                             framed.send(Message::Pong(seq)).await?;
                         }
+                        Some(Ok(Message::Pong(seq))) => {
+                            if let Some((_, sent)) = ping_in_flight.take_if(|(expected, _)| *expected == seq) {
+                                metrics.record_rtt(&device_id, sent.elapsed());
+                            }
+                        }
                         Some(Ok(Message::Bye { .. })) => break,
+                        Some(Ok(Message::ClipboardAck { transfer_id })) => {
+                            // 已取消的传输不再登记，其回执忽略
+                            if pending_acks.remove(&transfer_id).is_some()
+                                && transfers.remove(transfer_id).is_some()
+                            {
+                                debug!(%device_id, transfer_id, "clipboard transfer acknowledged");
+                                let _ = event_tx.send(ServerEvent::ClipboardSynced {
+                                    device_id: device_id.clone(),
+                                    transfer_id,
+                                });
+                            }
+                        }
+                        Some(Ok(Message::ClipboardHistoryRequest)) => {
+                            // 未启用剪贴板同步的设备只收到空历史
+                            let reader = clipboard_history.clone().filter(|_| clipboard_enabled);
+                            let items = match reader {
+                                Some(reader) => tokio::task::spawn_blocking(move || reader())
+                                    .await
+                                    .unwrap_or_default(),
+                                None => Vec::new(),
+                            };
+                            let items = history_reply(items, capabilities);
+                            debug!(%device_id, count = items.len(), "sending clipboard history");
+                            queue.push(Message::ClipboardHistory { items });
+                        }
                         Some(Ok(msg)) => {
-                            info!(%peer_addr, ?msg, "received from client");
+                            debug!(%peer_addr, ?msg, "received from client");
                         }
                         Some(Err(e)) => {
-                            error!(%peer_addr, "receive error: {e}");
+                            match e.downcast_ref::<CodecError>() {
+                                Some(codec_error) => {
+                                    error!(%peer_addr, "corrupt data from client, disconnecting: {codec_error}");
+                                    let _ = event_tx.send(ServerEvent::DecodeError {
+                                        device_id: device_id.clone(),
+                                        error: codec_error.to_string(),
+                                    });
+                                }
+                                None => error!(%peer_addr, "receive error: {e}"),
+                            }
                             break;
                         }
                         None => break,
                     }
                 }
-                Some(msg) = outgoing_rx.recv() => {
+                Some(msg) = queue.recv_high() => {
+                    // 连续到达的输入合并写出：先 feed 队列中已有的消息，取空后统一 flush，
+                    // 队列空闲时单条消息也会立即 flush，不增加延迟
+                    let mut next = Some(msg);
+                    for _ in 0..MAX_SEND_BATCH {
+                        let Some(msg) = next.take().or_else(|| queue.try_recv_high()) else {
+                            break;
+                        };
+                        // 插队发送的剪贴板（见 PeerInfo::send_ahead）同样等待确认
+                        let id = transfer_id(&msg);
+                        framed.feed(msg).await?;
+                        metrics.record_forwarded();
+                        if let Some(id) = id {
+                            transfers.mark_sent(id);
+                            pending_acks
+                                .insert(id, tokio::time::Instant::now() + CLIPBOARD_ACK_TIMEOUT);
+                        }
+                    }
+                    framed.flush().await?;
+                }
+                Some(msg) = queue.recv_bulk() => {
+                    // transfer_id 已在入队时分配
+                    let id = transfer_id(&msg);
                     framed.send(msg).await?;
+                    metrics.record_forwarded();
+                    if let Some(id) = id {
+                        transfers.mark_sent(id);
+                        pending_acks.insert(id, tokio::time::Instant::now() + CLIPBOARD_ACK_TIMEOUT);
+                    }
+                }
+                _ = rtt_ping.tick() => {
+                    ping_seq += 1;
+                    framed.send(Message::Ping(ping_seq)).await?;
+                    ping_in_flight = Some((ping_seq, Instant::now()));
+                }
+                _ = ack_timeout => {
+                    let now = tokio::time::Instant::now();
+                    pending_acks.retain(|transfer_id, deadline| {
+                        if *deadline > now {
+                            return true;
+                        }
+                        if transfers.remove(*transfer_id).is_none() {
+                            return false;
+                        }
+                        warn!(%device_id, transfer_id, "clipboard transfer was not acknowledged");
+                        let _ = event_tx.send(ServerEvent::Log(format!(
+                            "Clipboard transfer {transfer_id} to {device_name} was not acknowledged"
+                        )));
+                        false
+                    });
                 }
             }
         }
@@ -468,10 +3138,43 @@ async fn handle_client(
     }.await;
 
     // 清理
+    queue.close();
+    if let Some((udp, path)) = &udp_path {
+        udp.unregister(path.token());
+    }
     peers.write().await.remove(&device_id);
+    transfers.remove_device(&device_id);
+    metrics.device_disconnected(&device_id);
     {
         let mut fm = focus.lock().await;
         fm.remove_device(&device_id);
+        fm.device_screens.remove(&device_id);
+        fm.clipboard_delivered.remove(&device_id);
+        if fm.is_focused_on(&device_id) {
+            match fm.config.disconnect_policy {
+                DisconnectPolicy::ReturnLocal => {
+                    force_return_local(&mut fm, &device_id, &local_action_tx, &event_tx);
+                }
+                DisconnectPolicy::WaitForReconnect(grace) => {
+                    let _ = event_tx.send(ServerEvent::Log(format!(
+                        "Focused device {device_name} disconnected, waiting {grace:?} for reconnect"
+                    )));
+                    let focus = focus.clone();
+                    let peers = peers.clone();
+                    let device_id = device_id.clone();
+                    let local_action_tx = local_action_tx.clone();
+                    let event_tx = event_tx.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(grace).await;
+                        if peers.read().await.contains_key(&device_id) {
+                            return;
+                        }
+                        let mut fm = focus.lock().await;
+                        force_return_local(&mut fm, &device_id, &local_action_tx, &event_tx);
+                    });
+                }
+            }
+        }
     }
     let _ = event_tx.send(ServerEvent::DeviceDisconnected {
         device_id: device_id.clone(),
@@ -481,3 +3184,2141 @@ async fn handle_client(
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: &str = "laptop";
+    const SCREEN: ScreenRect = ScreenRect { x: 0, y: 0, width: 1920, height: 1080 };
+
+    fn test_peer(queue: Arc<PeerQueue>, edge: Option<Edge>) -> PeerInfo {
+        PeerInfo {
+            queue,
+            screen_w: 1280,
+            screen_h: 720,
+            device_name: DEVICE.into(),
+            label: None,
+            edge,
+            screens: Vec::new(),
+            clipboard_only: edge.is_none(),
+            scroll_unit: ScrollUnit::Lines,
+            capabilities: Capabilities::local(),
+            absolute_pointer: false,
+            udp: None,
+        }
+    }
+
+    /// 本地 1920x1080 屏幕，右边缘接一台 1280x720 的设备
+    struct Fixture {
+        focus: tokio::sync::Mutex<FocusManager>,
+        peers: PeerMap,
+        queue: Arc<PeerQueue>,
+        transfers: Arc<TransferMap>,
+        action_tx: mpsc::UnboundedSender<LocalAction>,
+        action_rx: mpsc::UnboundedReceiver<LocalAction>,
+        event_tx: mpsc::UnboundedSender<ServerEvent>,
+        event_rx: mpsc::UnboundedReceiver<ServerEvent>,
+    }
+
+    impl Fixture {
+        fn new(config: ServerConfig) -> Self {
+            let mut fm = FocusManager::new(SCREEN, config);
+            fm.set_edge_device(Edge::Right, DEVICE.into(), 1280, 720);
+            let transfers = Arc::new(TransferMap::default());
+            let queue = Arc::new(PeerQueue::new(QueuePolicy::default(), DEVICE.into(), transfers.clone()));
+            let peers = PeerMap::default();
+            peers.try_write().unwrap().insert(DEVICE.into(), test_peer(queue.clone(), Some(Edge::Right)));
+            let (action_tx, action_rx) = mpsc::unbounded_channel();
+            let (event_tx, event_rx) = mpsc::unbounded_channel();
+            Self {
+                focus: tokio::sync::Mutex::new(fm),
+                peers,
+                queue,
+                transfers,
+                action_tx,
+                action_rx,
+                event_tx,
+                event_rx,
+            }
+        }
+
+        async fn input(&self, msg: Message) {
+            handle_input_message(msg, &self.focus, &self.peers, &self.action_tx, &self.event_tx, None)
+                .await;
+        }
+
+        async fn move_to(&self, x: f64, y: f64) {
+            self.input(Message::MouseMove { x, y }).await;
+        }
+
+        /// 从屏幕中部推到右边缘，进入远程设备
+        async fn enter_right(&mut self) {
+            self.move_to(1000.0, 540.0).await;
+            self.move_to(1919.0, 540.0).await;
+            assert!(self.is_remote().await, "focus should have moved to the device");
+            self.sent();
+            self.actions();
+        }
+
+        async fn is_remote(&self) -> bool {
+            matches!(self.focus.lock().await.state, FocusState::Remote { .. })
+        }
+
+        /// 取出发往设备的消息
+        fn sent(&self) -> Vec<Message> {
+            std::iter::from_fn(|| self.queue.try_recv_high()).collect()
+        }
+
+        fn actions(&mut self) -> Vec<LocalAction> {
+            std::iter::from_fn(|| self.action_rx.try_recv().ok()).collect()
+        }
+
+        fn events(&mut self) -> Vec<ServerEvent> {
+            std::iter::from_fn(|| self.event_rx.try_recv().ok()).collect()
+        }
+
+        async fn control(&self, control: ServerControl) {
+            handle_control(control, &self.focus, &self.peers, &self.transfers, &self.action_tx, &self.event_tx)
+                .await;
+        }
+    }
+
+    /// 完整服务端测试的时限，场景卡住时让测试失败而不是挂起
+    async fn within<T>(scenario: impl std::future::Future<Output = T>) -> T {
+        tokio::time::timeout(Duration::from_secs(10), scenario).await.expect("scenario timed out")
+    }
+
+    /// 在内存连接上运行的完整服务端
+    struct Served {
+        server: Arc<Server>,
+        /// 已接入服务端的连接的客户端一端
+        clients: Vec<crate::transport::DuplexTransport>,
+        /// 本机输入源
+        input: mpsc::UnboundedSender<Sequenced>,
+        actions: mpsc::UnboundedReceiver<LocalAction>,
+        event_rx: mpsc::UnboundedReceiver<ServerEvent>,
+        cancel: CancellationToken,
+        run: tokio::task::JoinHandle<SynapseResult<()>>,
+    }
+
+    impl Served {
+        /// 等待第一个满足 `matches` 的事件，跳过之前的其他事件
+        async fn event(&mut self, matches: impl Fn(&ServerEvent) -> bool) -> ServerEvent {
+            loop {
+                let event = self.event_rx.recv().await.expect("server stopped");
+                if matches(&event) {
+                    return event;
+                }
+            }
+        }
+
+        /// 停止服务端并确认其正常退出
+        async fn stop(self) {
+            self.cancel.cancel();
+            within(self.run).await.unwrap().unwrap();
+        }
+    }
+
+    impl Fixture {
+        /// 在 `127.0.0.1:0` 上启动完整的服务端，并接入 `connections` 条内存连接
+        fn serve(config: ServerConfig, connections: usize) -> Served {
+            Self::serve_server(Server::new("127.0.0.1:0").with_config(config), connections)
+        }
+
+        /// 同 [`Self::serve`]，使用已配置好的服务端（如设置了剪贴板读取器）
+        fn serve_server(server: Server, connections: usize) -> Served {
+            let server = Arc::new(server);
+            let clients = (0..connections)
+                .map(|i| {
+                    let (client, server_end) = crate::transport::duplex_pair(1 << 16);
+                    server.attach(server_end, format!("client-{i}")).unwrap();
+                    client
+                })
+                .collect();
+            let (input, input_rx) = mpsc::unbounded_channel();
+            let (_clipboard_tx, clipboard_rx) = mpsc::unbounded_channel();
+            let (action_tx, actions) = mpsc::unbounded_channel();
+            let (event_tx, event_rx) = mpsc::unbounded_channel();
+            let cancel = CancellationToken::new();
+            let run = tokio::spawn({
+                let server = server.clone();
+                let cancel = cancel.clone();
+                async move {
+                    server
+                        .run(input_rx, clipboard_rx, action_tx, event_tx, SCREEN, Edge::Right, cancel)
+                        .await
+                }
+            });
+            Served { server, clients, input, actions, event_rx, cancel, run }
+        }
+    }
+
+    #[test]
+    fn gutter_counts_only_outward_push() {
+        let config = ServerConfig {
+            edge_gutter: HashMap::from([(Edge::Right, 3.0)]),
+            ..ServerConfig::default()
+        };
+        let mut fm = FocusManager::new(SCREEN, config);
+        let right = Some(Edge::Right);
+
+        // 钳制在边缘的事件计 1 像素，沿边缘滑动不计
+        assert_eq!(fm.gutter_passed(right, (1918.0, 500.0), (1918.0, 500.0)), None);
+        assert_eq!(fm.gutter_passed(right, (1918.0, 500.0), (1918.0, 700.0)), None);
+        assert_eq!(fm.gutter_passed(right, (1918.0, 700.0), (1919.0, 700.0)), None);
+        assert_eq!(fm.gutter_push, Some((Edge::Right, 2.0)));
+
+        // 向内移动清零
+        assert_eq!(fm.gutter_passed(right, (1919.0, 700.0), (1918.5, 700.0)), None);
+        assert_eq!(fm.gutter_push, None);
+
+        for _ in 0..2 {
+            assert_eq!(fm.gutter_passed(right, (1919.0, 700.0), (1919.0, 700.0)), None);
+        }
+        assert_eq!(fm.gutter_passed(right, (1919.0, 700.0), (1919.0, 700.0)), right);
+    }
+
+    #[tokio::test]
+    async fn recenters_from_absolute_motion_by_default() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.enter_right().await;
+        let anchor = fx.focus.lock().await.anchor;
+
+        fx.move_to(anchor.0 as f64 + 10.0, anchor.1 as f64).await;
+        assert!(matches!(fx.sent().as_slice(), [Message::MouseDelta { dx, dy }] if *dx == 10.0 && *dy == 0.0));
+        assert!(matches!(fx.actions().as_slice(), [LocalAction::MoveMouse(..)]));
+
+        // 未开启相对运动时忽略输入源的 MouseDelta
+        fx.input(Message::MouseDelta { dx: 5.0, dy: 0.0 }).await;
+        assert!(fx.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn motion_scale_multiplies_forwarded_delta_and_virtual_cursor() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.control(ServerControl::SetMotionScale { device_id: DEVICE.into(), scale: 2.0 }).await;
+        // 无效倍率被忽略，保留之前的设置
+        fx.control(ServerControl::SetMotionScale { device_id: DEVICE.into(), scale: -1.0 }).await;
+        fx.enter_right().await;
+        let virtual_position = |state: &FocusState| match state {
+            FocusState::Remote { virtual_x, virtual_y, .. } => (*virtual_x, *virtual_y),
+            _ => panic!("focus should be remote"),
+        };
+        let (anchor, before) = {
+            let fm = fx.focus.lock().await;
+            (fm.anchor, virtual_position(&fm.state))
+        };
+
+        fx.move_to(anchor.0 as f64 + 10.0, anchor.1 as f64 + 5.0).await;
+        assert!(matches!(fx.sent().as_slice(), [Message::MouseDelta { dx, dy }] if *dx == 20.0 && *dy == 10.0));
+        let after = virtual_position(&fx.focus.lock().await.state);
+        assert_eq!((after.0 - before.0, after.1 - before.1), (20.0, 10.0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn copy_before_crossing_reaches_device_ahead_of_enter_screen() {
+        let fx = Fixture::new(ServerConfig::default());
+        fx.input(Message::ClipboardText { text: "copied".into(), transfer_id: 0 }).await;
+        assert!(fx.sent().is_empty(), "clipboard waits while focus is local");
+
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        let sent = fx.sent();
+        let position = |matches: fn(&Message) -> bool| sent.iter().position(matches);
+        let clipboard = position(|msg| matches!(msg, Message::ClipboardText { text, .. } if text == "copied"));
+        let enter = position(|msg| matches!(msg, Message::EnterScreen { .. }));
+        assert!(matches!((clipboard, enter), (Some(c), Some(e)) if c < e), "{sent:?}");
+
+        // 设备已有该内容，返回后再次进入不重复发送
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 - 2000.0, anchor.1 as f64).await;
+        assert!(!fx.is_remote().await);
+        tokio::time::advance(DEFAULT_SWITCH_COOLDOWN.max(DEFAULT_RETURN_COOLDOWN)).await;
+        fx.sent();
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(fx.is_remote().await);
+        assert!(!fx.sent().iter().any(|msg| matches!(msg, Message::ClipboardText { .. })));
+    }
+
+    #[tokio::test]
+    async fn key_filter_drops_blocked_keys_and_keeps_pairs() {
+        use KeyAction::{Press, Release};
+        use KeyCode::{F4, KeyA, LeftAlt, LeftMeta, LeftShift};
+
+        let mut fx = Fixture::new(ServerConfig::default());
+        let filter = KeyFilter::new().with_key(LeftMeta).with_combo(vec![LeftAlt, F4]);
+        fx.control(ServerControl::SetKeyFilter { device_id: DEVICE.into(), filter }).await;
+        fx.enter_right().await;
+        let forwarded = |sent: Vec<Message>| -> Vec<(KeyCode, KeyAction)> {
+            sent.into_iter()
+                .filter_map(|msg| match msg {
+                    Message::KeyEvent { key, action } => Some((key, action)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // 屏蔽的键连同释放一并丢弃；组合键中先按下的修饰键照常转发
+        for (key, action) in [
+            (LeftMeta, Press),
+            (LeftMeta, Release),
+            (KeyA, Press),
+            (KeyA, Release),
+            (LeftAlt, Press),
+            (F4, Press),
+            (F4, Release),
+            (LeftAlt, Release),
+            (LeftShift, Press),
+        ] {
+            fx.input(Message::KeyEvent { key, action }).await;
+        }
+        assert_eq!(
+            forwarded(fx.sent()),
+            [(KeyA, Press), (KeyA, Release), (LeftAlt, Press), (LeftAlt, Release), (LeftShift, Press)]
+        );
+
+        // 按下已转发的键在之后被屏蔽时，释放仍然转发
+        let filter = KeyFilter::new().with_key(LeftShift);
+        fx.control(ServerControl::SetKeyFilter { device_id: DEVICE.into(), filter }).await;
+        fx.input(Message::KeyEvent { key: LeftShift, action: Release }).await;
+        assert_eq!(forwarded(fx.sent()), [(LeftShift, Release)]);
+    }
+
+    #[tokio::test]
+    async fn edge_gap_offsets_entry_and_swallows_motion_inside_gap() {
+        let virtual_position = |state: &FocusState| match state {
+            FocusState::Remote { virtual_x, virtual_y, .. } => (*virtual_x, *virtual_y),
+            _ => panic!("focus should be remote"),
+        };
+        let deltas = |sent: Vec<Message>| -> Vec<(f64, f64)> {
+            sent.into_iter()
+                .filter_map(|msg| match msg {
+                    Message::MouseDelta { dx, dy } => Some((dx, dy)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // 无间隙时从右边缘进入，虚拟光标在设备左边缘
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.enter_right().await;
+        assert_eq!(virtual_position(&fx.focus.lock().await.state), (0.0, 360.0));
+
+        // 40 像素间隙：虚拟光标从屏幕外 40 像素处开始，穿过间隙前远程光标不动
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.control(ServerControl::SetEdgeGap { device_id: DEVICE.into(), gap: 40 }).await;
+        fx.enter_right().await;
+        assert_eq!(virtual_position(&fx.focus.lock().await.state), (-40.0, 360.0));
+        let anchor = fx.focus.lock().await.anchor;
+        let nudge = |dx: f64| (anchor.0 as f64 + dx, anchor.1 as f64);
+
+        let (x, y) = nudge(30.0);
+        fx.move_to(x, y).await;
+        assert!(deltas(fx.sent()).is_empty());
+        let (x, y) = nudge(30.0);
+        fx.move_to(x, y).await;
+        assert_eq!(deltas(fx.sent()), [(20.0, 0.0)]);
+        assert_eq!(virtual_position(&fx.focus.lock().await.state), (20.0, 360.0));
+
+        // 返回同样需要穿过整个间隙：越过设备左边缘后仍在远程，直到离开间隙外侧
+        let (x, y) = nudge(-59.0);
+        fx.move_to(x, y).await;
+        assert!(fx.is_remote().await);
+        assert_eq!(deltas(fx.sent()), [(-20.0, 0.0)]);
+        assert_eq!(virtual_position(&fx.focus.lock().await.state), (-39.0, 360.0));
+        let (x, y) = nudge(-1.0);
+        fx.move_to(x, y).await;
+        assert!(!fx.is_remote().await);
+    }
+
+    #[tokio::test]
+    async fn echoed_key_is_sent_to_peer_and_simulated_locally() {
+        let config =
+            ServerConfig { local_echo: LocalEcho::Keys(HashSet::from([KeyCode::KeyA])), ..ServerConfig::default() };
+        let mut fx = Fixture::new(config);
+        fx.enter_right().await;
+
+        fx.input(Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press }).await;
+        assert!(matches!(
+            fx.sent().as_slice(),
+            [Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press }]
+        ));
+        assert!(matches!(fx.actions().as_slice(), [LocalAction::Key(KeyCode::KeyA, KeyAction::Press)]));
+
+        // 未列出的键与鼠标按键只转发
+        fx.input(Message::KeyEvent { key: KeyCode::KeyB, action: KeyAction::Press }).await;
+        fx.input(Message::MouseButtonEvent { button: MouseButton::Left, action: ButtonAction::Press }).await;
+        assert_eq!(fx.sent().len(), 2);
+        assert!(fx.actions().is_empty());
+
+        // All 模式下鼠标按键同样回显
+        fx.control(ServerControl::SetLocalEcho(LocalEcho::All)).await;
+        fx.input(Message::MouseButtonEvent { button: MouseButton::Left, action: ButtonAction::Release }).await;
+        assert!(matches!(
+            fx.sent().as_slice(),
+            [Message::MouseButtonEvent { button: MouseButton::Left, action: ButtonAction::Release }]
+        ));
+        assert!(matches!(
+            fx.actions().as_slice(),
+            [LocalAction::MouseButton(MouseButton::Left, ButtonAction::Release)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn large_jump_enters_at_crossing_point() {
+        let fx = Fixture::new(ServerConfig::default());
+        fx.move_to(1018.0, 90.0).await;
+        // 一个采样从屏幕中部斜跳到边缘外很远处，在 y=540 处越过触发线
+        fx.move_to(2818.0, 990.0).await;
+        assert!(fx.is_remote().await);
+        let entered = fx.sent().into_iter().find_map(|msg| match msg {
+            Message::EnterScreen { position, .. } => Some(position),
+            _ => None,
+        });
+        // 540 按 1080 → 720 缩放；若用终点 y=990 则会是 660
+        assert!(matches!(entered, Some(ScreenPosition { x, y }) if x == 0.0 && y == 360.0));
+    }
+
+    #[tokio::test]
+    async fn negative_origin_screen_switches_at_its_own_right_edge() {
+        // 主屏左侧的副屏：虚拟桌面坐标全部为负，右边缘紧挨 x = 0
+        let fx = Fixture::new(ServerConfig::default());
+        fx.focus.lock().await.set_screen(ScreenRect { x: -1920, y: 0, width: 1920, height: 1080 });
+
+        fx.move_to(-960.0, 270.0).await;
+        assert!(!fx.is_remote().await);
+        // 若忽略原点，x = -1 会被当作左边缘
+        fx.move_to(-1.0, 270.0).await;
+        assert!(fx.is_remote().await);
+
+        // 进入位置按相对原点的坐标缩放：y = 270 按 1080 → 720 为 180
+        let entered = fx.sent().into_iter().find_map(|msg| match msg {
+            Message::EnterScreen { position, .. } => Some(position),
+            _ => None,
+        });
+        assert!(matches!(entered, Some(ScreenPosition { x, y }) if x == 0.0 && y == 180.0));
+        // 光标锁定在本屏幕的中心
+        assert_eq!(fx.focus.lock().await.anchor, (-960, 540));
+    }
+
+    #[tokio::test]
+    async fn updated_screen_moves_switch_edge() {
+        let wide = ScreenRect { x: 0, y: 0, width: 2560, height: 1440 };
+        let fx = Fixture::new(ServerConfig::default());
+        fx.control(ServerControl::UpdateScreen { rect: wide }).await;
+        // 旧的右边缘已在屏幕内部，不再触发切换
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(!fx.is_remote().await);
+        fx.move_to(2559.0, 540.0).await;
+        assert!(fx.is_remote().await);
+
+        // 焦点在远程时光标锁定位置立即移到新屏幕中心
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.enter_right().await;
+        fx.control(ServerControl::UpdateScreen { rect: wide }).await;
+        assert_eq!(fx.focus.lock().await.anchor, (1280, 720));
+        assert!(matches!(fx.actions().as_slice(), [LocalAction::MoveMouse(1280, 720)]));
+
+        // 空区域被忽略
+        fx.control(ServerControl::UpdateScreen { rect: ScreenRect { width: 0, ..wide } }).await;
+        assert!(fx.actions().is_empty());
+        assert_eq!(fx.focus.lock().await.screen_w, 2560);
+    }
+
+    #[test]
+    fn follow_maps_remote_position_inside_margin() {
+        let fm = FocusManager::new(SCREEN, ServerConfig { follow_cursor: true, ..ServerConfig::default() });
+        assert_eq!(fm.follow_position(0.0, 0.0, 1280, 720), (50, 50));
+        assert_eq!(fm.follow_position(640.0, 360.0, 1280, 720), (960, 540));
+        assert_eq!(fm.follow_position(1280.0, 720.0, 1280, 720), (1870, 1030));
+        // 越过远程边缘（间隙内）时钳制在映射区域内
+        assert_eq!(fm.follow_position(1400.0, -20.0, 1280, 720), (1870, 50));
+        assert_eq!(fm.park_position(640.0, 360.0, 1280, 720), (960, 540));
+
+        let fm = FocusManager::new(SCREEN, ServerConfig::default());
+        assert_eq!(fm.park_position(0.0, 0.0, 1280, 720), (960, 540));
+    }
+
+    #[tokio::test]
+    async fn follow_mode_parks_cursor_at_mapped_position() {
+        let config = ServerConfig { follow_cursor: true, hide_cursor: true, ..ServerConfig::default() };
+        let mut fx = Fixture::new(config);
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        // 从右边缘进入时虚拟光标在设备左侧中部，本地光标停在映射区域左侧而不是屏幕中心，且不隐藏
+        assert!(matches!(fx.actions().as_slice(), [LocalAction::MoveMouse(50, 540)]));
+        fx.sent();
+
+        // 位移相对映射位置计算；本地光标虽靠近左边缘也不会触发切换。
+        // 虚拟光标 x=64 为设备宽度的 5%，映射为 50 + 0.05 * 1820
+        fx.move_to(114.0, 540.0).await;
+        assert!(matches!(fx.sent().as_slice(), [Message::MouseDelta { dx, dy }] if *dx == 64.0 && *dy == 0.0));
+        assert!(matches!(fx.actions().as_slice(), [LocalAction::MoveMouse(141, 540)]));
+        assert!(fx.is_remote().await);
+    }
+
+    #[tokio::test]
+    async fn detailed_focus_events_name_source_target_and_edge() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        let detailed = |events: Vec<ServerEvent>| {
+            events
+                .into_iter()
+                .filter(|event| matches!(event, ServerEvent::FocusChangedDetailed { .. }))
+                .collect::<Vec<_>>()
+        };
+        fx.enter_right().await;
+        assert!(matches!(
+            detailed(fx.events()).as_slice(),
+            [ServerEvent::FocusChangedDetailed { from, to, edge: Some(Edge::Right), target: Some(target) }]
+                if from == "local" && to == DEVICE
+                    && target.device_name == DEVICE && (target.width, target.height) == (1280, 720)
+        ));
+
+        // 返回时的边缘是虚拟光标越过的设备边缘
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 - 2000.0, anchor.1 as f64).await;
+        assert!(matches!(
+            detailed(fx.events()).as_slice(),
+            [ServerEvent::FocusChangedDetailed { from, to, edge: Some(Edge::Left), target: None }]
+                if from == DEVICE && to == "local"
+        ));
+    }
+
+    #[tokio::test]
+    async fn closed_peer_channel_reverts_focus() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.enter_right().await;
+        fx.events();
+
+        // 连接任务已结束但尚未清理时，下一条输入即发现并切回本地
+        fx.queue.close();
+        fx.input(Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press }).await;
+        assert!(!fx.is_remote().await);
+        assert!(fx.peers.read().await.is_empty());
+        assert!(!fx.focus.lock().await.edge_devices.contains_key(&Edge::Right));
+        assert!(fx.actions().iter().any(|action| matches!(action, LocalAction::MoveMouse(..))));
+        assert!(fx
+            .events()
+            .iter()
+            .any(|event| matches!(event, ServerEvent::FocusChanged { target } if target == "local")));
+    }
+
+    #[tokio::test]
+    async fn hides_cursor_while_remote() {
+        let mut fx = Fixture::new(ServerConfig { hide_cursor: true, ..ServerConfig::default() });
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(matches!(
+            fx.actions().as_slice(),
+            [LocalAction::MoveMouse(..), LocalAction::HideCursor]
+        ));
+
+        // 虚拟光标推回左边缘，焦点回到本地
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 - 2000.0, anchor.1 as f64).await;
+        assert!(!fx.is_remote().await);
+        assert!(fx.actions().iter().any(|action| matches!(action, LocalAction::ShowCursor)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn enters_one_device_from_two_edges() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.control(ServerControl::SetDeviceEdges { device_id: DEVICE.into(), edges: vec![Edge::Right, Edge::Left] })
+            .await;
+        let entered = |sent: Vec<Message>| {
+            sent.into_iter().find_map(|msg| match msg {
+                Message::EnterScreen { position, .. } => Some((position.x, position.y)),
+                _ => None,
+            })
+        };
+
+        // 从右边缘进入时出现在设备左侧，从设备左边缘离开
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert_eq!(entered(fx.sent()), Some((0.0, 360.0)));
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 - 2000.0, anchor.1 as f64).await;
+        assert!(!fx.is_remote().await);
+        assert!(matches!(fx.sent().as_slice(), [.., Message::LeaveScreen { edge: Edge::Left, .. }]));
+
+        // 从左边缘进入同一设备时出现在设备右侧，从设备右边缘离开
+        tokio::time::advance(DEFAULT_SWITCH_COOLDOWN.max(DEFAULT_RETURN_COOLDOWN)).await;
+        fx.move_to(1000.0, 270.0).await;
+        fx.move_to(0.0, 270.0).await;
+        assert_eq!(entered(fx.sent()), Some((1280.0, 180.0)));
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 + 2000.0, anchor.1 as f64).await;
+        assert!(!fx.is_remote().await);
+        assert!(matches!(fx.sent().as_slice(), [.., Message::LeaveScreen { edge: Edge::Right, .. }]));
+
+        let edges: Vec<_> = fx
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                ServerEvent::FocusChangedDetailed { to, edge, .. } if to == DEVICE => Some(edge),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(edges, [Some(Edge::Right), Some(Edge::Left)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn emits_one_focus_event_per_transition() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        let focus_targets = |events: Vec<ServerEvent>| -> Vec<String> {
+            events
+                .into_iter()
+                .filter_map(|event| match event {
+                    ServerEvent::FocusChanged { target } => Some(target),
+                    _ => None,
+                })
+                .collect()
+        };
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 - 2000.0, anchor.1 as f64).await;
+        assert!(!fx.is_remote().await);
+        // 刚切回本地时在边缘上来回推动不会再次切换
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(!fx.is_remote().await);
+        assert_eq!(focus_targets(fx.events()), ["laptop", "local"]);
+
+        // 目标未变的切换不重复发出
+        let mut fm = fx.focus.lock().await;
+        emit_focus_changed(&mut fm, &fx.event_tx, "laptop".into(), "local".into(), None, None);
+        drop(fm);
+        assert!(focus_targets(fx.events()).is_empty());
+
+        tokio::time::advance(DEFAULT_SWITCH_COOLDOWN.max(DEFAULT_RETURN_COOLDOWN)).await;
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(fx.is_remote().await);
+        assert_eq!(focus_targets(fx.events()), ["laptop"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn edge_crossed_fires_only_on_completed_switches() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        let crossings = |events: Vec<ServerEvent>| -> Vec<(Edge, String)> {
+            events
+                .into_iter()
+                .filter_map(|event| match event {
+                    ServerEvent::EdgeCrossed { edge, target } => Some((edge, target)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // 没有设备的边缘不算越界
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(0.0, 540.0).await;
+        assert!(crossings(fx.events()).is_empty());
+
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert_eq!(crossings(fx.events()), [(Edge::Right, DEVICE.to_string())]);
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 - 2000.0, anchor.1 as f64).await;
+        assert_eq!(crossings(fx.events()), [(Edge::Right, "local".to_string())]);
+
+        // 冷却期内被拦下的推动与外部控制的切换都不发送
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(!fx.is_remote().await);
+        tokio::time::advance(DEFAULT_SWITCH_COOLDOWN.max(DEFAULT_RETURN_COOLDOWN)).await;
+        fx.control(ServerControl::SwitchFocus { device_id: DEVICE.into() }).await;
+        assert!(fx.is_remote().await);
+        fx.control(ServerControl::ReturnLocal).await;
+        assert!(crossings(fx.events()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn relative_motion_skips_recentering() {
+        let mut fx = Fixture::new(ServerConfig { relative_motion: true, ..ServerConfig::default() });
+        fx.enter_right().await;
+
+        fx.input(Message::MouseDelta { dx: 5.0, dy: -3.0 }).await;
+        assert!(matches!(fx.sent().as_slice(), [Message::MouseDelta { dx, dy }] if *dx == 5.0 && *dy == -3.0));
+        assert!(fx.actions().is_empty());
+
+        // 相对运动模式下绝对坐标不参与计算
+        fx.move_to(100.0, 100.0).await;
+        assert!(fx.sent().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dwell_switches_after_holding_edge() {
+        let fx = Fixture::new(ServerConfig {
+            edge_trigger: EdgeTrigger::Dwell(Duration::from_millis(200)),
+            ..ServerConfig::default()
+        });
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        tokio::time::advance(Duration::from_millis(150)).await;
+        fx.move_to(1919.0, 545.0).await;
+        assert!(!fx.is_remote().await);
+
+        // 中途离开边缘重新计时
+        fx.move_to(1900.0, 545.0).await;
+        fx.move_to(1919.0, 545.0).await;
+        tokio::time::advance(Duration::from_millis(150)).await;
+        fx.move_to(1919.0, 550.0).await;
+        assert!(!fx.is_remote().await);
+        tokio::time::advance(Duration::from_millis(50)).await;
+        fx.move_to(1919.0, 555.0).await;
+        assert!(fx.is_remote().await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn double_push_switches_only_within_window() {
+        let fx = Fixture::new(ServerConfig {
+            edge_trigger: EdgeTrigger::DoublePush(Duration::from_millis(300)),
+            ..ServerConfig::default()
+        });
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        fx.move_to(1900.0, 540.0).await;
+        // 第二次推向边缘超出时间窗，只算作新的第一次
+        tokio::time::advance(Duration::from_millis(400)).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(!fx.is_remote().await);
+
+        fx.move_to(1900.0, 540.0).await;
+        tokio::time::advance(Duration::from_millis(250)).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(fx.is_remote().await);
+    }
+
+    #[tokio::test]
+    async fn converts_scroll_to_device_unit() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.enter_right().await;
+        fx.peers.write().await.get_mut(DEVICE).unwrap().scroll_unit = ScrollUnit::Pixels;
+        fx.input(Message::MouseScroll { dx: 0.0, dy: 2.0, precise: false }).await;
+        assert!(matches!(
+            fx.sent().as_slice(),
+            [Message::MouseScroll { dy, precise: false, .. }] if *dy == 2.0 * synapse_protocol::input::PIXELS_PER_LINE
+        ));
+
+        // 触控板按像素捕获的滚动换算为行，并标记为高精度
+        let mut fx = Fixture::new(ServerConfig { scroll_unit: ScrollUnit::Pixels, ..ServerConfig::default() });
+        fx.enter_right().await;
+        fx.input(Message::MouseScroll { dx: 0.0, dy: -10.0, precise: false }).await;
+        assert!(matches!(
+            fx.sent().as_slice(),
+            [Message::MouseScroll { dy, precise: true, .. }] if *dy == -10.0 / synapse_protocol::input::PIXELS_PER_LINE
+        ));
+    }
+
+    #[tokio::test]
+    async fn relative_motion_locks_pointer_while_remote() {
+        let mut fx = Fixture::new(ServerConfig { relative_motion: true, ..ServerConfig::default() });
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(matches!(fx.actions().as_slice(), [LocalAction::MoveMouse(..), LocalAction::LockPointer]));
+
+        // 推过设备的左边缘回到本地，先解锁再移动光标
+        fx.input(Message::MouseDelta { dx: -2000.0, dy: 0.0 }).await;
+        assert!(!fx.is_remote().await);
+        assert!(matches!(fx.actions().first(), Some(LocalAction::UnlockPointer)));
+    }
+
+    #[test]
+    fn ordered_batch_merges_channels_by_seq() {
+        let (input_tx, mut input_rx) = mpsc::unbounded_channel();
+        let (clipboard_tx, mut clipboard_rx) = mpsc::unbounded_channel();
+        let motion = Sequenced::new(Message::MouseDelta { dx: 1.0, dy: 0.0 });
+        // 剪贴板图片编码耗时，晚于之后采集的按键到达
+        let copied = Sequenced::reserve();
+        for msg in [
+            Message::KeyEvent { key: KeyCode::KeyV, action: KeyAction::Press },
+            Message::MouseDelta { dx: 2.0, dy: 0.0 },
+        ] {
+            input_tx.send(Sequenced::new(msg)).unwrap();
+        }
+        let clipboard = Message::ClipboardText { text: "a".into(), transfer_id: 0 };
+        clipboard_tx.send(Sequenced::with_seq(copied, clipboard)).unwrap();
+
+        let batch = drain_batch(motion, &mut input_rx, &mut clipboard_rx, true);
+        assert!(matches!(
+            batch.iter().map(|item| &item.msg).collect::<Vec<_>>().as_slice(),
+            [
+                Message::MouseDelta { .. },
+                Message::ClipboardText { .. },
+                Message::KeyEvent { key: KeyCode::KeyV, .. },
+                Message::MouseDelta { dx, .. },
+            ] if *dx == 2.0
+        ));
+        assert!(batch.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+    }
+
+    #[test]
+    fn unordered_batch_handles_clipboard_first() {
+        let (input_tx, mut input_rx) = mpsc::unbounded_channel();
+        let (clipboard_tx, mut clipboard_rx) = mpsc::unbounded_channel();
+        input_tx.send(Sequenced::new(Message::MouseDelta { dx: 1.0, dy: 0.0 })).unwrap();
+        clipboard_tx.send(Sequenced::new(Message::ClipboardText { text: "a".into(), transfer_id: 0 })).unwrap();
+        let key = Sequenced::new(Message::KeyEvent { key: KeyCode::KeyV, action: KeyAction::Press });
+
+        // 未开启时只并入剪贴板，其余输入留给下一轮
+        let batch = drain_batch(key, &mut input_rx, &mut clipboard_rx, false);
+        assert!(matches!(
+            batch.iter().map(|item| &item.msg).collect::<Vec<_>>().as_slice(),
+            [Message::ClipboardText { .. }, Message::KeyEvent { .. }]
+        ));
+        assert!(input_rx.try_recv().is_ok());
+    }
+
+    fn hello(device_id: &str) -> Message {
+        Message::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            device_id: synapse_protocol::DeviceId(device_id.into()),
+            device_name: device_id.into(),
+            screens: vec![ScreenInfo {
+                id: ScreenId(0),
+                name: "Display-1".into(),
+                rect: ScreenRect { x: 0, y: 0, width: 1280, height: 720 },
+                is_primary: true,
+            }],
+            preferred_edge: None,
+            clipboard_only: false,
+            scroll_unit: ScrollUnit::Lines,
+            capabilities: Capabilities::local(),
+            absolute_pointer: false,
+            compression: Vec::new(),
+            pairing_key: format!("{device_id}-key"),
+        }
+    }
+
+    #[tokio::test]
+    async fn honours_free_preferred_edge() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 2);
+        let prefer_left = |device_id: &str| {
+            let mut msg = hello(device_id);
+            if let Message::Hello { preferred_edge, .. } = &mut msg {
+                *preferred_edge = Some(Edge::Left);
+            }
+            msg
+        };
+        within(async {
+            sv.clients[0].send(prefer_left("desktop")).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::Log(msg) if msg == "desktop placed on the Left edge as requested"))
+                .await;
+            // 左边缘已被占用，退回服务端配置的右边缘
+            sv.clients[1].send(prefer_left(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[1].next().await, Some(Ok(Message::Welcome { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::Log(msg) if msg.contains("which is taken by desktop")))
+                .await;
+        })
+        .await;
+        let mut edges: Vec<_> =
+            sv.server.peers_snapshot().await.into_iter().map(|peer| (peer.device_id, peer.edge)).collect();
+        edges.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(edges, [("desktop".to_string(), Some(Edge::Left)), (DEVICE.to_string(), Some(Edge::Right))]);
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn layout_places_each_device_on_its_configured_edge() {
+        let entry = |device: &str, edge| DeviceLayout {
+            device: device.into(),
+            edge,
+            absolute_pointer: None,
+            clipboard: true,
+        };
+        let config = ServerConfig {
+            // nuc 按设备名匹配，其余按 ID
+            layout: vec![entry(DEVICE, Edge::Left), entry("tablet", Edge::Right), entry("NUC", Edge::Bottom)],
+            reject_unlisted: true,
+            ..ServerConfig::default()
+        };
+        let mut sv = Fixture::serve(config, 4);
+        let named = |device_id: &str, name: &str| {
+            let mut msg = hello(device_id);
+            if let Message::Hello { device_name, .. } = &mut msg {
+                *device_name = name.into();
+            }
+            msg
+        };
+        within(async {
+            for (i, msg) in [hello(DEVICE), hello("tablet"), named("nuc-7f3a", "NUC")].into_iter().enumerate() {
+                sv.clients[i].send(msg).await.unwrap();
+                assert!(matches!(sv.clients[i].next().await, Some(Ok(Message::Welcome { .. }))));
+                sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+            }
+            // 未列出的设备被拒绝
+            sv.clients[3].send(hello("stranger")).await.unwrap();
+            assert!(matches!(sv.clients[3].next().await, Some(Ok(Message::Bye { .. }))));
+        })
+        .await;
+        let mut edges: Vec<_> =
+            sv.server.peers_snapshot().await.into_iter().map(|peer| (peer.device_id, peer.edge)).collect();
+        edges.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            edges,
+            [
+                (DEVICE.to_string(), Some(Edge::Left)),
+                ("nuc-7f3a".to_string(), Some(Edge::Bottom)),
+                ("tablet".to_string(), Some(Edge::Right)),
+            ]
+        );
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn graceful_stop_delivers_queued_messages_before_bye() {
+        let config = ServerConfig { drain_timeout: Some(Duration::from_secs(1)), ..ServerConfig::default() };
+        let mut sv = Fixture::serve(config, 1);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+        })
+        .await;
+
+        // 入队后立即取消，连接任务还没来得及发送，只能在排空时送出
+        let queue = sv.server.peers.read().await[DEVICE].queue.clone();
+        queue.push(Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press });
+        queue.push(Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Release });
+        queue.push(Message::ClipboardText { text: "copied".into(), transfer_id: 0 });
+        sv.cancel.cancel();
+
+        let received = within(async {
+            let mut received = Vec::new();
+            while let Some(msg) = sv.clients[0].next().await {
+                received.push(msg.unwrap());
+            }
+            received
+        })
+        .await;
+        let received: Vec<_> = received.into_iter().filter(|msg| !matches!(msg, Message::Ping { .. })).collect();
+        assert!(matches!(
+            received.as_slice(),
+            [
+                Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press },
+                Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Release },
+                Message::ClipboardText { text, transfer_id },
+                Message::Bye { .. },
+            ] if text == "copied" && *transfer_id != 0
+        ), "{received:?}");
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn input_overtakes_queued_clipboard_images() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+        })
+        .await;
+
+        // 不可压缩的大图片，单张即可填满内存连接的缓冲区，发送任务卡在第一张上
+        let mut seed = 0x2545_f491_u32;
+        let mut noise = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    seed as u8
+                })
+                .collect()
+        };
+        let queue = sv.server.peers.read().await[DEVICE].queue.clone();
+        for width in 1..=3 {
+            queue.push(Message::ClipboardImage {
+                width,
+                height: 1,
+                encoding: synapse_protocol::ImageEncoding::Raw,
+                data: noise(256 * 1024),
+                transfer_id: 0,
+            });
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        queue.push(Message::MouseMove { x: 10.0, y: 20.0 });
+
+        let mut received = Vec::new();
+        within(async {
+            while received.len() < 4 {
+                match sv.clients[0].next().await.expect("connection closed").unwrap() {
+                    Message::Ping(_) => {}
+                    Message::ClipboardImage { width, .. } => received.push(Some(width)),
+                    Message::MouseMove { .. } => received.push(None),
+                    other => panic!("unexpected message: {other:?}"),
+                }
+            }
+        })
+        .await;
+        // 运动只等正在发送的那张图片，排在其余图片之前
+        assert_eq!(received, [Some(1), None, Some(2), Some(3)]);
+        sv.stop().await;
+    }
+
+    /// 记录收到的每帧长度前缀中是否带压缩标志
+    #[derive(Default)]
+    struct FrameProbe {
+        codec: MessageCodec,
+        compressed: Vec<bool>,
+    }
+
+    impl tokio_util::codec::Decoder for FrameProbe {
+        type Item = Message;
+        type Error = anyhow::Error;
+
+        fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Option<Message>> {
+            let compressed = src.first().is_some_and(|byte| byte & 0x80 != 0);
+            let msg = self.codec.decode(src)?;
+            if msg.is_some() {
+                self.compressed.push(compressed);
+            }
+            Ok(msg)
+        }
+    }
+
+    impl tokio_util::codec::Encoder<Message> for FrameProbe {
+        type Error = anyhow::Error;
+
+        fn encode(&mut self, msg: Message, dst: &mut bytes::BytesMut) -> Result<()> {
+            self.codec.encode(msg, dst)
+        }
+    }
+
+    #[tokio::test]
+    async fn compression_is_negotiated_per_connection() {
+        let config = ServerConfig { compression: vec![Compression::Lz4], ..ServerConfig::default() };
+        let mut sv = Fixture::serve(config, 0);
+        let addr = within(async {
+            loop {
+                match sv.server.local_addr() {
+                    Some(addr) => break addr,
+                    None => tokio::time::sleep(Duration::from_millis(5)).await,
+                }
+            }
+        })
+        .await;
+
+        // 不支持压缩的旧客户端照常接入，之后的大帧也不压缩；支持 LZ4 的客户端收到压缩帧
+        let mut connections = Vec::new();
+        for (device_id, supported, expected) in
+            [(DEVICE, vec![], Compression::None), ("desktop", vec![Compression::Lz4], Compression::Lz4)]
+        {
+            let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let mut framed = Framed::new(stream, FrameProbe::default());
+            let mut msg = hello(device_id);
+            if let Message::Hello { compression, capabilities, .. } = &mut msg {
+                *compression = supported;
+                capabilities.remove(Capability::FrameSync);
+                capabilities.remove(Capability::UdpMotion);
+            }
+            let text = "x".repeat(4096);
+            within(async {
+                framed.send(msg).await.unwrap();
+                match framed.next().await {
+                    Some(Ok(Message::Welcome { compression, .. })) => assert_eq!(compression, expected),
+                    other => panic!("expected Welcome, got {other:?}"),
+                }
+                sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+                let queue = sv.server.peers.read().await[device_id].queue.clone();
+                queue.push(Message::ClipboardText { text: text.clone(), transfer_id: 0 });
+                loop {
+                    match framed.next().await {
+                        Some(Ok(Message::ClipboardText { text: received, .. })) => {
+                            assert_eq!(received, text);
+                            break;
+                        }
+                        Some(Ok(_)) => {}
+                        other => panic!("expected ClipboardText, got {other:?}"),
+                    }
+                }
+            })
+            .await;
+            let compressed = &framed.codec().compressed;
+            assert_eq!(compressed.last(), Some(&(expected == Compression::Lz4)), "{device_id}");
+            // Welcome 始终不压缩
+            assert_eq!(compressed.first(), Some(&false));
+            connections.push(framed);
+        }
+        // 两种连接同时在线
+        assert_eq!(sv.server.peers_snapshot().await.len(), 2);
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn screen_names_and_primary_flags_cross_the_handshake() {
+        let screen = |id: u32, name: &str, x: i32, is_primary: bool| ScreenInfo {
+            id: ScreenId(id),
+            name: name.into(),
+            rect: ScreenRect { x, y: 0, width: 1920, height: 1080 },
+            is_primary,
+        };
+        let server_screens = vec![screen(0, "DELL U2720Q", 0, true), screen(1, "Display-2", 1920, false)];
+        let client_screens = vec![screen(0, "Built-in Retina", 0, false), screen(1, "LG HDR 4K", -1920, true)];
+        let labels = |screens: &[ScreenInfo]| -> Vec<(String, bool)> {
+            screens.iter().map(|s| (s.name.clone(), s.is_primary)).collect()
+        };
+        let mut sv = Fixture::serve(ServerConfig { screens: server_screens.clone(), ..ServerConfig::default() }, 1);
+        within(async {
+            let mut msg = hello(DEVICE);
+            if let Message::Hello { screens, .. } = &mut msg {
+                *screens = client_screens.clone();
+            }
+            sv.clients[0].send(msg).await.unwrap();
+            match sv.clients[0].next().await {
+                Some(Ok(Message::Welcome { screens, .. })) => {
+                    assert_eq!(labels(&screens), labels(&server_screens));
+                }
+                other => panic!("expected Welcome, got {other:?}"),
+            }
+            sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+        })
+        .await;
+        let peers = sv.server.peers_snapshot().await;
+        assert_eq!(peers.len(), 1);
+        assert_eq!(labels(&peers[0].screens), labels(&client_screens));
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicate_pending_pairing() {
+        let mut sv = Fixture::serve(ServerConfig { require_pairing: true, ..ServerConfig::default() }, 2);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            let request = sv.event(|event| matches!(event, ServerEvent::PairingRequest { .. })).await;
+            assert!(matches!(request, ServerEvent::PairingRequest { device_id, .. } if device_id == DEVICE));
+            // 第二个同 ID 的连接被直接拒绝，不会覆盖第一个的等待
+            sv.clients[1].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[1].next().await, Some(Ok(Message::Bye { .. }))));
+
+            sv.server.approve(DEVICE);
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn forwards_no_input_before_pairing_is_approved() {
+        let mut sv = Fixture::serve(ServerConfig { require_pairing: true, ..ServerConfig::default() }, 1);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            sv.event(|event| matches!(event, ServerEvent::PairingRequest { .. })).await;
+            // 等待确认期间推向设备所在的右边缘并按键
+            for msg in [
+                Message::MouseMove { x: 1000.0, y: 540.0 },
+                Message::MouseMove { x: 1919.0, y: 540.0 },
+                Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press },
+            ] {
+                sv.input.send(Sequenced::new(msg)).unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+
+            // 确认前发出的消息会排在 Welcome 之前
+            sv.server.approve(DEVICE);
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+            let forwarded = tokio::time::timeout(Duration::from_millis(100), async {
+                loop {
+                    match sv.clients[0].next().await {
+                        Some(Ok(Message::Ping(_) | Message::ClipboardHistory { .. })) => {}
+                        other => return other,
+                    }
+                }
+            })
+            .await;
+            assert!(forwarded.is_err(), "input leaked to the device: {forwarded:?}");
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn trusts_pairing_key_rather_than_device_id() {
+        let config = ServerConfig {
+            require_pairing: true,
+            trusted_devices: HashSet::from([crate::pairing::fingerprint(&format!("{DEVICE}-key"))]),
+            ..ServerConfig::default()
+        };
+        let mut sv = Fixture::serve(config, 2);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+
+            // 同一设备 ID 出示其他密钥，仍需确认
+            let mut spoofed = hello(DEVICE);
+            if let Message::Hello { device_id, pairing_key, .. } = &mut spoofed {
+                device_id.0 = "desktop".into();
+                *pairing_key = "guessed".into();
+            }
+            sv.clients[1].send(spoofed).await.unwrap();
+            let request = sv.event(|event| matches!(event, ServerEvent::PairingRequest { .. })).await;
+            assert!(matches!(
+                request,
+                ServerEvent::PairingRequest { fingerprint: Some(print), .. }
+                    if print == crate::pairing::fingerprint("guessed")
+            ));
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn warns_when_configured_edge_is_taken() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 2);
+        within(async {
+            sv.clients[0].send(hello("desktop")).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+            sv.clients[1].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[1].next().await, Some(Ok(Message::Welcome { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::Log(msg) if msg.contains("which was taken by desktop")))
+                .await;
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn rejects_other_protocol_version() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        within(async {
+            let mut old = hello(DEVICE);
+            if let Message::Hello { protocol_version, .. } = &mut old {
+                *protocol_version = PROTOCOL_VERSION - 1;
+            }
+            sv.clients[0].send(old).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Bye { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::Log(msg) if msg.contains("protocol version"))).await;
+        })
+        .await;
+        assert!(sv.server.peers_snapshot().await.is_empty());
+        sv.stop().await;
+    }
+
+    /// 收集日志输出的内存缓冲
+    #[derive(Clone, Default)]
+    struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_carry_session_and_target_spans() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        // 单线程运行时中服务端任务与测试在同一线程，线程内默认 subscriber 对它们都生效
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.enter_right().await;
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 - 2000.0, anchor.1 as f64).await;
+        assert!(!fx.is_remote().await);
+
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        within(async {
+            let mut msg = hello(DEVICE);
+            if let Message::Hello { screens, .. } = &mut msg {
+                screens.clear();
+            }
+            sv.clients[0].send(msg).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Bye { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::Log(_))).await;
+        })
+        .await;
+        sv.stop().await;
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let line = |needle: &str| {
+            output.lines().find(|line| line.contains(needle)).unwrap_or_else(|| panic!("no {needle:?} in {output}"))
+        };
+        // 转发输入时的日志带有目标设备
+        assert!(line("focus switching back to local").contains(r#"handle_input_message{target="laptop"}"#));
+        // 握手后的日志带有会话的连接地址与设备
+        let rejected = line("rejecting client");
+        assert!(rejected.contains("client{peer_addr="), "{rejected}");
+        assert!(rejected.contains(r#"device_id="laptop""#), "{rejected}");
+    }
+
+    #[tokio::test]
+    async fn refuses_connections_over_per_ip_limit() {
+        use tokio::io::AsyncReadExt;
+
+        let config = ServerConfig { max_connections_per_ip: 2, ..ServerConfig::default() };
+        let mut sv = Fixture::serve(config, 0);
+        within(async {
+            let addr = loop {
+                match sv.server.local_addr() {
+                    Some(addr) => break addr,
+                    None => tokio::time::sleep(Duration::from_millis(5)).await,
+                }
+            };
+            let connect = || tokio::net::TcpStream::connect(addr);
+            let is_log = |prefix: &'static str| {
+                move |event: &ServerEvent| matches!(event, ServerEvent::Log(msg) if msg.starts_with(prefix))
+            };
+
+            let first = connect().await.unwrap();
+            sv.event(is_log("New connection")).await;
+            let _second = connect().await.unwrap();
+            sv.event(is_log("New connection")).await;
+
+            // 同一 IP 的第三条连接在 accept 时被关闭
+            let mut third = connect().await.unwrap();
+            sv.event(is_log("Refused connection")).await;
+            assert_eq!(third.read(&mut [0; 16]).await.unwrap(), 0);
+
+            // 名额在连接结束后归还
+            drop(first);
+            loop {
+                let _retry = connect().await.unwrap();
+                match sv.event(|event| matches!(event, ServerEvent::Log(msg) if msg.contains("connection from"))).await {
+                    ServerEvent::Log(msg) if msg.starts_with("New connection") => break,
+                    _ => tokio::time::sleep(Duration::from_millis(5)).await,
+                }
+            }
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn focused_disconnect_returns_cursor_to_local() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+            for (x, y) in [(1000.0, 700.0), (1919.0, 700.0)] {
+                sv.input.send(Sequenced::new(Message::MouseMove { x, y })).unwrap();
+            }
+            sv.event(|event| matches!(event, ServerEvent::FocusChanged { target } if target == DEVICE)).await;
+
+            sv.clients.clear();
+            sv.event(|event| matches!(event, ServerEvent::Log(msg) if msg.contains("disconnected while focused")))
+                .await;
+            sv.event(|event| matches!(event, ServerEvent::FocusChanged { target } if target == "local")).await;
+        })
+        .await;
+        // 光标先被锁到屏幕中心，断开后回到进入时的位置附近而不是留在中心
+        let actions: Vec<_> = std::iter::from_fn(|| sv.actions.try_recv().ok()).collect();
+        assert!(matches!(
+            actions.as_slice(),
+            [LocalAction::MoveMouse(960, 540), .., LocalAction::MoveMouse(x, 700)] if *x > 1800
+        ));
+        sv.stop().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_timeout_returns_focus_to_local() {
+        const IDLE: Duration = Duration::from_secs(2);
+        let mut sv = Fixture::serve(ServerConfig { idle_timeout: Some(IDLE), ..ServerConfig::default() }, 1);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+            for (x, y) in [(1000.0, 540.0), (1919.0, 540.0)] {
+                sv.input.send(Sequenced::new(Message::MouseMove { x, y })).unwrap();
+            }
+            sv.event(|event| matches!(event, ServerEvent::FocusChanged { target } if target == DEVICE)).await;
+
+            // 超时前的输入重新计时
+            tokio::time::sleep(IDLE / 2).await;
+            sv.input.send(Sequenced::new(Message::MouseMove { x: 961.0, y: 540.0 })).unwrap();
+            let last_input = tokio::time::Instant::now();
+            sv.event(|event| matches!(event, ServerEvent::FocusChanged { target } if target == "local")).await;
+            assert!(last_input.elapsed() >= IDLE);
+
+            loop {
+                match sv.clients[0].next().await {
+                    Some(Ok(Message::LeaveScreen { .. })) => break,
+                    Some(Ok(_)) => {}
+                    other => panic!("connection ended: {other:?}"),
+                }
+            }
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    /// 接入设备后向其发送一条剪贴板文本，返回设备收到的传输 ID
+    async fn deliver_clipboard(sv: &mut Served) -> u64 {
+        sv.clients[0].send(hello(DEVICE)).await.unwrap();
+        assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+        sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+        let queue = sv.server.peers.read().await[DEVICE].queue.clone();
+        queue.push(Message::ClipboardText { text: "copied".into(), transfer_id: 0 });
+        loop {
+            match sv.clients[0].next().await {
+                Some(Ok(Message::ClipboardText { transfer_id, .. })) => return transfer_id,
+                Some(Ok(_)) => {}
+                other => panic!("connection ended: {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn clipboard_ack_reports_synced_transfer() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        within(async {
+            let id = deliver_clipboard(&mut sv).await;
+            assert_ne!(id, 0);
+            sv.clients[0].send(Message::ClipboardAck { transfer_id: id }).await.unwrap();
+            let synced = sv.event(|event| matches!(event, ServerEvent::ClipboardSynced { .. })).await;
+            assert!(matches!(
+                synced,
+                ServerEvent::ClipboardSynced { device_id, transfer_id }
+                    if device_id == DEVICE && transfer_id == id
+            ));
+            // 已确认的传输不再列为待发送
+            assert!(sv.server.transfers.list().is_empty());
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unacknowledged_clipboard_times_out() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        // 暂停的时钟会直接推进到回执时限，外层时限须长于 CLIPBOARD_ACK_TIMEOUT
+        tokio::time::timeout(CLIPBOARD_ACK_TIMEOUT * 3, async {
+            let id = deliver_clipboard(&mut sv).await;
+            let sent = tokio::time::Instant::now();
+            let expected = format!("Clipboard transfer {id} to {DEVICE} was not acknowledged");
+            sv.event(|event| matches!(event, ServerEvent::Log(msg) if *msg == expected)).await;
+            assert!(sent.elapsed() >= CLIPBOARD_ACK_TIMEOUT);
+
+            // 超时后到达的回执被忽略，不再报告同步完成
+            sv.clients[0].send(Message::ClipboardAck { transfer_id: id }).await.unwrap();
+            sv.clients[0].send(Message::Ping(1)).await.unwrap();
+            loop {
+                match sv.clients[0].next().await {
+                    Some(Ok(Message::Pong(1))) => break,
+                    Some(Ok(_)) => {}
+                    other => panic!("connection ended: {other:?}"),
+                }
+            }
+            while let Ok(event) = sv.event_rx.try_recv() {
+                assert!(!matches!(event, ServerEvent::ClipboardSynced { .. }), "{event:?}");
+            }
+        })
+        .await
+        .expect("scenario timed out");
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn release_all_reaches_the_device() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+            sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+
+            sv.server.release_all(DEVICE).await.unwrap();
+            loop {
+                match sv.clients[0].next().await {
+                    Some(Ok(Message::ReleaseAll)) => break,
+                    Some(Ok(_)) => {}
+                    other => panic!("connection ended: {other:?}"),
+                }
+            }
+        })
+        .await;
+        assert!(matches!(
+            sv.server.release_all("ghost").await,
+            Err(SynapseError::UnknownDevice(id)) if id == "ghost"
+        ));
+        sv.stop().await;
+    }
+
+    #[test]
+    fn parses_and_normalizes_bind_addresses() {
+        let parse = |addr: &str| parse_bind_addr(addr).map(|addr| addr.to_string());
+        assert_eq!(parse(DEFAULT_BIND).unwrap(), "0.0.0.0:24800");
+        assert_eq!(parse(" 192.168.1.5:9000 ").unwrap(), "192.168.1.5:9000");
+        assert_eq!(parse("[::1]:9000").unwrap(), "[::1]:9000");
+        // 只有端口时监听所有网卡，只有 IP 时使用默认端口
+        assert_eq!(parse("9000").unwrap(), "0.0.0.0:9000");
+        assert_eq!(parse(":9000").unwrap(), "0.0.0.0:9000");
+        assert_eq!(parse("10.0.0.2").unwrap(), "10.0.0.2:24800");
+        assert_eq!(parse("[::1]").unwrap(), "[::1]:24800");
+
+        for invalid in ["", "  ", "0.0.0.0:99999", "300.1.1.1:80", "localhost:24800", "0.0.0.0:"] {
+            assert!(
+                matches!(parse_bind_addr(invalid), Err(SynapseError::InvalidAddress(addr)) if addr == invalid),
+                "{invalid:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn recoverable_accept_errors_are_retried() {
+        use std::io::{Error, ErrorKind};
+
+        let (event_tx, mut events) = mpsc::unbounded_channel();
+        let delay = |e: Error| accept_retry_delay(&e, &event_tx);
+        assert_eq!(delay(Error::from(ErrorKind::ConnectionReset)), Some(Duration::ZERO));
+        assert_eq!(delay(Error::from(ErrorKind::ConnectionAborted)), Some(Duration::ZERO));
+        // 文件描述符耗尽（EMFILE）短暂退避
+        #[cfg(unix)]
+        assert_eq!(delay(Error::from_raw_os_error(24)), Some(ACCEPT_BACKOFF));
+        assert_eq!(delay(Error::from(ErrorKind::OutOfMemory)), Some(ACCEPT_BACKOFF));
+        assert!(events.try_recv().is_err());
+
+        // 其他错误以较长间隔重试并通知前端
+        assert_eq!(delay(Error::from(ErrorKind::PermissionDenied)), Some(ACCEPT_ERROR_BACKOFF));
+        assert!(matches!(events.try_recv(), Ok(ServerEvent::Log(msg)) if msg.starts_with("Accept failed")));
+    }
+
+    #[test]
+    fn invalid_listener_is_fatal() {
+        use std::io::Error;
+
+        // EBADF / EINVAL：监听 socket 本身已不可用，不再重试
+        let (event_tx, mut events) = mpsc::unbounded_channel();
+        #[cfg(unix)]
+        let codes = [9, 22];
+        #[cfg(windows)]
+        let codes = [10009, 10022];
+        for code in codes {
+            let e = Error::from_raw_os_error(code);
+            assert!(matches!(classify_accept_error(&e), AcceptErrorKind::Fatal));
+            assert_eq!(accept_retry_delay(&e, &event_tx), None);
+            assert!(matches!(events.try_recv(), Ok(ServerEvent::Log(msg)) if msg.starts_with("Listener failed")));
+        }
+    }
+
+    #[tokio::test]
+    async fn new_device_receives_clipboard_snapshot() {
+        let config = ServerConfig {
+            clipboard_opt_out: HashSet::from(["desktop".to_string()]),
+            ..ServerConfig::default()
+        };
+        let server = Server::new("127.0.0.1:0")
+            .with_config(config)
+            .with_clipboard_reader(|| Some(Message::ClipboardText { text: "snapshot".into(), transfer_id: 0 }));
+        let mut sv = Fixture::serve_server(server, 2);
+        within(async {
+            // 关闭剪贴板同步的设备收不到快照；消息循环的首个 Ping 说明快照步骤已经过去
+            sv.clients[0].send(hello("desktop")).await.unwrap();
+            loop {
+                match sv.clients[0].next().await {
+                    Some(Ok(Message::Ping(_))) => break,
+                    Some(Ok(Message::ClipboardText { .. })) => panic!("opted-out device got the snapshot"),
+                    Some(Ok(_)) => {}
+                    other => panic!("connection ended: {other:?}"),
+                }
+            }
+            sv.clients[1].send(hello(DEVICE)).await.unwrap();
+            loop {
+                match sv.clients[1].next().await {
+                    Some(Ok(Message::ClipboardText { text, .. })) => break assert_eq!(text, "snapshot"),
+                    Some(Ok(_)) => {}
+                    other => panic!("connection ended: {other:?}"),
+                }
+            }
+        })
+        .await;
+        let sent_to: Vec<_> = sv.server.transfers.list().into_iter().map(|t| t.device_id).collect();
+        assert_eq!(sent_to, [DEVICE]);
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_screen_lists() {
+        let screen = |id: u32, width: u32| ScreenInfo {
+            id: ScreenId(id),
+            name: format!("Display-{id}"),
+            rect: ScreenRect { x: 0, y: 0, width, height: 720 },
+            is_primary: id == 0,
+        };
+        let cases = [
+            (Vec::new(), "no screens"),
+            (vec![screen(0, 1280), screen(0, 1280)], "duplicate screen id 0"),
+            (vec![screen(0, 1280), screen(1, 0)], "screen 1 has zero size"),
+        ];
+        let mut sv = Fixture::serve(ServerConfig::default(), cases.len());
+        within(async {
+            for (i, (list, reason)) in cases.into_iter().enumerate() {
+                let client = &mut sv.clients[i];
+                let mut msg = hello(DEVICE);
+                if let Message::Hello { screens, .. } = &mut msg {
+                    *screens = list;
+                }
+                client.send(msg).await.unwrap();
+                assert!(matches!(client.next().await, Some(Ok(Message::Bye { .. }))));
+                sv.event(|event| matches!(event, ServerEvent::Log(msg) if msg.contains(reason))).await;
+            }
+        })
+        .await;
+        assert!(sv.server.peers_snapshot().await.is_empty());
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn reports_corrupt_frame_and_disconnects() {
+        use tokio::io::AsyncWriteExt;
+
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+
+            // 长度合法但载荷不是有效的 bincode 消息
+            let stream = sv.clients[0].get_mut();
+            stream.write_all(&[0, 0, 0, 4, 0xff, 0xff, 0xff, 0xff]).await.unwrap();
+            stream.flush().await.unwrap();
+
+            let event = sv.event(|event| matches!(event, ServerEvent::DecodeError { .. })).await;
+            assert!(matches!(
+                event,
+                ServerEvent::DecodeError { device_id, error }
+                    if device_id == DEVICE && error.contains("malformed 4-byte frame")
+            ));
+            sv.event(|event| matches!(event, ServerEvent::DeviceDisconnected { device_id } if device_id == DEVICE))
+                .await;
+            // 服务端关闭连接
+            while let Some(Ok(_)) = sv.clients[0].next().await {}
+        })
+        .await;
+        assert!(sv.server.peers_snapshot().await.is_empty());
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn label_overrides_reported_name() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            assert!(matches!(sv.clients[0].next().await, Some(Ok(Message::Welcome { .. }))));
+            let connected = sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+            assert!(matches!(connected, ServerEvent::DeviceConnected { device_name, .. } if device_name == DEVICE));
+
+            sv.server.set_device_label(DEVICE, "Kitchen PC");
+            let renamed = sv.event(|event| matches!(event, ServerEvent::DeviceRenamed { .. })).await;
+            assert!(matches!(
+                renamed,
+                ServerEvent::DeviceRenamed { device_id, device_name } if device_id == DEVICE && device_name == "Kitchen PC"
+            ));
+            assert_eq!(sv.server.peers_snapshot().await[0].device_name, "Kitchen PC");
+
+            // 焦点事件同样使用标签
+            sv.input.send(Sequenced::new(Message::MouseMove { x: 1000.0, y: 540.0 })).unwrap();
+            sv.input.send(Sequenced::new(Message::MouseMove { x: 1919.0, y: 540.0 })).unwrap();
+            let focus = sv.event(|event| matches!(event, ServerEvent::FocusChangedDetailed { .. })).await;
+            assert!(matches!(
+                focus,
+                ServerEvent::FocusChangedDetailed { target: Some(target), .. } if target.device_name == "Kitchen PC"
+            ));
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn focus_target_carries_negotiated_screen_size() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 1);
+        let mut msg = hello(DEVICE);
+        if let Message::Hello { screens, .. } = &mut msg {
+            // 目标尺寸取设备的主屏，而不是先上报的副屏
+            screens.insert(0, ScreenInfo {
+                id: ScreenId(1),
+                name: "Display-2".into(),
+                rect: ScreenRect { x: -3840, y: 0, width: 3840, height: 2160 },
+                is_primary: false,
+            });
+            screens[1].rect = ScreenRect { x: 0, y: 0, width: 2560, height: 1600 };
+        }
+        let focus = within(async {
+            sv.clients[0].send(msg).await.unwrap();
+            sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+            sv.input.send(Sequenced::new(Message::MouseMove { x: 1000.0, y: 540.0 })).unwrap();
+            sv.input.send(Sequenced::new(Message::MouseMove { x: 1919.0, y: 540.0 })).unwrap();
+            sv.event(|event| matches!(event, ServerEvent::FocusChangedDetailed { .. })).await
+        })
+        .await;
+        let peer = &sv.server.peers_snapshot().await[0];
+        let primary = peer.screens.iter().find(|screen| screen.is_primary).unwrap();
+        assert!(matches!(
+            focus,
+            ServerEvent::FocusChangedDetailed { target: Some(target), .. }
+                if target.device_name == peer.device_name
+                    && (target.width, target.height) == (primary.rect.width, primary.rect.height)
+                    && (target.width, target.height) == (2560, 1600)
+        ));
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn configured_label_applies_on_connect() {
+        let config = ServerConfig {
+            device_labels: HashMap::from([(DEVICE.to_string(), "Studio Mac".to_string())]),
+            ..ServerConfig::default()
+        };
+        let mut sv = Fixture::serve(config, 1);
+        within(async {
+            sv.clients[0].send(hello(DEVICE)).await.unwrap();
+            let connected = sv.event(|event| matches!(event, ServerEvent::DeviceConnected { .. })).await;
+            assert!(matches!(connected, ServerEvent::DeviceConnected { device_name, .. } if device_name == "Studio Mac"));
+            assert_eq!(sv.server.peers_snapshot().await[0].device_name, "Studio Mac");
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn snapshot_reports_rtt_per_device() {
+        let mut sv = Fixture::serve(ServerConfig::default(), 2);
+        within(async {
+            for (client, id) in sv.clients.iter_mut().zip(["desktop", DEVICE]) {
+                client.send(hello(id)).await.unwrap();
+                assert!(matches!(client.next().await, Some(Ok(Message::Welcome { .. }))));
+            }
+            // 进入消息循环后立即发出第一个 Ping，只有应答的设备会得到时延
+            loop {
+                match sv.clients[0].next().await {
+                    Some(Ok(Message::Ping(seq))) => break sv.clients[0].send(Message::Pong(seq)).await.unwrap(),
+                    Some(Ok(_)) => {}
+                    other => panic!("expected Ping, got {other:?}"),
+                }
+            }
+            loop {
+                let snapshot = sv.server.peers_snapshot().await;
+                let rtt = |id: &str| snapshot.iter().find(|peer| peer.device_id == id).unwrap().rtt;
+                if rtt("desktop").is_some() {
+                    assert_eq!(snapshot.len(), 2);
+                    assert_eq!(rtt(DEVICE), None);
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await;
+        sv.stop().await;
+    }
+
+    /// 向设备排入一次剪贴板传输，返回其 `transfer_id`
+    fn queue_clipboard(fx: &Fixture) -> u64 {
+        fx.queue.push(Message::ClipboardText { text: "secret".into(), transfer_id: 0 });
+        fx.transfers.list().last().unwrap().transfer_id
+    }
+
+    #[tokio::test]
+    async fn clipboard_clear_reaches_device() {
+        let fx = Fixture::new(ServerConfig::default());
+        let bulk = |fx: &Fixture| -> Vec<Message> { std::iter::from_fn(|| fx.queue.try_recv_bulk()).collect() };
+
+        // 焦点在本地时复制后又被清空，进入设备时补发的是清空而不是旧内容
+        fx.input(Message::ClipboardText { text: "secret".into(), transfer_id: 0 }).await;
+        fx.input(Message::ClipboardClear { transfer_id: 0 }).await;
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(matches!(
+            fx.sent().as_slice(),
+            [Message::ClipboardClear { .. }, Message::EnterScreen { .. }, ..]
+        ));
+
+        // 焦点在设备上时直接转发
+        fx.input(Message::ClipboardText { text: "secret".into(), transfer_id: 0 }).await;
+        fx.input(Message::ClipboardClear { transfer_id: 0 }).await;
+        assert!(matches!(
+            bulk(&fx).as_slice(),
+            [Message::ClipboardText { .. }, Message::ClipboardClear { .. }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancels_queued_transfer_without_sending_it() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        let id = queue_clipboard(&fx);
+
+        fx.control(ServerControl::CancelTransfer { transfer_id: id }).await;
+        assert!(fx.queue.try_recv_bulk().is_none());
+        assert!(fx.sent().is_empty(), "nothing was sent, so there is nothing to cancel on the device");
+        assert!(fx.transfers.list().is_empty());
+        assert!(matches!(
+            fx.events().as_slice(),
+            [ServerEvent::TransferCancelled { transfer_id, .. }] if *transfer_id == id
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancelling_sent_transfer_tells_the_device() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        let id = queue_clipboard(&fx);
+        assert!(fx.queue.try_recv_bulk().is_some());
+        fx.transfers.mark_sent(id);
+
+        fx.control(ServerControl::CancelTransfer { transfer_id: id }).await;
+        assert!(matches!(
+            fx.sent().as_slice(),
+            [Message::ClipboardCancel { transfer_id }] if *transfer_id == id
+        ));
+        // 不再等待回执，之后到达的回执不会报告为同步成功
+        assert!(fx.transfers.list().is_empty());
+        assert!(matches!(
+            fx.events().as_slice(),
+            [ServerEvent::TransferCancelled { transfer_id, .. }] if *transfer_id == id
+        ));
+    }
+
+    #[tokio::test]
+    async fn lock_all_skips_clipboard_only_devices() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        let clipboard_queue = Arc::new(PeerQueue::new(
+            QueuePolicy::default(),
+            "frame".into(),
+            Arc::new(TransferMap::default()),
+        ));
+        fx.peers.write().await.insert("frame".into(), test_peer(clipboard_queue.clone(), None));
+
+        fx.control(ServerControl::LockAll).await;
+        assert!(matches!(fx.sent().as_slice(), [Message::LockScreen]));
+        assert!(clipboard_queue.try_recv_high().is_none());
+        assert!(matches!(fx.actions().as_slice(), [LocalAction::LockScreen]));
+    }
+
+    #[tokio::test]
+    async fn clipboard_only_device_gets_clipboard_but_no_input() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        let frame_queue = Arc::new(PeerQueue::new(
+            QueuePolicy::default(),
+            "frame".into(),
+            Arc::new(TransferMap::default()),
+        ));
+        fx.peers.write().await.insert("frame".into(), test_peer(frame_queue.clone(), None));
+        let frame_sent = || -> Vec<Message> {
+            std::iter::from_fn(|| frame_queue.try_recv_high().or_else(|| frame_queue.try_recv_bulk())).collect()
+        };
+
+        fx.enter_right().await;
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 + 5.0, anchor.1 as f64).await;
+        fx.input(Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press }).await;
+        fx.input(Message::ClipboardText { text: "shared".into(), transfer_id: 0 }).await;
+
+        let sent = frame_sent();
+        assert!(matches!(sent.as_slice(), [Message::ClipboardText { text, .. }] if text == "shared"), "{sent:?}");
+        assert!(!sent.iter().any(|msg| matches!(msg, Message::MouseDelta { .. })));
+        // 焦点所在的设备照常收到输入
+        assert!(fx.sent().iter().any(|msg| matches!(msg, Message::MouseDelta { .. })));
+    }
+
+    #[tokio::test]
+    async fn programmatic_switch_enters_and_returns() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.move_to(1000.0, 540.0).await;
+
+        fx.control(ServerControl::SwitchFocus { device_id: DEVICE.into() }).await;
+        assert!(fx.is_remote().await);
+        assert!(fx.sent().iter().any(|msg| matches!(msg, Message::EnterScreen { .. })));
+        assert!(fx.actions().iter().any(|action| matches!(action, LocalAction::MoveMouse(..))));
+
+        fx.control(ServerControl::ReturnLocal).await;
+        assert!(!fx.is_remote().await);
+        assert!(fx.sent().iter().any(|msg| matches!(msg, Message::LeaveScreen { .. })));
+
+        // 未连接的设备只记录日志，焦点不变
+        fx.events();
+        fx.control(ServerControl::SwitchFocus { device_id: "ghost".into() }).await;
+        assert!(!fx.is_remote().await);
+        assert!(fx.sent().is_empty());
+        assert!(fx.events().iter().any(|event| matches!(event, ServerEvent::Log(text) if text.contains("ghost"))));
+    }
+
+    #[test]
+    fn client_position_clamps_to_device_pixels() {
+        assert_eq!(FocusManager::client_position(640.0, 360.0, 1280, 720), (640.0, 360.0));
+        assert_eq!(FocusManager::client_position(0.0, 0.0, 1280, 720), (0.0, 0.0));
+        // 刚好越过右下边缘时收回到最后一个像素
+        assert_eq!(FocusManager::client_position(1280.0, 720.0, 1280, 720), (1279.0, 719.0));
+        assert_eq!(FocusManager::client_position(-3.0, 800.0, 1280, 720), (0.0, 719.0));
+    }
+
+    #[tokio::test]
+    async fn absolute_pointer_device_gets_positions() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.peers.write().await.get_mut(DEVICE).unwrap().absolute_pointer = true;
+        fx.enter_right().await;
+
+        let (x, y) = match fx.focus.lock().await.state {
+            FocusState::Remote { virtual_x, virtual_y, .. } => (virtual_x, virtual_y),
+            FocusState::Local => unreachable!(),
+        };
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 + 10.0, anchor.1 as f64 - 4.0).await;
+        assert!(matches!(
+            fx.sent().as_slice(),
+            [Message::MouseMove { x: mx, y: my }] if *mx == x + 10.0 && *my == y - 4.0
+        ));
+    }
+
+    #[tokio::test]
+    async fn input_transform_applies_to_forwarded_input() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        let transform = InputTransform { swap_buttons: true, invert_y: true, ..InputTransform::default() };
+        fx.control(ServerControl::SetInputTransform { device_id: DEVICE.into(), transform }).await;
+        fx.enter_right().await;
+
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 + 4.0, anchor.1 as f64 + 6.0).await;
+        fx.input(Message::MouseButtonEvent { button: MouseButton::Left, action: ButtonAction::Press }).await;
+        let sent = fx.sent();
+        assert!(matches!(
+            sent.as_slice(),
+            [
+                Message::MouseDelta { dx, dy },
+                Message::MouseButtonEvent { button: MouseButton::Right, action: ButtonAction::Press },
+            ] if *dx == 4.0 && *dy == -6.0
+        ), "{sent:?}");
+
+        // 恒等变换清除设置
+        fx.control(ServerControl::SetInputTransform { device_id: DEVICE.into(), transform: InputTransform::default() })
+            .await;
+        fx.input(Message::MouseButtonEvent { button: MouseButton::Left, action: ButtonAction::Release }).await;
+        assert!(matches!(fx.sent().as_slice(), [Message::MouseButtonEvent { button: MouseButton::Left, .. }]));
+    }
+
+    #[tokio::test]
+    async fn escape_hotkey_returns_focus_mid_remote() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        fx.enter_right().await;
+
+        fx.input(Message::KeyEvent { key: KeyCode::LeftShift, action: KeyAction::Press }).await;
+        assert!(fx.is_remote().await);
+        fx.input(Message::KeyEvent { key: KeyCode::RightShift, action: KeyAction::Press }).await;
+        assert!(!fx.is_remote().await);
+
+        // 切回前先在远程释放已按下的两个 Shift，再离开屏幕
+        let sent = fx.sent();
+        let released: Vec<_> = sent
+            .iter()
+            .filter_map(|msg| match msg {
+                Message::KeyEvent { key, action: KeyAction::Release } => Some(*key),
+                _ => None,
+            })
+            .collect();
+        assert!(released.contains(&KeyCode::LeftShift) && released.contains(&KeyCode::RightShift));
+        assert!(matches!(sent.last(), Some(Message::LeaveScreen { .. })));
+        assert!(fx.actions().iter().any(|action| matches!(action, LocalAction::MoveMouse(..))));
+    }
+
+    #[tokio::test]
+    async fn escape_hotkey_can_be_disabled() {
+        let mut fx = Fixture::new(ServerConfig { escape_hotkey: None, ..ServerConfig::default() });
+        fx.enter_right().await;
+        for key in [KeyCode::LeftShift, KeyCode::RightShift] {
+            fx.input(Message::KeyEvent { key, action: KeyAction::Press }).await;
+        }
+        assert!(fx.is_remote().await);
+    }
+
+    #[test]
+    fn type_paste_matches_either_ctrl() {
+        let mut fm = FocusManager::new(SCREEN, ServerConfig::default());
+        fm.track_key(KeyCode::RightCtrl, KeyAction::Press);
+        fm.track_key(KeyCode::KeyV, KeyAction::Press);
+        assert!(fm.type_paste_completed(KeyCode::KeyV));
+
+        // 自定义后只认配置的组合
+        fm.config.type_paste_hotkeys = vec![vec![KeyCode::LeftShift, KeyCode::Insert]];
+        assert!(!fm.type_paste_completed(KeyCode::KeyV));
+        fm.track_key(KeyCode::LeftShift, KeyAction::Press);
+        fm.track_key(KeyCode::Insert, KeyAction::Press);
+        assert!(fm.type_paste_completed(KeyCode::Insert));
+    }
+
+    #[cfg(feature = "simulate")]
+    #[tokio::test]
+    async fn local_paste_hotkey_releases_modifiers_before_typing() {
+        use synapse_input::mock::{RecordingSimulator, SimulatorCall};
+
+        let hotkey = vec![KeyCode::LeftCtrl, KeyCode::LeftAlt, KeyCode::KeyV];
+        let mut fx = Fixture::new(ServerConfig { paste_hotkey: Some(hotkey.clone()), ..ServerConfig::default() });
+        let reader: ClipboardReader = Arc::new(|| Some(Message::ClipboardText { text: "hi".into(), transfer_id: 0 }));
+        for key in hotkey.iter().copied() {
+            let press = Message::KeyEvent { key, action: KeyAction::Press };
+            handle_input_message(press, &fx.focus, &fx.peers, &fx.action_tx, &fx.event_tx, Some(&reader)).await;
+        }
+
+        let mut simulator = RecordingSimulator::new();
+        for action in fx.actions() {
+            crate::apply::apply_local_action(&mut simulator, &action).unwrap();
+        }
+        let (typed, released) = simulator.calls.split_last().expect("nothing simulated");
+        assert_eq!(typed, &SimulatorCall::TypeText("hi".into()));
+        let released: HashSet<KeyCode> = released
+            .iter()
+            .map(|call| match call {
+                SimulatorCall::KeyEvent(key, KeyAction::Release) => *key,
+                other => panic!("unexpected call before typing: {other:?}"),
+            })
+            .collect();
+        assert_eq!(released, hotkey.into_iter().collect());
+    }
+
+    /// 右边缘整条归 `DEVICE`，另有两台已连接、可放入传送门的设备
+    fn portal_manager() -> FocusManager {
+        let mut fm = FocusManager::new(SCREEN, ServerConfig::default());
+        fm.set_edge_device(Edge::Right, DEVICE.into(), 1280, 720);
+        fm.device_screens.insert("upper".into(), (2560, 1440));
+        fm.device_screens.insert("lower".into(), (1024, 768));
+        fm
+    }
+
+    fn portal(start: f64, end: f64, device_id: &str) -> EdgePortal {
+        EdgePortal { edge: Edge::Right, start, end, device_id: device_id.into() }
+    }
+
+    #[test]
+    fn edge_portals_route_crossings_by_position() {
+        let mut fm = portal_manager();
+        fm.set_edge_portal(portal(0.0, 0.5, "upper"));
+        fm.set_edge_portal(portal(0.5, 1.0, "lower"));
+
+        // 上半段进入 upper，区段拉伸到整条边缘：上半段的中点对应整条边缘的中点
+        let (device, w, h, _, y) = fm.target_at(Edge::Right, 1919.0, 270.0).unwrap();
+        assert_eq!((device.as_str(), w, h, y), ("upper", 2560, 1440, 540.0));
+        // 下半段进入 lower
+        let (device, w, h, _, y) = fm.target_at(Edge::Right, 1919.0, 810.0).unwrap();
+        assert_eq!((device.as_str(), w, h, y), ("lower", 1024, 768, 540.0));
+        // 最下端仍属于终点为 1 的区段
+        assert_eq!(fm.target_at(Edge::Right, 1919.0, 1080.0).unwrap().0, "lower");
+        // 其他边缘不受影响
+        assert!(fm.target_at(Edge::Left, 0.0, 270.0).is_none());
+    }
+
+    #[test]
+    fn crossings_outside_portals_fall_back_to_edge_device() {
+        let mut fm = portal_manager();
+        fm.set_edge_portal(portal(0.0, 0.25, "upper"));
+        fm.set_edge_portal(portal(0.75, 1.0, "lower"));
+
+        // 区段之间进入整条边缘的设备，进入位置不做拉伸
+        let (device, w, h, _, y) = fm.target_at(Edge::Right, 1919.0, 540.0).unwrap();
+        assert_eq!((device.as_str(), w, h, y), (DEVICE, 1280, 720, 540.0));
+
+        // 传送门的设备断开后，其区段同样回退
+        fm.device_screens.remove("upper");
+        assert_eq!(fm.target_at(Edge::Right, 1919.0, 100.0).unwrap().0, DEVICE);
+        assert_eq!(fm.target_at(Edge::Right, 1919.0, 1000.0).unwrap().0, "lower");
+    }
+
+    #[test]
+    fn overlapping_portal_replaces_previous_ones() {
+        let mut fm = portal_manager();
+        fm.set_edge_portal(portal(0.0, 0.5, "upper"));
+        fm.set_edge_portal(portal(0.5, 1.0, "lower"));
+        fm.set_edge_portal(EdgePortal { edge: Edge::Left, start: 0.0, end: 1.0, device_id: "upper".into() });
+
+        // 与右边缘两段都重叠的新区段替换二者，只相邻（端点相接）或位于其他边缘的不受影响
+        fm.set_edge_portal(portal(0.25, 0.75, "lower"));
+        assert_eq!(fm.edge_portals.len(), 2);
+        assert!(fm.edge_portals.contains(&portal(0.25, 0.75, "lower")));
+        assert_eq!(fm.target_at(Edge::Right, 1919.0, 100.0).unwrap().0, DEVICE);
+        assert_eq!(fm.target_at(Edge::Right, 1919.0, 540.0).unwrap().0, "lower");
+
+        fm.set_edge_portal(portal(0.75, 1.0, "upper"));
+        assert_eq!(fm.edge_portals.len(), 3);
+        assert_eq!(fm.target_at(Edge::Right, 1919.0, 1000.0).unwrap().0, "upper");
+    }
+
+    #[test]
+    fn zero_size_screens_keep_edge_math_finite() {
+        let mut fm = FocusManager::new(ScreenRect { x: 0, y: 0, width: 0, height: 0 }, ServerConfig::default());
+        fm.set_edge_device(Edge::Right, DEVICE.into(), 0, 0);
+        assert!(fm.edge_fraction(Edge::Right, 0.0, 0.0).is_finite());
+        let (_, w, h) = fm.edge_devices[&Edge::Right].clone();
+        let (x, y) = FocusManager::entry_position(&Edge::Right, 0.0, 0.0, fm.screen_w, fm.screen_h, w, h);
+        assert!(x.is_finite() && y.is_finite());
+
+        fm.set_screen(ScreenRect { x: 0, y: 0, width: 1920, height: 0 });
+        assert!(fm.edge_fraction(Edge::Left, 0.0, 500.0).is_finite());
+    }
+
+    #[tokio::test]
+    async fn displaced_device_loses_its_edge() {
+        let mut fx = Fixture::new(ServerConfig::default());
+        let desk_queue = Arc::new(PeerQueue::new(QueuePolicy::default(), "desk".into(), Arc::new(TransferMap::default())));
+        fx.peers.write().await.insert("desk".into(), test_peer(desk_queue, Some(Edge::Left)));
+        fx.focus.lock().await.set_edge_device(Edge::Left, "desk".into(), 1280, 720);
+
+        fx.control(ServerControl::SetDeviceEdges { device_id: "desk".into(), edges: vec![Edge::Right, Edge::Left] })
+            .await;
+        assert_eq!(fx.peers.read().await[DEVICE].edge, None);
+        assert!(fx.events().iter().any(|event| matches!(
+            event,
+            ServerEvent::DeviceEdgeChanged { device_id, edge: None } if device_id == DEVICE
+        )));
+
+        // 只取走部分边缘时，设备留在剩余的边缘上
+        fx.control(ServerControl::SetDeviceEdges { device_id: DEVICE.into(), edges: vec![Edge::Left] }).await;
+        assert_eq!(fx.peers.read().await["desk"].edge, Some(Edge::Right));
+        assert_eq!(fx.focus.lock().await.device_edges["desk"], vec![Edge::Right]);
+    }
+
+    #[tokio::test]
+    async fn return_cooldown_blocks_immediate_reentry() {
+        tokio::time::pause();
+        let cooldown = Duration::from_millis(500);
+        let mut fx = Fixture::new(ServerConfig { return_cooldown: cooldown, ..ServerConfig::default() });
+        fx.enter_right().await;
+
+        // 虚拟光标推回左边缘，焦点回到本地
+        let anchor = fx.focus.lock().await.anchor;
+        fx.move_to(anchor.0 as f64 - 2000.0, anchor.1 as f64).await;
+        assert!(!fx.is_remote().await);
+
+        // 冷却期内再次推到右边缘不切换
+        tokio::time::advance(cooldown / 2).await;
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(!fx.is_remote().await);
+
+        tokio::time::advance(cooldown).await;
+        fx.move_to(1000.0, 540.0).await;
+        fx.move_to(1919.0, 540.0).await;
+        assert!(fx.is_remote().await);
+    }
+
+    #[test]
+    fn history_reply_fits_in_one_frame() {
+        let image = |size: usize| Message::ClipboardImage {
+            width: 1,
+            height: 1,
+            encoding: synapse_protocol::ImageEncoding::Raw,
+            data: vec![0; size],
+            transfer_id: 0,
+        };
+        let chunk = MAX_FRAME_SIZE as usize / 3;
+        let items = vec![image(chunk), image(chunk), image(chunk), image(16)];
+        let reply = history_reply(items, Capabilities::local());
+        // 较新的两条放得下，第三条起被截断，即使后面还有更小的条目
+        assert_eq!(reply.len(), 2);
+        let total: u64 = reply.iter().map(encoded_len).sum();
+        assert!(total < MAX_FRAME_SIZE as u64);
+
+        let text = Message::ClipboardText { text: "hi".into(), transfer_id: 0 };
+        let reply = history_reply(vec![image(16), text], Capabilities::default());
+        assert!(matches!(reply.as_slice(), [Message::ClipboardText { .. }]));
+    }
+}
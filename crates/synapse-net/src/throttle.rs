@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::ServerEvent;
+
+/// 统计窗口长度
+const WINDOW: Duration = Duration::from_secs(1);
+/// 每个窗口内最多转发的日志条数
+const MAX_LOGS_PER_WINDOW: u32 = 20;
+
+/// 日志事件节流：窗口内合并重复日志并限制条数，超出部分以摘要形式补发
+pub(crate) struct LogThrottle {
+    window: Duration,
+    max_per_window: u32,
+    window_start: Instant,
+    emitted: u32,
+    suppressed: u32,
+    last: Option<String>,
+}
+
+impl LogThrottle {
+    pub(crate) fn new(window: Duration, max_per_window: u32, now: Instant) -> Self {
+        Self {
+            window,
+            max_per_window,
+            window_start: now,
+            emitted: 0,
+            suppressed: 0,
+            last: None,
+        }
+    }
+
+    /// 处理一条日志，返回需要转发的日志（可能包含上一窗口的摘要）
+    pub(crate) fn push(&mut self, msg: String, now: Instant) -> Vec<String> {
+        let mut out = Vec::new();
+        if let Some(summary) = self.roll(now) {
+            out.push(summary);
+        }
+        if self.last.as_deref() == Some(msg.as_str()) || self.emitted >= self.max_per_window {
+            self.suppressed += 1;
+        } else {
+            self.emitted += 1;
+            self.last = Some(msg.clone());
+            out.push(msg);
+        }
+        out
+    }
+
+    /// 当前窗口的结束时间；仅在有被抑制的日志时需要定时补发摘要
+    pub(crate) fn flush_deadline(&self) -> Option<Instant> {
+        (self.suppressed > 0).then_some(self.window_start + self.window)
+    }
+
+    /// 窗口到期时结束当前窗口，返回被抑制日志的摘要
+    pub(crate) fn roll(&mut self, now: Instant) -> Option<String> {
+        if now.saturating_duration_since(self.window_start) < self.window {
+            return None;
+        }
+        let summary = (self.suppressed > 0)
+            .then(|| format!("… {} more log messages suppressed", self.suppressed));
+        self.window_start = now;
+        self.emitted = 0;
+        self.suppressed = 0;
+        self.last = None;
+        summary
+    }
+}
+
+/// 在 `event_tx` 之前插入日志节流，返回新的事件发送端；非日志事件原样转发
+pub(crate) fn throttled(
+    event_tx: mpsc::UnboundedSender<ServerEvent>,
+) -> mpsc::UnboundedSender<ServerEvent> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut throttle = LogThrottle::new(WINDOW, MAX_LOGS_PER_WINDOW, Instant::now());
+        loop {
+            let deadline = throttle.flush_deadline();
+            let flush = async move {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                event = rx.recv() => match event {
+                    Some(ServerEvent::Log(msg)) => {
+                        for msg in throttle.push(msg, Instant::now()) {
+                            let _ = event_tx.send(ServerEvent::Log(msg));
+                        }
+                    }
+                    Some(event) => {
+                        let _ = event_tx.send(event);
+                    }
+                    None => break,
+                },
+                _ = flush => {
+                    if let Some(summary) = throttle.roll(Instant::now()) {
+                        let _ = event_tx.send(ServerEvent::Log(summary));
+                    }
+                }
+            }
+        }
+    });
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_distinct_logs_per_window() {
+        let start = Instant::now();
+        let mut throttle = LogThrottle::new(WINDOW, 2, start);
+        assert_eq!(throttle.push("a".into(), start), ["a"]);
+        assert_eq!(throttle.push("b".into(), start), ["b"]);
+        assert!(throttle.push("c".into(), start).is_empty());
+        assert_eq!(throttle.flush_deadline(), Some(start + WINDOW));
+
+        // 下一窗口的首条日志前先补发摘要
+        let next = start + WINDOW;
+        assert_eq!(throttle.push("d".into(), next), ["… 1 more log messages suppressed", "d"]);
+        assert_eq!(throttle.flush_deadline(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn burst_of_identical_logs_collapses() {
+        let (event_tx, mut events) = mpsc::unbounded_channel();
+        let tx = throttled(event_tx);
+        for _ in 0..100 {
+            tx.send(ServerEvent::Log("client reconnected".into())).unwrap();
+        }
+        tx.send(ServerEvent::DeviceDisconnected { device_id: "laptop".into() }).unwrap();
+
+        assert!(matches!(events.recv().await, Some(ServerEvent::Log(msg)) if msg == "client reconnected"));
+        // 非日志事件不受节流影响
+        assert!(matches!(events.recv().await, Some(ServerEvent::DeviceDisconnected { .. })));
+        let started = Instant::now();
+        assert!(matches!(
+            events.recv().await,
+            Some(ServerEvent::Log(msg)) if msg == "… 99 more log messages suppressed"
+        ));
+        assert!(started.elapsed() <= WINDOW);
+        drop(tx);
+        assert!(events.recv().await.is_none());
+    }
+}
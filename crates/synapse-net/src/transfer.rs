@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use synapse_protocol::Message;
+
+/// 剪贴板传输的 `transfer_id`，进程内唯一
+static NEXT_TRANSFER_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 传输内容的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferKind {
+    Text,
+    Image,
+    Clear,
+}
+
+/// 传输所处的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferState {
+    /// 在发送队列中等待，可取消
+    Queued,
+    /// 已写出，等待设备确认
+    AwaitingAck,
+}
+
+/// 一次进行中的剪贴板传输
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferStatus {
+    pub transfer_id: u64,
+    pub device_id: String,
+    pub kind: TransferKind,
+    pub state: TransferState,
+    pub bytes_sent: usize,
+    pub total_bytes: usize,
+}
+
+/// 剪贴板消息的类型与载荷大小，非剪贴板传输消息返回 `None`
+fn describe(msg: &Message) -> Option<(TransferKind, usize)> {
+    match msg {
+        Message::ClipboardText { text, .. } => Some((TransferKind::Text, text.len())),
+        Message::ClipboardImage { data, .. } => Some((TransferKind::Image, data.len())),
+        Message::ClipboardClear { .. } => Some((TransferKind::Clear, 0)),
+        _ => None,
+    }
+}
+
+/// 剪贴板消息的 `transfer_id`
+pub(crate) fn transfer_id(msg: &Message) -> Option<u64> {
+    match msg {
+        Message::ClipboardText { transfer_id, .. }
+        | Message::ClipboardImage { transfer_id, .. }
+        | Message::ClipboardClear { transfer_id } => Some(*transfer_id),
+        _ => None,
+    }
+}
+
+/// 所有设备进行中的传输，按 `transfer_id` 索引
+#[derive(Debug, Default)]
+pub(crate) struct TransferMap {
+    transfers: Mutex<HashMap<u64, TransferStatus>>,
+}
+
+impl TransferMap {
+    /// 为将要排队的剪贴板消息分配 `transfer_id` 并登记；其他消息原样返回
+    pub(crate) fn register(&self, device_id: &str, mut msg: Message) -> Message {
+        let Some((kind, total_bytes)) = describe(&msg) else {
+            return msg;
+        };
+        let id = NEXT_TRANSFER_ID.fetch_add(1, Ordering::Relaxed);
+        if let Message::ClipboardText { transfer_id, .. }
+        | Message::ClipboardImage { transfer_id, .. }
+        | Message::ClipboardClear { transfer_id } = &mut msg
+        {
+            *transfer_id = id;
+        }
+        self.transfers.lock().unwrap().insert(id, TransferStatus {
+            transfer_id: id,
+            device_id: device_id.to_string(),
+            kind,
+            state: TransferState::Queued,
+            bytes_sent: 0,
+            total_bytes,
+        });
+        msg
+    }
+
+    /// 传输已完整写出，等待确认
+    pub(crate) fn mark_sent(&self, transfer_id: u64) {
+        if let Some(status) = self.transfers.lock().unwrap().get_mut(&transfer_id) {
+            status.state = TransferState::AwaitingAck;
+            status.bytes_sent = status.total_bytes;
+        }
+    }
+
+    pub(crate) fn get(&self, transfer_id: u64) -> Option<TransferStatus> {
+        self.transfers.lock().unwrap().get(&transfer_id).cloned()
+    }
+
+    /// 传输结束（已确认、超时、被丢弃或取消）
+    pub(crate) fn remove(&self, transfer_id: u64) -> Option<TransferStatus> {
+        self.transfers.lock().unwrap().remove(&transfer_id)
+    }
+
+    /// 设备断开，其传输全部结束
+    pub(crate) fn remove_device(&self, device_id: &str) {
+        self.transfers.lock().unwrap().retain(|_, status| status.device_id != device_id);
+    }
+
+    /// 按 `transfer_id` 排序的快照
+    pub(crate) fn list(&self) -> Vec<TransferStatus> {
+        let mut list: Vec<_> = self.transfers.lock().unwrap().values().cloned().collect();
+        list.sort_by_key(|status| status.transfer_id);
+        list
+    }
+}
@@ -0,0 +1,165 @@
+use synapse_protocol::input::MouseButton;
+use synapse_protocol::Message;
+
+/// 鼠标运动的旋转方向（顺时针），用于旋转安装的显示器
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+/// 针对单台设备的输入变换，在转发给该设备前应用，不影响其他设备
+///
+/// 通过 [`Server::set_input_transform`](crate::Server::set_input_transform) 设置
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputTransform {
+    /// 交换鼠标左右键（左手使用）
+    pub swap_buttons: bool,
+    /// 反转水平运动
+    pub invert_x: bool,
+    /// 反转垂直运动
+    pub invert_y: bool,
+    /// 旋转相对运动；绝对坐标只支持 180°（90°/270° 会改变屏幕宽高比）
+    pub rotation: Rotation,
+}
+
+impl InputTransform {
+    /// 是否不做任何变换
+    pub fn is_identity(&self) -> bool {
+        *self == InputTransform::default()
+    }
+
+    /// 变换一条将要发往设备的消息，`screen` 为设备屏幕尺寸（用于绝对坐标）
+    pub fn apply(&self, msg: Message, screen: (u32, u32)) -> Message {
+        match msg {
+            Message::MouseDelta { dx, dy } => {
+                let (dx, dy) = self.delta(dx, dy);
+                Message::MouseDelta { dx, dy }
+            }
+            Message::MouseMove { x, y } => {
+                let (x, y) = self.position(x, y, screen);
+                Message::MouseMove { x, y }
+            }
+            Message::MouseButtonEvent { button, action } => Message::MouseButtonEvent {
+                button: self.button(button),
+                action,
+            },
+            Message::MouseDoubleClick { button } => Message::MouseDoubleClick {
+                button: self.button(button),
+            },
+            msg => msg,
+        }
+    }
+
+    /// 相对运动：先旋转再反转
+    fn delta(&self, dx: f64, dy: f64) -> (f64, f64) {
+        let (dx, dy) = match self.rotation {
+            Rotation::None => (dx, dy),
+            Rotation::Cw90 => (-dy, dx),
+            Rotation::Cw180 => (-dx, -dy),
+            Rotation::Cw270 => (dy, -dx),
+        };
+        (
+            if self.invert_x { -dx } else { dx },
+            if self.invert_y { -dy } else { dy },
+        )
+    }
+
+    /// 绝对坐标：在设备屏幕内镜像（首尾像素互换）
+    fn position(&self, x: f64, y: f64, (w, h): (u32, u32)) -> (f64, f64) {
+        let (max_x, max_y) = (w.saturating_sub(1) as f64, h.saturating_sub(1) as f64);
+        let half_turn = self.rotation == Rotation::Cw180;
+        (
+            if self.invert_x != half_turn { max_x - x } else { x },
+            if self.invert_y != half_turn { max_y - y } else { y },
+        )
+    }
+
+    fn button(&self, button: MouseButton) -> MouseButton {
+        match (self.swap_buttons, button) {
+            (true, MouseButton::Left) => MouseButton::Right,
+            (true, MouseButton::Right) => MouseButton::Left,
+            (_, button) => button,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use synapse_protocol::input::ButtonAction;
+
+    use super::*;
+
+    const SCREEN: (u32, u32) = (1280, 720);
+
+    fn delta(transform: InputTransform, dx: f64, dy: f64) -> (f64, f64) {
+        match transform.apply(Message::MouseDelta { dx, dy }, SCREEN) {
+            Message::MouseDelta { dx, dy } => (dx, dy),
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    fn position(transform: InputTransform, x: f64, y: f64) -> (f64, f64) {
+        match transform.apply(Message::MouseMove { x, y }, SCREEN) {
+            Message::MouseMove { x, y } => (x, y),
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn swaps_left_and_right_buttons() {
+        let swap = InputTransform { swap_buttons: true, ..InputTransform::default() };
+        let press = |button| Message::MouseButtonEvent { button, action: ButtonAction::Press };
+        assert!(matches!(
+            swap.apply(press(MouseButton::Left), SCREEN),
+            Message::MouseButtonEvent { button: MouseButton::Right, action: ButtonAction::Press }
+        ));
+        assert!(matches!(
+            swap.apply(Message::MouseDoubleClick { button: MouseButton::Right }, SCREEN),
+            Message::MouseDoubleClick { button: MouseButton::Left }
+        ));
+        assert!(matches!(
+            swap.apply(press(MouseButton::Middle), SCREEN),
+            Message::MouseButtonEvent { button: MouseButton::Middle, .. }
+        ));
+    }
+
+    #[test]
+    fn inverts_and_rotates_motion() {
+        let invert_x = InputTransform { invert_x: true, ..InputTransform::default() };
+        let invert_y = InputTransform { invert_y: true, ..InputTransform::default() };
+        assert_eq!(delta(invert_x, 3.0, -2.0), (-3.0, -2.0));
+        assert_eq!(delta(invert_y, 3.0, -2.0), (3.0, 2.0));
+
+        let rotated = |rotation| InputTransform { rotation, ..InputTransform::default() };
+        assert_eq!(delta(rotated(Rotation::Cw90), 1.0, 0.0), (0.0, 1.0));
+        assert_eq!(delta(rotated(Rotation::Cw180), 1.0, 2.0), (-1.0, -2.0));
+        assert_eq!(delta(rotated(Rotation::Cw270), 1.0, 0.0), (0.0, -1.0));
+    }
+
+    #[test]
+    fn mirrors_absolute_positions_inside_screen() {
+        let invert_x = InputTransform { invert_x: true, ..InputTransform::default() };
+        assert_eq!(position(invert_x, 0.0, 100.0), (1279.0, 100.0));
+        assert_eq!(position(invert_x, 1279.0, 100.0), (0.0, 100.0));
+        // 旋转 180° 与同时反转两轴相同；再叠加反转则抵消
+        let half_turn = InputTransform { rotation: Rotation::Cw180, ..InputTransform::default() };
+        assert_eq!(position(half_turn, 0.0, 0.0), (1279.0, 719.0));
+        let cancelled = InputTransform { invert_y: true, ..half_turn };
+        assert_eq!(position(cancelled, 10.0, 20.0), (1269.0, 20.0));
+    }
+
+    #[test]
+    fn leaves_other_messages_alone() {
+        let transform = InputTransform { swap_buttons: true, invert_x: true, ..InputTransform::default() };
+        assert!(!transform.is_identity());
+        assert!(InputTransform::default().is_identity());
+        assert!(matches!(
+            transform.apply(Message::MouseScroll { dx: 1.0, dy: 2.0, precise: false }, SCREEN),
+            Message::MouseScroll { dx, dy, .. } if dx == 1.0 && dy == 2.0
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::Result;
+use futures::{Sink, Stream};
+use synapse_protocol::{Message, MessageCodec};
+use tokio::io::DuplexStream;
+use tokio_util::codec::Framed;
+
+/// 消息传输层：可收发 `Message` 的双向通道
+///
+/// `Framed<TcpStream, MessageCodec>` 与 [`DuplexTransport`] 均自动实现该 trait
+pub trait Transport:
+    Stream<Item = Result<Message>> + Sink<Message, Error = anyhow::Error> + Unpin + Send + 'static
+{
+}
+
+impl<T> Transport for T where
+    T: Stream<Item = Result<Message>> + Sink<Message, Error = anyhow::Error> + Unpin + Send + 'static
+{
+}
+
+/// 基于 `tokio::io::duplex` 的进程内传输
+pub type DuplexTransport = Framed<DuplexStream, MessageCodec>;
+
+/// 创建一对互联的内存传输，`max_buf_size` 为单向缓冲区大小
+pub fn duplex_pair(max_buf_size: usize) -> (DuplexTransport, DuplexTransport) {
+    let (a, b) = tokio::io::duplex(max_buf_size);
+    (Framed::new(a, MessageCodec::new()), Framed::new(b, MessageCodec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use synapse_protocol::screen::{Edge, ScreenRect};
+    use tokio::sync::mpsc;
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::{Client, ClientEvent, LocalAction, Sequenced, Server, ServerEvent};
+
+    #[tokio::test]
+    async fn client_and_server_switch_focus_over_memory_pipe() {
+        let screen = ScreenRect { x: 0, y: 0, width: 1920, height: 1080 };
+        let cancel = CancellationToken::new();
+        let server = Arc::new(Server::new("127.0.0.1:0"));
+        let (client_side, server_side) = duplex_pair(1 << 16);
+        server.attach(server_side, "memory").unwrap();
+
+        let (input, input_rx) = mpsc::unbounded_channel();
+        let (_clipboard_tx, clipboard_rx) = mpsc::unbounded_channel();
+        let (action_tx, mut actions) = mpsc::unbounded_channel();
+        let (event_tx, mut server_events) = mpsc::unbounded_channel();
+        let server_task = tokio::spawn({
+            let server = server.clone();
+            let cancel = cancel.clone();
+            async move {
+                server.run(input_rx, clipboard_rx, action_tx, event_tx, screen, Edge::Right, cancel).await
+            }
+        });
+
+        let (message_tx, mut messages) = mpsc::unbounded_channel();
+        let (client_event_tx, mut client_events) = mpsc::unbounded_channel();
+        let client_task = tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                Client::new("memory")
+                    .run_session(
+                        client_side,
+                        "laptop".into(),
+                        "Laptop".into(),
+                        (1280, 720),
+                        message_tx,
+                        client_event_tx,
+                        cancel,
+                    )
+                    .await
+            }
+        });
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            // Hello/Welcome 在两端都完成
+            while !matches!(client_events.recv().await, Some(ClientEvent::Connected { .. })) {}
+            while !matches!(
+                server_events.recv().await,
+                Some(ServerEvent::DeviceConnected { device_id, .. }) if device_id == "laptop"
+            ) {}
+
+            for (x, y) in [(1000.0, 540.0), (1919.0, 540.0)] {
+                input.send(Sequenced::new(Message::MouseMove { x, y })).unwrap();
+            }
+            while !matches!(messages.recv().await, Some(Message::EnterScreen { .. })) {}
+            let (x, y) = loop {
+                if let Some(LocalAction::MoveMouse(x, y)) = actions.recv().await {
+                    break (x as f64, y as f64);
+                }
+            };
+
+            // 虚拟光标越过设备左边缘，焦点回到本地
+            input.send(Sequenced::new(Message::MouseMove { x: x - 2000.0, y })).unwrap();
+            while !matches!(messages.recv().await, Some(Message::LeaveScreen { edge: Edge::Left, .. })) {}
+        })
+        .await
+        .expect("scenario timed out");
+
+        cancel.cancel();
+        server_task.await.unwrap().unwrap();
+        client_task.await.unwrap().unwrap();
+    }
+}
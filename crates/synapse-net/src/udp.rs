@@ -0,0 +1,298 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use synapse_protocol::{Datagram, Message, MAX_DATAGRAM_SIZE};
+use tokio::net::UdpSocket;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// 客户端重发注册数据报的间隔：补偿注册包丢失，并保持 NAT 映射
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+/// 客户端在 [`Message::UdpBarrier`] 处等待此前运动数据报的最长时间，超过即视为丢失
+pub(crate) const BARRIER_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// 经 UDP 旁路发送的消息：只有丢失单条也无害的鼠标运动
+pub(crate) fn is_motion(msg: &Message) -> bool {
+    matches!(msg, Message::MouseMove { .. } | Message::MouseDelta { .. })
+}
+
+/// 服务端的 UDP 运动旁路：所有设备共用一个套接字，按数据报中的令牌区分设备
+pub(crate) struct UdpMotion {
+    socket: Arc<UdpSocket>,
+    paths: Mutex<HashMap<u64, Arc<UdpPath>>>,
+}
+
+impl UdpMotion {
+    pub(crate) async fn bind(addr: SocketAddr) -> std::io::Result<Arc<Self>> {
+        let socket = UdpSocket::bind(addr).await?;
+        Ok(Arc::new(Self { socket: Arc::new(socket), paths: Mutex::new(HashMap::new()) }))
+    }
+
+    pub(crate) fn port(&self) -> u16 {
+        self.socket.local_addr().map(|addr| addr.port()).unwrap_or(0)
+    }
+
+    /// 为设备分配令牌；只接受来自 `peer_ip`（其 TCP 连接的来源）的注册
+    pub(crate) fn register(&self, device_id: &str, peer_ip: IpAddr) -> Arc<UdpPath> {
+        let mut hasher = RandomState::new().build_hasher();
+        device_id.hash(&mut hasher);
+        Instant::now().hash(&mut hasher);
+        let path = Arc::new(UdpPath {
+            socket: self.socket.clone(),
+            token: hasher.finish(),
+            peer_ip,
+            addr: Mutex::new(None),
+            seq: AtomicU64::new(0),
+            barrier: AtomicU64::new(0),
+        });
+        self.paths.lock().unwrap().insert(path.token, path.clone());
+        path
+    }
+
+    pub(crate) fn unregister(&self, token: u64) {
+        self.paths.lock().unwrap().remove(&token);
+    }
+
+    /// 接收客户端的注册数据报，记录各设备的 UDP 地址
+    pub(crate) async fn run(self: Arc<Self>, cancel: CancellationToken) {
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            let (len, from) = tokio::select! {
+                _ = cancel.cancelled() => break,
+                result = self.socket.recv_from(&mut buf) => match result {
+                    Ok(received) => received,
+                    Err(e) => {
+                        debug!("UDP receive error: {e}");
+                        continue;
+                    }
+                },
+            };
+            let token = match Datagram::decode(&buf[..len]) {
+                Ok(datagram) => datagram.token,
+                Err(e) => {
+                    debug!(%from, "ignoring malformed datagram: {e}");
+                    continue;
+                }
+            };
+            let path = self.paths.lock().unwrap().get(&token).cloned();
+            match path {
+                Some(path) if path.peer_ip == from.ip() => path.set_addr(from),
+                _ => debug!(%from, "ignoring datagram with unknown token"),
+            }
+        }
+    }
+}
+
+/// 单个设备的 UDP 发送路径
+pub(crate) struct UdpPath {
+    socket: Arc<UdpSocket>,
+    token: u64,
+    peer_ip: IpAddr,
+    /// 客户端注册数据报的来源地址，收到注册前为 `None`
+    addr: Mutex<Option<SocketAddr>>,
+    seq: AtomicU64,
+    /// 已由 [`Message::UdpBarrier`] 覆盖的最大序号
+    barrier: AtomicU64,
+}
+
+impl UdpPath {
+    pub(crate) fn token(&self) -> u64 {
+        self.token
+    }
+
+    fn set_addr(&self, addr: SocketAddr) {
+        let mut current = self.addr.lock().unwrap();
+        if *current != Some(addr) {
+            info!(%addr, "UDP motion path registered");
+            *current = Some(addr);
+        }
+    }
+
+    /// 自上次屏障以来经 UDP 发出过运动时返回其最大序号，调用方在下一条 TCP 输入前发送
+    /// [`Message::UdpBarrier`]
+    pub(crate) fn take_barrier(&self) -> Option<u64> {
+        let seq = self.seq.load(Ordering::Relaxed);
+        let previous = self.barrier.swap(seq, Ordering::Relaxed);
+        (seq > previous).then_some(seq)
+    }
+
+    /// 经 UDP 发送运动消息；客户端尚未注册或发送失败时返回 `false`，由调用方改走 TCP
+    pub(crate) fn send(&self, msg: &Message) -> bool {
+        let Some(addr) = *self.addr.lock().unwrap() else {
+            return false;
+        };
+        let datagram = Datagram {
+            token: self.token,
+            seq: self.seq.fetch_add(1, Ordering::Relaxed) + 1,
+            msg: Some(msg.clone()),
+        };
+        let sent = datagram
+            .encode()
+            .map_err(|e| e.to_string())
+            .and_then(|data| self.socket.try_send_to(&data, addr).map_err(|e| e.to_string()));
+        match sent {
+            Ok(_) => true,
+            Err(e) => {
+                debug!(%addr, "UDP send failed, falling back to TCP: {e}");
+                false
+            }
+        }
+    }
+}
+
+/// 客户端的 UDP 运动旁路：向服务端注册并接收运动数据报
+pub(crate) struct UdpReceiver {
+    socket: UdpSocket,
+    token: u64,
+    /// 已接收的最大序号，更旧的数据报乱序到达时丢弃
+    last_seq: u64,
+    /// 等待屏障时收到的、属于屏障之后的运动，下次接收时先返回
+    pending: Option<Message>,
+    keepalive: tokio::time::Interval,
+}
+
+impl UdpReceiver {
+    pub(crate) async fn connect(server: SocketAddr, token: u64) -> std::io::Result<Self> {
+        let local: IpAddr = match server {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+        let socket = UdpSocket::bind(SocketAddr::new(local, 0)).await?;
+        socket.connect(server).await?;
+        let mut receiver = Self {
+            socket,
+            token,
+            last_seq: 0,
+            pending: None,
+            keepalive: tokio::time::interval(KEEPALIVE_INTERVAL),
+        };
+        // interval 的第一次 tick 立即完成，用于发送首个注册数据报
+        receiver.keepalive.tick().await;
+        receiver.register().await;
+        info!(%server, "UDP motion path opened");
+        Ok(receiver)
+    }
+
+    async fn register(&self) {
+        let datagram = Datagram { token: self.token, seq: 0, msg: None };
+        if let Ok(data) = datagram.encode() {
+            if let Err(e) = self.socket.send(&data).await {
+                debug!("failed to send UDP registration: {e}");
+            }
+        }
+    }
+
+    /// 等待下一条有效的运动消息，期间定时重发注册数据报
+    pub(crate) async fn recv(&mut self) -> Message {
+        match self.pending.take() {
+            Some(msg) => msg,
+            None => self.recv_datagram().await.1,
+        }
+    }
+
+    /// 收取序号不超过屏障 `seq` 的运动，直到收齐或超过 [`BARRIER_TIMEOUT`]（数据报丢失）
+    pub(crate) async fn catch_up(&mut self, seq: u64) -> Vec<Message> {
+        let mut caught_up = Vec::new();
+        let deadline = tokio::time::Instant::now() + BARRIER_TIMEOUT;
+        while self.last_seq < seq && self.pending.is_none() {
+            match tokio::time::timeout_at(deadline, self.recv_datagram()).await {
+                Ok((received, msg)) if received <= seq => caught_up.push(msg),
+                // 屏障之后发出的运动先于缺失的数据报到达，缺失的已无法按序应用
+                Ok((_, msg)) => self.pending = Some(msg),
+                Err(_) => {
+                    debug!(seq, last_seq = self.last_seq, "UDP motion before barrier lost");
+                    break;
+                }
+            }
+        }
+        caught_up
+    }
+
+    async fn recv_datagram(&mut self) -> (u64, Message) {
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            let len = tokio::select! {
+                _ = self.keepalive.tick() => {
+                    self.register().await;
+                    continue;
+                }
+                result = self.socket.recv(&mut buf) => match result {
+                    Ok(len) => len,
+                    Err(e) => {
+                        // 如服务端尚未监听时收到的 ICMP 端口不可达，不影响之后的接收
+                        debug!("UDP receive error: {e}");
+                        continue;
+                    }
+                },
+            };
+            let datagram = match Datagram::decode(&buf[..len]) {
+                Ok(datagram) => datagram,
+                Err(e) => {
+                    warn!("ignoring malformed UDP datagram: {e}");
+                    continue;
+                }
+            };
+            if datagram.token != self.token || datagram.seq <= self.last_seq {
+                continue;
+            }
+            self.last_seq = datagram.seq;
+            match datagram.msg {
+                Some(msg) if is_motion(&msg) => return (datagram.seq, msg),
+                _ => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motion(x: f64) -> Message {
+        Message::MouseMove { x, y: 0.0 }
+    }
+
+    #[tokio::test]
+    async fn registers_by_token_and_orders_by_barrier() {
+        let cancel = CancellationToken::new();
+        let server = UdpMotion::bind((Ipv4Addr::LOCALHOST, 0).into()).await.unwrap();
+        tokio::spawn(server.clone().run(cancel.clone()));
+        let path = server.register("laptop", Ipv4Addr::LOCALHOST.into());
+        // 客户端注册前不能经 UDP 发送
+        assert!(!path.send(&motion(0.0)));
+        assert_eq!(path.take_barrier(), None);
+
+        let mut client =
+            UdpReceiver::connect((Ipv4Addr::LOCALHOST, server.port()).into(), path.token()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !path.send(&motion(1.0)) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("registration datagram was not accepted");
+        assert!(matches!(client.recv().await, Message::MouseMove { x, .. } if x == 1.0));
+
+        // 屏障覆盖之前发出的运动，之后的运动留待下次接收
+        assert!(path.send(&motion(2.0)));
+        assert!(path.send(&motion(3.0)));
+        let barrier = path.take_barrier().unwrap();
+        assert_eq!(path.take_barrier(), None);
+        assert!(path.send(&motion(4.0)));
+        let caught_up = client.catch_up(barrier).await;
+        assert!(matches!(caught_up.as_slice(), [
+            Message::MouseMove { x: a, .. },
+            Message::MouseMove { x: b, .. },
+        ] if *a == 2.0 && *b == 3.0));
+        assert!(matches!(client.recv().await, Message::MouseMove { x, .. } if x == 4.0));
+
+        // 屏障之前的数据报丢失时等待超时后继续
+        assert!(client.catch_up(barrier + 10).await.is_empty());
+        cancel.cancel();
+    }
+}
@@ -0,0 +1,142 @@
+//! 在回环地址上运行真实的服务端与客户端，验证越过边缘与返回的完整流程
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use synapse_net::{Client, ClientEvent, LocalAction, Sequenced, Server, ServerEvent};
+use synapse_protocol::screen::{Edge, ScreenRect};
+use synapse_protocol::Message;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// 服务端本地屏幕，客户端接在右边缘
+const SCREEN: ScreenRect = ScreenRect { x: 0, y: 0, width: 1920, height: 1080 };
+
+/// 场景卡住时让测试失败而不是挂起
+async fn within<T>(scenario: impl std::future::Future<Output = T>) -> T {
+    tokio::time::timeout(Duration::from_secs(10), scenario).await.expect("scenario timed out")
+}
+
+/// 绑定 `127.0.0.1:0` 的服务端与经 TCP 连接它的客户端
+struct Loopback {
+    input: mpsc::UnboundedSender<Sequenced>,
+    actions: mpsc::UnboundedReceiver<LocalAction>,
+    /// 客户端收到的服务端消息
+    messages: mpsc::UnboundedReceiver<Message>,
+    cancel: CancellationToken,
+    server: JoinHandle<()>,
+    client: JoinHandle<()>,
+}
+
+impl Loopback {
+    async fn start() -> Self {
+        let cancel = CancellationToken::new();
+        let server = Arc::new(Server::new("127.0.0.1:0"));
+        let (input, input_rx) = mpsc::unbounded_channel();
+        let (_clipboard_tx, clipboard_rx) = mpsc::unbounded_channel();
+        let (action_tx, actions) = mpsc::unbounded_channel();
+        let (event_tx, mut server_events) = mpsc::unbounded_channel();
+        let server_task = tokio::spawn({
+            let server = server.clone();
+            let cancel = cancel.clone();
+            async move {
+                server
+                    .run(input_rx, clipboard_rx, action_tx, event_tx, SCREEN, Edge::Right, cancel)
+                    .await
+                    .unwrap();
+            }
+        });
+        let addr = loop {
+            match server.local_addr() {
+                Some(addr) => break addr,
+                None => tokio::time::sleep(Duration::from_millis(5)).await,
+            }
+        };
+
+        let (message_tx, messages) = mpsc::unbounded_channel();
+        let (client_event_tx, mut client_events) = mpsc::unbounded_channel();
+        let client_task = tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                Client::new(addr.to_string())
+                    .connect("laptop".into(), "Laptop".into(), (1280, 720), message_tx, client_event_tx, cancel)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        // 两端都确认握手完成后再注入输入
+        loop {
+            match client_events.recv().await.expect("client stopped") {
+                ClientEvent::Connected { .. } => break,
+                ClientEvent::Disconnected => panic!("client disconnected during handshake"),
+                _ => {}
+            }
+        }
+        loop {
+            if let ServerEvent::DeviceConnected { device_id, .. } = server_events.recv().await.expect("server stopped") {
+                assert_eq!(device_id, "laptop");
+                break;
+            }
+        }
+
+        Self { input, actions, messages, cancel, server: server_task, client: client_task }
+    }
+
+    fn move_to(&self, x: f64, y: f64) {
+        self.input.send(Sequenced::new(Message::MouseMove { x, y })).unwrap();
+    }
+
+    /// 客户端收到的下一条焦点或运动消息，跳过心跳等其他消息
+    async fn next_motion(&mut self) -> Message {
+        loop {
+            let msg = self.messages.recv().await.expect("client stopped");
+            if matches!(
+                msg,
+                Message::EnterScreen { .. } | Message::LeaveScreen { .. } | Message::MouseDelta { .. }
+            ) {
+                return msg;
+            }
+        }
+    }
+
+    /// 服务端把本机鼠标锁定到的位置
+    async fn anchor(&mut self) -> (f64, f64) {
+        loop {
+            if let LocalAction::MoveMouse(x, y) = self.actions.recv().await.expect("server stopped") {
+                return (x as f64, y as f64);
+            }
+        }
+    }
+
+    async fn stop(self) {
+        self.cancel.cancel();
+        within(async {
+            self.server.await.unwrap();
+            self.client.await.unwrap();
+        })
+        .await;
+    }
+}
+
+#[tokio::test]
+async fn crosses_right_edge_and_returns() {
+    let mut lo = Loopback::start().await;
+    within(async {
+        lo.move_to(1000.0, 540.0);
+        lo.move_to(1919.0, 540.0);
+        assert!(matches!(lo.next_motion().await, Message::EnterScreen { .. }));
+
+        // 焦点在远程时本机鼠标被锁定在锚点，相对锚点的位移转发给设备
+        let (x, y) = lo.anchor().await;
+        lo.move_to(x + 10.0, y);
+        assert!(matches!(lo.next_motion().await, Message::MouseDelta { dx, dy } if dx == 10.0 && dy == 0.0));
+
+        // 虚拟光标越过设备左边缘，焦点回到本地
+        lo.move_to(x - 2000.0, y);
+        assert!(matches!(lo.next_motion().await, Message::LeaveScreen { edge: Edge::Left, .. }));
+    })
+    .await;
+    lo.stop().await;
+}
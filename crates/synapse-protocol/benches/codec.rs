@@ -0,0 +1,67 @@
+//! 帧编解码基准：小的输入消息与大的剪贴板消息，分别在不压缩与 LZ4 压缩下编码和解码
+//!
+//! 运行：`cargo bench -p synapse-protocol`
+
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use synapse_protocol::{Compression, Message, MessageCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+fn messages() -> Vec<(&'static str, Message)> {
+    let text = "The quick brown fox jumps over the lazy dog. ".repeat(1500);
+    vec![
+        ("mouse_delta", Message::MouseDelta { dx: 3.5, dy: -1.25 }),
+        ("clipboard_64k", Message::ClipboardText { text, transfer_id: 1 }),
+    ]
+}
+
+fn codec(compression: Compression) -> MessageCodec {
+    let codec = MessageCodec::new();
+    codec.compression_handle().set(compression);
+    codec
+}
+
+fn encoded(msg: &Message, compression: Compression) -> BytesMut {
+    let mut buf = BytesMut::new();
+    codec(compression).encode(msg.clone(), &mut buf).unwrap();
+    buf
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for (name, msg) in messages() {
+        for compression in [Compression::None, Compression::Lz4] {
+            group.throughput(Throughput::Bytes(synapse_protocol::encoded_len(&msg)));
+            let mut codec = codec(compression);
+            let mut buf = BytesMut::new();
+            group.bench_with_input(BenchmarkId::new(name, format!("{compression:?}")), &msg, |b, msg| {
+                b.iter(|| {
+                    buf.clear();
+                    codec.encode(msg.clone(), &mut buf).unwrap();
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, msg) in messages() {
+        for compression in [Compression::None, Compression::Lz4] {
+            group.throughput(Throughput::Bytes(synapse_protocol::encoded_len(&msg)));
+            let frame = encoded(&msg, compression);
+            let mut codec = codec(compression);
+            group.bench_with_input(BenchmarkId::new(name, format!("{compression:?}")), &frame, |b, frame| {
+                b.iter(|| {
+                    let mut buf = frame.clone();
+                    codec.decode(&mut buf).unwrap().unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_decode);
+criterion_main!(benches);
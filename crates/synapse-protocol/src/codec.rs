@@ -1,32 +1,251 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Arc;
+
 use anyhow::{bail, Result};
 use bytes::{Buf, BufMut, BytesMut};
+use serde::{Deserialize, Serialize};
 use tokio_util::codec::{Decoder, Encoder};
+use tracing::warn;
 
 use crate::message::Message;
 
-/// 最大帧大小: 16 MB
-const MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024;
+/// 最大帧大小: 16 MB（压缩帧按解压后的大小计）
+pub const MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024;
+
+/// 长度前缀的最高位：载荷经过 LZ4 压缩
+const COMPRESSED_FLAG: u32 = 1 << 31;
+
+/// 小于该字节数的载荷不压缩（输入事件等小消息压缩无收益）
+const MIN_COMPRESS_LEN: usize = 512;
+
+/// 帧同步模式下每帧开头的同步字
+const SYNC_MAGIC: [u8; 4] = [0x53, 0x59, 0x4e, 0xa5];
+
+/// 帧同步模式下同步字、长度前缀与头部校验和的总长度
+const SYNC_HEADER_LEN: usize = 12;
+
+/// 帧同步模式下载荷之后的校验和长度
+const CHECKSUM_LEN: usize = 4;
+
+/// 帧压缩算法，在握手中按连接协商
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    Lz4 = 1,
+}
+
+impl Compression {
+    /// 按 `preferred` 的顺序选出对端也支持的第一个算法，没有共同算法时不压缩
+    pub fn negotiate(preferred: &[Compression], supported: &[Compression]) -> Compression {
+        preferred
+            .iter()
+            .copied()
+            .find(|c| *c != Compression::None && supported.contains(c))
+            .unwrap_or(Compression::None)
+    }
+
+    fn from_u8(value: u8) -> Compression {
+        match value {
+            1 => Compression::Lz4,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// 编码器当前使用的压缩算法及帧同步模式，与 [`MessageCodec`] 共享
+///
+/// 编解码器被 `Framed` 持有后仍可通过该句柄在握手完成时切换压缩与帧同步
+#[derive(Debug, Clone, Default)]
+pub struct CompressionHandle {
+    compression: Arc<AtomicU8>,
+    frame_sync: Arc<AtomicBool>,
+}
+
+impl CompressionHandle {
+    pub fn get(&self) -> Compression {
+        Compression::from_u8(self.compression.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, compression: Compression) {
+        self.compression.store(compression as u8, Ordering::Relaxed);
+    }
+
+    /// 是否使用帧同步格式（收发两个方向同时切换）
+    pub fn frame_sync(&self) -> bool {
+        self.frame_sync.load(Ordering::Relaxed)
+    }
+
+    /// 切换帧同步格式，需在双方通过 [`Capability::FrameSync`](crate::Capability::FrameSync)
+    /// 协商一致后、握手之后的第一帧之前调用
+    pub fn set_frame_sync(&self, enabled: bool) {
+        self.frame_sync.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// 帧同步模式下的载荷校验和（FNV-1a）
+fn checksum(payload: &[u8]) -> u32 {
+    payload.iter().fold(0x811c_9dc5, |hash: u32, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// 帧同步模式下同步字与长度前缀的校验和
+///
+/// 在等待载荷之前校验，错位数据中偶然出现的同步字不会让解码器为一个伪造的长度等待最多 16 MB
+fn header_checksum(header: &[u8]) -> u32 {
+    checksum(header) ^ 0xa5a5_a5a5
+}
+
+/// 解码失败的原因，包装在 `anyhow::Error` 中返回，可通过 `downcast_ref` 区分于 I/O 错误
+///
+/// 普通长度前缀帧无法在流中途重新同步，出现此类错误后连接应当关闭；
+/// 帧同步模式下长度或校验和错误会跳过损坏的数据，不返回错误
+#[derive(Debug, thiserror::Error)]
+pub enum CodecError {
+    /// 长度前缀超出上限（通常意味着流已错位或数据损坏）
+    #[error("frame too large: {len} bytes (max {max})")]
+    FrameTooLarge { len: usize, max: u32 },
+    /// 载荷无法反序列化为 `Message`（数据损坏或协议版本不兼容）
+    #[error("malformed {len}-byte frame: {reason}")]
+    Malformed { len: usize, reason: String },
+}
 
 /// 长度前缀帧编解码器
 ///
-/// 帧格式: `[u32 BE 长度][bincode 载荷]`
-pub struct MessageCodec;
+/// 帧格式: `[u32 BE 长度][bincode 载荷]`；长度最高位置位时载荷为 LZ4 压缩的 bincode。
+/// 解码始终接受压缩帧，编码只在协商出压缩算法后才压缩，因此不会向不支持的对端发送压缩帧。
+///
+/// 协商启用帧同步后格式为 `[同步字][u32 BE 长度][u32 BE 头部校验和][载荷][u32 BE 载荷校验和]`：
+/// 流错位或数据损坏时向后查找下一个同步字并从那里继续，而不是断开连接
+#[derive(Debug, Clone, Default)]
+pub struct MessageCodec {
+    compression: CompressionHandle,
+}
+
+impl MessageCodec {
+    /// 不压缩的编解码器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 控制该编解码器压缩算法的句柄
+    pub fn compression_handle(&self) -> CompressionHandle {
+        self.compression.clone()
+    }
+}
+
+/// 消息未压缩时的载荷字节数，可用于在发送前判断是否超出 [`MAX_FRAME_SIZE`]
+pub fn encoded_len(msg: &Message) -> u64 {
+    bincode::serialized_size(msg).unwrap_or(u64::MAX)
+}
+
+/// 解压 LZ4 帧载荷（前 4 字节为解压后的长度），拒绝解压后超出帧大小上限的数据
+fn decompress(payload: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let (size, compressed) =
+        lz4_flex::block::uncompressed_size(payload).map_err(|e| e.to_string())?;
+    if size > MAX_FRAME_SIZE as usize {
+        return Err(format!("decompressed size {size} exceeds {MAX_FRAME_SIZE}"));
+    }
+    lz4_flex::block::decompress(compressed, size).map_err(|e| e.to_string())
+}
+
+/// 将载荷反序列化为消息，`compressed` 时先解压
+fn parse_payload(payload: &[u8], compressed: bool) -> Result<Message> {
+    let len = payload.len();
+    let msg = if compressed {
+        let data = decompress(payload).map_err(|reason| CodecError::Malformed { len, reason })?;
+        bincode::deserialize(&data)
+    } else {
+        bincode::deserialize(payload)
+    }
+    .map_err(|e| CodecError::Malformed { len, reason: e.to_string() })?;
+    Ok(msg)
+}
+
+/// 丢弃 `src` 开头的 `count` 个损坏字节
+fn skip_corrupt(src: &mut BytesMut, count: usize, reason: &str) {
+    warn!(dropped = count, "resynchronizing frame stream: {reason}");
+    src.advance(count);
+}
+
+impl MessageCodec {
+    /// 帧同步格式的解码：对齐到同步字，长度或校验和不对时跳过一个字节重新查找
+    fn decode_synced(&mut self, src: &mut BytesMut) -> Result<Option<Message>> {
+        loop {
+            match src.windows(SYNC_MAGIC.len()).position(|w| w == SYNC_MAGIC) {
+                Some(0) => {}
+                Some(pos) => skip_corrupt(src, pos, "data before sync word"),
+                None => {
+                    // 末尾可能是被截断的同步字，保留到下次读取
+                    let keep = src.len().min(SYNC_MAGIC.len() - 1);
+                    if src.len() > keep {
+                        skip_corrupt(src, src.len() - keep, "no sync word");
+                    }
+                    return Ok(None);
+                }
+            }
+            if src.len() < SYNC_HEADER_LEN {
+                return Ok(None);
+            }
+
+            let expected = u32::from_be_bytes([src[8], src[9], src[10], src[11]]);
+            if header_checksum(&src[..8]) != expected {
+                skip_corrupt(src, 1, "header checksum mismatch");
+                continue;
+            }
+            let header = u32::from_be_bytes([src[4], src[5], src[6], src[7]]);
+            let compressed = header & COMPRESSED_FLAG != 0;
+            let len = (header & !COMPRESSED_FLAG) as usize;
+            if len as u32 > MAX_FRAME_SIZE {
+                skip_corrupt(src, 1, "frame length out of range");
+                continue;
+            }
+
+            let frame_len = SYNC_HEADER_LEN + len + CHECKSUM_LEN;
+            if src.len() < frame_len {
+                src.reserve(frame_len - src.len());
+                return Ok(None);
+            }
+            let payload = &src[SYNC_HEADER_LEN..SYNC_HEADER_LEN + len];
+            let trailer = &src[SYNC_HEADER_LEN + len..frame_len];
+            let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+            if checksum(payload) != expected {
+                skip_corrupt(src, 1, "checksum mismatch");
+                continue;
+            }
+
+            src.advance(SYNC_HEADER_LEN);
+            let payload = src.split_to(len);
+            src.advance(CHECKSUM_LEN);
+            // 校验和正确但无法解析说明协议不兼容，而不是传输损坏
+            return parse_payload(&payload, compressed).map(Some);
+        }
+    }
+}
 
 impl Decoder for MessageCodec {
     type Item = Message;
     type Error = anyhow::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        if self.compression.frame_sync() {
+            return self.decode_synced(src);
+        }
+
         // 至少需要 4 字节读取长度
         if src.len() < 4 {
             return Ok(None);
         }
 
         // 读取帧长度（不消费）
-        let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        let header = u32::from_be_bytes([src[0], src[1], src[2], src[3]]);
+        let compressed = header & COMPRESSED_FLAG != 0;
+        let len = (header & !COMPRESSED_FLAG) as usize;
 
         if len as u32 > MAX_FRAME_SIZE {
-            bail!("frame too large: {} bytes (max {})", len, MAX_FRAME_SIZE);
+            return Err(CodecError::FrameTooLarge { len, max: MAX_FRAME_SIZE }.into());
         }
 
         // 等待完整帧
@@ -38,9 +257,7 @@ impl Decoder for MessageCodec {
         // 消费长度前缀
         src.advance(4);
         let payload = src.split_to(len);
-
-        let msg: Message = bincode::deserialize(&payload)?;
-        Ok(Some(msg))
+        parse_payload(&payload, compressed).map(Some)
     }
 }
 
@@ -55,9 +272,87 @@ impl Encoder<Message> for MessageCodec {
             bail!("frame too large: {} bytes (max {})", len, MAX_FRAME_SIZE);
         }
 
+        // 压缩后不更小时按原样发送
+        let (payload, flag) = match self.compression.get() {
+            Compression::Lz4 if payload.len() >= MIN_COMPRESS_LEN => {
+                let compressed = lz4_flex::compress_prepend_size(&payload);
+                if compressed.len() < payload.len() {
+                    (compressed, COMPRESSED_FLAG)
+                } else {
+                    (payload, 0)
+                }
+            }
+            _ => (payload, 0),
+        };
+
+        if self.compression.frame_sync() {
+            dst.reserve(SYNC_HEADER_LEN + payload.len() + CHECKSUM_LEN);
+            let start = dst.len();
+            dst.extend_from_slice(&SYNC_MAGIC);
+            dst.put_u32(payload.len() as u32 | flag);
+            let header = header_checksum(&dst[start..]);
+            dst.put_u32(header);
+            dst.extend_from_slice(&payload);
+            dst.put_u32(checksum(&payload));
+            return Ok(());
+        }
+
         dst.reserve(4 + payload.len());
-        dst.put_u32(len);
+        dst.put_u32(payload.len() as u32 | flag);
         dst.extend_from_slice(&payload);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced_codec() -> MessageCodec {
+        let codec = MessageCodec::new();
+        codec.compression_handle().set_frame_sync(true);
+        codec
+    }
+
+    #[test]
+    fn synced_decode_skips_garbage_before_frame() {
+        let mut codec = synced_codec();
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef, 0x00]);
+        // 伪造的同步字声明接近上限的长度，头部校验和不对，不应等待其载荷
+        buf.extend_from_slice(&SYNC_MAGIC);
+        buf.put_u32(MAX_FRAME_SIZE - 1);
+        buf.put_u32(0);
+        codec.encode(Message::Ping(42), &mut buf).unwrap();
+
+        match codec.decode(&mut buf).unwrap() {
+            Some(Message::Ping(42)) => {}
+            other => panic!("unexpected decode result: {other:?}"),
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn synced_decode_skips_corrupt_payload() {
+        let mut codec = synced_codec();
+        let mut buf = BytesMut::new();
+        codec.encode(Message::Ping(1), &mut buf).unwrap();
+        let last = buf.len() - 1;
+        buf[last] ^= 0xff;
+        codec.encode(Message::Ping(2), &mut buf).unwrap();
+
+        match codec.decode(&mut buf).unwrap() {
+            Some(Message::Ping(2)) => {}
+            other => panic!("unexpected decode result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn plain_decode_rejects_oversized_length() {
+        let mut codec = MessageCodec::new();
+        let mut buf = BytesMut::new();
+        buf.put_u32(MAX_FRAME_SIZE + 1);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CodecError::FrameTooLarge { .. })));
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::codec::CodecError;
+use crate::message::Message;
+
+/// 数据报头部：令牌与序号
+const HEADER_LEN: usize = 16;
+
+/// 运动旁路数据报的最大长度，保证不被 IP 分片（运动消息远小于该值）
+pub const MAX_DATAGRAM_SIZE: usize = 1200;
+
+/// UDP 运动旁路的数据报
+///
+/// 格式: `[u64 BE 令牌][u64 BE 序号][bincode 载荷]`。令牌由服务端在
+/// [`Message::UdpOffer`] 中下发，用于识别设备并丢弃无关数据报；序号单调递增，
+/// 接收方丢弃乱序到达的旧数据报。载荷为空的数据报是客户端的注册/保活包
+#[derive(Debug, Clone)]
+pub struct Datagram {
+    pub token: u64,
+    pub seq: u64,
+    pub msg: Option<Message>,
+}
+
+impl Datagram {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut buf = BytesMut::with_capacity(HEADER_LEN + 32);
+        buf.put_u64(self.token);
+        buf.put_u64(self.seq);
+        if let Some(msg) = &self.msg {
+            buf.put_slice(&bincode::serialize(msg)?);
+        }
+        if buf.len() > MAX_DATAGRAM_SIZE {
+            return Err(CodecError::FrameTooLarge {
+                len: buf.len(),
+                max: MAX_DATAGRAM_SIZE as u32,
+            }
+            .into());
+        }
+        Ok(buf.to_vec())
+    }
+
+    pub fn decode(mut data: &[u8]) -> Result<Self> {
+        let len = data.len();
+        if len < HEADER_LEN {
+            return Err(CodecError::Malformed { len, reason: "truncated datagram".into() }.into());
+        }
+        let token = data.get_u64();
+        let seq = data.get_u64();
+        let msg = if data.is_empty() {
+            None
+        } else {
+            let msg = bincode::deserialize(data)
+                .map_err(|e| CodecError::Malformed { len, reason: e.to_string() })?;
+            Some(msg)
+        };
+        Ok(Self { token, seq, msg })
+    }
+}
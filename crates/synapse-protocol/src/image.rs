@@ -0,0 +1,97 @@
+use anyhow::{bail, Result};
+
+use crate::message::{ImageEncoding, Message};
+
+/// 将 RGBA 图片编码为 PNG
+pub fn encode_png(width: u32, height: u32, data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// 解码 PNG 为 RGBA，返回 (宽, 高, 像素)
+///
+/// 在分配像素缓冲前按文件头检查像素数，避免恶意的超大尺寸耗尽内存
+pub fn decode_png(data: &[u8], max_pixels: u64) -> Result<(u32, u32, Vec<u8>)> {
+    let mut reader = png::Decoder::new(data).read_info()?;
+    let (width, height) = (reader.info().width, reader.info().height);
+    if width as u64 * height as u64 > max_pixels {
+        bail!("clipboard image too large: {}x{} (max {} pixels)", width, height, max_pixels);
+    }
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf)?;
+    if frame.color_type != png::ColorType::Rgba || frame.bit_depth != png::BitDepth::Eight {
+        bail!("unsupported PNG format: {:?} {:?}", frame.color_type, frame.bit_depth);
+    }
+    buf.truncate(frame.buffer_size());
+    Ok((width, height, buf))
+}
+
+/// 将 PNG 编码的剪贴板图片转换为原始 RGBA，供只支持原始图片的对端使用；其他消息原样返回
+///
+/// 解码结果的尺寸必须与消息头一致，因此像素数上限即消息头声明的尺寸
+pub fn png_to_raw(msg: Message) -> Result<Message> {
+    match msg {
+        Message::ClipboardImage { width, height, encoding: ImageEncoding::Png, data, transfer_id } => {
+            let (png_w, png_h, pixels) = decode_png(&data, width as u64 * height as u64)?;
+            if (png_w, png_h) != (width, height) {
+                bail!(
+                    "clipboard image size mismatch: header {}x{}, PNG {}x{}",
+                    width, height, png_w, png_h
+                );
+            }
+            Ok(Message::ClipboardImage {
+                width,
+                height,
+                encoding: ImageEncoding::Raw,
+                data: pixels,
+                transfer_id,
+            })
+        }
+        msg => Ok(msg),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_to_raw_restores_pixels() {
+        let pixels: Vec<u8> = (0..2 * 3 * 4).map(|i| i as u8).collect();
+        let png = encode_png(2, 3, &pixels).unwrap();
+        let msg = Message::ClipboardImage {
+            width: 2,
+            height: 3,
+            encoding: ImageEncoding::Png,
+            data: png,
+            transfer_id: 7,
+        };
+        match png_to_raw(msg).unwrap() {
+            Message::ClipboardImage { width, height, encoding, data, transfer_id } => {
+                assert_eq!((width, height, transfer_id), (2, 3, 7));
+                assert_eq!(encoding, ImageEncoding::Raw);
+                assert_eq!(data, pixels);
+            }
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn png_to_raw_rejects_size_mismatch() {
+        let png = encode_png(2, 2, &[0; 16]).unwrap();
+        let msg = Message::ClipboardImage {
+            width: 1,
+            height: 1,
+            encoding: ImageEncoding::Png,
+            data: png,
+            transfer_id: 1,
+        };
+        assert!(png_to_raw(msg).is_err());
+    }
+}
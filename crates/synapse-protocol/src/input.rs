@@ -31,6 +31,13 @@ pub enum MouseButton {
     Middle,
     Back,
     Forward,
+    // ── 扩展（只能在此之后追加） ──
+    /// 滚轮向左倾斜点击
+    TiltLeft,
+    /// 滚轮向右倾斜点击
+    TiltRight,
+    /// 其他额外按键（携带平台原始编号）
+    Other(u8),
 }
 
 /// 按键动作
@@ -46,3 +53,61 @@ pub enum ButtonAction {
     Press,
     Release,
 }
+
+/// 触控板手势类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GestureKind {
+    /// 双指捏合缩放，幅度为缩放比例的变化量（正值放大，如 0.1 为放大 10%）
+    Pinch,
+    /// 双指旋转，幅度为顺时针旋转的角度（度）
+    Rotate,
+}
+
+/// 捏合缩放退化为 Ctrl+滚轮时，每滚动一行对应的缩放量
+pub const PINCH_PER_LINE: f64 = 0.1;
+
+/// 捏合幅度折合的滚轮行数（正值向上滚动，即放大）
+pub fn pinch_lines(magnitude: f64) -> i32 {
+    (magnitude / PINCH_PER_LINE).round() as i32
+}
+
+/// 一行滚动折合的像素数
+pub const PIXELS_PER_LINE: f64 = 20.0;
+
+/// 滚动量的单位
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScrollUnit {
+    /// 按行（滚轮刻度）滚动，效果为逐格跳动
+    #[default]
+    Lines,
+    /// 按像素滚动（触控板等高精度设备），效果为平滑滚动
+    Pixels,
+}
+
+impl ScrollUnit {
+    /// 将以 `self` 为单位的滚动量换算为 `to` 单位
+    pub fn convert(self, delta: f64, to: ScrollUnit) -> f64 {
+        match (self, to) {
+            (ScrollUnit::Lines, ScrollUnit::Pixels) => delta * PIXELS_PER_LINE,
+            (ScrollUnit::Pixels, ScrollUnit::Lines) => delta / PIXELS_PER_LINE,
+            _ => delta,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_scroll_between_units() {
+        assert_eq!(ScrollUnit::Lines.convert(3.0, ScrollUnit::Pixels), 3.0 * PIXELS_PER_LINE);
+        assert_eq!(ScrollUnit::Pixels.convert(-30.0, ScrollUnit::Lines), -30.0 / PIXELS_PER_LINE);
+        // 往返换算不丢失精度
+        let lines = ScrollUnit::Pixels.convert(7.0, ScrollUnit::Lines);
+        assert_eq!(ScrollUnit::Lines.convert(lines, ScrollUnit::Pixels), 7.0);
+        // 同单位不换算
+        assert_eq!(ScrollUnit::Lines.convert(2.5, ScrollUnit::Lines), 2.5);
+        assert_eq!(ScrollUnit::Pixels.convert(2.5, ScrollUnit::Pixels), 2.5);
+    }
+}
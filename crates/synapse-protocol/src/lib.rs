@@ -1,7 +1,14 @@
 pub mod codec;
+pub mod datagram;
+pub mod image;
 pub mod input;
 pub mod message;
 pub mod screen;
 
-pub use codec::MessageCodec;
-pub use message::{DeviceId, Message};
+pub use codec::{
+    encoded_len, CodecError, Compression, CompressionHandle, MessageCodec, MAX_FRAME_SIZE,
+};
+pub use datagram::{Datagram, MAX_DATAGRAM_SIZE};
+pub use message::{
+    Capabilities, Capability, DeviceId, ImageEncoding, Message, Priority, PROTOCOL_VERSION,
+};
@@ -1,25 +1,173 @@
 use serde::{Deserialize, Serialize};
 
-use crate::input::{ButtonAction, KeyAction, KeyCode, MouseButton};
+use crate::codec::Compression;
+use crate::input::{ButtonAction, GestureKind, KeyAction, KeyCode, MouseButton, ScrollUnit};
 use crate::screen::{Edge, ScreenId, ScreenInfo, ScreenPosition};
 
 /// 设备标识
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct DeviceId(pub String);
 
+/// 消息发送优先级
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// 延迟敏感（输入、焦点切换、心跳等），优先发送
+    High,
+    /// 大块数据（剪贴板），在高优先级队列空闲时发送
+    Low,
+}
+
+/// 剪贴板图片数据的编码方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImageEncoding {
+    /// 未压缩的 RGBA 像素
+    Raw,
+    /// PNG（解码后为 RGBA 像素）
+    Png,
+}
+
+/// 可选功能，握手时双方声明各自支持的功能，只使用双方都支持的部分
+///
+/// 取值为 [`Capabilities`] 中的位序号，只能追加，不能重排
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Capability {
+    /// 剪贴板图片（未压缩 RGBA）
+    ClipboardImage = 0,
+    /// PNG 编码的剪贴板图片
+    ClipboardPng = 1,
+    /// 剪贴板清空同步
+    ClipboardClear = 2,
+    /// 按文本键入（[`Message::TypeText`]）
+    TypeText = 3,
+    /// 按字符键入（[`Message::KeyChar`]）
+    KeyChar = 4,
+    /// 一次性组合键（[`Message::KeyCombo`]）
+    KeyCombo = 5,
+    /// 远程锁屏
+    LockScreen = 6,
+    /// 远程释放所有按键（[`Message::ReleaseAll`]）
+    ReleaseAll = 7,
+    /// 剪贴板历史查询（[`Message::ClipboardHistoryRequest`]）
+    ClipboardHistory = 8,
+    /// 原子双击（[`Message::MouseDoubleClick`]）
+    DoubleClick = 9,
+    /// 触控板手势（[`Message::Gesture`]）
+    Gesture = 10,
+    /// 带同步字与校验和的帧格式，流损坏时可重新同步（[`MessageCodec`](crate::MessageCodec)）
+    FrameSync = 11,
+    /// 鼠标运动经 UDP 旁路发送（[`Message::UdpOffer`]）
+    UdpMotion = 12,
+    /// 取消已发出的剪贴板传输（[`Message::ClipboardCancel`]）
+    TransferCancel = 13,
+}
+
+impl Capability {
+    /// 本实现支持的全部功能
+    pub const ALL: [Capability; 14] = [
+        Capability::ClipboardImage,
+        Capability::ClipboardPng,
+        Capability::ClipboardClear,
+        Capability::TypeText,
+        Capability::KeyChar,
+        Capability::KeyCombo,
+        Capability::LockScreen,
+        Capability::ReleaseAll,
+        Capability::ClipboardHistory,
+        Capability::DoubleClick,
+        Capability::Gesture,
+        Capability::FrameSync,
+        Capability::UdpMotion,
+        Capability::TransferCancel,
+    ];
+}
+
+/// 功能集合，按位编码；不认识的位会被忽略，新旧版本之间可以互通
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities(pub u64);
+
+impl Capabilities {
+    /// 本实现支持的全部功能
+    pub fn local() -> Self {
+        Capability::ALL.into_iter().collect()
+    }
+
+    pub fn contains(self, cap: Capability) -> bool {
+        self.0 & (1 << cap as u8) != 0
+    }
+
+    pub fn insert(&mut self, cap: Capability) {
+        self.0 |= 1 << cap as u8;
+    }
+
+    pub fn remove(&mut self, cap: Capability) {
+        self.0 &= !(1 << cap as u8);
+    }
+
+    /// 双方都支持的功能
+    pub fn intersection(self, other: Capabilities) -> Self {
+        Capabilities(self.0 & other.0)
+    }
+
+    /// 对端是否能处理该消息
+    pub fn permits(self, msg: &Message) -> bool {
+        msg.required_capability().is_none_or(|cap| self.contains(cap))
+    }
+}
+
+impl FromIterator<Capability> for Capabilities {
+    fn from_iter<I: IntoIterator<Item = Capability>>(iter: I) -> Self {
+        let mut caps = Capabilities::default();
+        for cap in iter {
+            caps.insert(cap);
+        }
+        caps
+    }
+}
+
+/// 协议版本，在 Hello/Welcome 中交换，版本不同的双方拒绝握手
+///
+/// bincode 不是自描述格式，已有消息增删或调整字段后旧版本无法解码，此时必须递增版本；
+/// 只在末尾追加新变体（并配合 [`Capability`] 协商）不需要递增
+pub const PROTOCOL_VERSION: u32 = 3;
+
 /// 协议消息
+///
+/// bincode 按变体序号编码枚举，因此变体只能追加在末尾，不能插入或重排；
+/// 线上格式由 `testdata/messages.golden` 固定，新增变体时在该测试中补充样例
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Message {
     // ── 握手 ──
     Hello {
+        /// 发送方的 [`PROTOCOL_VERSION`]
+        protocol_version: u32,
         device_id: DeviceId,
         device_name: String,
         screens: Vec<ScreenInfo>,
+        /// 客户端希望位于服务端屏幕的哪条边缘；`None` 表示由服务端决定
+        preferred_edge: Option<Edge>,
+        /// 仅接收剪贴板同步，不接受鼠标键盘控制（如展示用设备）
+        clipboard_only: bool,
+        /// 客户端希望收到的 [`Message::MouseScroll`] 滚动量单位
+        scroll_unit: ScrollUnit,
+        capabilities: Capabilities,
+        /// 焦点在本机时希望收到绝对坐标（[`Message::MouseMove`]）而非相对位移
+        absolute_pointer: bool,
+        /// 客户端支持的帧压缩算法；为空表示不支持压缩
+        compression: Vec<Compression>,
+        /// 设备的配对密钥（每台设备随机生成并保存），服务端按其指纹记住已确认的设备；
+        /// 为空表示没有密钥，每次连接都需确认
+        pairing_key: String,
     },
     Welcome {
+        /// 发送方的 [`PROTOCOL_VERSION`]
+        protocol_version: u32,
         device_id: DeviceId,
         device_name: String,
         screens: Vec<ScreenInfo>,
+        capabilities: Capabilities,
+        /// 服务端选定的帧压缩算法，双方在 Welcome 之后的帧按此压缩
+        compression: Compression,
     },
     Bye {
         device_id: DeviceId,
@@ -34,9 +182,13 @@ pub enum Message {
         button: MouseButton,
         action: ButtonAction,
     },
+    /// 滚动量以接收方在 `Hello` 中声明的单位计
     MouseScroll {
         dx: f64,
         dy: f64,
+        /// 来自高精度设备（触控板）的连续滚动，接收方累计不足一行的部分平滑滚动；
+        /// 为 `false` 时是滚轮刻度，每条消息至少滚动一行
+        precise: bool,
     },
     /// 相对鼠标移动（跨屏 delta）
     MouseDelta {
@@ -60,16 +212,279 @@ pub enum Message {
     },
 
     // ── 剪贴板同步 ──
+    /// `transfer_id` 由发送方分配，接收方应用成功后以 [`Message::ClipboardAck`] 回执
     ClipboardText {
         text: String,
+        transfer_id: u64,
     },
     ClipboardImage {
         width: u32,
         height: u32,
+        /// `data` 的编码方式
+        encoding: ImageEncoding,
         data: Vec<u8>,
+        transfer_id: u64,
     },
 
     // ── 心跳 ──
     Ping(u64),
     Pong(u64),
+
+    // ── 扩展（只能在此之后追加） ──
+    /// 组合键：按顺序按下全部按键后逆序释放，作为一个整体执行
+    KeyCombo {
+        keys: Vec<KeyCode>,
+    },
+    /// 以键入方式输入文本（用于不允许粘贴的应用）
+    TypeText {
+        text: String,
+    },
+    /// 剪贴板内容已在接收方成功应用
+    ClipboardAck {
+        transfer_id: u64,
+    },
+    /// 布局感知模式下的可打印按键按下：接收方直接键入 `text`（按本机布局无关的字符），
+    /// 并忽略随后 `key` 的释放事件
+    KeyChar {
+        key: KeyCode,
+        text: String,
+    },
+    /// 连接中继后的首帧：加入指定会话码的会话（不会转发给对端）
+    RelayJoin {
+        session: String,
+    },
+    /// 锁定接收方屏幕
+    LockScreen,
+    /// 发送方剪贴板已被清空，接收方同步清空
+    ClipboardClear {
+        transfer_id: u64,
+    },
+    /// 释放接收方所有已模拟按下的键与鼠标按键（修饰键卡住时的补救）
+    ReleaseAll,
+    /// 请求对端的剪贴板历史，对端以 [`Message::ClipboardHistory`] 回复
+    ClipboardHistoryRequest,
+    /// 剪贴板历史，按从新到旧排列；每项为剪贴板消息（`transfer_id` 为 0），
+    /// 选中某项时按普通剪贴板消息写入本机剪贴板
+    ClipboardHistory {
+        items: Vec<Message>,
+    },
+    /// 双击：接收方连续执行两次按下/释放，不受网络时延影响系统的双击判定
+    MouseDoubleClick {
+        button: MouseButton,
+    },
+    /// 触控板手势（捏合缩放、旋转），接收方按平台尽量模拟
+    Gesture {
+        kind: GestureKind,
+        magnitude: f64,
+    },
+    /// 服务端开放的 UDP 运动旁路：客户端向服务端 `port` 发送带 `token` 的注册数据报后，
+    /// `MouseMove`/`MouseDelta` 改经 UDP 送达（见 [`Datagram`](crate::Datagram)）
+    UdpOffer {
+        port: u16,
+        token: u64,
+    },
+    /// 发送方取消了 `transfer_id` 对应的剪贴板传输，接收方丢弃尚未写入的内容且不再回执
+    ClipboardCancel {
+        transfer_id: u64,
+    },
+    /// 经 TCP 发送、先于 UDP 运动之后的第一条输入：`seq` 为此前经 UDP 发出的最大数据报序号，
+    /// 接收方收齐（或等待超时）这些运动后再处理之后的消息，保证点击落在运动之后的位置
+    UdpBarrier {
+        seq: u64,
+    },
+}
+
+impl Message {
+    /// 消息的发送优先级
+    pub fn priority(&self) -> Priority {
+        match self {
+            Message::ClipboardText { .. }
+            | Message::ClipboardImage { .. }
+            | Message::ClipboardClear { .. }
+            | Message::ClipboardHistory { .. } => Priority::Low,
+            _ => Priority::High,
+        }
+    }
+
+    /// 接收方处理该消息所需的可选功能；`None` 表示基础消息，所有实现都支持
+    pub fn required_capability(&self) -> Option<Capability> {
+        match self {
+            Message::ClipboardImage { encoding: ImageEncoding::Raw, .. } => {
+                Some(Capability::ClipboardImage)
+            }
+            Message::ClipboardImage { encoding: ImageEncoding::Png, .. } => {
+                Some(Capability::ClipboardPng)
+            }
+            Message::ClipboardClear { .. } => Some(Capability::ClipboardClear),
+            Message::TypeText { .. } => Some(Capability::TypeText),
+            Message::KeyChar { .. } => Some(Capability::KeyChar),
+            Message::KeyCombo { .. } => Some(Capability::KeyCombo),
+            Message::LockScreen => Some(Capability::LockScreen),
+            Message::ReleaseAll => Some(Capability::ReleaseAll),
+            Message::ClipboardHistoryRequest | Message::ClipboardHistory { .. } => {
+                Some(Capability::ClipboardHistory)
+            }
+            Message::MouseDoubleClick { .. } => Some(Capability::DoubleClick),
+            Message::Gesture { .. } => Some(Capability::Gesture),
+            Message::UdpOffer { .. } | Message::UdpBarrier { .. } => Some(Capability::UdpMotion),
+            Message::ClipboardCancel { .. } => Some(Capability::TransferCancel),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ButtonAction, KeyAction};
+    use crate::screen::ScreenRect;
+
+    /// 每个变体一个样例，按变体声明顺序排列
+    fn samples() -> Vec<Message> {
+        let device_id = DeviceId("laptop".into());
+        let screen = ScreenInfo {
+            id: ScreenId(1),
+            name: "DP-1".into(),
+            rect: ScreenRect { x: -1920, y: 0, width: 1920, height: 1080 },
+            is_primary: true,
+        };
+        let position = ScreenPosition { x: 0.25, y: 0.5 };
+        let text = |transfer_id| Message::ClipboardText { text: "hi".into(), transfer_id };
+        vec![
+            Message::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                device_id: device_id.clone(),
+                device_name: "Laptop".into(),
+                screens: vec![screen.clone()],
+                preferred_edge: Some(Edge::Left),
+                clipboard_only: false,
+                scroll_unit: ScrollUnit::Pixels,
+                capabilities: Capabilities(0b101),
+                absolute_pointer: true,
+                compression: vec![Compression::Lz4],
+                pairing_key: "k3y".into(),
+            },
+            Message::Welcome {
+                protocol_version: PROTOCOL_VERSION,
+                device_id: device_id.clone(),
+                device_name: "Desk".into(),
+                screens: vec![screen],
+                capabilities: Capabilities(0b11),
+                compression: Compression::None,
+            },
+            Message::Bye { device_id },
+            Message::MouseMove { x: 1.5, y: -2.0 },
+            Message::MouseButtonEvent { button: MouseButton::Other(9), action: ButtonAction::Release },
+            Message::MouseScroll { dx: 0.0, dy: -3.0, precise: true },
+            Message::MouseDelta { dx: 4.0, dy: 0.5 },
+            Message::KeyEvent { key: KeyCode::Unknown(0x1234), action: KeyAction::Press },
+            Message::EnterScreen { screen_id: ScreenId(0), position },
+            Message::LeaveScreen { screen_id: ScreenId(2), edge: Edge::Bottom, position },
+            text(7),
+            Message::ClipboardImage {
+                width: 1,
+                height: 1,
+                encoding: ImageEncoding::Png,
+                data: vec![1, 2, 3, 4],
+                transfer_id: 8,
+            },
+            Message::Ping(u64::MAX),
+            Message::Pong(1),
+            Message::KeyCombo { keys: vec![KeyCode::LeftCtrl, KeyCode::KeyC] },
+            Message::TypeText { text: "héllo".into() },
+            Message::ClipboardAck { transfer_id: 9 },
+            Message::KeyChar { key: KeyCode::KeyQ, text: "a".into() },
+            Message::RelayJoin { session: "abc123".into() },
+            Message::LockScreen,
+            Message::ClipboardClear { transfer_id: 10 },
+            Message::ReleaseAll,
+            Message::ClipboardHistoryRequest,
+            Message::ClipboardHistory { items: vec![text(0)] },
+            Message::MouseDoubleClick { button: MouseButton::Left },
+            Message::Gesture { kind: GestureKind::Rotate, magnitude: 90.0 },
+            Message::UdpOffer { port: 24800, token: 0x0102_0304_0506_0708 },
+            Message::ClipboardCancel { transfer_id: 11 },
+            Message::UdpBarrier { seq: 12 },
+        ]
+    }
+
+    /// 新增变体时编译失败，提醒在 [`samples`] 与 golden 数据中补充
+    fn variant_name(msg: &Message) -> &'static str {
+        match msg {
+            Message::Hello { .. } => "Hello",
+            Message::Welcome { .. } => "Welcome",
+            Message::Bye { .. } => "Bye",
+            Message::MouseMove { .. } => "MouseMove",
+            Message::MouseButtonEvent { .. } => "MouseButtonEvent",
+            Message::MouseScroll { .. } => "MouseScroll",
+            Message::MouseDelta { .. } => "MouseDelta",
+            Message::KeyEvent { .. } => "KeyEvent",
+            Message::EnterScreen { .. } => "EnterScreen",
+            Message::LeaveScreen { .. } => "LeaveScreen",
+            Message::ClipboardText { .. } => "ClipboardText",
+            Message::ClipboardImage { .. } => "ClipboardImage",
+            Message::Ping(_) => "Ping",
+            Message::Pong(_) => "Pong",
+            Message::KeyCombo { .. } => "KeyCombo",
+            Message::TypeText { .. } => "TypeText",
+            Message::ClipboardAck { .. } => "ClipboardAck",
+            Message::KeyChar { .. } => "KeyChar",
+            Message::RelayJoin { .. } => "RelayJoin",
+            Message::LockScreen => "LockScreen",
+            Message::ClipboardClear { .. } => "ClipboardClear",
+            Message::ReleaseAll => "ReleaseAll",
+            Message::ClipboardHistoryRequest => "ClipboardHistoryRequest",
+            Message::ClipboardHistory { .. } => "ClipboardHistory",
+            Message::MouseDoubleClick { .. } => "MouseDoubleClick",
+            Message::Gesture { .. } => "Gesture",
+            Message::UdpOffer { .. } => "UdpOffer",
+            Message::ClipboardCancel { .. } => "ClipboardCancel",
+            Message::UdpBarrier { .. } => "UdpBarrier",
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// 线上格式与提交的 golden 数据逐字节一致；有意修改格式时（同时递增 [`PROTOCOL_VERSION`]）
+    /// 以 `SYNAPSE_UPDATE_GOLDEN=1 cargo test` 重新生成
+    #[test]
+    fn encoding_matches_golden_bytes() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/messages.golden");
+        let actual: String = samples()
+            .iter()
+            .map(|msg| format!("{} {}\n", variant_name(msg), hex(&bincode::serialize(msg).unwrap())))
+            .collect();
+        if std::env::var_os("SYNAPSE_UPDATE_GOLDEN").is_some() {
+            std::fs::write(path, &actual).unwrap();
+        }
+        let expected = std::fs::read_to_string(path).unwrap();
+        for (line, (actual, expected)) in actual.lines().zip(expected.lines()).enumerate() {
+            assert_eq!(actual, expected, "wire format changed at line {}", line + 1);
+        }
+        assert_eq!(actual.lines().count(), expected.lines().count(), "variant added or removed");
+
+        // 每个变体都有样例，且 bincode 写出的变体序号即声明顺序
+        for (index, msg) in samples().iter().enumerate() {
+            let tag = bincode::serialize(msg).unwrap()[..4].to_vec();
+            assert_eq!(tag, (index as u32).to_le_bytes(), "{}", variant_name(msg));
+        }
+    }
+
+    #[test]
+    fn permits_checks_required_capability() {
+        let caps: Capabilities = [Capability::ClipboardImage].into_iter().collect();
+        let image = |encoding| Message::ClipboardImage {
+            width: 1,
+            height: 1,
+            encoding,
+            data: Vec::new(),
+            transfer_id: 0,
+        };
+        assert!(caps.permits(&image(ImageEncoding::Raw)));
+        assert!(!caps.permits(&image(ImageEncoding::Png)));
+        // 基础消息不需要任何功能
+        assert!(Capabilities::default().permits(&Message::Ping(1)));
+    }
 }
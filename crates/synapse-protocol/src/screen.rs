@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
 
 /// 屏幕标识符
@@ -20,6 +22,26 @@ pub enum Edge {
     Right,
 }
 
+/// 无法识别的边缘方向
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid direction {0:?}, expected left, right, top or bottom")]
+pub struct ParseEdgeError(pub String);
+
+impl FromStr for Edge {
+    type Err = ParseEdgeError;
+
+    /// 忽略大小写与首尾空白
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "left" => Ok(Edge::Left),
+            "right" => Ok(Edge::Right),
+            "top" => Ok(Edge::Top),
+            "bottom" => Ok(Edge::Bottom),
+            _ => Err(ParseEdgeError(s.to_string())),
+        }
+    }
+}
+
 /// 屏幕矩形区域
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct ScreenRect {
@@ -37,3 +59,29 @@ pub struct ScreenInfo {
     pub rect: ScreenRect,
     pub is_primary: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_edges_ignoring_case_and_padding() {
+        assert_eq!("left".parse(), Ok(Edge::Left));
+        assert_eq!("Right".parse(), Ok(Edge::Right));
+        assert_eq!("TOP".parse(), Ok(Edge::Top));
+        assert_eq!(" bottom\n".parse(), Ok(Edge::Bottom));
+        assert_eq!("\t left ".parse(), Ok(Edge::Left));
+    }
+
+    #[test]
+    fn rejects_unknown_edges() {
+        for input in ["rihgt", "", "  ", "up", "left right"] {
+            assert_eq!(input.parse::<Edge>(), Err(ParseEdgeError(input.to_string())));
+        }
+        // 错误信息保留原始输入，便于用户定位拼写错误
+        assert_eq!(
+            " rihgt".parse::<Edge>().unwrap_err().to_string(),
+            r#"invalid direction " rihgt", expected left, right, top or bottom"#
+        );
+    }
+}
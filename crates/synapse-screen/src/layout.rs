@@ -22,6 +22,12 @@ struct Neighbors {
     right: Option<ScreenId>,
 }
 
+impl Default for ScreenLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ScreenLayout {
     pub fn new() -> Self {
         Self {
@@ -1,30 +1,154 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
-use synapse_clipboard::{ClipboardContent, ClipboardWatcher};
-use synapse_input::capture::{get_screen_size, rdev_event_to_message, InputCapturer};
-use synapse_input::InputSimulator;
-use synapse_net::{Client, ClientEvent, LocalAction, Server, ServerEvent};
-use synapse_protocol::screen::Edge;
+#[cfg(feature = "clipboard")]
+use synapse_clipboard::{
+    ClipboardContent, ClipboardHistory, ClipboardPolicy, ClipboardWatcher,
+    DEFAULT_MAX_IMAGE_PIXELS,
+};
+use synapse_input::capture::{
+    get_screen_size, get_screens, primary_screen_rect, rdev_event_to_message, InputCapturer,
+};
+use synapse_input::simulate::{DEFAULT_CREATE_ATTEMPTS, DEFAULT_CREATE_RETRY_DELAY};
+use synapse_input::{create_with_retry, lock_screen, relative, InputSimulator};
+use synapse_net::{
+    apply_local_action, parse_bind_addr, Client, ClientEvent, DeviceLayout, FocusTarget,
+    LocalAction, MessageApplier, Server, Sequenced, ServerConfig, ServerControl, ServerEvent, ServerInfo, SynapseError, TransferStatus, DEFAULT_BIND,
+    DEFAULT_RETURN_COOLDOWN, DEFAULT_SWITCH_COOLDOWN,
+};
+use synapse_protocol::screen::{Edge, ScreenRect};
 use synapse_protocol::Message;
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
 use tokio::sync::{mpsc, Mutex};
 use tokio_util::sync::CancellationToken;
+use tracing_subscriber::EnvFilter;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub enum Role {
+    #[default]
     Idle,
     Server,
     Client,
 }
 
+/// 持久化的启动配置，`auto_start` 为真时启动后自动进入上次的角色
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartupConfig {
+    pub role: Role,
+    pub bind: Option<String>,
+    pub server_addr: Option<String>,
+    pub client_direction: Option<String>,
+    pub auto_start: bool,
+    /// 未知设备首次连接时需确认
+    pub require_pairing: bool,
+    /// 已确认的可信设备（配对密钥指纹）
+    pub trusted_devices: Vec<String>,
+    /// 作为客户端时的配对密钥，首次以客户端启动时生成
+    pub pairing_key: Option<String>,
+    /// 固定的多设备布局，设备连接时按 ID 或名称放到指定边缘
+    pub layout: Vec<DeviceLayout>,
+    /// 拒绝布局中未列出的设备
+    pub reject_unlisted: bool,
+    /// 停止服务端时等待发送队列清空的毫秒数；未设置时立即断开
+    pub drain_timeout_ms: Option<u64>,
+    /// 设备 ID → 界面中显示的名称
+    pub device_labels: HashMap<String, String>,
+    /// 不同步剪贴板图片
+    pub no_clipboard_images: bool,
+    /// 剪贴板文本最大字节数，超出时不同步
+    pub max_clipboard_text: Option<usize>,
+    /// 焦点在远程时隐藏本地光标（目前仅 X11 支持）
+    pub hide_cursor: bool,
+    /// 每次焦点切换后不响应边缘触发的毫秒数；未设置时使用默认值
+    pub switch_cooldown_ms: Option<u64>,
+    /// 切回本地后不响应边缘触发的毫秒数；未设置时使用默认值
+    pub return_cooldown_ms: Option<u64>,
+}
+
+impl StartupConfig {
+    /// 按配置决定哪些剪贴板内容参与同步
+    #[cfg(feature = "clipboard")]
+    fn clipboard_policy(&self) -> ClipboardPolicy {
+        ClipboardPolicy {
+            images: !self.no_clipboard_images,
+            max_text_len: self.max_clipboard_text,
+            ..ClipboardPolicy::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceInfo {
     pub device_id: String,
     pub device_name: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairingRequestInfo {
+    pub device_id: String,
+    pub device_name: String,
+    /// 设备未提供配对密钥时为空，确认后也不会被记住
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusInfo {
+    pub from: String,
+    pub to: String,
+    pub edge: Option<Edge>,
+    /// 目标设备的名称与屏幕尺寸，回到本地时为空
+    pub target: Option<FocusTarget>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EdgeCrossInfo {
+    pub edge: Edge,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceEdgeInfo {
+    pub device_id: String,
+    /// 设备已不在任何边缘时为空
+    pub edge: Option<Edge>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardSyncInfo {
+    pub device_id: String,
+    pub transfer_id: u64,
+}
+
+/// 剪贴板历史中的一项，供历史选择界面展示
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardHistoryItem {
+    pub index: usize,
+    /// "text" 或 "image"
+    pub kind: String,
+    /// 文本前若干字符，或图片尺寸
+    pub preview: String,
+}
+
+/// 历史选择界面中文本预览的最大字符数
+const HISTORY_PREVIEW_CHARS: usize = 80;
+
+impl ClipboardHistoryItem {
+    fn from_message(index: usize, msg: &Message) -> Option<Self> {
+        let (kind, preview) = match msg {
+            Message::ClipboardText { text, .. } => {
+                ("text", text.chars().take(HISTORY_PREVIEW_CHARS).collect())
+            }
+            Message::ClipboardImage { width, height, .. } => ("image", format!("{width}×{height}")),
+            _ => return None,
+        };
+        Some(Self { index, kind: kind.into(), preview })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppStatus {
     pub role: Role,
@@ -38,6 +162,17 @@ struct AppState {
     devices: Vec<DeviceInfo>,
     cancel: Option<CancellationToken>,
     handle: Option<tokio::task::JoinHandle<()>>,
+    control: Option<mpsc::UnboundedSender<ServerControl>>,
+    /// 服务端模式下的服务端实例，用于查询运行状态
+    server: Option<Arc<Server>>,
+    /// 客户端模式下向服务端发送消息的通道
+    client_tx: Option<mpsc::UnboundedSender<Message>>,
+    /// 最近一次收到的剪贴板历史
+    clipboard_history: Vec<Message>,
+    /// 服务端停止时的排空时限
+    drain_timeout: Option<Duration>,
+    /// 持久化配置，启动时从配置文件读取，保存成功后同步更新
+    settings: StartupConfig,
 }
 
 impl Default for AppState {
@@ -48,19 +183,119 @@ impl Default for AppState {
             devices: vec![],
             cancel: None,
             handle: None,
+            control: None,
+            server: None,
+            client_tx: None,
+            clipboard_history: vec![],
+            drain_timeout: None,
+            settings: StartupConfig::default(),
         }
     }
 }
 
 type SharedState = Arc<Mutex<AppState>>;
 
-fn parse_direction(s: &str) -> Edge {
-    match s.to_lowercase().as_str() {
-        "left" => Edge::Left,
-        "right" => Edge::Right,
-        "top" => Edge::Top,
-        "bottom" => Edge::Bottom,
-        _ => Edge::Right,
+const CONFIG_FILE: &str = "config.json";
+/// 排空时限之外额外等待服务端任务退出的时长
+const DRAIN_GRACE: Duration = Duration::from_millis(500);
+/// “测试连接”等待握手完成的时限
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn config_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    Ok(dir.join(CONFIG_FILE))
+}
+
+/// 读取配置文件的 JSON；文件不存在时返回 `Ok(None)`，读取或解析失败时返回错误
+fn read_config_value(app: &AppHandle) -> Result<Option<serde_json::Value>, String> {
+    let path = config_path(app)?;
+    let data = match std::fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+    };
+    serde_json::from_str(&data)
+        .map(Some)
+        .map_err(|e| format!("invalid config {}: {e}", path.display()))
+}
+
+/// 读取配置文件；文件不存在时返回 `Ok(None)`
+fn read_config(app: &AppHandle) -> Result<Option<StartupConfig>, String> {
+    read_config_value(app)?
+        .map(|value| serde_json::from_value(value).map_err(|e| format!("invalid config: {e}")))
+        .transpose()
+}
+
+/// 读取配置文件，失败时只记录日志
+fn load_config(app: &AppHandle) -> Option<StartupConfig> {
+    read_config(app).unwrap_or_else(|e| {
+        tracing::warn!("{e}");
+        None
+    })
+}
+
+/// 把 `patch` 的顶层字段合并进已有配置，`patch` 中没有的字段保持原值
+fn merge_config(
+    existing: Option<serde_json::Value>,
+    patch: serde_json::Value,
+) -> Result<StartupConfig, String> {
+    let mut merged = match existing {
+        Some(serde_json::Value::Object(map)) => map,
+        Some(_) => return Err("config file is not a JSON object".into()),
+        None => serde_json::Map::new(),
+    };
+    let serde_json::Value::Object(patch) = patch else {
+        return Err("settings must be a JSON object".into());
+    };
+    merged.extend(patch);
+    serde_json::from_value(serde_json::Value::Object(merged)).map_err(|e| e.to_string())
+}
+
+fn save_config(app: &AppHandle, config: &StartupConfig) -> Result<(), String> {
+    let path = config_path(app)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let data = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    std::fs::write(&path, data).map_err(|e| e.to_string())
+}
+
+/// 更新并保存配置文件中的部分字段，返回保存后的配置
+///
+/// 配置文件存在但无法读取或解析时不覆盖，返回错误
+fn update_config(
+    app: &AppHandle,
+    update: impl FnOnce(&mut StartupConfig),
+) -> Result<StartupConfig, String> {
+    let mut config = read_config(app)?.unwrap_or_default();
+    update(&mut config);
+    save_config(app, &config)?;
+    Ok(config)
+}
+
+/// 记住本次启动使用的参数；保存失败不影响启动，只在日志与界面中提示
+fn remember_settings(app: &AppHandle, state: &mut AppState, update: impl FnOnce(&mut StartupConfig)) {
+    match update_config(app, update) {
+        Ok(config) => state.settings = config,
+        Err(e) => {
+            tracing::warn!("settings not saved: {e}");
+            let _ = app.emit("synapse://log", format!("Settings not saved: {e}"));
+        }
+    }
+}
+
+/// 按持久化配置自动启动服务端或客户端
+async fn auto_start(app: AppHandle, config: StartupConfig) -> Result<(), String> {
+    let state = app.state::<SharedState>();
+    match config.role {
+        Role::Server => {
+            start_server(app.clone(), state, config.bind, config.client_direction).await
+        }
+        Role::Client => match config.server_addr {
+            Some(addr) => start_client(app.clone(), state, addr).await,
+            None => Err("auto start: no server address configured".into()),
+        },
+        Role::Idle => Ok(()),
     }
 }
 
@@ -68,14 +303,33 @@ fn parse_direction(s: &str) -> Edge {
 async fn start_server(
     app: AppHandle,
     state: tauri::State<'_, SharedState>,
-    bind: String,
+    bind: Option<String>,
     client_direction: Option<String>,
 ) -> Result<(), String> {
+    // 未指定地址时沿用配置文件中的地址，都没有时使用默认地址
+    let requested_bind = match bind {
+        Some(bind) => Some(bind),
+        None => state.lock().await.settings.bind.clone(),
+    };
+    // 在启动任务前校验，地址或方向写错时立即返回错误而不是在日志中延迟出现
+    let bind = parse_bind_addr(requested_bind.as_deref().unwrap_or(DEFAULT_BIND))
+        .map_err(|e| e.to_string())?;
+    let direction = match client_direction.as_deref() {
+        Some(direction) => direction.parse::<Edge>().map_err(|e| e.to_string())?,
+        None => Edge::Right,
+    };
     let mut s = state.lock().await;
     if s.role != Role::Idle {
         return Err("Already running".into());
     }
 
+    // 记住本次的角色与参数，下次打开时界面据此预填
+    remember_settings(&app, &mut s, |config| {
+        config.role = Role::Server;
+        config.bind = requested_bind;
+        config.client_direction = client_direction;
+    });
+
     let cancel = CancellationToken::new();
     s.role = Role::Server;
     s.connected = true;
@@ -89,18 +343,61 @@ async fn start_server(
 
     let state_clone = state.inner().clone();
     let app_clone = app.clone();
-    let direction = parse_direction(&client_direction.unwrap_or_else(|| "right".into()));
+
+    let stored = s.settings.clone();
+    #[cfg(feature = "clipboard")]
+    let policy = stored.clipboard_policy();
+    // 平台支持时改用原始相对运动，焦点在远程时锁定指针而不是锁回中心
+    let (raw_tx, mut raw_rx) = mpsc::unbounded_channel();
+    let relative_motion = relative::start(raw_tx);
+    let config = ServerConfig {
+        relative_motion,
+        require_pairing: stored.require_pairing,
+        trusted_devices: stored.trusted_devices.into_iter().collect(),
+        layout: stored.layout,
+        reject_unlisted: stored.reject_unlisted,
+        drain_timeout: stored.drain_timeout_ms.map(Duration::from_millis),
+        device_labels: stored.device_labels,
+        hide_cursor: stored.hide_cursor,
+        switch_cooldown: stored.switch_cooldown_ms.map_or(DEFAULT_SWITCH_COOLDOWN, Duration::from_millis),
+        return_cooldown: stored.return_cooldown_ms.map_or(DEFAULT_RETURN_COOLDOWN, Duration::from_millis),
+        screens: get_screens(),
+        ..ServerConfig::default()
+    };
+    s.drain_timeout = config.drain_timeout;
+    let server = Server::new(bind.to_string()).with_config(config);
+    #[cfg(feature = "clipboard")]
+    let history = Arc::new(std::sync::Mutex::new(ClipboardHistory::default()));
+    #[cfg(feature = "clipboard")]
+    let server = {
+        let history_reader = history.clone();
+        let policy_reader = policy.clone();
+        server
+            .with_clipboard_reader(move || {
+                ClipboardWatcher::get_current()
+                    .ok()
+                    .flatten()
+                    .filter(|content| policy_reader.allows(content))
+                    .map(ClipboardContent::into_message)
+            })
+            .with_clipboard_history(move || {
+                history_reader.lock().map(|history| history.to_messages()).unwrap_or_default()
+            })
+    };
+    let server = Arc::new(server);
+    s.control = Some(server.control_sender());
+    s.server = Some(server.clone());
 
     let handle = tokio::spawn(async move {
-        // 获取屏幕尺寸
-        let screen_size = get_screen_size();
+        // 获取屏幕尺寸，并在显示配置变化时通知服务端
+        let screen = primary_screen_rect();
+        tokio::spawn(watch_screen_changes(screen, server.control_sender(), cancel.clone()));
 
         // 输入捕获 channel
         let (rdev_tx, mut rdev_rx) = mpsc::unbounded_channel();
         let (input_tx, input_rx) = mpsc::unbounded_channel();
 
         // 剪贴板 channel
-        let (clip_content_tx, mut clip_content_rx) = mpsc::unbounded_channel();
         let (clip_msg_tx, clip_msg_rx) = mpsc::unbounded_channel();
 
         // 服务端事件 channel
@@ -120,43 +417,48 @@ async fn start_server(
                     _ = cancel_input.cancelled() => break,
                     Some(event) = rdev_rx.recv() => {
                         if let Some(msg) = rdev_event_to_message(&event) {
-                            let _ = input_tx.send(msg);
+                            let _ = input_tx.send(msg.into());
                         }
                     }
+                    Some(msg) = raw_rx.recv() => {
+                        let _ = input_tx.send(msg.into());
+                    }
                     else => break,
                 }
             }
         });
 
         // 启动剪贴板监控
-        let watcher = ClipboardWatcher::new(Duration::from_millis(500));
-        let _ = watcher.watch(clip_content_tx).await;
+        #[cfg(feature = "clipboard")]
+        {
+            let (clip_content_tx, mut clip_content_rx) = mpsc::unbounded_channel();
+            let watcher = ClipboardWatcher::new(Duration::from_millis(500)).with_history(history);
+            let _ = watcher.watch(clip_content_tx, cancel.clone()).await;
 
-        // 剪贴板内容 -> protocol 转换
-        let cancel_clip = cancel.clone();
-        tokio::spawn(async move {
-            loop {
-                tokio::select! {
-                    _ = cancel_clip.cancelled() => break,
-                    Some(content) = clip_content_rx.recv() => {
-                        let msg = match content {
-                            ClipboardContent::Text(text) => {
-                                Message::ClipboardText { text }
-                            }
-                            ClipboardContent::Image { width, height, data } => {
-                                Message::ClipboardImage {
-                                    width: width as u32,
-                                    height: height as u32,
-                                    data,
-                                }
+            // 剪贴板内容 -> protocol 转换
+            let cancel_clip = cancel.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        _ = cancel_clip.cancelled() => break,
+                        Some(content) = clip_content_rx.recv() => {
+                            // 收到变化即分配序号，图片编码耗时不影响与输入的先后顺序
+                            let seq = Sequenced::reserve();
+                            if !policy.allows(&content) {
+                                tracing::debug!("clipboard change skipped by policy");
+                                continue;
                             }
-                        };
-                        let _ = clip_msg_tx.send(msg);
+                            let msg = Sequenced::with_seq(seq, content.into_message());
+                            let _ = clip_msg_tx.send(msg);
+                        }
+                        else => break,
                     }
-                    else => break,
                 }
-            }
-        });
+            });
+        }
+        // 不含剪贴板支持的构建从不读取本机剪贴板，剪贴板通道立即关闭
+        #[cfg(not(feature = "clipboard"))]
+        drop(clip_msg_tx);
 
         // 事件桥接到前端
         let state_events = state_clone.clone();
@@ -180,9 +482,65 @@ async fn start_server(
                         s.devices.retain(|d| d.device_id != *device_id);
                         let _ = app_events.emit("synapse://device-disconnected", device_id.clone());
                     }
+                    ServerEvent::DeviceRenamed { device_id, device_name } => {
+                        let mut s = state_events.lock().await;
+                        let device = s.devices.iter_mut().find(|d| d.device_id == *device_id);
+                        if let Some(device) = device {
+                            device.device_name = device_name.clone();
+                        }
+                        let _ = app_events.emit("synapse://device-renamed", DeviceInfo {
+                            device_id: device_id.clone(),
+                            device_name: device_name.clone(),
+                        });
+                    }
                     ServerEvent::FocusChanged { target } => {
                         let _ = app_events.emit("synapse://log", format!("Focus → {target}"));
                     }
+                    ServerEvent::FocusChangedDetailed { from, to, edge, target } => {
+                        let _ = app_events.emit("synapse://focus", FocusInfo {
+                            from: from.clone(),
+                            to: to.clone(),
+                            edge: *edge,
+                            target: target.clone(),
+                        });
+                    }
+                    ServerEvent::PairingRequest { device_id, device_name, fingerprint } => {
+                        let _ = app_events.emit("synapse://pairing-request", PairingRequestInfo {
+                            device_id: device_id.clone(),
+                            device_name: device_name.clone(),
+                            fingerprint: fingerprint.clone(),
+                        });
+                    }
+                    ServerEvent::DecodeError { device_id, error } => {
+                        let _ = app_events.emit(
+                            "synapse://log",
+                            format!("{device_id} sent corrupt data and was disconnected: {error}"),
+                        );
+                    }
+                    ServerEvent::EdgeCrossed { edge, target } => {
+                        let _ = app_events.emit("synapse://edge-crossed", EdgeCrossInfo {
+                            edge: *edge,
+                            target: target.clone(),
+                        });
+                    }
+                    ServerEvent::ClipboardSynced { device_id, transfer_id } => {
+                        let _ = app_events.emit("synapse://clipboard-synced", ClipboardSyncInfo {
+                            device_id: device_id.clone(),
+                            transfer_id: *transfer_id,
+                        });
+                    }
+                    ServerEvent::TransferCancelled { device_id, transfer_id } => {
+                        let _ = app_events.emit("synapse://transfer-cancelled", ClipboardSyncInfo {
+                            device_id: device_id.clone(),
+                            transfer_id: *transfer_id,
+                        });
+                    }
+                    ServerEvent::DeviceEdgeChanged { device_id, edge } => {
+                        let _ = app_events.emit("synapse://device-edge-changed", DeviceEdgeInfo {
+                            device_id: device_id.clone(),
+                            edge: *edge,
+                        });
+                    }
                     ServerEvent::Log(msg) => {
                         let _ = app_events.emit("synapse://log", msg.clone());
                     }
@@ -207,8 +565,25 @@ async fn start_server(
             rt.block_on(async {
                 while let Some(action) = local_action_rx.recv().await {
                     match action {
-                        LocalAction::MoveMouse(x, y) => {
-                            let _ = simulator.move_mouse(x, y);
+                        LocalAction::HideCursor => {
+                            let _ = simulator.set_cursor_visible(false);
+                        }
+                        LocalAction::ShowCursor => {
+                            let _ = simulator.set_cursor_visible(true);
+                        }
+                        LocalAction::LockPointer => {
+                            let _ = simulator.set_pointer_locked(true);
+                        }
+                        LocalAction::UnlockPointer => {
+                            let _ = simulator.set_pointer_locked(false);
+                        }
+                        LocalAction::LockScreen => {
+                            if let Err(e) = lock_screen() {
+                                tracing::warn!("screen lock failed: {e}");
+                            }
+                        }
+                        action => {
+                            let _ = apply_local_action(&mut simulator, &action);
                         }
                     }
                 }
@@ -216,13 +591,13 @@ async fn start_server(
         });
 
         // 启动服务端
-        let server = Server::new(bind);
         if let Err(e) = server.run(
             input_rx, clip_msg_rx, local_action_tx, event_tx,
-            screen_size, direction, cancel,
+            screen, direction, cancel,
         ).await {
             let _ = app_clone.emit("synapse://log", format!("Server error: {e}"));
         }
+        capturer.stop();
 
         // 清理状态
         let mut s = state_clone.lock().await;
@@ -251,6 +626,13 @@ async fn start_client(
         return Err("Already running".into());
     }
 
+    remember_settings(&app, &mut s, |config| {
+        config.role = Role::Client;
+        config.server_addr = Some(server_addr.clone());
+        // 沿用同一密钥，服务端才能认出已确认过的本机
+        config.pairing_key.get_or_insert_with(synapse_net::pairing::generate_key);
+    });
+
     let cancel = CancellationToken::new();
     s.role = Role::Client;
     s.cancel = Some(cancel.clone());
@@ -261,6 +643,15 @@ async fn start_client(
         devices: vec![],
     });
 
+    #[cfg(feature = "clipboard")]
+    let policy = s.settings.clipboard_policy();
+    let mut client = Client::new(server_addr).with_screens(get_screens());
+    if let Some(key) = s.settings.pairing_key.clone() {
+        client = client.with_pairing_key(key);
+    }
+    let _ = app.emit("synapse://log", format!("Pairing fingerprint: {}", client.pairing_fingerprint()));
+    s.client_tx = Some(client.sender());
+
     let state_clone = state.inner().clone();
     let app_clone = app.clone();
 
@@ -299,6 +690,12 @@ async fn start_client(
                             devices: vec![],
                         });
                     }
+                    ClientEvent::SimulationFailing { error, .. } => {
+                        let _ = app_events.emit("synapse://simulation-error", error.clone());
+                    }
+                    ClientEvent::SimulatorUnavailable { error, .. } => {
+                        let _ = app_events.emit("synapse://simulator-unavailable", error.clone());
+                    }
                     ClientEvent::Log(msg) => {
                         let _ = app_events.emit("synapse://log", msg.clone());
                     }
@@ -307,55 +704,70 @@ async fn start_client(
         });
 
         // 消息处理线程（InputSimulator 需要在独立线程运行）
+        let server_tx = client.sender();
         let cancel_sim = cancel.clone();
+        let event_tx_sim = event_tx.clone();
+        let app_sim = app_clone.clone();
+        let state_sim = state_clone.clone();
         std::thread::spawn(move || {
-            let mut simulator = match InputSimulator::new() {
+            let created = create_with_retry(
+                DEFAULT_CREATE_ATTEMPTS,
+                DEFAULT_CREATE_RETRY_DELAY,
+                InputSimulator::new,
+                |attempt, e| {
+                    tracing::warn!(attempt, "failed to create InputSimulator: {e}");
+                    if attempt < DEFAULT_CREATE_ATTEMPTS {
+                        let _ = event_tx_sim.send(ClientEvent::Log(format!(
+                            "Input simulator not ready ({e}), retrying"
+                        )));
+                    }
+                },
+            );
+            let simulator = match created {
                 Ok(s) => s,
                 Err(e) => {
                     tracing::error!("Failed to create InputSimulator: {e}");
+                    let _ = event_tx_sim.send(ClientEvent::SimulatorUnavailable {
+                        attempts: DEFAULT_CREATE_ATTEMPTS,
+                        error: e.to_string(),
+                    });
                     return;
                 }
             };
+            let applier = MessageApplier::new(simulator, server_tx, event_tx_sim);
+            // 不含剪贴板支持的构建不写入本机剪贴板，也不回执
+            #[cfg(feature = "clipboard")]
+            let mut applier = applier.with_clipboard(move |msg| {
+                if !policy.allows_message(msg) {
+                    return Ok(false);
+                }
+                ClipboardWatcher::write_message(msg).map(|()| true)
+            });
+            #[cfg(not(feature = "clipboard"))]
+            let mut applier = applier;
             let rt = tokio::runtime::Builder::new_current_thread()
                 .enable_all()
                 .build()
                 .unwrap();
-            rt.block_on(async {
-                loop {
-                    tokio::select! {
-                        _ = cancel_sim.cancelled() => break,
-                        Some(msg) = message_rx.recv() => {
-                            match msg {
-                                Message::MouseMove { x, y } => {
-                                    let _ = simulator.move_mouse(x as i32, y as i32);
-                                }
-                                Message::MouseDelta { dx, dy } => {
-                                    let _ = simulator.move_mouse_relative(dx as i32, dy as i32);
-                                }
-                                Message::MouseButtonEvent { button, action } => {
-                                    let _ = simulator.mouse_button(button, action);
-                                }
-                                Message::KeyEvent { key, action } => {
-                                    let _ = simulator.key_event(key, action);
-                                }
-                                Message::MouseScroll { dx, dy } => {
-                                    let _ = simulator.scroll(dx as i32, dy as i32);
-                                }
-                                Message::ClipboardText { text } => {
-                                    let _ = ClipboardWatcher::set_text(&text);
-                                }
-                                _ => {}
-                            }
-                        }
-                        else => break,
+            rt.block_on(applier.run(&mut message_rx, cancel_sim, |msg| {
+                let app_sim = app_sim.clone();
+                let state_sim = state_sim.clone();
+                async move {
+                    if let Message::ClipboardHistory { items } = msg {
+                        let preview: Vec<ClipboardHistoryItem> = items
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(i, item)| ClipboardHistoryItem::from_message(i, item))
+                            .collect();
+                        let _ = app_sim.emit("synapse://clipboard-history", preview);
+                        state_sim.lock().await.clipboard_history = items;
                     }
                 }
-            });
+            }));
         });
 
         // 启动客户端连接
         let screen_size = get_screen_size();
-        let client = Client::new(server_addr);
         if let Err(e) = client.connect(
             hostname.clone(),
             hostname,
@@ -364,13 +776,25 @@ async fn start_client(
             event_tx,
             cancel,
         ).await {
-            let _ = app_clone.emit("synapse://log", format!("Client error: {e}"));
+            let message = match e {
+                SynapseError::Cancelled => None,
+                SynapseError::Auth(_) => Some(format!("Server rejected this device: {e}")),
+                SynapseError::ProtocolVersion { .. } => {
+                    Some(format!("Incompatible Synapse version: {e}"))
+                }
+                e => Some(format!("Client error: {e}")),
+            };
+            if let Some(message) = message {
+                let _ = app_clone.emit("synapse://log", message);
+            }
         }
 
         // 清理状态
         let mut s = state_clone.lock().await;
         s.role = Role::Idle;
         s.connected = false;
+        s.client_tx = None;
+        s.clipboard_history.clear();
         let _ = app_clone.emit("synapse://status", AppStatus {
             role: Role::Idle,
             connected: false,
@@ -395,11 +819,24 @@ async fn stop(
     if let Some(cancel) = s.cancel.take() {
         cancel.cancel();
     }
+    // 优雅停止：服务端在时限内发完各设备队列中的消息并发送 Bye，等待其结束后再清理状态
+    if let (Some(timeout), Some(handle)) = (s.drain_timeout.take(), s.handle.take()) {
+        drop(s);
+        let _ = app.emit("synapse://log", "Stopping, flushing pending messages".to_string());
+        if tokio::time::timeout(timeout + DRAIN_GRACE, handle).await.is_err() {
+            tracing::warn!("server did not stop within the drain timeout");
+        }
+        s = state.lock().await;
+    }
 
     s.role = Role::Idle;
     s.connected = false;
     s.devices.clear();
     s.handle = None;
+    s.control = None;
+    s.server = None;
+    s.client_tx = None;
+    s.clipboard_history.clear();
 
     let _ = app.emit("synapse://status", AppStatus {
         role: Role::Idle,
@@ -411,6 +848,175 @@ async fn stop(
     Ok(())
 }
 
+/// 允许等待确认的设备接入，并记入可信设备列表
+#[tauri::command]
+async fn approve_device(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    device_id: String,
+    fingerprint: Option<String>,
+) -> Result<(), String> {
+    let mut s = state.lock().await;
+    let control = s.control.as_ref().ok_or("Server is not running")?;
+    let _ = control.send(ServerControl::ApprovePairing { device_id });
+
+    let Some(fingerprint) = fingerprint else {
+        return Ok(());
+    };
+    s.settings = update_config(&app, |config| {
+        if !config.trusted_devices.contains(&fingerprint) {
+            config.trusted_devices.push(fingerprint);
+        }
+    })?;
+    Ok(())
+}
+
+/// 为设备设置显示名称（为空时恢复设备上报的名称），保存到配置中供下次启动使用
+#[tauri::command]
+async fn set_device_label(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    device_id: String,
+    label: String,
+) -> Result<(), String> {
+    let label = label.trim().to_string();
+    let mut s = state.lock().await;
+    if let Some(server) = s.server.as_ref() {
+        server.set_device_label(device_id.clone(), label.clone());
+    }
+
+    s.settings = update_config(&app, |config| {
+        if label.is_empty() {
+            config.device_labels.remove(&device_id);
+        } else {
+            config.device_labels.insert(device_id, label);
+        }
+    })?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn reject_device(
+    state: tauri::State<'_, SharedState>,
+    device_id: String,
+) -> Result<(), String> {
+    let s = state.lock().await;
+    let control = s.control.as_ref().ok_or("Server is not running")?;
+    let _ = control.send(ServerControl::RejectPairing { device_id });
+    Ok(())
+}
+
+/// 锁定所有已连接设备及本机
+#[tauri::command]
+async fn lock_all_devices(state: tauri::State<'_, SharedState>) -> Result<(), String> {
+    let s = state.lock().await;
+    let control = s.control.as_ref().ok_or("Server is not running")?;
+    let _ = control.send(ServerControl::LockAll);
+    Ok(())
+}
+
+/// 进行中的剪贴板传输，供界面显示进度
+#[tauri::command]
+async fn list_transfers(
+    state: tauri::State<'_, SharedState>,
+) -> Result<Vec<TransferStatus>, String> {
+    let s = state.lock().await;
+    let server = s.server.as_ref().ok_or("Server is not running")?;
+    Ok(server.active_transfers())
+}
+
+/// 取消排队中的剪贴板传输，结果通过 synapse://transfer-cancelled 事件返回
+#[tauri::command]
+async fn cancel_transfer(
+    state: tauri::State<'_, SharedState>,
+    transfer_id: u64,
+) -> Result<(), String> {
+    let s = state.lock().await;
+    let server = s.server.as_ref().ok_or("Server is not running")?;
+    server.cancel_transfer(transfer_id);
+    Ok(())
+}
+
+/// 测试服务端地址是否可达并完成握手，返回服务端的名称与屏幕，不建立会话
+#[tauri::command]
+async fn probe_server(addr: String) -> Result<ServerInfo, String> {
+    Client::probe(&addr, PROBE_TIMEOUT).await.map_err(|e| e.to_string())
+}
+
+/// 向服务端请求剪贴板历史，结果通过 synapse://clipboard-history 事件返回
+#[tauri::command]
+async fn request_clipboard_history(state: tauri::State<'_, SharedState>) -> Result<(), String> {
+    let s = state.lock().await;
+    let tx = s.client_tx.as_ref().ok_or("Client is not running")?;
+    tx.send(Message::ClipboardHistoryRequest).map_err(|_| "Not connected".to_string())
+}
+
+/// 将剪贴板历史中的一项写入本机剪贴板
+#[tauri::command]
+async fn select_clipboard_history(
+    state: tauri::State<'_, SharedState>,
+    index: usize,
+) -> Result<(), String> {
+    let item = {
+        let s = state.lock().await;
+        s.clipboard_history.get(index).cloned().ok_or("No such clipboard history item")?
+    };
+    #[cfg(feature = "clipboard")]
+    {
+        let result = match item {
+            Message::ClipboardText { text, .. } => ClipboardWatcher::set_text(&text),
+            Message::ClipboardImage { width, height, encoding, data, .. } => {
+                ClipboardWatcher::set_image(width, height, encoding, &data, DEFAULT_MAX_IMAGE_PIXELS)
+            }
+            _ => return Err("Unsupported clipboard history item".into()),
+        };
+        result.map_err(|e| e.to_string())
+    }
+    #[cfg(not(feature = "clipboard"))]
+    {
+        let _ = item;
+        Err("Clipboard support is not included in this build".into())
+    }
+}
+
+#[tauri::command]
+async fn set_autostart(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    config: StartupConfig,
+) -> Result<(), String> {
+    // 只更新启动相关的字段；配对、可信设备等其余设置由各自的命令或配置文件维护，保持不变
+    let mut s = state.lock().await;
+    s.settings = update_config(&app, |stored| {
+        stored.role = config.role;
+        stored.bind = config.bind;
+        stored.server_addr = config.server_addr;
+        stored.client_direction = config.client_direction;
+        stored.auto_start = config.auto_start;
+    })?;
+    Ok(())
+}
+
+/// 读取持久化配置（上次使用的角色、地址等），供界面预填；没有配置文件时返回默认值
+#[tauri::command]
+async fn get_settings(state: tauri::State<'_, SharedState>) -> Result<StartupConfig, String> {
+    Ok(state.lock().await.settings.clone())
+}
+
+/// 把 `config` 中给出的字段合并进持久化配置，其余字段保持不变，返回合并后的配置
+#[tauri::command]
+async fn save_settings(
+    app: AppHandle,
+    state: tauri::State<'_, SharedState>,
+    config: serde_json::Value,
+) -> Result<StartupConfig, String> {
+    let mut s = state.lock().await;
+    let merged = merge_config(read_config_value(&app)?, config)?;
+    save_config(&app, &merged)?;
+    s.settings = merged.clone();
+    Ok(merged)
+}
+
 #[tauri::command]
 async fn get_status(
     state: tauri::State<'_, SharedState>,
@@ -423,16 +1029,134 @@ async fn get_status(
     })
 }
 
+/// 显示配置的检查间隔
+const SCREEN_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 定期检查本机主屏区域，变化（接入显示器、改变分辨率或旋转）时通知服务端
+async fn watch_screen_changes(
+    mut screen: ScreenRect,
+    control: mpsc::UnboundedSender<ServerControl>,
+    cancel: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(SCREEN_POLL_INTERVAL) => {}
+        }
+        let current = match tokio::task::spawn_blocking(primary_screen_rect).await {
+            Ok(rect) => rect,
+            Err(_) => continue,
+        };
+        if current != screen {
+            tracing::info!(?current, "display configuration changed");
+            screen = current;
+            if control.send(ServerControl::UpdateScreen { rect: current }).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// 初始化日志；设置环境变量 `SYNAPSE_LOG_FORMAT=json` 时输出 JSON 格式，便于日志收集系统解析
+fn init_logging() {
+    let filter = EnvFilter::from_default_env().add_directive(
+        "synapse=info".parse().expect("valid log directive"),
+    );
+    let json = std::env::var("SYNAPSE_LOG_FORMAT").is_ok_and(|f| f.eq_ignore_ascii_case("json"));
+    let result = if json {
+        tracing_subscriber::fmt().json().with_env_filter(filter).try_init()
+    } else {
+        tracing_subscriber::fmt().with_env_filter(filter).try_init()
+    };
+    if let Err(e) = result {
+        eprintln!("failed to initialize logging: {e}");
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    init_logging();
     tauri::Builder::default()
         .manage(SharedState::default())
+        .setup(|app| {
+            let handle = app.handle().clone();
+            let settings = load_config(&handle);
+            app.state::<SharedState>().blocking_lock().settings =
+                settings.clone().unwrap_or_default();
+            if let Some(config) = settings.filter(|c| c.auto_start) {
+                // 在异步运行时上启动，不阻塞 setup；启动结果通过 synapse://status 事件通知界面
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = auto_start(handle.clone(), config).await {
+                        tracing::error!("auto start failed: {e}");
+                        let _ = handle.emit("synapse://log", format!("Auto start failed: {e}"));
+                    }
+                });
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             start_server,
             start_client,
             stop,
+            set_autostart,
+            get_settings,
+            save_settings,
+            approve_device,
+            reject_device,
+            set_device_label,
+            lock_all_devices,
+            list_transfers,
+            cancel_transfer,
+            probe_server,
+            request_clipboard_history,
+            select_clipboard_history,
             get_status,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn startup_config_round_trip() {
+        let config = StartupConfig {
+            role: Role::Client,
+            server_addr: Some("192.168.1.20:24800".into()),
+            client_direction: Some("left".into()),
+            trusted_devices: vec!["laptop-1".into()],
+            drain_timeout_ms: Some(500),
+            device_labels: HashMap::from([("laptop-1".into(), "Laptop".into())]),
+            max_clipboard_text: Some(1024),
+            ..StartupConfig::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        let parsed: StartupConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::to_value(&config).unwrap());
+
+        // 旧版本写出的配置缺少新字段时取默认值
+        let parsed: StartupConfig = serde_json::from_str(r#"{"role": "Server"}"#).unwrap();
+        assert_eq!(parsed.role, Role::Server);
+        assert!(parsed.trusted_devices.is_empty());
+    }
+
+    #[test]
+    fn merge_keeps_fields_missing_from_patch() {
+        let existing = serde_json::json!({
+            "role": "Server",
+            "trusted_devices": ["laptop-1"],
+            "reject_unlisted": true,
+        });
+        let patch = serde_json::json!({ "role": "Client", "server_addr": "10.0.0.2:24800" });
+        let merged = merge_config(Some(existing), patch).unwrap();
+        assert_eq!(merged.role, Role::Client);
+        assert_eq!(merged.server_addr.as_deref(), Some("10.0.0.2:24800"));
+        assert_eq!(merged.trusted_devices, ["laptop-1"]);
+        assert!(merged.reject_unlisted);
+
+        assert!(merge_config(None, serde_json::json!([])).is_err());
+        assert!(merge_config(Some(serde_json::json!("broken")), serde_json::json!({})).is_err());
+    }
+}
@@ -1,49 +1,231 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
-use synapse_clipboard::{ClipboardContent, ClipboardWatcher};
-use synapse_input::capture::{get_screen_size, rdev_event_to_message, InputCapturer};
-use synapse_input::InputSimulator;
-use synapse_net::{ClientEvent, LocalAction, Server, ServerEvent};
-use synapse_protocol::screen::Edge;
+use serde::Deserialize;
+#[cfg(feature = "clipboard")]
+use synapse_clipboard::{
+    ClipboardContent, ClipboardHistory, ClipboardPolicy, ClipboardWatcher,
+    DEFAULT_HISTORY_BYTES,
+};
+use synapse_input::capture::{
+    get_screen_size, get_screens, primary_screen_rect, rdev_event_to_layout_message, rdev_event_to_message,
+    InputCapturer,
+};
+use synapse_input::simulate::{DEFAULT_CREATE_ATTEMPTS, DEFAULT_CREATE_RETRY_DELAY};
+use synapse_input::{create_with_retry, lock_screen, relative, InputSimulator};
+use synapse_net::{
+    apply_local_action, parse_bind_addr, ClientEvent, EdgeTrigger, LocalAction, LocalEcho,
+    MessageApplier, MessageRecorder, PasteStrategy, Server, DeviceLayout, ServerConfig, ServerControl, ServerEvent,
+    SynapseError,
+    DEFAULT_BIND, DEFAULT_RETURN_COOLDOWN, DEFAULT_SWITCH_COOLDOWN,
+};
+#[cfg(feature = "clipboard")]
+use synapse_net::Sequenced;
+use synapse_protocol::input::ScrollUnit;
+use synapse_protocol::screen::{Edge, ScreenRect};
 use synapse_protocol::Message;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
+use std::net::SocketAddr;
+#[cfg(feature = "clipboard")]
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+mod selftest;
+
+/// `--layout` 指定的多设备布局文件
+#[derive(Debug, Default, Deserialize)]
+struct LayoutFile {
+    devices: Vec<DeviceLayout>,
+    /// 拒绝未列出的设备
+    #[serde(default)]
+    reject_unlisted: bool,
+}
+
+impl LayoutFile {
+    fn load(path: &std::path::Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read layout file {}", path.display()))?;
+        let layout: LayoutFile = serde_json::from_str(&data)
+            .with_context(|| format!("invalid layout file {}", path.display()))?;
+        tracing::info!(devices = layout.devices.len(), "loaded device layout");
+        Ok(layout)
+    }
+}
+
 #[derive(Parser)]
 #[command(name = "synapse", version, about = "多设备跨平台协作工具")]
 struct Cli {
+    /// 日志格式 (text: 便于阅读 / json: 每行一个 JSON 对象，便于日志收集系统解析)
+    #[arg(long, global = true, default_value = "text", value_parser = ["text", "json"])]
+    log_format: String,
     #[command(subcommand)]
     command: Command,
 }
 
+// 命令只在启动时解析一次，变体大小差异无关紧要
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Command {
     /// 以服务端模式运行（主控端）
     Server {
-        /// 监听地址
-        #[arg(short, long, default_value = "0.0.0.0:24800")]
-        bind: String,
+        /// 监听地址（host:port；只给端口时监听所有网卡）
+        #[arg(short, long, default_value = DEFAULT_BIND, value_parser = parse_bind_addr)]
+        bind: SocketAddr,
         /// Client 所在方向 (left/right/top/bottom)
         #[arg(short = 'd', long, default_value = "right")]
-        client_direction: String,
+        client_direction: Edge,
+        /// 持续推向边缘该毫秒数后才切换
+        #[arg(long, conflicts_with = "edge_double_push")]
+        edge_dwell: Option<u64>,
+        /// 需在该毫秒数内两次推向同一边缘才切换
+        #[arg(long)]
+        edge_double_push: Option<u64>,
+        /// 按字符而非物理键位转发可打印按键（两端键盘布局不同时使用）
+        #[arg(long)]
+        layout_aware: bool,
+        /// 焦点在远程时本地光标跟随远程光标的位置（演示用），而非锁定在屏幕中心
+        #[arg(long)]
+        follow_cursor: bool,
+        /// 焦点在远程时隐藏本地光标（目前仅 X11 支持，其他平台忽略）
+        #[arg(long)]
+        hide_cursor: bool,
+        /// 不使用原始相对运动：焦点在远程时照旧把光标锁回屏幕中心、不锁定指针
+        /// （默认在支持的平台上启用，目前仅 X11）
+        #[arg(long)]
+        no_relative_motion: bool,
+        /// 客户端支持时使用带同步字与校验和的帧格式，数据损坏时跳过损坏部分而不断开
+        #[arg(long)]
+        frame_sync: bool,
+        /// 客户端支持时鼠标运动改经 UDP 发送（与监听同一端口），弱网下减少光标卡顿
+        #[arg(long)]
+        udp_motion: bool,
+        /// 焦点在远程时按键与鼠标按键同时在本机模拟（演示用）
+        #[arg(long)]
+        local_echo: bool,
+        /// 不向焦点设备同步剪贴板，改为在其上按 Ctrl+V 时直接键入剪贴板文本
+        #[arg(long)]
+        type_paste: bool,
+        /// 不同步剪贴板图片
+        #[arg(long)]
+        no_clipboard_images: bool,
+        /// 超过该字节数的剪贴板文本不同步
+        #[arg(long)]
+        max_clipboard_text: Option<usize>,
+        /// 本机剪贴板被清空时同步清空客户端剪贴板
+        #[arg(long)]
+        sync_clipboard_clear: bool,
+        /// 保留最近该数量的剪贴板内容，供客户端查询剪贴板历史（0 表示不保留）
+        #[arg(long, default_value_t = 0)]
+        clipboard_history: usize,
+        /// 停止（Ctrl+C）时最多等待该毫秒数，发完各设备队列中的消息后再断开；未设置时立即断开
+        #[arg(long)]
+        drain_timeout_ms: Option<u64>,
+        /// 每次焦点切换后该毫秒数内不响应边缘触发
+        #[arg(long, default_value_t = DEFAULT_SWITCH_COOLDOWN.as_millis() as u64)]
+        switch_cooldown_ms: u64,
+        /// 从远程切回本地后该毫秒数内不响应边缘触发，防止在边界处来回切换
+        #[arg(long, default_value_t = DEFAULT_RETURN_COOLDOWN.as_millis() as u64)]
+        return_cooldown_ms: u64,
+        /// 未知设备首次连接时在终端询问是否允许接入
+        #[arg(long)]
+        require_pairing: bool,
+        /// 无需确认即可接入的设备配对指纹（客户端启动时打印，可重复）
+        #[arg(long = "trust", value_name = "FINGERPRINT")]
+        trusted_devices: Vec<String>,
+        /// 通过中继接入客户端（中继地址）
+        #[arg(long, requires = "session")]
+        relay: Option<String>,
+        /// 中继会话码
+        #[arg(long, requires = "relay")]
+        session: Option<String>,
+        /// 多设备布局文件（JSON：`{"devices": [{"device", "edge", "absolute_pointer", "clipboard"}],
+        /// "reject_unlisted"}`），设备连接时按 ID 或名称放到指定边缘
+        #[arg(long)]
+        layout: Option<std::path::PathBuf>,
+        /// 将输入消息录制到该文件，用于复现问题
+        #[arg(long)]
+        record: Option<std::path::PathBuf>,
+        /// 以录制文件代替本机输入捕获，按原始时间间隔回放
+        #[arg(long, conflicts_with = "record")]
+        replay: Option<std::path::PathBuf>,
+        /// /health 与 /metrics 的 HTTP 监听地址（需启用 `metrics` feature）
+        #[cfg(feature = "metrics")]
+        #[arg(long)]
+        metrics_addr: Option<String>,
     },
     /// 以客户端模式运行（被控端）
     Client {
         /// 服务端地址
         #[arg(short, long)]
         server: String,
+        /// 鼠标运动抖动缓冲深度（消息数，0 表示关闭）
+        #[arg(long, default_value_t = 0)]
+        jitter_buffer: u32,
+        /// 中继会话码；指定时 `--server` 为中继地址
+        #[arg(long)]
+        session: Option<String>,
+        /// 希望位于服务端屏幕的哪条边缘 (left/right/top/bottom)
+        #[arg(long)]
+        edge: Option<Edge>,
+        /// 仅接收剪贴板同步，不接受鼠标键盘控制
+        #[arg(long)]
+        clipboard_only: bool,
+        /// 要求服务端发送绝对坐标而非相对位移
+        #[arg(long)]
+        absolute_pointer: bool,
+        /// 滚动单位 (lines: 逐格滚动 / pixels: 按像素累计，触控板的平滑滚动不会被取整为整格)
+        #[arg(long, default_value = "lines", value_parser = ["lines", "pixels"])]
+        scroll_unit: String,
+        /// 发往服务端的剪贴板变化合并窗口（毫秒，0 表示立即发送）
+        #[arg(long, default_value_t = 250)]
+        clipboard_debounce_ms: u64,
+        /// 服务端启用时经 UDP 接收鼠标运动（通过中继连接时无效）
+        #[arg(long)]
+        udp_motion: bool,
+        /// 模拟粘滞键：单独敲击的修饰键暂存，作用于下一个按键后释放；按住时照常转发
+        #[arg(long)]
+        sticky_keys: bool,
+        /// 配对密钥文件，不存在时生成；未指定时每次启动使用新密钥，服务端需重新确认
+        #[arg(long)]
+        pairing_key_file: Option<std::path::PathBuf>,
+    },
+    /// 以中继模式运行（转发无法直连的服务端与客户端）
+    Relay {
+        /// 监听地址
+        #[arg(short, long, default_value = "0.0.0.0:24801")]
+        bind: String,
+    },
+    /// 检查本机的输入捕获与模拟是否可用（权限等），不连接任何设备
+    SelfTest {
+        /// 每一步等待用户操作的时长（秒）
+        #[arg(long, default_value_t = 10)]
+        timeout_secs: u64,
     },
 }
 
+/// 按 `--log-format` 构建日志订阅者，输出写入 `writer`
+fn log_subscriber<W>(format: &str, filter: EnvFilter, writer: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let fmt = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer);
+    if format == "json" {
+        Box::new(fmt.json().finish())
+    } else {
+        Box::new(fmt.finish())
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive("synapse=info".parse()?))
-        .init();
-
     let cli = Cli::parse();
+    let filter = EnvFilter::from_default_env().add_directive("synapse=info".parse()?);
+    log_subscriber(&cli.log_format, filter, std::io::stdout).init();
+
     let cancel = CancellationToken::new();
 
     // Ctrl+C 处理
@@ -55,24 +237,43 @@ async fn main() -> Result<()> {
     });
 
     match cli.command {
-        Command::Server { bind, client_direction } => {
+        Command::Server {
+            bind, client_direction, edge_dwell, edge_double_push, layout_aware, follow_cursor,
+            hide_cursor, no_relative_motion, frame_sync, udp_motion, local_echo, type_paste,
+            no_clipboard_images, max_clipboard_text, sync_clipboard_clear, clipboard_history,
+            drain_timeout_ms, switch_cooldown_ms, return_cooldown_ms, require_pairing, trusted_devices,
+            relay, session, layout, record, replay,
+            #[cfg(feature = "metrics")]
+            metrics_addr,
+        } => {
             tracing::info!(addr = %bind, "starting synapse server");
 
-            let direction = match client_direction.to_lowercase().as_str() {
-                "left" => Edge::Left,
-                "right" => Edge::Right,
-                "top" => Edge::Top,
-                "bottom" => Edge::Bottom,
-                _ => Edge::Right,
-            };
-            let screen_size = get_screen_size();
-            tracing::info!(?screen_size, ?direction, "screen config");
+            let direction = client_direction;
+            let screen = primary_screen_rect();
+            tracing::info!(?screen, ?direction, "screen config");
 
             // 输入捕获
             let (rdev_tx, mut rdev_rx) = mpsc::unbounded_channel();
             let (input_tx, input_rx) = mpsc::unbounded_channel();
+            let (raw_tx, mut raw_rx) = mpsc::unbounded_channel();
+            let mut relative_motion = false;
             let capturer = InputCapturer::new();
-            capturer.start(rdev_tx)?;
+            if let Some(path) = replay {
+                let input_tx = input_tx.clone();
+                tokio::spawn(async move {
+                    match synapse_net::record::replay(&path, &input_tx, true).await {
+                        Ok(count) => tracing::info!(count, "replay finished"),
+                        Err(e) => tracing::error!("replay failed: {e}"),
+                    }
+                });
+            } else {
+                capturer.start(rdev_tx)?;
+                // 平台支持时改用原始相对运动，焦点在远程时锁定指针而不是锁回中心
+                relative_motion = !no_relative_motion && relative::start(raw_tx);
+                if relative_motion {
+                    tracing::info!("using raw pointer motion while a device has focus");
+                }
+            }
 
             let cancel_input = cancel.clone();
             tokio::spawn(async move {
@@ -80,46 +281,80 @@ async fn main() -> Result<()> {
                     tokio::select! {
                         _ = cancel_input.cancelled() => break,
                         Some(event) = rdev_rx.recv() => {
-                            if let Some(msg) = rdev_event_to_message(&event) {
-                                let _ = input_tx.send(msg);
+                            let msg = if layout_aware {
+                                rdev_event_to_layout_message(&event)
+                            } else {
+                                rdev_event_to_message(&event)
+                            };
+                            if let Some(msg) = msg {
+                                let _ = input_tx.send(msg.into());
                             }
                         }
+                        Some(msg) = raw_rx.recv() => {
+                            let _ = input_tx.send(msg.into());
+                        }
                         else => break,
                     }
                 }
             });
 
             // 剪贴板监控
-            let (clip_tx, mut clip_rx) = mpsc::unbounded_channel();
             let (clip_msg_tx, clip_msg_rx) = mpsc::unbounded_channel();
-            let watcher = ClipboardWatcher::new(Duration::from_millis(500));
-            watcher.watch(clip_tx).await?;
+            #[cfg(feature = "clipboard")]
+            let (policy, history) = {
+                let (clip_tx, mut clip_rx) = mpsc::unbounded_channel();
+                let watcher = ClipboardWatcher::new(Duration::from_millis(500))
+                    .with_adaptive_polling(Duration::from_millis(50), Duration::from_secs(2))
+                    .with_clear_detection(sync_clipboard_clear);
+                let history = Arc::new(Mutex::new(ClipboardHistory::new(
+                    clipboard_history,
+                    DEFAULT_HISTORY_BYTES,
+                )));
+                let watcher = if clipboard_history > 0 {
+                    watcher.with_history(history.clone())
+                } else {
+                    watcher
+                };
+                watcher.watch(clip_tx, cancel.clone()).await?;
 
-            let cancel_clip = cancel.clone();
-            tokio::spawn(async move {
-                loop {
-                    tokio::select! {
-                        _ = cancel_clip.cancelled() => break,
-                        Some(content) = clip_rx.recv() => {
-                            let msg = match content {
-                                ClipboardContent::Text(text) => Message::ClipboardText { text },
-                                ClipboardContent::Image { width, height, data } => {
-                                    Message::ClipboardImage {
-                                        width: width as u32,
-                                        height: height as u32,
-                                        data,
-                                    }
+                let policy = ClipboardPolicy {
+                    images: !no_clipboard_images,
+                    max_text_len: max_clipboard_text,
+                    ..ClipboardPolicy::default()
+                };
+                let policy_clip = policy.clone();
+                let cancel_clip = cancel.clone();
+                tokio::spawn(async move {
+                    loop {
+                        tokio::select! {
+                            _ = cancel_clip.cancelled() => break,
+                            Some(content) = clip_rx.recv() => {
+                                // 收到变化即分配序号，图片编码耗时不影响与输入的先后顺序
+                                let seq = Sequenced::reserve();
+                                if !policy_clip.allows(&content) {
+                                    tracing::debug!("clipboard change skipped by policy");
+                                    continue;
                                 }
-                            };
-                            let _ = clip_msg_tx.send(msg);
+                                let msg = Sequenced::with_seq(seq, content.into_message());
+                                let _ = clip_msg_tx.send(msg);
+                            }
+                            else => break,
                         }
-                        else => break,
                     }
-                }
-            });
+                });
+                (policy, history)
+            };
+            // 不含剪贴板支持的构建从不读取本机剪贴板，剪贴板通道立即关闭
+            #[cfg(not(feature = "clipboard"))]
+            {
+                let _ = (no_clipboard_images, max_clipboard_text, sync_clipboard_clear);
+                let _ = clipboard_history;
+                drop(clip_msg_tx);
+            }
 
             // 服务端事件处理
             let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+            let (pairing_tx, pairing_rx) = mpsc::unbounded_channel();
             tokio::spawn(async move {
                 while let Some(event) = event_rx.recv().await {
                     match event {
@@ -129,9 +364,34 @@ async fn main() -> Result<()> {
                         ServerEvent::DeviceDisconnected { device_id } => {
                             tracing::info!(%device_id, "device disconnected");
                         }
+                        ServerEvent::DeviceRenamed { device_id, device_name } => {
+                            tracing::info!(%device_id, %device_name, "device renamed");
+                        }
                         ServerEvent::FocusChanged { target } => {
                             tracing::info!(%target, "focus changed");
                         }
+                        ServerEvent::FocusChangedDetailed { from, to, edge, target } => {
+                            tracing::debug!(%from, %to, ?edge, ?target, "focus changed (detailed)");
+                        }
+                        ServerEvent::PairingRequest { device_id, device_name, fingerprint } => {
+                            tracing::info!(%device_id, %device_name, ?fingerprint, "pairing request");
+                            let _ = pairing_tx.send((device_id, device_name, fingerprint));
+                        }
+                        ServerEvent::DecodeError { device_id, error } => {
+                            tracing::error!(%device_id, %error, "dropped device after decode error");
+                        }
+                        ServerEvent::EdgeCrossed { edge, target } => {
+                            tracing::debug!(?edge, %target, "edge crossed");
+                        }
+                        ServerEvent::ClipboardSynced { device_id, transfer_id } => {
+                            tracing::debug!(%device_id, transfer_id, "clipboard synced");
+                        }
+                        ServerEvent::TransferCancelled { device_id, transfer_id } => {
+                            tracing::info!(%device_id, transfer_id, "clipboard transfer cancelled");
+                        }
+                        ServerEvent::DeviceEdgeChanged { device_id, edge } => {
+                            tracing::warn!(%device_id, ?edge, "device displaced from its edge");
+                        }
                         ServerEvent::Log(msg) => {
                             tracing::info!("{msg}");
                         }
@@ -160,8 +420,25 @@ async fn main() -> Result<()> {
                             _ = cancel_la.cancelled() => break,
                             Some(action) = local_action_rx.recv() => {
                                 match action {
-                                    LocalAction::MoveMouse(x, y) => {
-                                        let _ = simulator.move_mouse(x, y);
+                                    LocalAction::HideCursor => {
+                                        let _ = simulator.set_cursor_visible(false);
+                                    }
+                                    LocalAction::ShowCursor => {
+                                        let _ = simulator.set_cursor_visible(true);
+                                    }
+                                    LocalAction::LockPointer => {
+                                        let _ = simulator.set_pointer_locked(true);
+                                    }
+                                    LocalAction::UnlockPointer => {
+                                        let _ = simulator.set_pointer_locked(false);
+                                    }
+                                    LocalAction::LockScreen => {
+                                        if let Err(e) = lock_screen() {
+                                            tracing::warn!("screen lock failed: {e}");
+                                        }
+                                    }
+                                    action => {
+                                        let _ = apply_local_action(&mut simulator, &action);
                                     }
                                 }
                             }
@@ -171,13 +448,79 @@ async fn main() -> Result<()> {
                 });
             });
 
-            let server = Server::new(bind);
+            let edge_trigger = match (edge_dwell, edge_double_push) {
+                (Some(ms), _) => EdgeTrigger::Dwell(Duration::from_millis(ms)),
+                (_, Some(ms)) => EdgeTrigger::DoublePush(Duration::from_millis(ms)),
+                _ => EdgeTrigger::Immediate,
+            };
+            let layout = match layout {
+                Some(path) => LayoutFile::load(&path)?,
+                None => LayoutFile::default(),
+            };
+            let config = ServerConfig {
+                edge_trigger,
+                layout: layout.devices,
+                reject_unlisted: layout.reject_unlisted,
+                drain_timeout: drain_timeout_ms.map(Duration::from_millis),
+                switch_cooldown: Duration::from_millis(switch_cooldown_ms),
+                return_cooldown: Duration::from_millis(return_cooldown_ms),
+                require_pairing,
+                trusted_devices: trusted_devices.into_iter().collect(),
+                follow_cursor,
+                hide_cursor,
+                relative_motion,
+                frame_sync,
+                udp_motion,
+                local_echo: if local_echo { LocalEcho::All } else { LocalEcho::Off },
+                screens: get_screens(),
+                paste_strategy: if type_paste {
+                    PasteStrategy::TypeText
+                } else {
+                    PasteStrategy::Clipboard
+                },
+                ..ServerConfig::default()
+            };
+            let server = Server::new(bind.to_string()).with_config(config);
+            #[cfg(feature = "clipboard")]
+            let server = server
+                .with_clipboard_reader(move || {
+                    ClipboardWatcher::get_current()
+                        .ok()
+                        .flatten()
+                        .filter(|content| policy.allows(content))
+                        .map(ClipboardContent::into_message)
+                })
+                .with_clipboard_history(move || {
+                    history.lock().map(|history| history.to_messages()).unwrap_or_default()
+                });
+            if let (Some(relay), Some(session)) = (relay, session) {
+                server.connect_relay(&relay, &session).await?;
+            }
+            #[cfg(feature = "metrics")]
+            if let Some(addr) = metrics_addr {
+                let metrics = server.metrics();
+                let cancel = cancel.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = synapse_net::metrics::serve(&addr, metrics, cancel).await {
+                        tracing::error!("metrics endpoint failed: {e}");
+                    }
+                });
+            }
+            let input_rx = match record {
+                Some(path) => MessageRecorder::new(path).tap(input_rx, cancel.clone()).await?,
+                None => input_rx,
+            };
+            tokio::spawn(watch_screen_changes(screen, server.control_sender(), cancel.clone()));
+            tokio::spawn(prompt_pairings(pairing_rx, server.control_sender()));
             server.run(
                 input_rx, clip_msg_rx, local_action_tx, event_tx,
-                screen_size, direction, cancel,
+                screen, direction, cancel,
             ).await?;
         }
-        Command::Client { server } => {
+        Command::Client {
+            server, jitter_buffer, session, edge, clipboard_only, scroll_unit, absolute_pointer,
+            clipboard_debounce_ms, udp_motion, sticky_keys, pairing_key_file,
+        } => {
             tracing::info!(addr = %server, "connecting to synapse server");
 
             let hostname = hostname::get()
@@ -197,6 +540,12 @@ async fn main() -> Result<()> {
                         ClientEvent::Disconnected => {
                             tracing::info!("disconnected from server");
                         }
+                        ClientEvent::SimulationFailing { consecutive_failures, error } => {
+                            tracing::error!(consecutive_failures, %error, "input simulation failing");
+                        }
+                        ClientEvent::SimulatorUnavailable { attempts, error } => {
+                            tracing::error!(attempts, %error, "input simulator unavailable");
+                        }
                         ClientEvent::Log(msg) => {
                             tracing::info!("{msg}");
                         }
@@ -204,58 +553,237 @@ async fn main() -> Result<()> {
                 }
             });
 
+            let scroll_unit = match scroll_unit.as_str() {
+                "pixels" => ScrollUnit::Pixels,
+                _ => ScrollUnit::Lines,
+            };
+            let mut client = synapse_net::Client::new(server)
+                .with_jitter_buffer(jitter_buffer)
+                .with_clipboard_debounce(Duration::from_millis(clipboard_debounce_ms))
+                .with_clipboard_only(clipboard_only)
+                .with_scroll_unit(scroll_unit)
+                .with_absolute_pointer(absolute_pointer)
+                .with_udp_motion(udp_motion)
+                .with_screens(get_screens());
+            if let Some(session) = session {
+                client = client.with_relay_session(session);
+            }
+            if let Some(edge) = edge {
+                client = client.with_preferred_edge(edge);
+            }
+            if let Some(path) = pairing_key_file {
+                client = client.with_pairing_key(load_pairing_key(&path)?);
+            }
+            tracing::info!(fingerprint = %client.pairing_fingerprint(), "pairing fingerprint");
+
             // 消息处理（输入模拟）
+            let server_tx = client.sender();
             let cancel_sim = cancel.clone();
+            let event_tx_sim = event_tx.clone();
             std::thread::spawn(move || {
-                let mut simulator = match InputSimulator::new() {
-                    Ok(s) => s,
+                let created = create_with_retry(
+                    DEFAULT_CREATE_ATTEMPTS,
+                    DEFAULT_CREATE_RETRY_DELAY,
+                    InputSimulator::new,
+                    |attempt, e| {
+                        tracing::warn!(attempt, "failed to create InputSimulator: {e}");
+                    },
+                );
+                let simulator = match created {
+                    Ok(s) => s.with_scroll_unit(scroll_unit).with_sticky_keys(sticky_keys),
                     Err(e) => {
                         tracing::error!("Failed to create InputSimulator: {e}");
+                        let _ = event_tx_sim.send(ClientEvent::SimulatorUnavailable {
+                            attempts: DEFAULT_CREATE_ATTEMPTS,
+                            error: e.to_string(),
+                        });
                         return;
                     }
                 };
+                let applier = MessageApplier::new(simulator, server_tx, event_tx_sim);
+                // 不含剪贴板支持的构建不写入本机剪贴板，也不回执
+                #[cfg(feature = "clipboard")]
+                let mut applier = applier.with_clipboard(|msg| {
+                    ClipboardWatcher::write_message(msg).map(|()| true)
+                });
+                #[cfg(not(feature = "clipboard"))]
+                let mut applier = applier;
                 let rt = tokio::runtime::Builder::new_current_thread()
                     .enable_all()
                     .build()
                     .unwrap();
-                rt.block_on(async {
-                    loop {
-                        tokio::select! {
-                            _ = cancel_sim.cancelled() => break,
-                            Some(msg) = message_rx.recv() => {
-                                match msg {
-                                    Message::MouseMove { x, y } => {
-                                        let _ = simulator.move_mouse(x as i32, y as i32);
-                                    }
-                                    Message::MouseDelta { dx, dy } => {
-                                        let _ = simulator.move_mouse_relative(dx as i32, dy as i32);
-                                    }
-                                    Message::MouseButtonEvent { button, action } => {
-                                        let _ = simulator.mouse_button(button, action);
-                                    }
-                                    Message::KeyEvent { key, action } => {
-                                        let _ = simulator.key_event(key, action);
-                                    }
-                                    Message::MouseScroll { dx, dy } => {
-                                        let _ = simulator.scroll(dx as i32, dy as i32);
-                                    }
-                                    Message::ClipboardText { text } => {
-                                        let _ = ClipboardWatcher::set_text(&text);
-                                    }
-                                    _ => {}
-                                }
-                            }
-                            else => break,
-                        }
+                rt.block_on(applier.run(&mut message_rx, cancel_sim, |msg| async move {
+                    if let Message::ClipboardHistory { items } = msg {
+                        tracing::info!(count = items.len(), "received clipboard history");
                     }
-                });
+                }));
             });
 
-            let client = synapse_net::Client::new(server);
             let screen_size = get_screen_size();
-            client.connect(hostname.clone(), hostname, screen_size, message_tx, event_tx, cancel).await?;
+            match client.connect(hostname.clone(), hostname, screen_size, message_tx, event_tx, cancel).await {
+                // Ctrl+C 打断握手属于正常退出
+                Ok(()) | Err(SynapseError::Cancelled) => {}
+                Err(e @ (SynapseError::Auth(_) | SynapseError::ProtocolVersion { .. })) => {
+                    tracing::error!("server refused this device: {e}");
+                    return Err(e.into());
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Command::Relay { bind } => {
+            tracing::info!(addr = %bind, "starting synapse relay");
+            synapse_net::Relay::new(bind).run(cancel).await?;
+        }
+        Command::SelfTest { timeout_secs } => {
+            let (rdev_tx, mut rdev_rx) = mpsc::unbounded_channel();
+            let capturer = InputCapturer::new();
+            capturer.start(rdev_tx)?;
+            let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                while let Some(event) = rdev_rx.recv().await {
+                    if let Some(msg) = rdev_event_to_message(&event) {
+                        if event_tx.send(msg).is_err() {
+                            break;
+                        }
+                    }
+                }
+            });
+
+            let mut simulator = InputSimulator::new().map_err(|e| e.to_string());
+            let run = selftest::run(
+                &mut event_rx,
+                simulator.as_mut().map_err(|e| e.clone()),
+                Duration::from_secs(timeout_secs),
+                |text| println!("{text}"),
+            );
+            let report = tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                report = run => report,
+            };
+            capturer.stop();
+            println!("Self-test results:\n{report}");
+            if !report.passed() {
+                anyhow::bail!("self-test failed");
+            }
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// 读取配对密钥文件，不存在时生成新密钥并写入
+fn load_pairing_key(path: &std::path::Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(key) => Ok(key.trim().to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = synapse_net::pairing::generate_key();
+            std::fs::write(path, &key)
+                .with_context(|| format!("failed to write pairing key {}", path.display()))?;
+            tracing::info!(path = %path.display(), "generated new pairing key");
+            Ok(key)
+        }
+        Err(e) => Err(e).with_context(|| format!("failed to read pairing key {}", path.display())),
+    }
+}
+
+/// 在终端逐个询问等待确认的设备是否允许接入，回答 y 以外均视为拒绝
+async fn prompt_pairings(
+    mut requests: mpsc::UnboundedReceiver<(String, String, Option<String>)>,
+    control: mpsc::UnboundedSender<ServerControl>,
+) {
+    while let Some((device_id, device_name, fingerprint)) = requests.recv().await {
+        let fingerprint = fingerprint.as_deref().unwrap_or("none, cannot be remembered");
+        let question = format!(
+            "Allow device {device_name} ({device_id}, fingerprint {fingerprint}) to connect? [y/N] "
+        );
+        let approved = tokio::task::spawn_blocking(move || {
+            use std::io::Write;
+            eprint!("{question}");
+            let _ = std::io::stderr().flush();
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer).is_ok()
+                && answer.trim().eq_ignore_ascii_case("y")
+        })
+        .await
+        .unwrap_or(false);
+        let decision = if approved {
+            ServerControl::ApprovePairing { device_id }
+        } else {
+            ServerControl::RejectPairing { device_id }
+        };
+        if control.send(decision).is_err() {
+            break;
+        }
+    }
+}
+
+/// 显示配置的检查间隔
+const SCREEN_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 定期检查本机主屏区域，变化（接入显示器、改变分辨率或旋转）时通知服务端
+async fn watch_screen_changes(
+    mut screen: ScreenRect,
+    control: mpsc::UnboundedSender<ServerControl>,
+    cancel: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(SCREEN_POLL_INTERVAL) => {}
+        }
+        let current = match tokio::task::spawn_blocking(primary_screen_rect).await {
+            Ok(rect) => rect,
+            Err(_) => continue,
+        };
+        if current != screen {
+            tracing::info!(?current, "display configuration changed");
+            screen = current;
+            if control.send(ServerControl::UpdateScreen { rect: current }).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// 收集日志输出的内存缓冲
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_logs_carry_event_fields() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = log_subscriber("json", EnvFilter::new("info"), move || writer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(device_id = "laptop", peer_addr = "192.168.1.20:50000", "client connected");
+            tracing::debug!("filtered out");
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> =
+            output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 1, "{output}");
+        let line = &lines[0];
+        assert_eq!(line["level"], "INFO");
+        assert!(line["timestamp"].is_string());
+        assert_eq!(line["fields"]["message"], "client connected");
+        assert_eq!(line["fields"]["device_id"], "laptop");
+        assert_eq!(line["fields"]["peer_addr"], "192.168.1.20:50000");
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+use std::time::Duration;
+
+use synapse_input::Simulator;
+use synapse_protocol::input::KeyAction;
+use synapse_protocol::Message;
+use tokio::sync::mpsc;
+
+/// 模拟移动光标的距离（像素），检查后移回原位
+const NUDGE: i32 = 10;
+/// 等待模拟的光标移动被捕获的时长
+const ECHO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 单项检查的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    /// 前置检查未通过，无法进行
+    Skipped(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+/// 自检报告
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// 所有检查均通过
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.outcome == Outcome::Pass)
+    }
+
+    fn record(&mut self, name: &'static str, outcome: Outcome) {
+        self.checks.push(Check { name, outcome });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.outcome {
+                Outcome::Pass => writeln!(f, "  [PASS] {}", check.name)?,
+                Outcome::Fail(reason) => writeln!(f, "  [FAIL] {}: {reason}", check.name)?,
+                Outcome::Skipped(reason) => writeln!(f, "  [SKIP] {}: {reason}", check.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 在 `timeout` 内等待满足条件的消息，通道关闭或超时返回 `None`
+async fn wait_for(
+    events: &mut mpsc::UnboundedReceiver<Message>,
+    timeout: Duration,
+    matches: impl Fn(&Message) -> bool,
+) -> Option<Message> {
+    let wait = async {
+        while let Some(msg) = events.recv().await {
+            if matches(&msg) {
+                return Some(msg);
+            }
+        }
+        None
+    };
+    tokio::time::timeout(timeout, wait).await.ok().flatten()
+}
+
+/// 依次检查鼠标捕获、光标模拟与键盘捕获
+///
+/// `events` 为本机捕获并转换后的消息，`prompt` 向用户显示操作提示。
+/// 模拟只移动光标并移回原位，不模拟任何按键或点击
+pub async fn run<S: Simulator>(
+    events: &mut mpsc::UnboundedReceiver<Message>,
+    simulator: Result<&mut S, String>,
+    timeout: Duration,
+    prompt: impl Fn(&str),
+) -> Report {
+    let mut report = Report::default();
+
+    // ── 鼠标捕获 ──
+    prompt("Move the mouse...");
+    let position = match wait_for(events, timeout, |msg| matches!(msg, Message::MouseMove { .. }))
+        .await
+    {
+        Some(Message::MouseMove { x, y }) => {
+            report.record("mouse capture", Outcome::Pass);
+            Some((x, y))
+        }
+        _ => {
+            report.record(
+                "mouse capture",
+                Outcome::Fail(format!(
+                    "no mouse movement captured within {timeout:?}; check input monitoring permission"
+                )),
+            );
+            None
+        }
+    };
+
+    // ── 光标模拟 ──
+    let outcome = match (simulator, position) {
+        (Err(e), _) => Outcome::Fail(format!("cannot create input simulator: {e}")),
+        (Ok(_), None) => Outcome::Skipped("no cursor position captured".into()),
+        (Ok(simulator), Some((x, y))) => {
+            prompt("Stop moving the mouse, the cursor will be nudged and returned...");
+            tokio::time::sleep(ECHO_TIMEOUT).await;
+            // 以最新的光标位置为起点
+            let (mut x, mut y) = (x as i32, y as i32);
+            while let Ok(msg) = events.try_recv() {
+                if let Message::MouseMove { x: mx, y: my } = msg {
+                    (x, y) = (mx as i32, my as i32);
+                }
+            }
+            let (tx, ty) = (x + NUDGE, y);
+            let outcome = match simulator.move_mouse(tx, ty) {
+                Err(e) => Outcome::Fail(format!("moving the cursor failed: {e}")),
+                Ok(()) => {
+                    let echoed = wait_for(events, ECHO_TIMEOUT, |msg| {
+                        matches!(msg, Message::MouseMove { x, y }
+                            if (*x as i32 - tx).abs() <= 1 && (*y as i32 - ty).abs() <= 1)
+                    })
+                    .await;
+                    match echoed {
+                        Some(_) => Outcome::Pass,
+                        None => Outcome::Fail(
+                            "simulated cursor movement was not observed; \
+                             check accessibility permission"
+                                .into(),
+                        ),
+                    }
+                }
+            };
+            // 无论结果如何都移回原位
+            if let Err(e) = simulator.move_mouse(x, y) {
+                tracing::warn!("failed to return the cursor to its position: {e}");
+            }
+            outcome
+        }
+    };
+    report.record("cursor simulation", outcome);
+
+    // ── 键盘捕获 ──
+    prompt("Press any key...");
+    let key = wait_for(events, timeout, |msg| {
+        matches!(msg, Message::KeyEvent { action: KeyAction::Press, .. })
+    })
+    .await;
+    let outcome = match key {
+        Some(_) => Outcome::Pass,
+        None => Outcome::Fail(format!(
+            "no key press captured within {timeout:?}; check input monitoring permission"
+        )),
+    };
+    report.record("keyboard capture", outcome);
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synapse_input::mock::{RecordingSimulator, SimulatorCall};
+    use synapse_protocol::input::KeyCode;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// 按提示模拟用户操作：移动鼠标、按键；`echo` 为 true 时在光标模拟后报告移动到的位置
+    fn user(echo: bool) -> (mpsc::UnboundedReceiver<Message>, impl Fn(&str)) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let prompt = move |text: &str| {
+            if text.starts_with("Move the mouse") {
+                let _ = tx.send(Message::MouseMove { x: 100.0, y: 200.0 });
+            } else if text.starts_with("Stop moving") && echo {
+                // 捕获线程在模拟移动之后才报告新位置
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(ECHO_TIMEOUT + Duration::from_millis(100)).await;
+                    let _ = tx.send(Message::MouseMove { x: 110.0, y: 200.0 });
+                });
+            } else if text.starts_with("Press any key") {
+                let _ = tx.send(Message::KeyEvent { key: KeyCode::KeyA, action: KeyAction::Press });
+            }
+        };
+        (rx, prompt)
+    }
+
+    fn outcomes(report: &Report) -> Vec<(&'static str, Outcome)> {
+        report.checks.iter().map(|check| (check.name, check.outcome.clone())).collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn all_checks_pass_and_cursor_is_returned() {
+        let (mut events, prompt) = user(true);
+        let mut simulator = RecordingSimulator::new();
+        let report = run(&mut events, Ok(&mut simulator), TIMEOUT, prompt).await;
+        assert!(report.passed(), "{report}");
+        assert_eq!(
+            outcomes(&report).iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            ["mouse capture", "cursor simulation", "keyboard capture"]
+        );
+        assert_eq!(simulator.calls, [SimulatorCall::MoveMouse(110, 200), SimulatorCall::MoveMouse(100, 200)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unobserved_nudge_fails_but_still_returns_cursor() {
+        let (mut events, prompt) = user(false);
+        let mut simulator = RecordingSimulator::new();
+        let report = run(&mut events, Ok(&mut simulator), TIMEOUT, prompt).await;
+        assert!(!report.passed());
+        let outcomes = outcomes(&report);
+        assert_eq!(outcomes[0].1, Outcome::Pass);
+        assert!(matches!(&outcomes[1].1, Outcome::Fail(reason) if reason.contains("accessibility")));
+        assert_eq!(outcomes[2].1, Outcome::Pass);
+        assert_eq!(simulator.calls, [SimulatorCall::MoveMouse(110, 200), SimulatorCall::MoveMouse(100, 200)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn missing_capture_skips_simulation() {
+        let (_tx, mut events) = mpsc::unbounded_channel();
+        let mut simulator = RecordingSimulator::new();
+        let report = run(&mut events, Ok(&mut simulator), TIMEOUT, |_| {}).await;
+        let outcomes = outcomes(&report);
+        assert!(matches!(&outcomes[0].1, Outcome::Fail(reason) if reason.contains("input monitoring")));
+        assert!(matches!(outcomes[1].1, Outcome::Skipped(_)));
+        assert!(matches!(outcomes[2].1, Outcome::Fail(_)));
+        assert!(simulator.calls.is_empty(), "nothing is simulated without a known cursor position");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn simulator_creation_failure_is_reported() {
+        let (mut events, prompt) = user(true);
+        let report =
+            run::<RecordingSimulator>(&mut events, Err("permission denied".into()), TIMEOUT, prompt).await;
+        let outcomes = outcomes(&report);
+        assert_eq!(outcomes[0].1, Outcome::Pass);
+        assert_eq!(outcomes[1].1, Outcome::Fail("cannot create input simulator: permission denied".into()));
+        assert_eq!(outcomes[2].1, Outcome::Pass);
+    }
+}
@@ -14,6 +14,7 @@ const directionField = document.getElementById("direction-field");
 const serverInput = document.getElementById("server-input");
 const startBtn = document.getElementById("start-btn");
 const stopBtn = document.getElementById("stop-btn");
+const testBtn = document.getElementById("test-btn");
 const devicesCard = document.getElementById("devices-card");
 const deviceCount = document.getElementById("device-count");
 const deviceList = document.getElementById("device-list");
@@ -45,6 +46,7 @@ function setMode(mode) {
   bindField.style.display = mode === "server" ? "block" : "none";
   directionField.style.display = mode === "server" ? "block" : "none";
   serverField.style.display = mode === "client" ? "block" : "none";
+  testBtn.style.display = mode === "client" ? "block" : "none";
   devicesCard.style.display = mode === "server" && running ? "block" : "none";
 }
 
@@ -64,6 +66,7 @@ function updateStatus(status) {
       : `${status.role} - Waiting...`;
 
   startBtn.disabled = running;
+  testBtn.disabled = running;
   stopBtn.disabled = !running;
   modeServer.style.pointerEvents = running ? "none" : "auto";
   modeClient.style.pointerEvents = running ? "none" : "auto";
@@ -81,9 +84,10 @@ function updateStatus(status) {
 startBtn.addEventListener("click", async () => {
   try {
     if (currentMode === "server") {
-      const bind = bindInput.value || "0.0.0.0:24800";
+      // 留空时由后端使用配置文件中的地址或默认地址，地址格式也由后端校验
+      const bind = bindInput.value.trim() || null;
       const clientDirection = directionSelect.value;
-      appendLog(`Starting server on ${bind} (client: ${clientDirection})...`);
+      appendLog(`Starting server on ${bind ?? "the default address"} (client: ${clientDirection})...`);
       await invoke("start_server", { bind, clientDirection });
     } else {
       const addr = serverInput.value;
@@ -99,6 +103,28 @@ startBtn.addEventListener("click", async () => {
   }
 });
 
+// 测试连接：只握手，不建立会话
+testBtn.addEventListener("click", async () => {
+  const addr = serverInput.value.trim();
+  if (!addr) {
+    appendLog("Please enter server address");
+    return;
+  }
+  testBtn.disabled = true;
+  appendLog(`Testing ${addr}...`);
+  try {
+    const info = await invoke("probe_server", { addr });
+    const screens = info.screens
+      .map((s) => `${s.rect.width}×${s.rect.height}`)
+      .join(", ");
+    appendLog(`Reached ${info.device_name} (${screens})`);
+  } catch (err) {
+    appendLog(`Test failed: ${err}`);
+  } finally {
+    testBtn.disabled = running;
+  }
+});
+
 stopBtn.addEventListener("click", async () => {
   try {
     await invoke("stop");
@@ -114,6 +140,18 @@ function addDevice(info) {
   el.className = "device-item";
   el.dataset.id = info.device_id;
   el.innerHTML = `<span class="dot"></span><span class="name">${info.device_name}</span><span class="id">${info.device_id}</span>`;
+  el.title = "Double-click to rename";
+  // 双击设置仅在本机显示的名称，留空恢复设备上报的名称
+  el.addEventListener("dblclick", async () => {
+    const current = el.querySelector(".name").textContent;
+    const label = window.prompt(`Display name for ${info.device_id}`, current);
+    if (label === null) return;
+    try {
+      await invoke("set_device_label", { deviceId: info.device_id, label });
+    } catch (err) {
+      appendLog(`Error: ${err}`);
+    }
+  });
   deviceList.appendChild(el);
   deviceCount.textContent = deviceList.children.length;
 }
@@ -133,18 +171,73 @@ listen("synapse://log", (event) => {
   appendLog(event.payload);
 });
 
+listen("synapse://focus", (event) => {
+  const { to, edge, target } = event.payload;
+  const name = target ? `${target.device_name} ${target.width}×${target.height}` : to;
+  appendLog(edge ? `→ ${name} (${edge.toLowerCase()})` : `→ ${name}`);
+});
+
+listen("synapse://pairing-request", async (event) => {
+  const { device_id, device_name, fingerprint } = event.payload;
+  appendLog(`Pairing request from ${device_name}`);
+  // 指纹应与设备日志中的 "Pairing fingerprint" 一致，不一致说明是冒用设备 ID 的连接
+  const shown = fingerprint ?? "none (will not be remembered)";
+  const approved = window.confirm(
+    `Allow ${device_name} (${device_id}) to connect?\nFingerprint: ${shown}`,
+  );
+  if (approved) {
+    await invoke("approve_device", { deviceId: device_id, fingerprint });
+  } else {
+    await invoke("reject_device", { deviceId: device_id });
+  }
+});
+
+listen("synapse://clipboard-synced", (event) => {
+  appendLog(`Clipboard synced to ${event.payload.device_id}`);
+});
+
+listen("synapse://transfer-cancelled", (event) => {
+  appendLog(`Clipboard transfer to ${event.payload.device_id} cancelled`);
+});
+
 listen("synapse://device-connected", (event) => {
   addDevice(event.payload);
   appendLog(`Device connected: ${event.payload.device_name}`);
 });
 
+listen("synapse://device-renamed", (event) => {
+  const { device_id, device_name } = event.payload;
+  const el = deviceList.querySelector(`[data-id="${device_id}"] .name`);
+  if (el) el.textContent = device_name;
+  appendLog(`Device ${device_id} is now shown as ${device_name}`);
+});
+
+listen("synapse://simulator-unavailable", (event) => {
+  appendLog(`Input simulation unavailable: ${event.payload}`);
+  window.alert(`Synapse cannot control this machine: ${event.payload}`);
+});
+
 listen("synapse://device-disconnected", (event) => {
   removeDevice(event.payload);
   appendLog(`Device disconnected: ${event.payload}`);
 });
 
+// 按上次使用的设置预填
+async function loadSettings() {
+  const settings = await invoke("get_settings");
+  if (settings.bind) bindInput.value = settings.bind;
+  if (settings.client_direction) directionSelect.value = settings.client_direction;
+  if (settings.server_addr) serverInput.value = settings.server_addr;
+  if (settings.role === "Client") setMode("client");
+}
+
 // 初始化
 (async () => {
+  try {
+    await loadSettings();
+  } catch (err) {
+    appendLog(`Failed to load settings: ${err}`);
+  }
   try {
     const status = await invoke("get_status");
     updateStatus(status);